@@ -0,0 +1,223 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[cfg(unix)]
+use fuser::{BackgroundSession, Filesystem, MountOption};
+#[cfg(unix)]
+use log::debug;
+use log::error;
+
+use crate::Shared;
+#[cfg(unix)]
+use crate::DEFAULT_FSNAME;
+
+/// A mechanism of the platform that presents a file system at a mountpoint.
+pub(crate) trait Backend {
+    /// A mounted file system, which is unmounted when dropped.
+    type Session: fmt::Debug + Send;
+
+    /// The path the file system of the session is mounted at.
+    fn mountpoint(session: &Self::Session) -> &Path;
+
+    /// Unmounts the file system and waits until it has shut down. Returns the error that ended
+    /// the session, if any.
+    fn unmount(session: Self::Session) -> io::Result<()>;
+}
+
+/// Mounting a file system of type `FS` through a backend.
+pub(crate) trait Mount<FS>: Backend {
+    /// Mounts `filesystem` at `mountpoint`. The options are given like on the command line and
+    /// are interpreted by the backend.
+    fn mount(filesystem: FS, mountpoint: &Path, options: &[&OsStr]) -> io::Result<Self::Session>;
+}
+
+/// The backend SplitFS and CatFS are mounted through on this platform.
+#[cfg(unix)]
+pub(crate) type Platform = Fuse;
+
+#[cfg(windows)]
+pub(crate) type Platform = WinFsp;
+
+/// Mounts through FUSE in a background thread.
+#[cfg(unix)]
+pub(crate) struct Fuse;
+
+#[cfg(unix)]
+impl Backend for Fuse {
+    type Session = BackgroundSession;
+
+    fn mountpoint(session: &BackgroundSession) -> &Path {
+        &session.mountpoint
+    }
+
+    fn unmount(session: BackgroundSession) -> io::Result<()> {
+        // Leaving the block drops the mount, which unmounts the file system and ends the session
+        let guard = {
+            let session = session;
+            let BackgroundSession { guard, .. } = session;
+            guard
+        };
+
+        guard
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("session thread panicked")))
+    }
+}
+
+#[cfg(unix)]
+impl<FS> Mount<FS> for Fuse
+where
+    FS: Filesystem + Shared + Send + 'static,
+{
+    fn mount(
+        filesystem: FS,
+        mountpoint: &Path,
+        options: &[&OsStr],
+    ) -> io::Result<BackgroundSession> {
+        let fuse_options = options
+            .iter()
+            .map(|x| mount_option_from_str(&x.to_string_lossy()));
+
+        // Only lenient writes need a writable mount, writing the content still fails then
+        let access = if filesystem.attr_overrides().is_enabled() {
+            MountOption::RW
+        } else {
+            MountOption::RO
+        };
+
+        // The name and the type shown in mount listings can be overridden by the given options
+        let options = {
+            let mut options = vec![
+                access,
+                MountOption::FSName(String::from(DEFAULT_FSNAME)),
+                MountOption::Subtype(String::from(FS::SUBTYPE)),
+            ];
+            for opt in fuse_options {
+                match opt {
+                    MountOption::FSName(_) => {
+                        options.retain(|opt| !matches!(opt, MountOption::FSName(_)))
+                    }
+                    MountOption::Subtype(_) => {
+                        options.retain(|opt| !matches!(opt, MountOption::Subtype(_)))
+                    }
+                    _ => {}
+                }
+                options.push(opt);
+            }
+            options
+        };
+
+        match fuser::spawn_mount2(filesystem, mountpoint, options.as_ref()) {
+            Ok(session) => {
+                debug!("Mounted at {:?}", mountpoint);
+                Ok(session)
+            }
+            Err(e) => {
+                error!("Could not mount at {:?}: {}", mountpoint, e);
+                Err(e)
+            }
+        }
+    }
+}
+
+// Copied from fuser, mount_options.rs. When this becomes part of their public API, delete this function.
+#[cfg(unix)]
+fn mount_option_from_str(s: &str) -> MountOption {
+    match s {
+        "auto_unmount" => MountOption::AutoUnmount,
+        "allow_other" => MountOption::AllowOther,
+        "allow_root" => MountOption::AllowRoot,
+        "default_permissions" => MountOption::DefaultPermissions,
+        "dev" => MountOption::Dev,
+        "nodev" => MountOption::NoDev,
+        "suid" => MountOption::Suid,
+        "nosuid" => MountOption::NoSuid,
+        "ro" => MountOption::RO,
+        "rw" => MountOption::RW,
+        "exec" => MountOption::Exec,
+        "noexec" => MountOption::NoExec,
+        "atime" => MountOption::Atime,
+        "noatime" => MountOption::NoAtime,
+        "dirsync" => MountOption::DirSync,
+        "sync" => MountOption::Sync,
+        "async" => MountOption::Async,
+        x if x.starts_with("fsname=") => MountOption::FSName(x[7..].into()),
+        x if x.starts_with("subtype=") => MountOption::Subtype(x[8..].into()),
+        x => MountOption::CUSTOM(x.into()),
+    }
+}
+
+/// Placeholder for mounting through WinFsp. The listings and chunk contents of SplitFS do not
+/// depend on FUSE, but serving them through WinFsp is not implemented yet, so mounting fails.
+#[cfg(windows)]
+pub(crate) struct WinFsp;
+
+/// A session of WinFsp, which cannot exist as long as mounting always fails.
+#[cfg(windows)]
+#[derive(Debug)]
+pub(crate) enum WinFspSession {}
+
+#[cfg(windows)]
+impl Backend for WinFsp {
+    type Session = WinFspSession;
+
+    fn mountpoint(session: &WinFspSession) -> &Path {
+        match *session {}
+    }
+
+    fn unmount(session: WinFspSession) -> io::Result<()> {
+        match session {}
+    }
+}
+
+#[cfg(windows)]
+impl<FS> Mount<FS> for WinFsp
+where
+    FS: Shared + Send + 'static,
+{
+    fn mount(_filesystem: FS, mountpoint: &Path, _options: &[&OsStr]) -> io::Result<WinFspSession> {
+        error!(
+            "Could not mount at {:?}: WinFsp is not supported yet",
+            mountpoint
+        );
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Mounting through WinFsp is not supported yet",
+        ))
+    }
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::shared::tests::create_files_and_symlinks;
+    use crate::{mount, Config, Settings, SplitFS};
+
+    #[test]
+    fn test_mount_is_unsupported() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![("a".to_string(), (0..10).collect::<Vec<u8>>())];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        // The chunks are indexed like on any other platform, only mounting them fails
+        let config = Config::default().blocksize(4).unwrap();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config,
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        assert_eq!(fs.chunked_files().len(), 1);
+
+        let error = mount(fs, &mountpoint, Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+
+        Ok(())
+    }
+}
@@ -1,81 +1,248 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::{OsStr, OsString};
+use std::fs;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
-use std::{fs, thread};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
+use flate2::read::GzDecoder;
+use fuser::consts::FOPEN_DIRECT_IO;
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
-    ReplyOpen, Request,
+    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyLseek, ReplyOpen, ReplyStatfs,
+    ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
-use libc::ENOENT;
+use libc::{c_int, EBADF, EINVAL, EIO, ENOENT, ENXIO, EROFS, SEEK_DATA, SEEK_HOLE};
+use log::{debug, warn};
 use rusqlite::{params, Connection};
 
+use crate::shared::is_writing_open;
 use crate::{
-    convert_filetype, convert_metadata_to_attr, Config, DropHookFn, FileHandle, FileInfo,
-    FileInfoRow, Shared, CONFIG_FILE_NAME, INO_FIRST_FREE, INO_OUTSIDE, INO_ROOT, STMT_CREATE,
-    STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, STMT_INSERT, STMT_QUERY_BY_PARENT_INO,
+    chunk_content_size, convert_metadata_to_attr, dir_listing, insert_file_info, insert_link,
+    is_special_file, limit_background_requests, open_chunk, open_mirror_file, pack_file_name,
+    parse_flat_name, presented_root, read_chunk, read_errno, read_segments, reply_dir_listing,
+    special_file_kind, tree_digest, AttrOverrides, ChunkFetcher, Config, DirListing, DropHookFn,
+    FileHandle, FileInfo, FileInfoRow, FileMeta, IdMap, IdleTimer, LocalChunks, LocalMirror,
+    MirrorSource, MountError, OpenFileLimit, PackEntry, PositionedReader, PrefetchedMirror,
+    ProgressReporter, ReadLimiter, Settings, Shared, ThreadPool, BLOCKSIZE_FILE_NAME,
+    CHECKSUM_SUFFIX, COUNT_FILE_NAME, INO_FIRST_FREE, INO_OUTSIDE, INO_ROOT, META_FILE_NAME,
+    PACK_FILE_PREFIX, PACK_INDEX_FILE_NAME, READS_FILE_NAME, STMT_CREATE,
+    STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, STMT_CREATE_LINKS, STMT_QUERY_BY_INO,
+    STMT_QUERY_BY_PARENT_INO, STMT_QUERY_BY_PARENT_INO_AND_FILENAME, STMT_QUERY_PARTS, TTL,
+    WHOLE_FILE_NAME, XATTR_PARTS,
 };
 
 pub(crate) struct CatFS {
     file_db: Connection,
     file_handles: HashMap<u64, Vec<FileHandle>>,
+    dir_handles: HashMap<u64, DirListing>,
     next_fh: u64,
+
+    // Chunks that have been opened by reads of a file handle, closed on release
+    open_files: HashMap<u64, Arc<OpenFiles>>,
     config: Config,
-    drop_hook: DropHookFn,
+
+    // Maps the inode of a packed file to its location inside of the pack object
+    packed: HashMap<u64, FileHandle>,
+
+    // Maps the inode of a restored file to its original metadata from .scfs_meta
+    metas: HashMap<u64, FileMeta>,
+
+    // Maps the inode of a restored file to its own blocksize from .scfs_blocksize
+    blocksizes: HashMap<u64, u64>,
+
+    // Maps the inode of a chunk to the size of its content, recorded while indexing so that
+    // the sizes of the files are known without fetching their chunks
+    chunk_sizes: HashMap<u64, u64>,
+
+    // Directories that only exist in the chunk source, they get the attributes of the root
+    source_dirs: HashSet<u64>,
+
+    // Whether the chunk at the given path only contains zeros, filled by lseek
+    zero_chunks: HashMap<OsString, bool>,
+
+    pool: ThreadPool,
+    ttl: Duration,
+
+    // Missing chunks leave gaps in their files instead of shifting the following chunks
+    allow_missing: bool,
+
+    special_files: bool,
+    single_threaded: bool,
+    noatime: bool,
+    uid_map: IdMap,
+    gid_map: IdMap,
+    ready: Option<Sender<()>>,
+
+    // Locates the chunks, fetching them if they are missing in the mirror
+    fetcher: Arc<ChunkFetcher>,
+
+    // Shared by the reads of all file handles, so the read limit applies to the whole mount
+    limiter: Arc<ReadLimiter>,
+    open_limit: Arc<OpenFileLimit>,
+
+    attr_overrides: AttrOverrides,
+
+    idle: IdleTimer,
+    drop_hook: Arc<DropHookFn>,
 }
 
 impl Shared for CatFS {
+    const SUBTYPE: &'static str = "catfs";
+
     fn file_db(&self) -> &Connection {
         &self.file_db
     }
 
-    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> FileAttr {
+    fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn attr_overrides(&self) -> &AttrOverrides {
+        &self.attr_overrides
+    }
+
+    fn attr_overrides_mut(&mut self) -> &mut AttrOverrides {
+        &mut self.attr_overrides
+    }
+
+    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        let mut attr = self.get_mirror_attr(file_info)?;
+        attr.uid = self.uid_map.map(attr.uid);
+        attr.gid = self.gid_map.map(attr.gid);
+
+        // All paths of a file with shared chunks are presented as hard links of the first one
         if file_info.vdir {
-            let parts = self.get_files_info_from_parent_ino(file_info.ino);
-            let attrs = parts
-                .iter()
-                .map(|info| {
-                    convert_metadata_to_attr(
-                        fs::symlink_metadata(&info.path).unwrap(),
-                        Some(info.ino),
-                    )
-                })
-                .collect::<Vec<_>>();
-            let mut attr = attrs.get(0).unwrap().clone();
-            attr.ino = file_info.ino;
-            attr.blocks = attrs.iter().map(|attr| attr.blocks).sum();
-            attr.size = attrs.iter().map(|attr| attr.size).sum();
-            attr
-        } else {
-            let attr = convert_metadata_to_attr(
-                fs::symlink_metadata(&file_info.path).unwrap(),
-                Some(file_info.ino),
-            );
-            attr
+            attr.ino = self.get_link_target(file_info.ino);
+            attr.nlink = self.count_links(attr.ino);
         }
+        Some(attr)
+    }
+
+    fn get_xattrs(&self, ino: u64) -> Option<Vec<(&'static str, Vec<u8>)>> {
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        if !file_info.vdir {
+            return Some(Vec::new());
+        }
+
+        let parts = self
+            .get_files_info_from_parent_ino(ino)
+            .iter()
+            .filter(|part| part.part > 0)
+            .count();
+        Some(vec![(XATTR_PARTS, parts.to_string().into_bytes())])
     }
 }
 
 impl CatFS {
-    pub(crate) fn new(mirror: &OsStr, drop_hook: DropHookFn) -> Self {
-        let config = serde_json::from_str(
-            &fs::read_to_string(Path::new(&mirror).join(CONFIG_FILE_NAME))
-                .expect("SCFS config file not found"),
-        )
-        .expect("SCFS config file contains invalid JSON");
+    pub(crate) fn new(
+        mirror: &OsStr,
+        mut settings: Settings,
+        drop_hook: DropHookFn,
+    ) -> Result<Self, MountError> {
+        let config = Config::from_file(Path::new(&mirror).join(settings.config_file_name()))?;
+
+        // Flat mirrors keep the chunks of all files in their root, there is no subdirectory to
+        // start at
+        if config.flat && settings.subdir.is_some() {
+            return Err(MountError::InvalidConfig(String::from(
+                "Flat mirrors cannot be mounted from a subdirectory",
+            )));
+        }
+        let root = presented_root(
+            Path::new(mirror),
+            settings.subdir.as_deref(),
+            &LocalMirror::default(),
+        )?;
 
         let file_db = Connection::open_in_memory().unwrap();
 
         file_db.execute(STMT_CREATE, []).unwrap();
+        file_db.execute(STMT_CREATE_LINKS, []).unwrap();
+
+        let mut packed = HashMap::new();
+        let mut metas = HashMap::new();
+        let mut blocksizes = HashMap::new();
+        let mut chunk_sizes = HashMap::new();
+        let mut source_dirs = HashSet::new();
+        let mut progress = ProgressReporter::new(settings.progress.take());
+        debug!("Indexing {:?}", mirror);
 
-        CatFS::populate(&file_db, &mirror, INO_OUTSIDE, INO_FIRST_FREE);
+        let has_chunk_source = settings.chunk_source.is_some();
+        let chunk_source = settings
+            .chunk_source
+            .take()
+            .unwrap_or_else(|| Arc::new(LocalChunks));
+
+        // Without a transaction, every single insert would be committed on its own
+        file_db.execute_batch("BEGIN").unwrap();
+        let mut source_chunks = None;
+        if config.flat {
+            CatFS::populate_flat(
+                &file_db,
+                Path::new(mirror),
+                &config,
+                settings.config_file_name(),
+                &mut progress,
+                settings.first_free_ino(),
+            );
+        } else {
+            let local = LocalMirror::default();
+            let source =
+                PrefetchedMirror::walk(&local, &root, false, settings.index_threads(), &|_| false);
+            let next_ino = CatFS::populate(
+                &file_db,
+                &root,
+                &config,
+                settings.config_file_name(),
+                settings.special_files,
+                &source,
+                &mut packed,
+                &mut metas,
+                &mut blocksizes,
+                &mut HashMap::new(),
+                &mut progress,
+                INO_OUTSIDE,
+                settings.first_free_ino(),
+            )?;
+            source_chunks = Some((next_ino, chunk_source.list()?));
+        }
+        file_db.execute_batch("COMMIT").unwrap();
 
         file_db
             .execute(STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, [])
             .unwrap();
 
+        if let Some((next_ino, chunks)) = source_chunks {
+            file_db.execute_batch("BEGIN").unwrap();
+            CatFS::populate_source(
+                &file_db,
+                &root,
+                &config,
+                chunks,
+                &mut chunk_sizes,
+                &mut source_dirs,
+                next_ino,
+            );
+            file_db.execute_batch("COMMIT").unwrap();
+        }
+        progress.finish();
+
+        CatFS::record_chunk_sizes(&file_db, &config, &blocksizes, &mut chunk_sizes);
+
+        if settings.verify {
+            CatFS::verify_parts(&file_db, &config, &blocksizes, &chunk_sizes)?;
+        }
+
+        // Only directories with chunks are files. Since SplitFS gives even an empty file one empty
+        // chunk, a directory without chunks is always a real directory, even if it is empty.
         {
             let query = "UPDATE Files SET vdir = 1
                  WHERE ino IN (
@@ -85,14 +252,264 @@ impl CatFS {
             stmt.execute([]).unwrap();
         }
 
+        // Chunked files listed with a suffix by SplitFS get their original names back
+        if let Some(suffix) = &config.chunked_suffix {
+            let query = "UPDATE Files SET file_name = substr(file_name, 1, length(file_name) - ?1)
+                 WHERE vdir = 1 AND length(file_name) > ?1 AND substr(file_name, -?1) = ?2";
+            file_db
+                .execute(query, params![suffix.len() as i64, suffix.as_bytes()])
+                .unwrap();
+        }
+
+        // Partly downloaded mirrors and subdirectories do not contain all chunks of the digest
+        if let Some(expected) = config
+            .tree_digest
+            .as_ref()
+            .filter(|_| !settings.allow_missing && !has_chunk_source && settings.subdir.is_none())
+        {
+            if CatFS::tree_digest(&file_db, &root, &config, &blocksizes, &chunk_sizes) != *expected
+            {
+                let message = String::from("the chunks do not match the tree digest of the config");
+                if settings.strict {
+                    return Err(MountError::Inconsistent(message));
+                }
+                warn!("SCFS mirror {:?} is inconsistent: {}", mirror, message);
+            }
+        }
+
         let file_handles = Default::default();
 
-        CatFS {
+        let pool = settings.thread_pool();
+
+        let drop_hook = Arc::new(drop_hook);
+        let idle = IdleTimer::new(settings.idle_timeout, Arc::clone(&drop_hook));
+
+        Ok(CatFS {
             file_db,
             file_handles,
+            dir_handles: Default::default(),
             next_fh: 0,
+            open_files: Default::default(),
             config,
+            packed,
+            metas,
+            blocksizes,
+            chunk_sizes,
+            source_dirs,
+            zero_chunks: Default::default(),
+            pool,
+            ttl: settings.attr_ttl.unwrap_or(TTL),
+            allow_missing: settings.allow_missing,
+            special_files: settings.special_files,
+            single_threaded: settings.single_threaded,
+            noatime: settings.noatime,
+            uid_map: settings.uid_map.clone(),
+            gid_map: settings.gid_map.clone(),
+            ready: settings.ready.take(),
+            fetcher: Arc::new(ChunkFetcher::new(chunk_source)),
+            limiter: Arc::new(ReadLimiter::new(settings.read_limit)),
+            open_limit: Arc::new(OpenFileLimit::new(settings.max_open_files)),
+            attr_overrides: AttrOverrides::new(settings.lenient_writes),
+            idle,
             drop_hook,
+        })
+    }
+
+    // The attributes like they are derived from the mirror, before mapping their owner and group.
+    fn get_mirror_attr(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        if let Some(handle) = self.packed.get(&file_info.ino) {
+            let mut attr = convert_metadata_to_attr(
+                fs::symlink_metadata(&handle.file).unwrap(),
+                Some(file_info.ino),
+            )?;
+            attr.size = handle.end - handle.start;
+            attr.blocks = attr.size.div_ceil(512);
+            Some(attr)
+        } else if file_info.vdir {
+            let parts = self.get_files_info_from_parent_ino(file_info.ino);
+            let size = self.file_size(&self.part_handles(file_info, parts.clone()));
+
+            // Chunks that have been fetched or only exist in the chunk source are not fetched
+            // again just for their attributes, without any chunk in the mirror the file gets the
+            // attributes of its directory
+            let attrs = parts
+                .iter()
+                .filter_map(|info| {
+                    let meta = fs::symlink_metadata(&info.path).ok()?;
+                    convert_metadata_to_attr(meta, Some(info.ino))
+                })
+                .collect::<Vec<_>>();
+            let mut attr = match attrs.first() {
+                Some(attr) => *attr,
+                None => {
+                    let dir = FileInfo {
+                        vdir: false,
+                        ..file_info.clone()
+                    };
+                    let mut attr = self.get_mirror_attr(&dir)?;
+                    attr.kind = FileType::RegularFile;
+                    attr.perm &= 0o666;
+                    attr.nlink = 1;
+                    attr
+                }
+            };
+            attr.ino = file_info.ino;
+            attr.blocks = if attrs.len() == parts.len() {
+                attrs.iter().map(|attr| attr.blocks).sum()
+            } else {
+                size.div_ceil(512)
+            };
+            attr.size = size;
+            if let Some(meta) = self.metas.get(&file_info.ino) {
+                meta.apply(&mut attr);
+            }
+            Some(attr)
+        } else if self.config.flat && file_info.ino != INO_ROOT {
+            // The directories of flat mirrors only exist in the names of the chunks
+            let root = self.get_file_info_from_ino(INO_ROOT).ok()?;
+            convert_metadata_to_attr(
+                fs::symlink_metadata(&root.path).unwrap(),
+                Some(file_info.ino),
+            )
+        } else if self.source_dirs.contains(&file_info.ino) {
+            let root = self.get_file_info_from_ino(INO_ROOT).ok()?;
+            convert_metadata_to_attr(fs::symlink_metadata(root.path).ok()?, Some(file_info.ino))
+        } else {
+            convert_metadata_to_attr(
+                fs::symlink_metadata(&file_info.path).ok()?,
+                Some(file_info.ino),
+            )
+        }
+    }
+
+    // The handles of the parts of a chunked file, each with the range of the file it covers.
+    // Every part starts at its block, without the bytes it repeats from the previous block, and
+    // its size is taken from the index, so that no chunk has to be fetched. With allowed missing
+    // chunks, the gaps get the paths the chunks would have and count as full blocks.
+    fn part_handles(&self, file_info: &FileInfo, mut parts: Vec<FileInfo>) -> Vec<FileHandle> {
+        parts.sort_by_key(|part| part.part);
+        let config = self.file_config(file_info.ino);
+        let handle = |part: u64, file: OsString, size: u64| {
+            let start = (part - 1) * config.blocksize;
+            FileHandle {
+                file,
+                start,
+                end: start + size.saturating_sub(config.chunk_overlap(part - 1)),
+                content: None,
+            }
+        };
+        let chunk_size = |ino: u64| self.chunk_sizes.get(&ino).copied().unwrap_or_default();
+
+        if self.allow_missing {
+            let last = parts.last().map_or(0, |part| part.part);
+            let mut parts = parts.into_iter().peekable();
+            (1..=last)
+                .map(|part| match parts.next_if(|file| file.part == part) {
+                    Some(file) => handle(part, file.path, chunk_size(file.ino)),
+                    None => handle(
+                        part,
+                        Path::new(&file_info.path)
+                            .join(self.config.chunk_name(part - 1))
+                            .into(),
+                        config.blocksize + config.chunk_overlap(part - 1),
+                    ),
+                })
+                .collect()
+        } else {
+            parts
+                .into_iter()
+                .map(|file| handle(file.part, file.path, chunk_size(file.ino)))
+                .collect()
+        }
+    }
+
+    // The size of a file is the sum of its parts. With allowed missing chunks, the file ends with
+    // its last present chunk.
+    fn file_size(&self, handles: &[FileHandle]) -> u64 {
+        if self.allow_missing {
+            handles.last().map_or(0, |handle| handle.end)
+        } else {
+            handles.iter().map(|handle| handle.end - handle.start).sum()
+        }
+    }
+
+    // Chunks that only contain zeros are presented as holes, so that copies of the restored file
+    // can be sparse again. Missing chunks are holes as well.
+    fn is_zero_chunk(&mut self, path: &OsStr, config: &Config) -> std::io::Result<bool> {
+        if let Some(is_zero) = self.zero_chunks.get(path) {
+            return Ok(*is_zero);
+        }
+
+        let is_zero = self.open_limit.run(|| {
+            let path = match self.fetcher.locate(path.as_ref()) {
+                Ok(path) => path,
+                Err(e) if e.kind() == ErrorKind::NotFound && self.allow_missing => return Ok(true),
+                Err(e) => return Err(e),
+            };
+
+            let mut chunk = open_chunk(path, config, 0)?;
+            let mut buf = [0; 8192];
+            loop {
+                match chunk.read(&mut buf)? {
+                    0 => return Ok(true),
+                    read if buf[..read].iter().any(|byte| *byte != 0) => return Ok(false),
+                    _ => {}
+                }
+            }
+        })?;
+
+        self.zero_chunks.insert(path.to_os_string(), is_zero);
+        Ok(is_zero)
+    }
+
+    // The config of a restored file, which only differs from the global one if SplitFS used
+    // another blocksize for it.
+    fn file_config(&self, ino: u64) -> Cow<'_, Config> {
+        self.config
+            .with_blocksize(self.blocksizes.get(&ino).copied())
+    }
+
+    // A relative symlink into a chunked directory, like to one of its chunks or with a trailing
+    // slash, is rewritten to point at the concatenated file instead. Absolute symlinks and
+    // symlinks leaving the mirror are presented as they are.
+    fn rewrite_symlink_target(&self, link: &FileInfo, target: &Path) -> PathBuf {
+        if target.is_absolute() {
+            return target.to_path_buf();
+        }
+
+        let mut ino = link.parent_ino;
+        let mut rewritten = PathBuf::new();
+
+        for component in target.components() {
+            rewritten.push(component);
+
+            let file_info = match component {
+                Component::CurDir => continue,
+                Component::ParentDir if ino == INO_ROOT => break,
+                Component::ParentDir => self
+                    .get_file_info_from_ino(ino)
+                    .map(|file_info| FileInfo::with_ino(file_info.parent_ino)),
+                Component::Normal(name) => {
+                    self.get_file_info_from_parent_ino_and_file_name(ino, name.to_os_string())
+                }
+                _ => break,
+            };
+
+            match file_info {
+                Ok(file_info) if file_info.vdir => return rewritten,
+                Ok(file_info) => ino = file_info.ino,
+                Err(_) => break,
+            }
+        }
+
+        target.to_path_buf()
+    }
+
+    fn special_file_kind(&self, file_info: &FileInfo) -> Option<FileType> {
+        if self.special_files && file_info.part == 0 {
+            special_file_kind(&file_info.path)
+        } else {
+            None
         }
     }
 
@@ -110,24 +527,83 @@ impl CatFS {
             .collect()
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn populate<P: AsRef<Path>>(
         file_db: &Connection,
         path: P,
+        config: &Config,
+        config_name: &str,
+        special_files: bool,
+        source: &dyn MirrorSource,
+        packed: &mut HashMap<u64, FileHandle>,
+        metas: &mut HashMap<u64, FileMeta>,
+        blocksizes: &mut HashMap<u64, u64>,
+        links: &mut HashMap<(u64, u64), u64>,
+        progress: &mut ProgressReporter,
         parent_ino: u64,
         mut next_ino: u64,
-    ) -> u64 {
+    ) -> Result<u64, MountError> {
         let path = path.as_ref();
 
-        let meta = path.symlink_metadata().unwrap();
+        let meta = source.symlink_metadata(path)?;
+
+        let attr = match meta.attr {
+            Some(attr) if special_files || !is_special_file(attr.kind) => attr,
+            _ => {
+                warn!("Skipping {:?}, its file type is not supported", path);
+                return Ok(next_ino);
+            }
+        };
+        let dev_ino = (meta.dev, attr.ino);
+
+        if path.file_name().unwrap() == config_name {
+            return Ok(next_ino);
+        }
+
+        // Synthetic count, metadata, read statistics, blocksize and whole files from SplitFS are
+        // no chunks. A real file with such a name would have become a virtual directory, so only
+        // regular files have to be skipped.
+        if attr.kind == FileType::RegularFile
+            && (path.file_name().unwrap() == COUNT_FILE_NAME
+                || path.file_name().unwrap() == META_FILE_NAME
+                || path.file_name().unwrap() == READS_FILE_NAME
+                || path.file_name().unwrap() == BLOCKSIZE_FILE_NAME
+                || path.file_name().unwrap() == WHOLE_FILE_NAME)
+        {
+            return Ok(next_ino);
+        }
 
-        if let None = convert_filetype(meta.file_type()) {
-            return next_ino;
+        // Checksum sidecars from SplitFS are no chunks either.
+        if attr.kind == FileType::RegularFile
+            && path
+                .file_name()
+                .unwrap()
+                .as_bytes()
+                .ends_with(CHECKSUM_SUFFIX.as_bytes())
+        {
+            return Ok(next_ino);
         }
 
-        let attr = convert_metadata_to_attr(meta, None);
+        // Pack objects and their indices are handled together with their directory.
+        if attr.kind == FileType::RegularFile
+            && (path.file_name().unwrap() == PACK_INDEX_FILE_NAME
+                || path
+                    .file_name()
+                    .unwrap()
+                    .as_bytes()
+                    .starts_with(PACK_FILE_PREFIX.as_bytes()))
+        {
+            return Ok(next_ino);
+        }
 
-        if path.file_name().unwrap() == CONFIG_FILE_NAME {
-            return next_ino;
+        // Stray files like .DS_Store or leftovers of a download would mess up the concatenated
+        // file. Without a split size, every regular file must be a chunk.
+        if attr.kind == FileType::RegularFile
+            && config.min_split_size.is_none()
+            && config.chunk_index(path.file_name().unwrap()).is_none()
+        {
+            warn!("Skipping {:?}, its name is no valid chunk name", path);
+            return Ok(next_ino);
         }
 
         let ino = if parent_ino == INO_OUTSIDE {
@@ -138,584 +614,3925 @@ impl CatFS {
             ino
         };
 
-        let file_info = FileInfoRow::from(FileInfo {
-            ino,
-            parent_ino,
-            path: OsString::from(path),
-            file_name: path.file_name().unwrap().into(),
-            part: if let FileType::RegularFile = attr.kind {
-                path.file_name().unwrap().to_str().unwrap()[5..]
-                    .parse::<u64>()
-                    .unwrap()
-                    + 1
-            } else {
-                0
+        let part = if let FileType::RegularFile = attr.kind {
+            match config.chunk_index(path.file_name().unwrap()) {
+                Some(index) => index + 1,
+
+                // Files below the split size are presented as they are, just like packed files
+                None => {
+                    packed.insert(
+                        ino,
+                        FileHandle {
+                            file: OsString::from(path),
+                            start: 0,
+                            end: attr.size,
+                            content: None,
+                        },
+                    );
+                    0
+                }
+            }
+        } else {
+            0
+        };
+
+        insert_file_info(
+            file_db,
+            FileInfo {
+                ino,
+                parent_ino,
+                path: OsString::from(path),
+                file_name: path.file_name().unwrap().into(),
+                part,
+                vdir: false,
+                symlink: attr.kind == FileType::Symlink,
             },
-            vdir: false,
-            symlink: attr.kind == FileType::Symlink,
+        );
+        progress.add(if attr.kind == FileType::RegularFile {
+            attr.size
+        } else {
+            0
         });
 
-        file_db
-            .prepare_cached(STMT_INSERT)
-            .unwrap()
-            .execute(params![
-                file_info.ino,
-                file_info.parent_ino,
-                file_info.path,
-                file_info.file_name,
-                file_info.part,
-                file_info.vdir,
-                file_info.symlink,
-            ])
-            .unwrap();
+        // Files whose first chunks are the same, like the hard links presented by SplitFS, are
+        // hard links of each other
+        if part == 1 && attr.nlink > 1 {
+            match links.entry(dev_ino) {
+                Entry::Occupied(target) => insert_link(file_db, parent_ino, *target.get()),
+                Entry::Vacant(target) => {
+                    target.insert(parent_ino);
+                }
+            }
+        }
 
         if let FileType::Directory = attr.kind {
-            for entry in fs::read_dir(path).unwrap() {
-                let entry = entry.unwrap();
-                next_ino = CatFS::populate(&file_db, entry.path(), ino, next_ino);
+            for entry in source.read_dir(path)? {
+                next_ino = CatFS::populate(
+                    file_db,
+                    entry,
+                    config,
+                    config_name,
+                    special_files,
+                    source,
+                    packed,
+                    metas,
+                    blocksizes,
+                    links,
+                    progress,
+                    ino,
+                    next_ino,
+                )?;
+            }
+
+            let meta_file = path.join(META_FILE_NAME);
+            if meta_file.is_file() {
+                let meta = serde_json::from_str(&fs::read_to_string(&meta_file)?)
+                    .map_err(|e| MountError::InvalidJson(meta_file, e))?;
+                metas.insert(ino, meta);
+            }
+
+            let blocksize_file = path.join(BLOCKSIZE_FILE_NAME);
+            if blocksize_file.is_file() {
+                let blocksize = fs::read_to_string(&blocksize_file)?
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|blocksize| *blocksize > 0)
+                    .ok_or_else(|| {
+                        MountError::InvalidConfig(format!(
+                            "{} contains no valid blocksize",
+                            blocksize_file.display()
+                        ))
+                    })?;
+                blocksizes.insert(ino, blocksize);
+            }
+
+            let pack_index = path.join(PACK_INDEX_FILE_NAME);
+            if pack_index.is_file() {
+                next_ino =
+                    CatFS::populate_packs(file_db, path, &pack_index, packed, ino, next_ino)?;
             }
         }
 
-        next_ino
+        Ok(next_ino)
     }
-}
 
-impl Drop for CatFS {
-    fn drop(&mut self) {
-        let _ = &(self.drop_hook)();
-    }
-}
+    // Flat mirrors contain the chunks of all files in their root. The directories and files are
+    // restored from the names of the chunks, their paths do not exist in the mirror.
+    fn populate_flat(
+        file_db: &Connection,
+        mirror: &Path,
+        config: &Config,
+        config_name: &str,
+        progress: &mut ProgressReporter,
+        mut next_ino: u64,
+    ) {
+        insert_file_info(
+            file_db,
+            FileInfo {
+                ino: INO_ROOT,
+                parent_ino: INO_OUTSIDE,
+                path: OsString::from(mirror),
+                file_name: mirror.file_name().unwrap_or_default().into(),
+                part: 0,
+                vdir: false,
+                symlink: false,
+            },
+        );
 
-impl Filesystem for CatFS {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        Shared::lookup(self, _req, parent, name, reply);
-    }
+        let mut inos = HashMap::from([(PathBuf::new(), INO_ROOT)]);
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        Shared::getattr(self, _req, ino, reply);
-    }
+        for entry in fs::read_dir(mirror).unwrap() {
+            let entry = entry.unwrap();
+            let path = entry.path();
+            let name = entry.file_name();
 
-    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
-        Shared::readlink(self, _req, ino, reply);
-    }
+            if name == config_name {
+                continue;
+            }
 
-    fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
-        let files = self.get_files_info_from_parent_ino(ino);
+            let meta = path.symlink_metadata().unwrap();
+            let chunk = parse_flat_name(&name)
+                .filter(|_| meta.is_file())
+                .and_then(|(file, chunk_name)| Some((file, config.chunk_index(chunk_name)?)));
+            let (file, index) = match chunk {
+                Some(chunk) => chunk,
+                None => {
+                    warn!("Skipping {:?}, its name is no valid flat chunk name", path);
+                    continue;
+                }
+            };
 
-        let fhs = files
-            .iter()
-            .map(|file| FileHandle {
-                file: file.path.clone(),
-                start: 0,
-                end: 0,
-            })
-            .collect();
+            let mut parent_ino = INO_ROOT;
+            let mut relative = PathBuf::new();
+            for component in file.iter() {
+                relative.push(component);
+                let path = mirror.join(&relative);
+                parent_ino = *inos.entry(relative.clone()).or_insert_with(|| {
+                    let ino = next_ino;
+                    next_ino += 1;
+                    insert_file_info(
+                        file_db,
+                        FileInfo {
+                            ino,
+                            parent_ino,
+                            path: path.into(),
+                            file_name: component.into(),
+                            part: 0,
+                            vdir: false,
+                            symlink: false,
+                        },
+                    );
+                    ino
+                });
+            }
 
-        let fh = self.next_fh;
-        self.next_fh += 1;
-        self.file_handles.insert(fh, fhs);
-        reply.opened(fh, 0);
+            insert_file_info(
+                file_db,
+                FileInfo {
+                    ino: next_ino,
+                    parent_ino,
+                    path: path.into(),
+                    file_name: name,
+                    part: index + 1,
+                    vdir: false,
+                    symlink: false,
+                },
+            );
+            next_ino += 1;
+            progress.add(meta.len());
+        }
     }
 
-    fn read(
-        &mut self,
-        _req: &Request<'_>,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        size: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyData,
+    // Chunks that only the chunk source provides are indexed like the chunks found in the mirror,
+    // including the directories they are located in. Chunks of the mirror are not indexed again.
+    fn populate_source(
+        file_db: &Connection,
+        root: &Path,
+        config: &Config,
+        chunks: Vec<(PathBuf, u64)>,
+        chunk_sizes: &mut HashMap<u64, u64>,
+        source_dirs: &mut HashSet<u64>,
+        mut next_ino: u64,
     ) {
-        let offset = offset as usize;
-        let size = size as usize;
+        let find = |parent_ino: u64, file_name: &OsStr| {
+            file_db
+                .prepare_cached(STMT_QUERY_BY_PARENT_INO_AND_FILENAME)
+                .unwrap()
+                .query_row(
+                    params![
+                        FileInfoRow::from(FileInfo::with_parent_ino(parent_ino)).parent_ino,
+                        file_name.as_bytes()
+                    ],
+                    |row| Ok(FileInfo::from(row).ino),
+                )
+                .ok()
+        };
 
-        let file_size = self
-            .get_attr_from_file_info(&self.get_file_info_from_ino(ino).unwrap())
-            .size as usize;
+        for (path, size) in chunks {
+            // Chunks outside of the mounted subdirectory are not presented
+            let relative = match path.strip_prefix(root) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            let (dir, index) = match (
+                relative.parent(),
+                relative
+                    .file_name()
+                    .and_then(|name| config.chunk_index(name)),
+            ) {
+                (Some(dir), Some(index)) => (dir, index),
+                _ => {
+                    warn!("Skipping {:?}, its name is no valid chunk name", path);
+                    continue;
+                }
+            };
 
-        let offset = offset.min(file_size);
-        let size = size.min(file_size - offset);
+            let mut parent_ino = INO_ROOT;
+            let mut parent = root.to_path_buf();
+            for component in dir.iter() {
+                parent.push(component);
+                parent_ino = match find(parent_ino, component) {
+                    Some(ino) => ino,
+                    None => {
+                        let ino = next_ino;
+                        next_ino += 1;
+                        insert_file_info(
+                            file_db,
+                            FileInfo {
+                                ino,
+                                parent_ino,
+                                path: parent.clone().into(),
+                                file_name: component.into(),
+                                part: 0,
+                                vdir: false,
+                                symlink: false,
+                            },
+                        );
+                        source_dirs.insert(ino);
+                        ino
+                    }
+                };
+            }
 
-        if size == 0 {
-            reply.data(&[]);
-            return;
+            let file_name = path.file_name().unwrap();
+            if find(parent_ino, file_name).is_some() {
+                continue;
+            }
+            insert_file_info(
+                file_db,
+                FileInfo {
+                    ino: next_ino,
+                    parent_ino,
+                    path: path.clone().into(),
+                    file_name: file_name.into(),
+                    part: index + 1,
+                    vdir: false,
+                    symlink: false,
+                },
+            );
+            chunk_sizes.insert(next_ino, size);
+            next_ino += 1;
         }
+    }
 
-        let part_start = offset / self.config.blocksize as usize;
-        let part_end = (offset + size - 1) / self.config.blocksize as usize;
+    // Records the sizes of the content of all indexed chunks that are not known yet. Unreadable
+    // chunks are left out, check_parts reports them.
+    fn record_chunk_sizes(
+        file_db: &Connection,
+        config: &Config,
+        blocksizes: &HashMap<u64, u64>,
+        chunk_sizes: &mut HashMap<u64, u64>,
+    ) {
+        let mut stmt = file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap());
 
-        let files = (part_start..=part_end)
-            .map(|part| {
-                self.file_handles
-                    .get(&fh)
-                    .unwrap()
-                    .get(part)
-                    .unwrap()
-                    .file
-                    .clone()
-            })
-            .collect::<Vec<_>>();
+        for part in parts {
+            if let Entry::Vacant(entry) = chunk_sizes.entry(part.ino) {
+                let config = config.with_blocksize(blocksizes.get(&part.parent_ino).copied());
+                if let Ok(size) = chunk_content_size(&part.path, &config) {
+                    entry.insert(size);
+                }
+            }
+        }
+    }
 
-        let blocksize = self.config.blocksize;
+    // The size of the content of an indexed chunk, which is only read from the chunk if it could
+    // not be recorded while indexing.
+    fn chunk_size(
+        part: &FileInfo,
+        config: &Config,
+        chunk_sizes: &HashMap<u64, u64>,
+    ) -> std::io::Result<u64> {
+        match chunk_sizes.get(&part.ino) {
+            Some(size) => Ok(*size),
+            None => chunk_content_size(&part.path, config),
+        }
+    }
 
-        thread::spawn(move || {
-            let part_start = 0;
+    // The digest of the chunks found in the mirror, see tree_digest. Chunked files are named like
+    // in the original mirror, without the suffix and the flat layout of the splitted mirror.
+    fn tree_digest(
+        file_db: &Connection,
+        root: &Path,
+        config: &Config,
+        blocksizes: &HashMap<u64, u64>,
+        chunk_sizes: &HashMap<u64, u64>,
+    ) -> String {
+        let mut stmt = file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap());
 
-            let bytes = files
-                .iter()
-                .enumerate()
-                .map(|(part, file)| {
-                    let mut file = BufReader::new(File::open(file).unwrap());
-
-                    file.seek(SeekFrom::Start(if part == part_start {
-                        offset as u64 % blocksize
-                    } else {
-                        0
-                    }))
+        let mut files = HashMap::new();
+        let mut chunks = Vec::new();
+        for part in parts {
+            let file = files.entry(part.parent_ino).or_insert_with(|| {
+                let file = file_db
+                    .prepare_cached(STMT_QUERY_BY_INO)
+                    .unwrap()
+                    .query_row(
+                        params![FileInfoRow::from(FileInfo::with_ino(part.parent_ino)).ino],
+                        |row| Ok(FileInfo::from(row)),
+                    )
                     .unwrap();
+                let path = Path::new(&file.path);
+                let parent = path.parent().unwrap_or(path);
+                parent
+                    .strip_prefix(root)
+                    .unwrap_or(parent)
+                    .join(&file.file_name)
+            });
 
-                    file.bytes().map(|b| b.unwrap())
-                })
-                .flatten()
-                .take(size)
-                .collect::<Vec<_>>();
-
-            reply.data(&bytes);
-        });
-    }
+            let config = config.with_blocksize(blocksizes.get(&part.parent_ino).copied());
+            let size = CatFS::chunk_size(&part, &config, chunk_sizes).unwrap_or(0);
+            chunks.push((file.join(config.chunk_name(part.part - 1)), size));
+        }
 
-    fn release(
-        &mut self,
-        _req: &Request<'_>,
-        _ino: u64,
-        fh: u64,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        _flush: bool,
-        reply: ReplyEmpty,
-    ) {
-        self.file_handles.remove(&fh);
-        reply.ok();
+        tree_digest(chunks)
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
-        let file_info = self.get_file_info_from_ino(ino);
+    fn verify_parts(
+        file_db: &Connection,
+        config: &Config,
+        blocksizes: &HashMap<u64, u64>,
+        chunk_sizes: &HashMap<u64, u64>,
+    ) -> Result<(), MountError> {
+        for (_, error) in CatFS::check_parts(file_db, config, blocksizes, chunk_sizes) {
+            if let Some(error) = error {
+                return Err(MountError::Inconsistent(error));
+            }
+        }
+        Ok(())
+    }
 
-        if let Ok(file_info) = file_info {
-            if offset < 2 {
-                if offset == 0 {
-                    if reply.add(file_info.ino, 1, FileType::Directory, ".") {
-                        unreachable!()
-                    }
+    // Checks that the parts of each file are numbered contiguously and that all parts but the
+    // last one have exactly the size of a block. Otherwise, reads would return wrong data. Returns
+    // the path of each chunked file together with the first problem found in its parts.
+    fn check_parts(
+        file_db: &Connection,
+        config: &Config,
+        blocksizes: &HashMap<u64, u64>,
+        chunk_sizes: &HashMap<u64, u64>,
+    ) -> Vec<(OsString, Option<String>)> {
+        let mut stmt = file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .collect::<Vec<_>>();
+
+        let mut results: Vec<(u64, OsString, Option<String>)> = Vec::new();
+
+        for (i, part) in parts.iter().enumerate() {
+            if results
+                .last()
+                .is_none_or(|(parent_ino, _, _)| *parent_ino != part.parent_ino)
+            {
+                // The chunks of flat mirrors are not located in the directory of their file
+                let file = file_db
+                    .prepare_cached(STMT_QUERY_BY_INO)
+                    .unwrap()
+                    .query_row(
+                        params![FileInfoRow::from(FileInfo::with_ino(part.parent_ino)).ino],
+                        |row| Ok(FileInfo::from(row)),
+                    )
+                    .unwrap()
+                    .path;
+                results.push((part.parent_ino, file, None));
+            }
+
+            let error = &mut results.last_mut().unwrap().2;
+            if error.is_some() {
+                continue;
+            }
+
+            let expected_part = match i.checked_sub(1).map(|i| &parts[i]) {
+                Some(prev) if prev.parent_ino == part.parent_ino => prev.part + 1,
+                _ => 1,
+            };
+            if part.part != expected_part {
+                *error = Some(format!(
+                    "expected part {:010} instead of {:?}",
+                    expected_part - 1,
+                    part.path
+                ));
+                continue;
+            }
+
+            let is_last = parts
+                .get(i + 1)
+                .is_none_or(|next| next.parent_ino != part.parent_ino);
+            let config = config.with_blocksize(blocksizes.get(&part.parent_ino).copied());
+            let expected_size = config.blocksize + config.chunk_overlap(part.part - 1);
+            match CatFS::chunk_size(part, &config, chunk_sizes) {
+                Ok(size) if size > expected_size || (!is_last && size != expected_size) => {
+                    *error = Some(format!(
+                        "{:?} has a size of {} instead of {}",
+                        part.path, size, expected_size
+                    ))
                 }
-                if reply.add(
-                    if file_info.parent_ino == INO_OUTSIDE {
-                        file_info.ino
-                    } else {
-                        file_info.parent_ino
-                    },
-                    2,
-                    FileType::Directory,
-                    "..",
-                ) {
-                    unreachable!()
+                Ok(_) => {}
+                Err(e) => *error = Some(format!("{:?} is not readable: {}", part.path, e)),
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|(_, file, error)| (file, error))
+            .collect()
+    }
+
+    /// Checks a mirror without mounting it and writes a PASS or FAIL line for the config and for
+    /// each chunked file to `out`. The config is read from the file `config_name` in the root of
+    /// the mirror. Returns whether all checks passed.
+    pub(crate) fn check<W: Write>(
+        mirror: &OsStr,
+        config_name: &str,
+        mut out: W,
+    ) -> std::io::Result<bool> {
+        let config = fs::read_to_string(Path::new(mirror).join(config_name))
+            .map_err(|e| format!("not readable: {}", e))
+            .and_then(|config| {
+                serde_json::from_str::<Config>(&config).map_err(|e| format!("invalid JSON: {}", e))
+            })
+            .and_then(|config| {
+                if config.blocksize == 0 {
+                    Err(String::from("blocksize is not positive"))
+                } else if !config.is_format_supported() {
+                    Err(format!(
+                        "format version {} is not supported",
+                        config.format_version
+                    ))
+                } else {
+                    Ok(config)
                 }
+            });
+
+        let config = match config {
+            Ok(config) => {
+                writeln!(out, "PASS {}", config_name)?;
+                config
+            }
+            Err(error) => {
+                writeln!(out, "FAIL {}: {}", config_name, error)?;
+                return Ok(false);
             }
+        };
 
-            let mut stmt = self
-                .file_db
-                .prepare_cached(STMT_QUERY_BY_PARENT_INO)
-                .unwrap();
-            let items = stmt
-                .query_map(
-                    params![
-                        FileInfoRow::from(FileInfo::with_parent_ino(file_info.ino)).parent_ino,
-                        // The offset includes . and .., both which are not included in the
-                        // database, so the SELECT offset must be adjusted. Since the offset could
-                        // be negative, set it to 0 in that case.
-                        0.max(offset - 2)
-                    ],
-                    |row| Ok(FileInfo::from(row)),
-                )
-                .unwrap();
-            for (off, item) in items.enumerate() {
-                let item = item.unwrap();
+        let file_db = Connection::open_in_memory().unwrap();
+        file_db.execute(STMT_CREATE, []).unwrap();
+        file_db.execute(STMT_CREATE_LINKS, []).unwrap();
 
-                // Here the item is added to the directory listing. It is important to note that
-                // the offset parameter is quite crucial for correct function. The offset parameter
-                // is used for succeeding calls to start with the next item after the last item
-                // from the previous call. So, the offset parameter has to be one more than the
-                // index of the current item. Furthermore, since "." and ".." have been added
-                // manually as to not pollute the database with them, they also have to be handled
-                // properly. They always get inserted in positions 0 and 1 respectively. If the
-                // call starts at offset 0, then both of the directory hardlinks are included and
-                // the offset must be increased by 2. If the starting offset is 1, then only "."
-                // has been already added. For the additional "..", the offset has to be increased
-                // by 1. If the offset is greater than 1, then the hardlinks have been taken care
-                // of and the offset is already correct.
-                let is_full = reply.add(
-                    item.ino,
-                    offset
-                        + if offset == 0 {
-                            2
-                        } else if offset == 1 {
-                            1
-                        } else {
-                            0
-                        }
-                        + off as i64
-                        + 1,
-                    if item.symlink {
-                        FileType::Symlink
-                    } else if item.vdir {
-                        FileType::RegularFile
-                    } else {
-                        FileType::Directory
-                    },
-                    Path::new(&item.path).file_name().unwrap(),
-                );
-
-                if is_full {
-                    break;
+        // Special files have no chunks to check, but must not be reported as unsupported either
+        let mut blocksizes = HashMap::new();
+        file_db.execute_batch("BEGIN").unwrap();
+        if config.flat {
+            CatFS::populate_flat(
+                &file_db,
+                Path::new(mirror),
+                &config,
+                config_name,
+                &mut ProgressReporter::new(None),
+                INO_FIRST_FREE,
+            );
+        } else if let Err(error) = CatFS::populate(
+            &file_db,
+            mirror,
+            &config,
+            config_name,
+            true,
+            &LocalMirror::default(),
+            &mut HashMap::new(),
+            &mut HashMap::new(),
+            &mut blocksizes,
+            &mut HashMap::new(),
+            &mut ProgressReporter::new(None),
+            INO_OUTSIDE,
+            INO_FIRST_FREE,
+        ) {
+            // Broken metadata, blocksize or pack index files make the whole mirror unusable
+            writeln!(out, "FAIL {}", error)?;
+            return Ok(false);
+        }
+        file_db.execute_batch("COMMIT").unwrap();
+
+        let mut results = CatFS::check_parts(&file_db, &config, &blocksizes, &HashMap::new());
+        results.sort();
+
+        let mut passed = true;
+        for (file, error) in results {
+            let file = Path::new(&file);
+            let file = file.strip_prefix(mirror).unwrap_or(file).display();
+            match error {
+                Some(error) => {
+                    writeln!(out, "FAIL {}: {}", file, error)?;
+                    passed = false;
                 }
+                None => writeln!(out, "PASS {}", file)?,
             }
-            reply.ok();
-        } else {
-            reply.error(ENOENT);
         }
+
+        Ok(passed)
+    }
+
+    /// Writes the content of the file at the relative path `file` of the mirror to `out`, without
+    /// mounting it. The chunks are read in the order of their parts, just like reads of the file
+    /// on a mount would do.
+    pub(crate) fn write_file<W: Write>(&self, file: &Path, mut out: W) -> std::io::Result<()> {
+        let not_found = || {
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} does not exist in the mirror", file.display()),
+            )
+        };
+
+        let mut file_info = self.get_file_info_from_ino(INO_ROOT).unwrap();
+        for component in file.components() {
+            file_info = match component {
+                Component::CurDir => continue,
+                Component::Normal(name) => self
+                    .get_file_info_from_parent_ino_and_file_name(file_info.ino, name.to_os_string())
+                    .map_err(|_| not_found())?,
+                _ => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} is no relative path in the mirror", file.display()),
+                    ))
+                }
+            };
+        }
+
+        if let Some(handle) = self.packed.get(&file_info.ino) {
+            let bytes = read_segments(
+                std::slice::from_ref(handle),
+                0,
+                handle.end - handle.start,
+                &LocalMirror::new(self.noatime),
+            )?;
+            out.write_all(&bytes)?;
+            return out.flush();
+        }
+
+        if !file_info.vdir {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is no regular file", file.display()),
+            ));
+        }
+
+        let config = self.file_config(file_info.ino);
+        let mut parts = self.get_files_info_from_parent_ino(file_info.ino);
+        parts.sort_by_key(|part| part.part);
+        for (i, part) in parts.into_iter().enumerate() {
+            // A missing chunk would shift all following bytes
+            if part.part != i as u64 + 1 {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} misses its chunk {}",
+                        file.display(),
+                        self.config.chunk_name(i as u64).to_string_lossy()
+                    ),
+                ));
+            }
+
+            let path = self.fetcher.locate(part.path.as_ref())?;
+            let overlap = config.chunk_overlap(i as u64);
+            std::io::copy(&mut open_chunk(path, &config, overlap)?, &mut out)?;
+        }
+
+        out.flush()
+    }
+
+    fn populate_packs(
+        file_db: &Connection,
+        path: &Path,
+        pack_index: &Path,
+        packed: &mut HashMap<u64, FileHandle>,
+        parent_ino: u64,
+        mut next_ino: u64,
+    ) -> Result<u64, MountError> {
+        let index: Vec<PackEntry> = serde_json::from_str(&fs::read_to_string(pack_index)?)
+            .map_err(|e| MountError::InvalidJson(pack_index.to_path_buf(), e))?;
+
+        for entry in index {
+            let pack = path.join(pack_file_name(entry.pack));
+
+            insert_file_info(
+                file_db,
+                FileInfo {
+                    ino: next_ino,
+                    parent_ino,
+                    path: OsString::from(path.join(&entry.file_name)),
+                    file_name: entry.file_name,
+                    part: 0,
+                    vdir: false,
+                    symlink: false,
+                },
+            );
+
+            packed.insert(
+                next_ino,
+                FileHandle {
+                    file: pack.into_os_string(),
+                    start: entry.offset,
+                    end: entry.offset + entry.size,
+                    content: None,
+                },
+            );
+
+            next_ino += 1;
+        }
+
+        Ok(next_ino)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::fs::DirEntry;
-    use std::iter;
-    use std::ops::Deref;
+impl Drop for CatFS {
+    fn drop(&mut self) {
+        debug!("Unmounted CatFS");
+        let _ = &(self.drop_hook)();
+    }
+}
 
-    use fuser::BackgroundSession;
-    use rand::{thread_rng, Rng, RngCore};
-    use tempfile::{tempdir, TempDir};
+impl Filesystem for CatFS {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        if self.single_threaded {
+            limit_background_requests(config);
+        }
+        if let Some(ready) = self.ready.take() {
+            ready.send(()).unwrap_or(());
+        }
+        Ok(())
+    }
 
-    use crate::mount;
-    use crate::shared::tests::{check_symlinks, create_files_and_symlinks};
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        self.idle.touch();
 
-    use super::*;
+        Shared::lookup(self, _req, parent, name, reply);
+    }
 
-    // Helper struct to keep necessary variables in scope. To not make the compiler complain,
-    // prefix them with an underscore. If for example the TempDir variables are not kept in scope
-    // this way, the directories would be deleted before the tests can be run.
-    struct TempSession {
-        _session: BackgroundSession,
-        _mirror: TempDir,
-        pub(crate) mountpoint: TempDir,
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        Shared::getattr(self, _req, ino, reply);
     }
 
-    fn mount_and_create_files_with_symlinks<'a>(
-        files: &Vec<(String, Vec<u8>)>,
-        symlinks: Vec<(String, String)>,
-    ) -> Result<TempSession, std::io::Error> {
-        let mirror = tempdir()?;
-        let mountpoint = tempdir()?;
+    fn access(&mut self, req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        Shared::access(self, req, ino, mask, reply);
+    }
 
-        create_files_and_symlinks(mirror.path(), &files, &symlinks)?;
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let file_info = match self.get_file_info_from_ino(ino) {
+            Ok(file_info) => file_info,
+            Err(_) => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
 
-        let fs = CatFS::new(mirror.path().as_os_str(), Box::new(|| ()));
+        match fs::read_link(&file_info.path) {
+            Ok(target) => {
+                let target = self.rewrite_symlink_target(&file_info, &target);
+                reply.data(target.as_os_str().as_bytes());
+            }
+            Err(_) => reply.error(EIO),
+        }
+    }
 
-        let session = mount(fs, &mountpoint, Vec::new());
+    fn statfs(&mut self, _req: &Request, ino: u64, reply: ReplyStatfs) {
+        Shared::statfs(self, _req, ino, reply);
+    }
 
-        Ok(TempSession {
-            _mirror: mirror,
-            mountpoint,
-            _session: session,
-        })
+    fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        Shared::getxattr(self, req, ino, name, size, reply);
     }
 
-    fn mount_and_create_files<'a>(
-        files: &Vec<(String, Vec<u8>)>,
-    ) -> Result<TempSession, std::io::Error> {
-        mount_and_create_files_with_symlinks(files, Vec::new())
+    fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        Shared::listxattr(self, req, ino, size, reply);
     }
 
-    fn create_random_file_tuples(
-        blocksize: usize,
-        num_files: usize,
-        max_num_fragments: usize,
-    ) -> Vec<(String, Vec<u8>)> {
-        let mut rng = thread_rng();
+    fn setattr(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        Shared::setattr(self, req, ino, mode, uid, gid, size, atime, mtime, reply);
+    }
 
-        (0..num_files)
-            .flat_map(|file_num| {
-                let max_num_fragments = rng.gen_range(1..max_num_fragments);
-                (0..max_num_fragments)
-                    .map(|fragment_num| {
-                        let file_name = format!("file_{}/scfs.{:010}", file_num, fragment_num);
+    fn write(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        Shared::write(self, req, ino, reply);
+    }
 
-                        let fragment_size = if fragment_num == max_num_fragments - 1 && rng.gen() {
-                            rng.gen_range(1..(blocksize + 1))
-                        } else {
-                            blocksize
-                        };
+    fn create(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        Shared::create(self, req, parent, name, reply);
+    }
 
-                        let mut content = vec![0u8; fragment_size];
-                        rng.fill_bytes(&mut content);
+    fn mknod(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
 
-                        (file_name, content)
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>()
+    fn mkdir(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _target: &Path,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn link(&mut self, req: &Request, _ino: u64, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _newparent: u64,
+        _newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        if is_writing_open(flags) {
+            reply.error(EROFS);
+            return;
+        }
+
+        let file_info = match self.get_file_info_from_ino(ino) {
+            Ok(file_info) => file_info,
+            Err(_) => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        // The ranges of the parts are computed once, so reads do not have to look at the
+        // chunks of the file again. Missing chunks get the path they would have, so reading them
+        // fails like reading a chunk that has been deleted.
+        let fhs = self.part_handles(&file_info, self.get_files_info_from_parent_ino(ino));
+
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        self.file_handles.insert(fh, fhs);
+        // Chunks kept open by a file handle would count against the open file limit even between
+        // reads, so with a limit every read opens its chunks again
+        let capacity = if self.open_limit.is_limited() {
+            0
+        } else {
+            OpenFiles::CAPACITY
+        };
+        self.open_files
+            .insert(fh, Arc::new(OpenFiles::new(self.noatime, capacity)));
+
+        // Without direct I/O, the kernel would read ahead into missing chunks and take a short
+        // read for the end of the file
+        let flags = if self.allow_missing {
+            FOPEN_DIRECT_IO
+        } else {
+            0
+        };
+        reply.opened(fh, flags);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        self.idle.touch();
+
+        if let Some(handle) = self.packed.get(&ino) {
+            let segments = vec![handle.clone()];
+            let source = LocalMirror::new(self.noatime);
+            let limiter = Arc::clone(&self.limiter);
+            let open_limit = Arc::clone(&self.open_limit);
+            self.pool.execute(move || {
+                let bytes = open_limit
+                    .run(|| read_segments(&segments, offset as u64, size as u64, &source));
+                match bytes {
+                    Ok(bytes) => {
+                        limiter.throttle(bytes.len() as u64);
+                        reply.data(&bytes)
+                    }
+                    Err(e) => reply.error(read_errno(&e)),
+                }
+            });
+            return;
+        }
+
+        let offset = offset as usize;
+        let size = size as usize;
+
+        let handles = match self.file_handles.get(&fh) {
+            Some(handles) => handles,
+            None => {
+                reply.error(EIO);
+                return;
+            }
+        };
+        let file_size = self.file_size(handles) as usize;
+
+        // Reads at or past the end of the file are empty, so the offset has to be clamped before
+        // the remaining size is computed
+        let offset = offset.min(file_size);
+        let size = size.min(file_size - offset);
+
+        if size == 0 {
+            reply.data(&[]);
+            return;
+        }
+
+        let config = self.file_config(ino).into_owned();
+
+        let part_start = offset / config.blocksize as usize;
+        let part_end = (offset + size - 1) / config.blocksize as usize;
+
+        let parts = handles.get(part_start..=part_end).map(|handles| {
+            let files = handles
+                .iter()
+                .map(|handle| handle.file.clone())
+                .collect::<Vec<_>>();
+            (files, offset as u64 - handles[0].start)
+        });
+        let fetcher = Arc::clone(&self.fetcher);
+        let ((files, part_offset), open_files) = match (parts, self.open_files.get(&fh)) {
+            (Some(parts), Some(open_files)) => (parts, Arc::clone(open_files)),
+            _ => {
+                reply.error(EIO);
+                return;
+            }
+        };
+
+        let limiter = Arc::clone(&self.limiter);
+        let open_limit = Arc::clone(&self.open_limit);
+
+        self.pool.execute(move || {
+            let bytes = open_limit.run(|| {
+                read_parts(
+                    &files,
+                    part_start as u64,
+                    &open_files,
+                    &fetcher,
+                    &config,
+                    part_offset,
+                    size,
+                )
+            });
+            match bytes {
+                Ok(bytes) => {
+                    limiter.throttle(bytes.len() as u64);
+                    reply.data(&bytes)
+                }
+                Err(e) => reply.error(read_errno(&e)),
+            }
+        });
     }
 
-    fn create_config_file_tuple(config: Option<Config>) -> (String, Vec<u8>) {
-        (
-            CONFIG_FILE_NAME.to_string(),
-            serde_json::to_vec(&config.unwrap_or_default()).unwrap(),
+    // Only SEEK_DATA and SEEK_HOLE are passed on by the kernel, in steps of whole chunks
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: ReplyLseek,
+    ) {
+        self.idle.touch();
+
+        if whence != SEEK_DATA && whence != SEEK_HOLE {
+            reply.error(EINVAL);
+            return;
+        }
+
+        let file_size = match (self.packed.get(&ino), self.file_handles.get(&fh)) {
+            (Some(handle), _) => handle.end - handle.start,
+            (None, Some(handles)) => self.file_size(handles),
+            (None, None) => {
+                reply.error(EBADF);
+                return;
+            }
+        };
+
+        let offset = match u64::try_from(offset) {
+            Ok(offset) if offset < file_size => offset,
+            _ => {
+                reply.error(ENXIO);
+                return;
+            }
+        };
+
+        // Packed files are presented without any holes
+        if self.packed.contains_key(&ino) {
+            reply.offset(if whence == SEEK_DATA {
+                offset
+            } else {
+                file_size
+            } as i64);
+            return;
+        }
+
+        let chunks = match self.file_handles.get(&fh) {
+            Some(handles) => handles
+                .iter()
+                .map(|handle| handle.file.clone())
+                .collect::<Vec<_>>(),
+            None => {
+                reply.error(EBADF);
+                return;
+            }
+        };
+
+        let config = self.file_config(ino).into_owned();
+        let mut position = offset;
+        for chunk in chunks.iter().skip((offset / config.blocksize) as usize) {
+            match self.is_zero_chunk(chunk, &config) {
+                Ok(is_zero) if is_zero == (whence == SEEK_HOLE) => {
+                    reply.offset(position as i64);
+                    return;
+                }
+                Ok(_) => position = (position / config.blocksize + 1) * config.blocksize,
+                Err(e) => {
+                    reply.error(read_errno(&e));
+                    return;
+                }
+            }
+        }
+
+        // Behind the last chunk, there is only the implicit hole at the end of the file
+        if whence == SEEK_DATA {
+            reply.error(ENXIO);
+        } else {
+            reply.offset(file_size as i64);
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.file_handles.remove(&fh);
+        if let Some(open_files) = self.open_files.remove(&fh) {
+            debug!(
+                "Released file handle {} after opening {} chunks",
+                fh,
+                open_files.opened()
+            );
+        }
+        reply.ok();
+    }
+
+    // The file system is read-only, so there is never anything to write back
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn fallocate(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _length: i64,
+        _mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        Shared::fallocate(self, req, ino, reply);
+    }
+
+    fn bmap(&mut self, req: &Request<'_>, ino: u64, _blocksize: u32, _idx: u64, reply: ReplyBmap) {
+        Shared::bmap(self, req, ino, reply);
+    }
+
+    fn ioctl(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: u32,
+        _cmd: u32,
+        _in_data: &[u8],
+        _out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        Shared::ioctl(self, req, ino, reply);
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        self.idle.touch();
+
+        let file_info = match self.get_file_info_from_ino(ino) {
+            Ok(file_info) => file_info,
+            Err(_) => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let mut stmt = self
+            .file_db
+            .prepare_cached(STMT_QUERY_BY_PARENT_INO)
+            .unwrap();
+        let mut entries = stmt
+            .query_map(
+                params![
+                    FileInfoRow::from(FileInfo::with_parent_ino(file_info.ino)).parent_ino,
+                    0
+                ],
+                |row| Ok(FileInfo::from(row)),
+            )
+            .unwrap()
+            .map(|item| {
+                let item = item.unwrap();
+                let kind = if item.symlink {
+                    FileType::Symlink
+                } else if item.vdir || self.packed.contains_key(&item.ino) {
+                    FileType::RegularFile
+                } else if let Some(kind) = self.special_file_kind(&item) {
+                    kind
+                } else {
+                    FileType::Directory
+                };
+                let ino = if item.vdir {
+                    self.get_link_target(item.ino)
+                } else {
+                    item.ino
+                };
+                (ino, kind, item.file_name)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.2.cmp(&b.2));
+        drop(stmt);
+
+        let mut listing = dir_listing(&file_info);
+        listing.extend(entries);
+
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        self.dir_handles.insert(fh, listing);
+
+        reply.opened(fh, 0);
+    }
+
+    fn readdir(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        self.idle.touch();
+
+        match self.dir_handles.get(&fh) {
+            Some(listing) => reply_dir_listing(listing, offset, reply),
+            None => reply.error(EBADF),
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.dir_handles.remove(&fh);
+        reply.ok();
+    }
+}
+
+/// Chunks opened by a file handle, so that sequential reads do not open the same chunk over and
+/// over again. Only the most recently used chunks are kept open, to not run out of file
+/// descriptors on files with many chunks.
+struct OpenFiles {
+    files: Mutex<VecDeque<(OsString, Arc<File>)>>,
+
+    // Decoders of compressed chunks with the offset of the content they stopped at, so that
+    // sequential reads continue decompressing where the previous read stopped instead of at the
+    // start of the chunk
+    decoders: Mutex<VecDeque<(OsString, u64, GzDecoder<PositionedReader>)>>,
+
+    opened: AtomicU64,
+    noatime: bool,
+
+    // Number of chunks kept open, zero closes each chunk once it has been read
+    capacity: usize,
+}
+
+impl OpenFiles {
+    const CAPACITY: usize = 16;
+
+    fn new(noatime: bool, capacity: usize) -> Self {
+        OpenFiles {
+            files: Default::default(),
+            decoders: Default::default(),
+            opened: Default::default(),
+            noatime,
+            capacity,
+        }
+    }
+
+    fn get(&self, path: &OsStr, fetcher: &ChunkFetcher) -> std::io::Result<Arc<File>> {
+        let mut files = self.files.lock().unwrap();
+
+        if let Some(index) = files.iter().position(|(file, _)| file == path) {
+            let entry = files.remove(index).unwrap();
+            let file = Arc::clone(&entry.1);
+            files.push_back(entry);
+            return Ok(file);
+        }
+
+        let file = Arc::new(open_mirror_file(
+            fetcher.locate(path.as_ref())?,
+            self.noatime,
+        )?);
+        self.opened.fetch_add(1, Ordering::Relaxed);
+
+        if self.capacity == 0 {
+            return Ok(file);
+        }
+
+        if files.len() >= self.capacity {
+            files.pop_front();
+        }
+        files.push_back((path.to_os_string(), Arc::clone(&file)));
+
+        Ok(file)
+    }
+
+    // Reads the content of a chunk into `buf`, starting at `offset` of the content, until `buf` is
+    // full or the chunk ends. Returns the number of bytes read.
+    fn read(
+        &self,
+        path: &OsStr,
+        fetcher: &ChunkFetcher,
+        config: &Config,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> std::io::Result<usize> {
+        if config.compression.is_none() {
+            return read_full(
+                &mut read_chunk(self.get(path, fetcher)?, config, offset)?,
+                buf,
+            );
+        }
+
+        // A decoder that stopped behind the offset cannot go back, the chunk has to be
+        // decompressed from its start again
+        let cached = {
+            let mut decoders = self.decoders.lock().unwrap();
+            decoders
+                .iter()
+                .position(|(file, position, _)| file == path && *position <= offset)
+                .and_then(|index| decoders.remove(index))
+        };
+        let (position, mut decoder) = match cached {
+            Some((_, position, decoder)) => (position, decoder),
+            None => {
+                let file = self.get(path, fetcher)?;
+                (0, GzDecoder::new(PositionedReader { file, position: 0 }))
+            }
+        };
+
+        let skipped = std::io::copy(
+            &mut (&mut decoder).take(offset - position),
+            &mut std::io::sink(),
+        )?;
+        let read = read_full(&mut decoder, buf)?;
+
+        if self.capacity > 0 {
+            let mut decoders = self.decoders.lock().unwrap();
+            if decoders.len() >= self.capacity {
+                decoders.pop_front();
+            }
+            decoders.push_back((
+                path.to_os_string(),
+                position + skipped + read as u64,
+                decoder,
+            ));
+        }
+
+        Ok(read)
+    }
+
+    /// Number of times a chunk had to be opened.
+    fn opened(&self) -> u64 {
+        self.opened.load(Ordering::Relaxed)
+    }
+}
+
+// Reads `size` bytes from the given consecutive chunks, starting at `offset` into the first
+// chunk. All following chunks are read from their beginning.
+fn read_parts(
+    files: &[OsString],
+    first: u64,
+    open_files: &OpenFiles,
+    fetcher: &ChunkFetcher,
+    config: &Config,
+    offset: u64,
+    size: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![0; size];
+    let mut filled = 0;
+
+    for (part, file) in files.iter().enumerate() {
+        // Only the bytes of the block itself are part of the file
+        let overlap = config.chunk_overlap(first + part as u64);
+        let offset = if part == 0 { offset + overlap } else { overlap };
+        match open_files.read(file, fetcher, config, offset, &mut bytes[filled..]) {
+            Ok(read) => filled += read,
+
+            // The bytes before a missing chunk can still be served
+            Err(e) if e.kind() == ErrorKind::NotFound && filled > 0 => break,
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    bytes.truncate(filled);
+    Ok(bytes)
+}
+
+// Reads from `reader` until `buf` is full or the reader ends.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = reader.read(&mut buf[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{DirEntry, File};
+    use std::io::{Seek, SeekFrom};
+    use std::iter;
+    use std::ops::Deref;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt};
+
+    use fuser::BackgroundSession;
+    use log::Level;
+    use nix::errno::Errno;
+    use nix::fcntl::FallocateFlags;
+    use nix::sys::stat::Mode;
+    use nix::unistd::mkfifo;
+    use rand::{thread_rng, Rng, RngCore};
+    use tempfile::{tempdir, TempDir};
+
+    use crate::shared::tests::{
+        check_symlinks, create_files_and_symlinks, init_test_logger, logged_messages,
+    };
+    use crate::{
+        mount, ChunkSource, Compression, Settings, SplitFS, BLOCKSIZES_MANIFEST_NAME,
+        CONFIG_FILE_NAME, CONFIG_FORMAT_VERSION,
+    };
+
+    use super::*;
+
+    // Helper struct to keep necessary variables in scope. To not make the compiler complain,
+    // prefix them with an underscore. If for example the TempDir variables are not kept in scope
+    // this way, the directories would be deleted before the tests can be run.
+    struct TempSession {
+        _session: BackgroundSession,
+        _mirror: TempDir,
+        pub(crate) mountpoint: TempDir,
+    }
+
+    fn mount_and_create_files_with_settings(
+        files: &Vec<(String, Vec<u8>)>,
+        symlinks: Vec<(String, String)>,
+        settings: Settings,
+    ) -> Result<TempSession, std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        create_files_and_symlinks(mirror.path(), files, &symlinks)?;
+
+        let fs = CatFS::new(mirror.path().as_os_str(), settings, Box::new(|| ())).unwrap();
+
+        let session = mount(fs, &mountpoint, Vec::new())?;
+
+        Ok(TempSession {
+            _mirror: mirror,
+            mountpoint,
+            _session: session,
+        })
+    }
+
+    fn mount_and_create_files_with_symlinks(
+        files: &Vec<(String, Vec<u8>)>,
+        symlinks: Vec<(String, String)>,
+    ) -> Result<TempSession, std::io::Error> {
+        mount_and_create_files_with_settings(files, symlinks, Settings::default())
+    }
+
+    fn mount_and_create_files(
+        files: &Vec<(String, Vec<u8>)>,
+    ) -> Result<TempSession, std::io::Error> {
+        mount_and_create_files_with_symlinks(files, Vec::new())
+    }
+
+    // Returns the error of indexing a mirror with the given files, which must not be usable.
+    fn index_error(files: &Vec<(String, Vec<u8>)>, settings: Settings) -> MountError {
+        let mirror = tempdir().unwrap();
+        create_files_and_symlinks(mirror.path(), files, &Vec::new()).unwrap();
+
+        match CatFS::new(mirror.path().as_os_str(), settings, Box::new(|| ())) {
+            Ok(_) => panic!("CatFS accepted the mirror"),
+            Err(error) => error,
+        }
+    }
+
+    fn create_random_file_tuples(
+        blocksize: usize,
+        num_files: usize,
+        max_num_fragments: usize,
+    ) -> Vec<(String, Vec<u8>)> {
+        let mut rng = thread_rng();
+
+        (0..num_files)
+            .flat_map(|file_num| {
+                let max_num_fragments = rng.gen_range(1..max_num_fragments);
+                (0..max_num_fragments)
+                    .map(|fragment_num| {
+                        let file_name = format!("file_{}/scfs.{:010}", file_num, fragment_num);
+
+                        let fragment_size = if fragment_num == max_num_fragments - 1 && rng.gen() {
+                            rng.gen_range(1..(blocksize + 1))
+                        } else {
+                            blocksize
+                        };
+
+                        let mut content = vec![0u8; fragment_size];
+                        rng.fill_bytes(&mut content);
+
+                        (file_name, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    }
+
+    fn create_config_file_tuple(config: Option<Config>) -> (String, Vec<u8>) {
+        (
+            CONFIG_FILE_NAME.to_string(),
+            serde_json::to_vec(&config.unwrap_or_default()).unwrap(),
+        )
+    }
+
+    fn with_config_file(files: Vec<(String, Vec<u8>)>, config: Config) -> Vec<(String, Vec<u8>)> {
+        files
+            .into_iter()
+            .chain(iter::once(create_config_file_tuple(Some(config))))
+            .collect()
+    }
+
+    fn check_files(
+        mountpoint: &Path,
+        files_expected: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), std::io::Error> {
+        let files_actual = fs::read_dir(mountpoint)?
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+
+        let mut files_expected_map = HashMap::new();
+        for (file_name, content_chunk) in files_expected {
+            if file_name == CONFIG_FILE_NAME {
+                continue;
+            }
+
+            let file_name = mountpoint.join(file_name).parent().unwrap().to_path_buf();
+            let content = files_expected_map.entry(file_name).or_insert(Vec::new());
+            for c in content_chunk {
+                content.push(c)
+            }
+        }
+
+        assert_eq!(files_actual.len(), files_expected_map.len());
+
+        for file in files_actual {
+            let content_actual = fs::read(&file).unwrap();
+            let content_actual = content_actual.deref();
+
+            let content_expected = files_expected_map.get(&file).unwrap();
+            let content_expected = content_expected.deref();
+
+            assert_eq!(content_actual, content_expected)
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_mirror() {
+        // Since a valid SplitFS needs a config file, fail if there is no such file
+
+        let error = index_error(&Vec::new(), Settings::default());
+        assert!(matches!(error, MountError::MissingConfig(_)), "{}", error);
+        assert!(error.to_string().starts_with("SCFS config file not found"));
+    }
+
+    #[test]
+    fn test_empty_mirror_with_config() -> Result<(), std::io::Error> {
+        // If there is a valid config file, but nothing else, then the CatFS mountpoint is
+        // completely empty.
+
+        let files = vec![create_config_file_tuple(None)];
+
+        let session = mount_and_create_files(&files)?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_mirror_with_wrong_config() {
+        // An invalid config file must result in an error
+
+        let files = vec![(CONFIG_FILE_NAME.to_string(), "{}".into())];
+
+        let error = index_error(&files, Settings::default());
+        assert!(matches!(error, MountError::InvalidJson(_, _)), "{}", error);
+        assert!(error.to_string().contains("contains invalid JSON"));
+    }
+
+    #[test]
+    fn test_empty_mirror_with_unsupported_format_version() {
+        // A mirror created by a newer, incompatible version must be rejected
+
+        let config = format!(
+            r#"{{"blocksize":1024,"format_version":{}}}"#,
+            CONFIG_FORMAT_VERSION + 1
+        );
+        let files = vec![(CONFIG_FILE_NAME.to_string(), config.into())];
+
+        let error = index_error(&files, Settings::default());
+        assert!(matches!(error, MountError::InvalidConfig(_)), "{}", error);
+        assert!(error
+            .to_string()
+            .starts_with("SCFS mirror has format version"));
+    }
+
+    #[test]
+    fn test_config_of_other_versions() -> Result<(), std::io::Error> {
+        // Configs of older mirrors lack the newer fields, configs of newer mirrors may contain
+        // fields that are unknown to this version. Both must still be put together correctly.
+
+        for config in [
+            String::from(r#"{"blocksize":4}"#),
+            format!(
+                r#"{{"blocksize":4,"format_version":{},"future":true}}"#,
+                CONFIG_FORMAT_VERSION
+            ),
+        ] {
+            let mirror = tempdir()?;
+            let mountpoint = tempdir()?;
+
+            let files = vec![
+                (CONFIG_FILE_NAME.to_string(), config.into()),
+                ("file/scfs.0000000000".to_string(), b"abcd".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"ef".to_vec()),
+            ];
+            create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+            let fs = CatFS::new(
+                mirror.path().as_os_str(),
+                Settings::default(),
+                Box::new(|| ()),
+            )
+            .unwrap();
+            let _session = mount(fs, &mountpoint, Vec::new())?;
+
+            assert_eq!(fs::read(mountpoint.path().join("file"))?, b"abcdef");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_meta_file() {
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"abcd".to_vec()),
+                ("file/.scfs_meta".to_string(), b"{".to_vec()),
+            ],
+            Config::default(),
+        );
+
+        let error = index_error(&files, Settings::default());
+        assert!(matches!(error, MountError::InvalidJson(..)), "{}", error);
+        assert!(error.to_string().contains(".scfs_meta"), "{}", error);
+    }
+
+    #[test]
+    fn test_malformed_blocksize_file() {
+        for blocksize in ["", "0", "four"] {
+            let files = with_config_file(
+                vec![
+                    ("file/scfs.0000000000".to_string(), b"abcd".to_vec()),
+                    ("file/.scfs_blocksize".to_string(), blocksize.into()),
+                ],
+                Config::default(),
+            );
+
+            let error = index_error(&files, Settings::default());
+            assert!(matches!(error, MountError::InvalidConfig(_)), "{}", error);
+            assert!(error.to_string().contains(".scfs_blocksize"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_malformed_pack_index() {
+        let files = with_config_file(
+            vec![
+                ("dir/scfs.pack.0000000000".to_string(), b"abcd".to_vec()),
+                ("dir/.scfs_packs".to_string(), br#"[{"pack":0}]"#.to_vec()),
+            ],
+            Config::default(),
+        );
+
+        let error = index_error(&files, Settings::default());
+        assert!(matches!(error, MountError::InvalidJson(..)), "{}", error);
+        assert!(error.to_string().contains(".scfs_packs"), "{}", error);
+    }
+
+    #[test]
+    fn test_empty_mirror_with_zero_blocksize() {
+        let files = vec![(CONFIG_FILE_NAME.to_string(), r#"{"blocksize":0}"#.into())];
+
+        let error = index_error(&files, Settings::default());
+        assert!(matches!(error, MountError::InvalidConfig(_)), "{}", error);
+        assert_eq!(
+            error.to_string(),
+            "SCFS config file contains a blocksize of 0"
+        );
+    }
+
+    #[test]
+    fn test_empty_file_and_empty_dir() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("empty_file/scfs.0000000000".to_string(), Vec::new()),
+                ("dir/empty_file/scfs.0000000000".to_string(), Vec::new()),
+            ],
+            Config::default(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        fs::create_dir(mirror.path().join("empty_dir"))?;
+        fs::create_dir(mirror.path().join("dir/empty_dir"))?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        for file in ["empty_file", "dir/empty_file"] {
+            let file = mountpoint.path().join(file);
+            assert!(file.symlink_metadata()?.is_file());
+            assert_eq!(file.symlink_metadata()?.len(), 0);
+            assert!(fs::read(file)?.is_empty());
+        }
+
+        for dir in ["empty_dir", "dir/empty_dir"] {
+            let dir = mountpoint.path().join(dir);
+            assert!(dir.symlink_metadata()?.is_dir());
+            assert_eq!(fs::read_dir(dir)?.count(), 0);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_parts() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(16).unwrap();
+        let files_expected = with_config_file(create_random_file_tuples(16, 20, 10), config);
+
+        let session = mount_and_create_files_with_settings(
+            &files_expected,
+            Vec::new(),
+            Settings::default().verify(true),
+        )?;
+
+        check_files(session.mountpoint.path(), files_expected)
+    }
+
+    #[test]
+    fn test_verify_parts_short_chunk() {
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"1234".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"123".to_vec()),
+                ("file/scfs.0000000002".to_string(), b"12".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+
+        let error = index_error(&files, Settings::default().verify(true));
+        assert!(matches!(error, MountError::Inconsistent(_)), "{}", error);
+        assert!(error.to_string().contains("has a size of 3 instead of 4"));
+    }
+
+    #[test]
+    fn test_verify_parts_missing_chunk() {
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"1234".to_vec()),
+                ("file/scfs.0000000002".to_string(), b"12".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+
+        let error = index_error(&files, Settings::default().verify(true));
+        assert!(matches!(error, MountError::Inconsistent(_)), "{}", error);
+        assert!(error.to_string().contains("expected part 0000000001"));
+    }
+
+    #[test]
+    fn test_check() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("ok/scfs.0000000000".to_string(), b"1234".to_vec()),
+                ("ok/scfs.0000000001".to_string(), b"12".to_vec()),
+                ("short/scfs.0000000000".to_string(), b"123".to_vec()),
+                ("short/scfs.0000000001".to_string(), b"12".to_vec()),
+                ("gap/scfs.0000000001".to_string(), b"1234".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let mut report = Vec::new();
+        assert!(!CatFS::check(
+            mirror.path().as_os_str(),
+            CONFIG_FILE_NAME,
+            &mut report
+        )?);
+
+        let report = String::from_utf8(report).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "PASS .scfs_config");
+        assert!(lines[1].starts_with("FAIL gap: expected part 0000000000 instead of "));
+        assert_eq!(lines[2], "PASS ok");
+        assert!(lines[3].starts_with("FAIL short: "));
+        assert!(lines[3].ends_with(" has a size of 3 instead of 4"));
+
+        // Without the corrupted files, everything passes
+        fs::remove_dir_all(mirror.path().join("gap"))?;
+        fs::remove_dir_all(mirror.path().join("short"))?;
+        assert!(CatFS::check(
+            mirror.path().as_os_str(),
+            CONFIG_FILE_NAME,
+            std::io::sink()
+        )?);
+
+        // A blocksize of zero is no valid config
+        fs::write(mirror.path().join(CONFIG_FILE_NAME), r#"{"blocksize":0}"#)?;
+        let mut report = Vec::new();
+        assert!(!CatFS::check(
+            mirror.path().as_os_str(),
+            CONFIG_FILE_NAME,
+            &mut report
+        )?);
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "FAIL .scfs_config: blocksize is not positive\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_file() -> Result<(), std::io::Error> {
+        // Files are restored from the chunks presented by SplitFS without mounting a CatFS
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+
+        let mut rng = thread_rng();
+        let mut content = vec![0u8; 1234];
+        rng.fill_bytes(&mut content);
+        let files = vec![
+            ("dir/file".to_string(), content.clone()),
+            ("empty".to_string(), Vec::new()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default()
+            .blocksize(100)
+            .unwrap()
+            .compression(Some(Compression::Gzip));
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config,
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        fs.write_file(Path::new("dir/file"), &mut out)?;
+        assert_eq!(out, content);
+
+        let mut out = Vec::new();
+        fs.write_file(Path::new("./empty"), &mut out)?;
+        assert!(out.is_empty());
+
+        let error = |file: &str| fs.write_file(Path::new(file), std::io::sink()).unwrap_err();
+        assert_eq!(error("missing").kind(), ErrorKind::NotFound);
+        assert_eq!(error("dir").kind(), ErrorKind::InvalidInput);
+        assert_eq!(error("../file").kind(), ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocksize_1() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(1).unwrap();
+        let blocksize = config.blocksize as usize;
+        let num_files = 50;
+        let max_num_fragments = 100;
+
+        let files_expected = with_config_file(
+            create_random_file_tuples(blocksize, num_files, max_num_fragments),
+            config,
+        );
+
+        let session = mount_and_create_files(&files_expected)?;
+
+        check_files(session.mountpoint.path(), files_expected)
+    }
+
+    #[test]
+    fn test_blocksize_1kb() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(1024).unwrap();
+        let blocksize = config.blocksize as usize;
+        let num_files = 20;
+        let max_num_fragments = 10;
+
+        let files_expected = with_config_file(
+            create_random_file_tuples(blocksize, num_files, max_num_fragments),
+            config,
+        );
+
+        let session = mount_and_create_files(&files_expected)?;
+
+        check_files(session.mountpoint.path(), files_expected)
+    }
+
+    #[test]
+    fn test_read_across_part_boundary() -> Result<(), std::io::Error> {
+        // Reading from a non-zero offset across several part boundaries must yield exactly the
+        // requested bytes.
+
+        let config = Config::default().blocksize(10).unwrap();
+
+        let data = (0..35).collect::<Vec<u8>>();
+        let files = with_config_file(
+            data.chunks(10)
+                .enumerate()
+                .map(|(part, chunk)| (format!("file/scfs.{:010}", part), chunk.to_vec()))
+                .collect(),
+            config,
+        );
+
+        let session = mount_and_create_files(&files)?;
+
+        let mut file = File::open(session.mountpoint.path().join("file"))?;
+
+        for (offset, size) in [(7, 6), (13, 20), (25, 20), (0, 35)] {
+            let mut buf = vec![0u8; size];
+            file.seek(SeekFrom::Start(offset))?;
+            let read = file.read(&mut buf)?;
+            let end = (offset as usize + size).min(data.len());
+            assert_eq!(&buf[..read], &data[offset as usize..end]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_beyond_end() -> Result<(), std::io::Error> {
+        // Reading at or far beyond the end of the file must yield no bytes instead of failing.
+
+        let config = Config::default().blocksize(10).unwrap();
+
+        let data = (0..35).collect::<Vec<u8>>();
+        let files = with_config_file(
+            data.chunks(10)
+                .enumerate()
+                .map(|(part, chunk)| (format!("file/scfs.{:010}", part), chunk.to_vec()))
+                .collect(),
+            config,
+        );
+
+        let session = mount_and_create_files(&files)?;
+
+        let file = File::open(session.mountpoint.path().join("file"))?;
+        let mut buf = [0u8; 16];
+        for offset in [35, 36, 1000, 1 << 40] {
+            assert_eq!(file.read_at(&mut buf, offset)?, 0, "{}", offset);
+        }
+
+        // Reads that start before the end are cut off there
+        assert_eq!(file.read_at(&mut buf, 30)?, 5);
+        assert_eq!(&buf[..5], &data[30..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_sequentially_in_small_requests() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(10).unwrap();
+
+        let data = (0..95).collect::<Vec<u8>>();
+        let files = with_config_file(
+            data.chunks(10)
+                .enumerate()
+                .map(|(part, chunk)| (format!("file/scfs.{:010}", part), chunk.to_vec()))
+                .collect(),
+            config.clone(),
+        );
+
+        let session = mount_and_create_files(&files)?;
+
+        let file = File::open(session.mountpoint.path().join("file"))?;
+        let mut content = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let read = file.read_at(&mut buf, content.len() as u64)?;
+            if read == 0 {
+                break;
+            }
+            content.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(content, data);
+
+        // The same sequence of requests directly on the chunks must open each chunk only once
+        let chunks = (0..10)
+            .map(|part| {
+                session
+                    ._mirror
+                    .path()
+                    .join(format!("file/scfs.{:010}", part))
+                    .into_os_string()
+            })
+            .collect::<Vec<_>>();
+        let open_files = OpenFiles::new(false, OpenFiles::CAPACITY);
+        let fetcher = ChunkFetcher::new(Arc::new(LocalChunks));
+        let mut content = Vec::new();
+        for offset in (0..data.len()).step_by(3) {
+            let part_start = offset / 10;
+            let part_end = ((offset + 2) / 10).min(9);
+            content.extend(read_parts(
+                &chunks[part_start..=part_end],
+                part_start as u64,
+                &open_files,
+                &fetcher,
+                &config,
+                offset as u64 % 10,
+                3,
+            )?);
+        }
+        assert_eq!(content, data);
+        assert_eq!(open_files.opened(), 10);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_continue_compressed_chunk() -> Result<(), std::io::Error> {
+        let dir = tempdir()?;
+        let data = (0..100).collect::<Vec<u8>>();
+        let chunk = dir.path().join("scfs.0000000000");
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&chunk)?, flate2::Compression::default());
+        encoder.write_all(&data)?;
+        encoder.finish()?;
+        let chunk = chunk.into_os_string();
+
+        let config = Config::default()
+            .blocksize(100)
+            .unwrap()
+            .compression(Some(Compression::Gzip));
+        let open_files = OpenFiles::new(false, OpenFiles::CAPACITY);
+        let fetcher = ChunkFetcher::new(Arc::new(LocalChunks));
+        let positions = || {
+            open_files
+                .decoders
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, position, _)| *position)
+                .collect::<Vec<_>>()
+        };
+
+        // Sequential reads continue with the decoder of the previous read
+        let mut buf = [0; 10];
+        for offset in (0..100).step_by(10) {
+            assert_eq!(
+                open_files.read(&chunk, &fetcher, &config, offset, &mut buf)?,
+                10
+            );
+            assert_eq!(buf, data[offset as usize..][..10]);
+            assert_eq!(positions(), vec![offset + 10]);
+        }
+
+        // A read before the position of the decoder starts at the beginning of the chunk again
+        assert_eq!(open_files.read(&chunk, &fetcher, &config, 5, &mut buf)?, 10);
+        assert_eq!(buf, data[5..15]);
+        assert_eq!(positions(), vec![100, 15]);
+        assert_eq!(open_files.opened(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_stray_files() -> Result<(), std::io::Error> {
+        init_test_logger();
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"1234".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"56".to_vec()),
+                ("file/.DS_Store".to_string(), b"junk".to_vec()),
+                ("file/scfs.0000000002.part".to_string(), b"junk".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+
+        let session = mount_and_create_files(&files)?;
+        let mountpoint = session.mountpoint.path();
+
+        assert_eq!(fs::read_dir(mountpoint)?.count(), 1);
+        assert_eq!(fs::read(mountpoint.join("file"))?, b"123456");
+
+        let ds_store = session._mirror.path().join("file/.DS_Store");
+        assert!(logged_messages()
+            .iter()
+            .any(|(level, message)| *level == Level::Warn
+                && message.contains(&format!("{:?}", ds_store))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_deleted_part() -> Result<(), std::io::Error> {
+        // If a part vanishes after the file has been opened, reading must fail with an I/O error.
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![("file/scfs.0000000000".to_string(), b"content".to_vec())],
+            Config::default(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let mut file = File::open(mountpoint.path().join("file"))?;
+        fs::remove_file(mirror.path().join("file/scfs.0000000000"))?;
+
+        let error = file.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(EIO));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allow_missing_chunks() -> Result<(), std::io::Error> {
+        // A mirror with a missing chunk in the middle must still serve the present bytes.
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000002".to_string(), b"89".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default().allow_missing(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let file = File::open(mountpoint.path().join("file"))?;
+        assert_eq!(file.metadata()?.len(), 10);
+
+        let mut buf = [0; 4];
+        file.read_exact_at(&mut buf, 0)?;
+        assert_eq!(&buf, b"0123");
+
+        let mut buf = [0; 2];
+        file.read_exact_at(&mut buf, 8)?;
+        assert_eq!(&buf, b"89");
+
+        let error = file.read_at(&mut [0; 4], 4).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(EIO));
+
+        // A read into the missing chunk ends before it
+        let mut buf = [0; 10];
+        assert_eq!(file.read_at(&mut buf, 2)?, 2);
+        assert_eq!(&buf[..2], b"23");
+
+        Ok(())
+    }
+
+    // Fetches chunks by copying them from a staging directory, counting the fetches
+    struct StagingSource {
+        mirror: PathBuf,
+        staging: PathBuf,
+        cache: PathBuf,
+        fetches: AtomicU64,
+        chunks: Vec<(PathBuf, u64)>,
+    }
+
+    impl ChunkSource for StagingSource {
+        fn fetch(&self, path: &Path) -> std::io::Result<PathBuf> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            let relative = path.strip_prefix(&self.mirror).unwrap();
+            let local = self
+                .cache
+                .join(relative.to_string_lossy().replace('/', "_"));
+            fs::copy(self.staging.join(relative), &local)?;
+            Ok(local)
+        }
+
+        fn list(&self) -> std::io::Result<Vec<(PathBuf, u64)>> {
+            Ok(self.chunks.clone())
+        }
+    }
+
+    #[test]
+    fn test_fetch_missing_chunks() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+        let staging = tempdir()?;
+        let cache = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"4567".to_vec()),
+                ("file/scfs.0000000002".to_string(), b"89".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let source = Arc::new(StagingSource {
+            mirror: mirror.path().to_path_buf(),
+            staging: staging.path().to_path_buf(),
+            cache: cache.path().to_path_buf(),
+            fetches: AtomicU64::new(0),
+            chunks: Vec::new(),
+        });
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default().chunk_source(Some(Arc::clone(&source) as Arc<dyn ChunkSource>)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // The chunks are moved to the remote storage after indexing
+        fs::create_dir(staging.path().join("file"))?;
+        for part in 1..3 {
+            let chunk = format!("file/scfs.{:010}", part);
+            fs::rename(mirror.path().join(&chunk), staging.path().join(&chunk))?;
+        }
+
+        // The size of the file is known without fetching any chunk
+        assert_eq!(fs::metadata(mountpoint.path().join("file"))?.len(), 10);
+        assert_eq!(source.fetches.load(Ordering::Relaxed), 0);
+
+        for _ in 0..2 {
+            let content = fs::read(mountpoint.path().join("file"))?;
+            assert_eq!(content, b"0123456789");
+        }
+        assert_eq!(source.fetches.load(Ordering::Relaxed), 2);
+
+        // Chunks that cannot be fetched still fail the read
+        let mut file = File::open(mountpoint.path().join("file"))?;
+        fs::remove_file(mirror.path().join("file/scfs.0000000000"))?;
+        let error = file.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(EIO));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_source_chunks() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+        let staging = tempdir()?;
+        let cache = tempdir()?;
+
+        // Only the config is in the mirror, the chunks are all kept in the remote storage
+        create_files_and_symlinks(
+            mirror.path(),
+            &with_config_file(Vec::new(), Config::default().blocksize(4).unwrap()),
+            &Vec::new(),
+        )?;
+        let chunks = vec![
+            ("dir/file/scfs.0000000000", b"0123".to_vec()),
+            ("dir/file/scfs.0000000001", b"45".to_vec()),
+        ];
+        fs::create_dir_all(staging.path().join("dir/file"))?;
+        for (chunk, content) in &chunks {
+            fs::write(staging.path().join(chunk), content)?;
+        }
+
+        let source = Arc::new(StagingSource {
+            mirror: mirror.path().to_path_buf(),
+            staging: staging.path().to_path_buf(),
+            cache: cache.path().to_path_buf(),
+            fetches: AtomicU64::new(0),
+            chunks: chunks
+                .iter()
+                .map(|(chunk, content)| (mirror.path().join(chunk), content.len() as u64))
+                .collect(),
+        });
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default().chunk_source(Some(Arc::clone(&source) as Arc<dyn ChunkSource>)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert!(fs::metadata(mountpoint.path().join("dir"))?.is_dir());
+        let meta = fs::metadata(mountpoint.path().join("dir/file"))?;
+        assert!(meta.is_file());
+        assert_eq!(meta.len(), 6);
+        assert_eq!(source.fetches.load(Ordering::Relaxed), 0);
+
+        assert_eq!(fs::read(mountpoint.path().join("dir/file"))?, b"012345");
+        assert_eq!(source.fetches.load(Ordering::Relaxed), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_id_maps() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"45".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        let uid = fs::metadata(mirror.path())?.uid();
+        let gid = fs::metadata(mirror.path())?.gid();
+
+        // Explicit mappings take precedence over the one for all IDs
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default()
+                .uid_map(IdMap(vec![(None, 1), (Some(uid), 1234)]))
+                .gid_map(IdMap(vec![(Some(gid + 1), 0)])),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let file = fs::metadata(mountpoint.path().join("file"))?;
+        assert_eq!((file.uid(), file.gid()), (1234, gid));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_noatime() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"45".to_vec()),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        // An access time before the modification time would be updated by any read
+        let chunk = mirror.path().join("file/scfs.0000000000");
+        let accessed = std::time::UNIX_EPOCH + Duration::from_secs(1000);
+        File::options()
+            .write(true)
+            .open(&chunk)?
+            .set_times(fs::FileTimes::new().set_accessed(accessed))?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default().noatime(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert_eq!(fs::read(mountpoint.path().join("file"))?, b"012345");
+        assert_eq!(fs::metadata(&chunk)?.accessed()?, accessed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_holes() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000001".to_string(), vec![0; 4]),
+                ("file/scfs.0000000002".to_string(), vec![0; 4]),
+                ("file/scfs.0000000003".to_string(), b"\089".to_vec()),
+                ("zeros/scfs.0000000000".to_string(), vec![0; 4]),
+            ],
+            Config::default().blocksize(4).unwrap(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let seek = |file: &File, offset: i64, whence: c_int| match unsafe {
+            libc::lseek(file.as_raw_fd(), offset, whence)
+        } {
+            -1 => Err(std::io::Error::last_os_error().raw_os_error()),
+            offset => Ok(offset),
+        };
+
+        let file = File::open(mountpoint.path().join("file"))?;
+        assert_eq!(seek(&file, 0, SEEK_DATA), Ok(0));
+        assert_eq!(seek(&file, 0, SEEK_HOLE), Ok(4));
+        assert_eq!(seek(&file, 5, SEEK_HOLE), Ok(5));
+        assert_eq!(seek(&file, 5, SEEK_DATA), Ok(12));
+        assert_eq!(seek(&file, 12, SEEK_HOLE), Ok(15));
+        assert_eq!(seek(&file, 15, SEEK_DATA), Err(Some(ENXIO)));
+
+        // The holes still read as zeros
+        assert_eq!(
+            fs::read(mountpoint.path().join("file"))?,
+            b"0123\0\0\0\0\0\0\0\0\089"
+        );
+
+        let zeros = File::open(mountpoint.path().join("zeros"))?;
+        assert_eq!(seek(&zeros, 0, SEEK_DATA), Err(Some(ENXIO)));
+        assert_eq!(seek(&zeros, 1, SEEK_HOLE), Ok(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_open_files() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = (0..32)
+            .flat_map(|file| {
+                [
+                    (format!("file_{}/scfs.0000000000", file), vec![file; 4]),
+                    (format!("file_{}/scfs.0000000001", file), vec![file; 2]),
+                ]
+            })
+            .collect();
+        let files = with_config_file(files, Config::default().blocksize(4).unwrap());
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default()
+                .max_open_files(Some(2))
+                .read_threads(Some(8)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // All files are open at once and read concurrently, but only two chunks at a time get
+        // opened in the mirror
+        let opened = (0..32)
+            .map(|file| File::open(mountpoint.path().join(format!("file_{}", file))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let threads = opened
+            .into_iter()
+            .map(|mut file| {
+                std::thread::spawn(move || {
+                    let mut content = Vec::new();
+                    file.read_to_end(&mut content).map(|_| content)
+                })
+            })
+            .collect::<Vec<_>>();
+        for (file, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap()?, vec![file as u8; 6]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_limit() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"0123".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"45".to_vec()),
+                ("small".to_string(), b"small".to_vec()),
+            ],
+            Config::default()
+                .blocksize(4)
+                .unwrap()
+                .min_split_size(Some(8)),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default().read_limit(Some(1 << 30)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert_eq!(fs::read(mountpoint.path().join("file"))?, b"012345");
+        assert_eq!(fs::read(mountpoint.path().join("small"))?, b"small");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fsync() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = with_config_file(
+            vec![("file/scfs.0000000000".to_string(), b"content".to_vec())],
+            Config::default(),
+        );
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let file = File::open(mountpoint.path().join("file"))?;
+        file.sync_all()?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_operations() -> Result<(), std::io::Error> {
+        // Operations that are not supported fail cleanly and leave the mount usable
+
+        let files = with_config_file(
+            vec![("file/scfs.0000000000".to_string(), b"content".to_vec())],
+            Config::default(),
+        );
+        let session = mount_and_create_files(&files)?;
+
+        let path = session.mountpoint.path().join("file");
+        let file = File::open(&path)?;
+
+        // Read-only file descriptors are already refused by the kernel
+        let error =
+            nix::fcntl::fallocate(file.as_raw_fd(), FallocateFlags::empty(), 0, 10).unwrap_err();
+        assert!([Errno::EBADF, Errno::EROFS].contains(&error), "{}", error);
+
+        let mut flags = 0;
+        assert_eq!(
+            unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) },
+            -1
+        );
+        assert_eq!(Errno::last(), Errno::ENOTTY);
+
+        assert_eq!(fs::read(&path)?, b"content");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_gzip() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS with compressed chunks, the original files must be
+        // presented again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let mut rng = thread_rng();
+        let files = [0, 1, 99, 100, 101, 1234]
+            .iter()
+            .map(|size| {
+                let mut content = vec![0u8; *size];
+                rng.fill_bytes(&mut content);
+                (format!("file_{}", size), content)
+            })
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default()
+            .blocksize(100)
+            .unwrap()
+            .compression(Some(Compression::Gzip));
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config,
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            let file = mountpoint_cat.path().join(file_name);
+            assert_eq!(fs::metadata(&file)?.len(), content.len() as u64);
+            assert_eq!(fs::read(&file)?, content);
+
+            // Reading from an offset must skip into the right chunk
+            if content.len() > 150 {
+                let mut file = File::open(&file)?;
+                file.seek(SeekFrom::Start(150))?;
+                let mut buf = Vec::new();
+                file.read_to_end(&mut buf)?;
+                assert_eq!(buf, content[150..]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn test_expensive_blocksize_default() -> Result<(), std::io::Error> {
+        let config = Config::default();
+        let blocksize = config.blocksize as usize;
+        let num_files = 10;
+        let max_num_fragments = 5;
+
+        let files_expected = with_config_file(
+            create_random_file_tuples(blocksize, num_files, max_num_fragments),
+            config,
+        );
+
+        let session = mount_and_create_files(&files_expected)?;
+
+        check_files(session.mountpoint.path(), files_expected)
+    }
+
+    #[test]
+    fn test_symlink_relative_file() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such. A
+        // relative symlink pointing to a file inside the chunked directory should translate into
+        // the same concatenated file as the real file.
+
+        let config = Config::default().blocksize(1).unwrap();
+        let blocksize = config.blocksize as usize;
+        let num_files = 1;
+        let max_num_fragments = 5;
+
+        let files = with_config_file(
+            create_random_file_tuples(blocksize, num_files, max_num_fragments),
+            config,
+        );
+
+        let mut symlink_map: HashMap<String, String> = HashMap::new();
+        symlink_map.insert(
+            "link_rel".into(),
+            files.first().unwrap().0.split("/").next().unwrap().into(),
+        );
+
+        let session = mount_and_create_files_with_symlinks(
+            &files,
+            symlink_map.clone().into_iter().collect(),
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), num_files + symlink_map.len());
+
+        let symlinks_found: Vec<&DirEntry> = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        let contents = entries
+            .iter()
+            .map(|file| fs::read(file.path()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(contents
+            .iter()
+            .all(|content| content.eq(contents.first().unwrap())));
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_symlink_relative_vdir() -> Result<(), std::io::Error> {
+        // A relative symlink pointing into a chunked directory should translate into the
+        // concatenated file, the reverse of what SplitFS does. Absolute symlinks and symlinks
+        // leaving the mirror are presented as they are.
+
+        let files = with_config_file(
+            vec![
+                ("a/b/c/scfs.0000000000".to_string(), b"42".to_vec()),
+                ("d/e/scfs.0000000000".to_string(), b"23".to_vec()),
+            ],
+            Config::default(),
+        );
+
+        let symlinks = vec![
+            ("link_c".to_string(), "a/b/c".to_string()),
+            ("link_slash".to_string(), "a/b/c/".to_string()),
+            (
+                "link_chunk".to_string(),
+                "./a/b/c/scfs.0000000000".to_string(),
+            ),
+            ("d/link_up".to_string(), "../a/b/c/.".to_string()),
+            ("link_out".to_string(), "../outside/a/b/c/".to_string()),
+            ("link_abs".to_string(), "/a/b/c/".to_string()),
+        ];
+
+        let session = mount_and_create_files_with_symlinks(&files, symlinks)?;
+        let mountpoint = session.mountpoint.path();
+
+        for (link, target) in [
+            ("link_c", "a/b/c"),
+            ("link_slash", "a/b/c"),
+            ("link_chunk", "./a/b/c"),
+            ("d/link_up", "../a/b/c"),
+            ("link_out", "../outside/a/b/c/"),
+            ("link_abs", "/a/b/c/"),
+        ] {
+            assert_eq!(fs::read_link(mountpoint.join(link))?, Path::new(target));
+        }
+
+        for link in ["link_c", "link_slash", "link_chunk", "d/link_up"] {
+            assert_eq!(fs::read(mountpoint.join(link))?, b"42");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_absolute_dir() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such.
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_abs".into(), "/".into());
+
+        let session = mount_and_create_files_with_symlinks(
+            &with_config_file(Vec::new(), Config::default()),
+            symlink_map.clone().into_iter().collect(),
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), symlink_map.len());
+
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        assert!(symlinks_found.first().unwrap().path().is_dir());
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_symlink_broken() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such. If
+        // the target does not exist, just show a broken symlink, do not panic out.
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_rel".into(), "a/b/c".into());
+        symlink_map.insert("link_abs".into(), "/home/nobody/nothing".into());
+
+        let session = mount_and_create_files_with_symlinks(
+            &with_config_file(Vec::new(), Config::default()),
+            symlink_map.clone().into_iter().collect(),
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), symlink_map.len());
+
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_ignore_count_file() -> Result<(), std::io::Error> {
+        // A .count file emitted by SplitFS must not be interpreted as a chunk.
+
+        let config = Config::default().blocksize(1024).unwrap();
+
+        let files = with_config_file(create_random_file_tuples(1024, 5, 5), config);
+
+        let files_with_count = files
+            .iter()
+            .cloned()
+            .chain((0..5).map(|file_num| {
+                (
+                    format!("file_{}/{}", file_num, COUNT_FILE_NAME),
+                    b"1\n".to_vec(),
+                )
+            }))
+            .collect::<Vec<_>>();
+
+        let session = mount_and_create_files(&files_with_count)?;
+
+        check_files(session.mountpoint.path(), files)
+    }
+
+    #[test]
+    fn test_ignore_checksum_files() -> Result<(), std::io::Error> {
+        // Checksum sidecars created by SplitFS are no chunks and must not become part of the file.
+
+        let config = Config::default().blocksize(4).unwrap();
+        let files = with_config_file(
+            vec![
+                ("file/scfs.0000000000".to_string(), b"1234".to_vec()),
+                ("file/scfs.0000000000.sha256".to_string(), b"abc".to_vec()),
+                ("file/scfs.0000000001".to_string(), b"56".to_vec()),
+                ("file/scfs.0000000001.sha256".to_string(), b"def".to_vec()),
+            ],
+            config,
+        );
+
+        let session = mount_and_create_files(&files)?;
+
+        assert_eq!(fs::read(session.mountpoint.path().join("file"))?, b"123456");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_small_files() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS that packs small files, the original files must be
+        // presented again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = (0..20)
+            .map(|i| (format!("dir_{}/file_{}", i % 3, i), vec![i as u8; i * 7]))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(16).unwrap(),
+            Settings::default().pack_small(Some(64)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            let file = mountpoint_cat.path().join(file_name);
+            assert!(file.is_file());
+            assert_eq!(fs::read(file)?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunk_format() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS with custom chunk names, the original files must be
+        // presented again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("a".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("b/c".to_string(), Vec::new()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let chunk_format = "part-{index:04}.bin".parse().unwrap();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(7)
+                .unwrap()
+                .chunk_format(Some(chunk_format)),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        assert!(mountpoint_split.path().join("a/part-0000.bin").is_file());
+        assert!(mountpoint_split.path().join("a/part-0014.bin").is_file());
+        assert!(mountpoint_split.path().join("b/c/part-0000.bin").is_file());
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            assert_eq!(fs::read(mountpoint_cat.path().join(file_name))?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_with_offset() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS whose chunk names contain their offset and length, the
+        // original files must be presented again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("a".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("b/c".to_string(), Vec::new()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(7)
+                .unwrap()
+                .name_with_offset(true),
+            Settings::default().checksums(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let split = mountpoint_split.path();
+        assert_eq!(
+            fs::read(split.join("a/scfs.0000000014.00000000098.2"))?,
+            vec![98, 99]
+        );
+        assert!(split.join("a/scfs.0000000000.00000000000.7").is_file());
+        assert!(split.join("b/c/scfs.0000000000.00000000000.0").is_file());
+        assert!(String::from_utf8(fs::read(
+            split.join("a/scfs.0000000001.00000000007.7.sha256")
+        )?)
+        .unwrap()
+        .ends_with("  scfs.0000000001.00000000007.7\n"));
+
+        let fs = CatFS::new(
+            split.as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            assert_eq!(fs::read(mountpoint_cat.path().join(file_name))?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_split_size() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS that leaves small files as they are, all files must be
+        // presented again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = (0..10)
+            .map(|i| (format!("dir_{}/file_{}", i % 3, i), vec![i as u8; i * 3]))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(4)
+                .unwrap()
+                .min_split_size(Some(12)),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        assert!(mountpoint_split.path().join("dir_1/file_4").is_file());
+        assert!(mountpoint_split.path().join("dir_2/file_5").is_dir());
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            let file = mountpoint_cat.path().join(file_name);
+            assert!(file.is_file());
+            assert_eq!(fs::read(file)?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_utf8_names() -> Result<(), std::io::Error> {
+        // File names and symlink targets do not have to be valid UTF-8, CatFS over SplitFS must
+        // still present them as they are.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let name = OsStr::from_bytes(b"file_\xff\xfe");
+        let link = OsStr::from_bytes(b"link_\xff");
+        fs::write(mirror.path().join(name), b"0123456789")?;
+        std::os::unix::fs::symlink(name, mirror.path().join(link))?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(4).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+        assert_eq!(fs::read_link(mountpoint_split.path().join(link))?, name);
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        assert_eq!(fs::read(mountpoint_cat.path().join(name))?, b"0123456789");
+        assert_eq!(fs::read_link(mountpoint_cat.path().join(link))?, name);
+        assert_eq!(fs::read(mountpoint_cat.path().join(link))?, b"0123456789");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mark_chunked() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS that marks chunked files, the files must be presented
+        // under their original names again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("big".to_string(), (0..10).collect::<Vec<u8>>()),
+            ("dir.scfs/file".to_string(), (0..5).collect::<Vec<u8>>()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(4)
+                .unwrap()
+                .chunked_suffix(Some(".scfs".to_string())),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+        assert!(mountpoint_split.path().join("dir.scfs/file.scfs").is_dir());
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let mut names = fs::read_dir(mountpoint_cat.path())?
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["big", "dir.scfs"]);
+
+        for (file_name, content) in files {
+            assert_eq!(fs::read(mountpoint_cat.path().join(file_name))?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocksize_manifest() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS that splits two files with different blocksizes, both
+        // files must be put together again.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("big".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("dir/small".to_string(), (0..100).rev().collect::<Vec<u8>>()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        fs::write(
+            mirror.path().join(BLOCKSIZES_MANIFEST_NAME),
+            r#"{"big": 30}"#,
+        )?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(7).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            let file = mountpoint_cat.path().join(file_name);
+            assert_eq!(fs::read(&file)?, content);
+
+            // Reads crossing chunk boundaries have to find the right chunks
+            let mut buf = vec![0; 40];
+            File::open(&file)?.read_exact_at(&mut buf, 25)?;
+            assert_eq!(buf, content[25..65]);
+        }
+        assert!(!mountpoint_cat
+            .path()
+            .join("big")
+            .join(BLOCKSIZE_FILE_NAME)
+            .exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlap() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS whose chunks repeat the end of the previous block, the
+        // repeated bytes must not end up in the files.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("file".to_string(), (0..25).collect::<Vec<u8>>()),
+            ("dir/narrow".to_string(), (0..7).rev().collect::<Vec<u8>>()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        fs::write(
+            mirror.path().join(BLOCKSIZES_MANIFEST_NAME),
+            r#"{"dir/narrow": 2}"#,
+        )?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap().overlap(Some(3)),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let file = mountpoint_split.path().join("file");
+        assert_eq!(fs::read(file.join("scfs.0000000000"))?, files[0].1[..10]);
+        assert_eq!(fs::read(file.join("scfs.0000000001"))?, files[0].1[7..20]);
+        assert_eq!(fs::read(file.join("scfs.0000000002"))?, files[0].1[17..]);
+
+        // Blocks smaller than the overlap are repeated as a whole
+        let narrow = mountpoint_split.path().join("dir/narrow");
+        assert_eq!(fs::read(narrow.join("scfs.0000000003"))?, files[1].1[4..]);
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            let file = mountpoint_cat.path().join(file_name);
+            assert_eq!(fs::metadata(&file)?.len(), content.len() as u64);
+            assert_eq!(fs::read(&file)?, content);
+
+            // Reads crossing chunk boundaries have to skip the repeated bytes of each chunk
+            let mut buf = vec![0; 4];
+            File::open(&file)?.read_exact_at(&mut buf, 1)?;
+            assert_eq!(buf, content[1..5]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_files_are_no_chunks() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![("file".to_string(), (0..25).collect::<Vec<u8>>())];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default().emit_whole(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, files[0].1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parts() -> Result<(), std::io::Error> {
+        // Mount a CatFS over a SplitFS that splits each file into a fixed number of parts, which
+        // gives each file its own blocksize.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("big".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("dir/odd".to_string(), (0..10).rev().collect::<Vec<u8>>()),
+            ("tiny".to_string(), vec![1, 2]),
+            ("empty".to_string(), Vec::new()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().parts(Some(4)).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        // Files smaller than the number of parts get one chunk per byte, but at least one
+        for (file_name, blocksize, chunks) in [
+            ("big", 25, 4),
+            ("dir/odd", 3, 4),
+            ("tiny", 1, 2),
+            ("empty", 1, 1),
+        ] {
+            let dir = mountpoint_split.path().join(file_name);
+            assert_eq!(
+                fs::read_to_string(dir.join(BLOCKSIZE_FILE_NAME))?,
+                format!("{}\n", blocksize)
+            );
+            assert_eq!(fs::read_dir(&dir)?.count(), chunks + 1);
+        }
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().verify(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        for (file_name, content) in files {
+            assert_eq!(fs::read(mountpoint_cat.path().join(file_name))?, content);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_special_files() -> Result<(), std::io::Error> {
+        // A FIFO presented by SplitFS must still be a FIFO when presented by CatFS.
+
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        mkfifo(&mirror.path().join("fifo"), Mode::S_IRWXU)?;
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let settings = Settings::default().special_files(true);
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            settings.clone(),
+            Box::new(|| ()),
         )
-    }
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
 
-    fn with_config_file(files: Vec<(String, Vec<u8>)>, config: Config) -> Vec<(String, Vec<u8>)> {
-        files
-            .into_iter()
-            .chain(iter::once(create_config_file_tuple(Some(config))))
-            .collect()
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            settings,
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let entries = fs::read_dir(mountpoint_cat.path())?
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.file_name(), entry.file_type().unwrap()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[OsStr::new("fifo")].is_fifo());
+        assert!(entries[OsStr::new("file")].is_file());
+
+        let fifo = mountpoint_cat.path().join("fifo");
+        assert!(fs::symlink_metadata(fifo)?.file_type().is_fifo());
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, b"content");
+
+        Ok(())
     }
 
-    fn check_files(
-        mountpoint: &Path,
-        files_expected: Vec<(String, Vec<u8>)>,
-    ) -> Result<(), std::io::Error> {
-        let files_actual = fs::read_dir(mountpoint)?
-            .map(|entry| entry.unwrap().path())
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_restore_metadata() -> Result<(), std::io::Error> {
+        // Copies of chunks do not keep the original metadata, so CatFS takes it from .scfs_meta
 
-        let mut files_expected_map = HashMap::new();
-        for (file_name, content_chunk) in files_expected {
-            if file_name == CONFIG_FILE_NAME {
-                continue;
-            }
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::SystemTime;
 
-            let file_name = mountpoint.join(file_name).parent().unwrap().to_path_buf();
-            let content = files_expected_map.entry(file_name).or_insert(Vec::new());
-            for c in content_chunk {
-                content.push(c)
-            }
-        }
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let copy = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        assert_eq!(files_actual.len(), files_expected_map.len());
+        let mtime = SystemTime::UNIX_EPOCH + Duration::new(1_234_567_890, 123_456_789);
 
-        for file in files_actual {
-            let content_actual = fs::read(&file).unwrap();
-            let content_actual = content_actual.deref();
+        let file = mirror.path().join("file");
+        fs::write(&file, (0..100).collect::<Vec<u8>>())?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640))?;
+        File::options()
+            .write(true)
+            .open(&file)?
+            .set_modified(mtime)?;
 
-            let content_expected = files_expected_map.get(&file).unwrap();
-            let content_expected = content_expected.deref();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(30).unwrap(),
+            Settings::default().emit_meta(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
 
-            assert_eq!(content_actual, content_expected)
+        fs::copy(
+            mountpoint_split.path().join(CONFIG_FILE_NAME),
+            copy.path().join(CONFIG_FILE_NAME),
+        )?;
+        fs::create_dir(copy.path().join("file"))?;
+        for entry in fs::read_dir(mountpoint_split.path().join("file"))? {
+            let entry = entry?;
+            fs::copy(
+                entry.path(),
+                copy.path().join("file").join(entry.file_name()),
+            )?;
         }
+        fs::set_permissions(
+            copy.path().join("file/scfs.0000000000"),
+            fs::Permissions::from_mode(0o600),
+        )?;
+
+        let fs = CatFS::new(
+            copy.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let meta = fs::metadata(mountpoint_cat.path().join("file"))?;
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(meta.modified()?, mtime);
+        assert_eq!(meta.len(), 100);
+
+        // The metadata file itself is not presented
+        assert_eq!(fs::read_dir(mountpoint_cat.path())?.count(), 1);
 
         Ok(())
     }
 
     #[test]
-    #[should_panic(expected = "SCFS config file not found")]
-    fn test_empty_mirror() {
-        // Since a valid SplitFS needs a config file, panic if there is no such file
+    fn test_statfs() -> Result<(), std::io::Error> {
+        let files = vec![
+            (
+                CONFIG_FILE_NAME.to_string(),
+                serde_json::to_vec(&Config::default().blocksize(10).unwrap())?,
+            ),
+            ("a/scfs.0000000000".to_string(), vec![0u8; 10]),
+            ("a/scfs.0000000001".to_string(), vec![0u8; 5]),
+            ("b/c/scfs.0000000000".to_string(), vec![0u8; 0]),
+        ];
+
+        let session = mount_and_create_files(&files)?;
 
-        mount_and_create_files(&Vec::new()).unwrap();
+        let stat = nix::sys::statvfs::statvfs(session.mountpoint.path())?;
+
+        // The root, a with its 2 chunks, b, and c with its single chunk
+        assert_eq!(stat.files(), 1 + 1 + 2 + 1 + 1 + 1);
+
+        let mirror_stat = nix::sys::statvfs::statvfs(session._mirror.path())?;
+        assert_eq!(stat.blocks(), mirror_stat.blocks());
+
+        Ok(())
     }
 
     #[test]
-    fn test_empty_mirror_with_config() -> Result<(), std::io::Error> {
-        // If there is a valid config file, but nothing else, then the CatFS mountpoint is
-        // completely empty.
+    fn test_config_name() -> Result<(), std::io::Error> {
+        // A mirror presented with a custom config name can only be read back with the same name
 
-        let files = vec![create_config_file_tuple(None)];
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let session = mount_and_create_files(&files)?;
+        fs::write(mirror.path().join("file"), b"content")?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
+        let settings = Settings::default().config_name(Some("scfs.json".into()));
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            settings.clone(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let config = fs::read_to_string(mountpoint_split.path().join("scfs.json"))?;
+        assert!(serde_json::from_str::<Config>(&config).is_ok());
+        assert!(!mountpoint_split.path().join(CONFIG_FILE_NAME).exists());
+
+        let names = fs::read_dir(mountpoint_split.path())?
+            .map(|entry| entry.unwrap().file_name())
             .collect::<Vec<_>>();
+        assert!(names.iter().any(|name| name == "scfs.json"));
+        assert!(!names.iter().any(|name| name == CONFIG_FILE_NAME));
 
-        assert_eq!(entries.len(), 0);
+        assert!(CatFS::check(
+            mountpoint_split.path().as_os_str(),
+            "scfs.json",
+            std::io::sink()
+        )?);
+        assert!(!CatFS::check(
+            mountpoint_split.path().as_os_str(),
+            CONFIG_FILE_NAME,
+            std::io::sink()
+        )?);
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            settings,
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let names = fs::read_dir(mountpoint_cat.path())?
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["file"]);
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, b"content");
 
         Ok(())
     }
 
     #[test]
-    #[should_panic(expected = "SCFS config file contains invalid JSON")]
-    fn test_empty_mirror_with_wrong_config() {
-        // An invalid config file must result in a panic
+    fn test_hard_links() -> Result<(), std::io::Error> {
+        use std::os::unix::fs::DirEntryExt;
 
-        let files = vec![(CONFIG_FILE_NAME.to_string(), "{}".into())];
+        // Hard links in the mirror share their chunks in SplitFS and are hard links again in CatFS
 
-        mount_and_create_files(&files).unwrap();
-    }
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-    #[test]
-    fn test_blocksize_1() -> Result<(), std::io::Error> {
-        let config = Config::default().blocksize(1);
-        let blocksize = config.blocksize as usize;
-        let num_files = 50;
-        let max_num_fragments = 100;
+        let data = (0..25).collect::<Vec<u8>>();
+        fs::create_dir(mirror.path().join("dir"))?;
+        fs::write(mirror.path().join("file"), &data)?;
+        fs::hard_link(mirror.path().join("file"), mirror.path().join("dir/link"))?;
+        fs::write(mirror.path().join("other"), &data)?;
 
-        let files_expected = with_config_file(
-            create_random_file_tuples(blocksize, num_files, max_num_fragments),
-            config,
-        );
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
 
-        let session = mount_and_create_files(&files_expected)?;
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
 
-        check_files(session.mountpoint.path(), files_expected)
+        let file = mountpoint_cat.path().join("file");
+        let link = mountpoint_cat.path().join("dir/link");
+        assert_eq!(fs::metadata(&file)?.ino(), fs::metadata(&link)?.ino());
+        assert_eq!(fs::metadata(&file)?.nlink(), 2);
+        assert_eq!(fs::read(&link)?, data);
+
+        let entry = fs::read_dir(mountpoint_cat.path().join("dir"))?
+            .next()
+            .unwrap()?;
+        assert_eq!(entry.ino(), fs::metadata(&file)?.ino());
+
+        let other = mountpoint_cat.path().join("other");
+        assert_ne!(fs::metadata(&other)?.ino(), fs::metadata(&file)?.ino());
+        assert_eq!(fs::metadata(&other)?.nlink(), 1);
+        assert_eq!(fs::read(&other)?, data);
+
+        Ok(())
     }
 
     #[test]
-    fn test_blocksize_1kb() -> Result<(), std::io::Error> {
-        let config = Config::default().blocksize(1024);
-        let blocksize = config.blocksize as usize;
-        let num_files = 20;
-        let max_num_fragments = 10;
+    #[cfg(target_os = "linux")]
+    fn test_xattrs() -> Result<(), std::io::Error> {
+        use crate::shared::tests::{get_xattr, list_xattrs};
 
-        let files_expected = with_config_file(
-            create_random_file_tuples(blocksize, num_files, max_num_fragments),
-            config,
-        );
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let session = mount_and_create_files(&files_expected)?;
+        fs::write(mirror.path().join("file"), (0..25).collect::<Vec<u8>>())?;
+        fs::write(mirror.path().join("empty"), [])?;
 
-        check_files(session.mountpoint.path(), files_expected)
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let file = mountpoint_cat.path().join("file");
+        assert_eq!(list_xattrs(&file)?, [XATTR_PARTS]);
+        assert_eq!(get_xattr(&file, XATTR_PARTS)?, b"3");
+        assert_eq!(
+            get_xattr(&mountpoint_cat.path().join("empty"), XATTR_PARTS)?,
+            b"1"
+        );
+        assert_eq!(
+            get_xattr(&file, "user.scfs.part")
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::ENODATA)
+        );
+        assert!(list_xattrs(mountpoint_cat.path())?.is_empty());
+
+        Ok(())
     }
 
     #[test]
-    #[ignore]
-    fn test_expensive_blocksize_default() -> Result<(), std::io::Error> {
-        let config = Config::default();
-        let blocksize = config.blocksize as usize;
-        let num_files = 10;
-        let max_num_fragments = 5;
+    fn test_ino_offset() -> Result<(), std::io::Error> {
+        use crate::shared::tests::collect_inos;
 
-        let files_expected = with_config_file(
-            create_random_file_tuples(blocksize, num_files, max_num_fragments),
-            config,
-        );
+        let offset = 1 << 40;
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let session = mount_and_create_files(&files_expected)?;
+        fs::create_dir(mirror.path().join("dir"))?;
+        fs::write(mirror.path().join("dir/file"), (0..25).collect::<Vec<u8>>())?;
 
-        check_files(session.mountpoint.path(), files_expected)
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().ino_offset(offset),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        // The stacked mounts occupy disjoint ranges, only their roots share an inode
+        assert_eq!(fs::metadata(mountpoint_cat.path())?.ino(), INO_ROOT);
+        let inos_split = collect_inos(mountpoint_split.path())?;
+        let inos_cat = collect_inos(mountpoint_cat.path())?;
+        assert_eq!(inos_cat.len(), 2);
+        assert!(inos_split.iter().all(|(_, ino)| *ino < offset));
+        assert!(inos_cat.iter().all(|(_, ino)| *ino > offset));
+
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_relative_file() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such. A
-        // relative symlink pointing to a file inside the chunked directory should translate into
-        // the same concatenated file as the real file.
+    fn test_lenient_writes() -> Result<(), std::io::Error> {
+        use std::os::unix::fs::PermissionsExt;
 
-        let config = Config::default().blocksize(1);
-        let blocksize = config.blocksize as usize;
-        let num_files = 1;
-        let max_num_fragments = 5;
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let files = with_config_file(
-            create_random_file_tuples(blocksize, num_files, max_num_fragments),
-            config,
-        );
+        fs::write(mirror.path().join("file"), (0..25).collect::<Vec<u8>>())?;
 
-        let mut symlink_map: HashMap<String, String> = HashMap::new();
-        symlink_map.insert(
-            "link_rel".into(),
-            files.first().unwrap().0.split("/").next().unwrap().into(),
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().lenient_writes(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let file = mountpoint_cat.path().join("file");
+        let status = std::process::Command::new("touch")
+            .arg("-d")
+            .arg("@1000000000")
+            .arg(&file)
+            .status()?;
+        assert!(status.success());
+        assert_eq!(fs::metadata(&file)?.mtime(), 1000000000);
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600))?;
+        assert_eq!(fs::metadata(&file)?.mode() & 0o7777, 0o600);
+
+        // The content and the entries stay as they are
+        assert_eq!(fs::read(&file)?, (0..25).collect::<Vec<u8>>());
+        for result in [
+            fs::OpenOptions::new().write(true).open(&file).map(|_| ()),
+            fs::write(mountpoint_cat.path().join("new"), b"content"),
+            fs::remove_file(&file),
+            fs::create_dir(mountpoint_cat.path().join("dir")),
+        ] {
+            assert_eq!(result.unwrap_err().raw_os_error(), Some(libc::EROFS));
+        }
+        assert_eq!(
+            fs::metadata(mirror.path().join("file"))?
+                .permissions()
+                .mode()
+                & 0o7777,
+            0o644
         );
 
-        let session = mount_and_create_files_with_symlinks(
-            &files,
-            symlink_map.clone().into_iter().collect(),
-        )?;
+        Ok(())
+    }
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_many_files() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        assert_eq!(entries.len(), num_files + symlink_map.len());
+        let files = 2000;
+        for file in 0..files {
+            let dir = mirror.path().join(format!("dir_{}", file % 20));
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(format!("file_{}", file)), vec![0; file % 30])?;
+        }
 
-        let symlinks_found: Vec<&DirEntry> = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
 
-        let contents = entries
-            .iter()
-            .map(|file| fs::read(file.path()).unwrap())
-            .collect::<Vec<_>>();
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let chunks = (0..files)
+            .map(|file| (file % 30).div_ceil(10).max(1))
+            .sum::<usize>();
+        let rows: i64 = fs
+            .file_db
+            .query_row("SELECT COUNT(*) FROM Files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows as usize, 1 + 20 + files + chunks);
 
-        assert!(contents
-            .iter()
-            .all(|content| content.eq(contents.first().unwrap())));
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+        let dir = mountpoint_cat.path().join("dir_7");
+        assert_eq!(fs::read_dir(&dir)?.count(), files / 20);
+        assert_eq!(fs::read(dir.join("file_27"))?, vec![0; 27]);
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_absolute_dir() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such.
-
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_abs".into(), "/".into());
+    fn test_subdir() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let session = mount_and_create_files_with_symlinks(
-            &with_config_file(Vec::new(), Config::default()),
-            symlink_map.clone().into_iter().collect(),
+        fs::create_dir_all(mirror.path().join("backup/home/dir"))?;
+        fs::write(mirror.path().join("outside"), b"outside")?;
+        fs::write(
+            mirror.path().join("backup/home/file"),
+            (0..25).collect::<Vec<u8>>(),
         )?;
+        fs::write(mirror.path().join("backup/home/dir/file"), b"nested")?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
-
-        assert_eq!(entries.len(), symlink_map.len());
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        // The config file stays in the root of the mirror, above the presented subdirectory
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().subdir(Some(PathBuf::from("backup/home"))),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
 
-        assert!(symlinks_found.first().unwrap().path().is_dir());
+        let mut names = fs::read_dir(mountpoint_cat.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+        assert_eq!(names, ["dir", "file"]);
+        assert_eq!(
+            fs::read(mountpoint_cat.path().join("file"))?,
+            (0..25).collect::<Vec<u8>>()
+        );
+        assert_eq!(fs::read(mountpoint_cat.path().join("dir/file"))?, b"nested");
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_broken() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such. If
-        // the target does not exist, just show a broken symlink, do not panic out.
+    fn test_tree_digest() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let splitted = tempdir()?;
 
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_rel".into(), "a/b/c".into());
-        symlink_map.insert("link_abs".into(), "/home/nobody/nothing".into());
+        fs::create_dir(mirror.path().join("dir"))?;
+        fs::write(mirror.path().join("file"), (0..25).collect::<Vec<u8>>())?;
+        fs::write(mirror.path().join("dir/file"), b"nested")?;
 
-        let session = mount_and_create_files_with_symlinks(
-            &with_config_file(Vec::new(), Config::default()),
-            symlink_map.clone().into_iter().collect(),
+        let config = Config::default()
+            .blocksize(10)
+            .unwrap()
+            .chunked_suffix(Some(String::from(".chunked")));
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config,
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        // Copy the splitted mirror, so that it can be altered
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            fs::create_dir_all(splitted.path().join(&dir))?;
+            for entry in fs::read_dir(mountpoint_split.path().join(&dir))? {
+                let entry = entry?;
+                let relative = dir.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    dirs.push(relative);
+                } else {
+                    fs::copy(entry.path(), splitted.path().join(relative))?;
+                }
+            }
+        }
+        drop(session_split);
+
+        let strict = || {
+            CatFS::new(
+                splitted.path().as_os_str(),
+                Settings::default().strict(true),
+                Box::new(|| ()),
+            )
+        };
+        assert!(strict().is_ok());
+
+        fs::write(splitted.path().join("file.chunked/scfs.0000000003"), b"x")?;
+        let error = strict().err().expect("CatFS accepted the altered mirror");
+        assert!(matches!(error, MountError::Inconsistent(_)), "{}", error);
+        assert!(error.to_string().contains("tree digest"));
+
+        // Without strict, the mismatch is only reported
+        assert!(CatFS::new(
+            splitted.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ())
+        )
+        .is_ok());
+
+        // Renamed chunks are detected as well
+        fs::remove_file(splitted.path().join("file.chunked/scfs.0000000003"))?;
+        fs::rename(
+            splitted.path().join("dir/file.chunked/scfs.0000000000"),
+            splitted.path().join("dir/file.chunked/scfs.0000000001"),
         )?;
+        assert!(strict().is_err());
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        assert_eq!(entries.len(), symlink_map.len());
+    #[test]
+    fn test_single_threaded() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        let mut rng = thread_rng();
+        let mut content = vec![0u8; 1000];
+        rng.fill_bytes(&mut content);
+        fs::write(mirror.path().join("file"), &content)?;
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(64).unwrap(),
+            Settings::default().single_threaded(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let fs = CatFS::new(
+            mountpoint_split.path().as_os_str(),
+            Settings::default().single_threaded(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
+
+        let file = File::open(mountpoint_cat.path().join("file"))?;
+        let mut buf = vec![0u8; 100];
+        file.read_exact_at(&mut buf, 950 - 100)?;
+        assert_eq!(buf, content[850..950]);
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, content);
+
+        Ok(())
     }
 }
@@ -1,14 +1,35 @@
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::iter;
 use std::iter::FromIterator;
-use std::path::PathBuf;
-use std::sync::mpsc::channel;
+use std::num::NonZeroUsize;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use clap::{Args, Parser, Subcommand};
-use daemonize::Daemonize;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use daemonize::{Daemonize, Outcome};
+use log::{error, warn, LevelFilter};
+use nix::unistd::{access, geteuid, AccessFlags};
 
-use crate::{mount, CatFS, Config, SplitFS, CONFIG_DEFAULT_BLOCKSIZE};
+use crate::stream::{cat_from_reader, split_to_writer};
+use crate::{
+    mount, mount_cat, mount_split, CatFS, ChunkFormat, Compression, Config, ConfigError, Exclude,
+    IdMap, MountError, PopulateProgress, SelfVerifier, Settings, Shared, SplitFS,
+    CONFIG_DEFAULT_BLOCKSIZE, CONFIG_FILE_NAME, DEFAULT_FSNAME, INO_OFFSET_MAX, INO_ROOT, TTL,
+};
 
 pub enum Cli {
     SCFS,
@@ -42,6 +63,8 @@ struct CommandCatFs {
     args: ArgsCat,
 }
 
+// The arguments are only parsed once, so their size does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Mode {
     /// Create a splitting file system
@@ -49,6 +72,30 @@ enum Mode {
 
     /// Create a concatenating file system
     Cat(ArgsCat),
+
+    /// Check a mirror created by SplitFS for consistency without mounting it
+    Check(ArgsCheck),
+
+    /// Print the internal database of a file system as JSON lines without mounting it
+    Dump(ArgsDump),
+
+    /// Print how many chunks SplitFS would split the files of a directory into
+    Plan(ArgsPlan),
+
+    /// Rewrite the config of a mirror created by an older SplitFS in the current format
+    Migrate(ArgsMigrate),
+
+    /// Print the chunks of a directory with their byte ranges as JSON lines, to resume uploads
+    Manifest(ArgsManifest),
+
+    /// Write a file of a mirror created by SplitFS to stdout without mounting the mirror
+    CatToStdout(ArgsCatToStdout),
+
+    /// Write the chunks SplitFS would present for a directory as real files to another directory
+    SplitToDir(ArgsSplitToDir),
+
+    /// Check whether this system can mount SCFS by splitting and concatenating a temporary file
+    Doctor,
 }
 
 #[derive(Args, Debug)]
@@ -60,10 +107,12 @@ struct ArgsScfs {
 #[derive(Args, Debug)]
 struct ArgsCommon {
     /// Defines the directory that will be mirrored
-    mirror: PathBuf,
+    #[arg(required = true)]
+    mirror: Option<PathBuf>,
 
     /// Defines the mountpoint, where the mirror will be accessible
-    mountpoint: PathBuf,
+    #[arg(required = true)]
+    mountpoint: Option<PathBuf>,
 
     /// Additional options, which are passed down to FUSE
     #[arg(long, short = 'o')]
@@ -73,10 +122,99 @@ struct ArgsCommon {
     #[arg(long, short = 'd')]
     daemon: bool,
 
+    /// Write the process ID of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+    #[arg(long, value_name = "PATH", requires = "daemon")]
+    pid_file: Option<PathBuf>,
+
+    /// Redirect the output of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+    #[arg(long, value_name = "PATH", requires = "daemon")]
+    log_file: Option<PathBuf>,
+
+    /// Wait until the mountpoint can be used, with --daemon the parent process only exits then
+    #[arg(long)]
+    wait_ready: bool,
+
+    /// Seconds to wait for the mountpoint, including the time to index the mirror
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 60,
+        requires = "wait_ready"
+    )]
+    mount_timeout: u64,
+
     /// Create mountpoint directory if it does not exist already
     #[arg(long)]
     mkdir: bool,
 
+    /// Log details about mounting and indexing the mirror
+    #[arg(long, short = 'v', conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only log errors and do not show the indexing progress
+    #[arg(long, short = 'q')]
+    quiet: bool,
+
+    /// Seconds the kernel may cache file attributes and directory entries
+    #[arg(long, value_name = "SECONDS", default_value_t = TTL.as_secs())]
+    attr_ttl: u64,
+
+    /// Present only this directory inside of the mirror, given relative to the mirror
+    #[arg(long, value_name = "PATH")]
+    subdir: Option<PathBuf>,
+
+    /// Accept changes of permissions and times without applying them to the mirror
+    #[arg(long)]
+    lenient_writes: bool,
+
+    /// Shift all inodes but the one of the root by this much, to tell apart stacked mounts
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = convert_ino_offset)]
+    ino_offset: u64,
+
+    /// Unmount after this many seconds without any access, zero disables the timeout
+    #[arg(long, value_name = "SECONDS")]
+    idle_timeout: Option<u64>,
+
+    /// Present device files and FIFOs as they are instead of leaving them out
+    #[arg(long)]
+    special_files: bool,
+
+    /// Do not update the access times of the mirrored files when reading them
+    #[arg(long)]
+    noatime: bool,
+
+    /// Present files of the user ID FROM as owned by TO, a FROM of * maps all other IDs
+    #[arg(long, value_name = "FROM:TO", value_parser = convert_id_mapping)]
+    uid_map: Vec<(Option<u32>, u32)>,
+
+    /// Present files of the group ID FROM as owned by TO, a FROM of * maps all other IDs
+    #[arg(long, value_name = "FROM:TO", value_parser = convert_id_mapping)]
+    gid_map: Vec<(Option<u32>, u32)>,
+
+    /// Number of threads indexing the mirror and serving read requests, defaults to the number of CPUs
+    #[arg(long, value_name = "THREADS")]
+    read_threads: Option<NonZeroUsize>,
+
+    /// Serve one request after the other on a single thread, which trades throughput for determinism
+    #[arg(long, conflicts_with = "read_threads")]
+    single_threaded: bool,
+
+    /// Keep at most this many files of the mirror open at once, further reads wait for them
+    #[arg(long, value_name = "FILES")]
+    max_open_files: Option<NonZeroUsize>,
+
+    /// Serve at most this many bytes per second across all reads, for example 10M
+    #[arg(long, value_name = "BYTES", value_parser = convert_symbolic_quantity)]
+    read_limit: Option<u64>,
+
+    /// Name of the config file in the root of the mirror, defaults to .scfs_config
+    #[arg(long, value_name = "NAME", value_parser = convert_config_name)]
+    config_name: Option<String>,
+
+    /// Name of the mounted file system in mount listings, for example to tell mirrors apart
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_FSNAME, value_parser = convert_fsname)]
+    fsname: String,
+
     /// Additional options, which are passed down to FUSE
     #[arg(last = true)]
     fuse_options_extra: Vec<OsString>,
@@ -85,37 +223,380 @@ struct ArgsCommon {
 #[derive(Args, Debug)]
 struct ArgsSplit {
     /// Sets the desired blocksize
-    #[arg(long, short = 'b', value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
+    #[arg(long, short = 'b', env = "SCFS_BLOCKSIZE", value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
     blocksize: u64,
 
+    /// Split each file into at most this many chunks of equal size instead of using a blocksize
+    #[arg(long, value_name = "N", conflicts_with = "flat")]
+    parts: Option<u64>,
+
+    /// Repeat this many bytes from the end of the previous block at the start of each chunk
+    #[arg(long, value_name = "SIZE", value_parser = convert_symbolic_quantity)]
+    overlap: Option<u64>,
+
+    /// Derive the inode of each entry from its path, so that every mount presents the same inodes
+    #[arg(long, conflicts_with = "ino_offset")]
+    preallocate_inodes: bool,
+
+    /// Compress each chunk with the given codec
+    #[arg(long, value_name = "CODEC", value_enum)]
+    compress: Option<Compression>,
+
+    /// Name the chunks after this template, where {index} or {index:0N} stands for the chunk number
+    #[arg(long, value_name = "TEMPLATE", value_parser = ChunkFormat::from_str)]
+    chunk_format: Option<ChunkFormat>,
+
+    /// Append the offset of each chunk in its file and its length to the chunk names
+    #[arg(long)]
+    name_with_offset: bool,
+
+    /// Present files up to this size as they are, only larger files get split
+    #[arg(long, value_name = "SIZE", value_parser = convert_symbolic_quantity)]
+    min_split_size: Option<u64>,
+
+    /// Append a suffix to the names of chunked files in directory listings, ".scfs" by default
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, default_missing_value = ".scfs", value_parser = convert_suffix)]
+    mark_chunked: Option<String>,
+
+    /// List the chunks of all files in the root, named after the paths of their files
+    #[arg(long, conflicts_with_all = ["min_split_size", "mark_chunked", "self_verify", "emit_count", "emit_meta", "emit_reads", "emit_whole", "checksums", "pack_small", "placeholder", "dry_run"])]
+    flat: bool,
+
+    /// Periodically compare random chunks against the mirror and report mismatches
+    #[arg(long, conflicts_with = "compress")]
+    self_verify: bool,
+
+    /// Seconds between two self-verification samples
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 60,
+        requires = "self_verify"
+    )]
+    self_verify_interval: u64,
+
+    /// Provide a .count file with the number of chunks in each chunked file's directory
+    #[arg(long)]
+    emit_count: bool,
+
+    /// Provide a .scfs_meta file with the original mode, owner and timestamps of each chunked file
+    #[arg(long)]
+    emit_meta: bool,
+
+    /// Provide a .scfs_reads file with the number of reads of each chunk of each chunked file
+    #[arg(long)]
+    emit_reads: bool,
+
+    /// Provide a .whole file with the original content of each chunked file next to its chunks
+    #[arg(long)]
+    emit_whole: bool,
+
+    /// Fail with ESTALE on files whose size or modification time changed since they were indexed
+    #[arg(long)]
+    mirror_change_detection: bool,
+
+    /// Provide a .sha256 file with the SHA-256 checksum next to each chunk
+    #[arg(long)]
+    checksums: bool,
+
+    /// Pack files smaller than this size into combined pack objects of at most this size
+    #[arg(long, value_name = "SIZE", value_parser = convert_symbolic_quantity)]
+    pack_small: Option<u64>,
+
+    /// Present symlinks like the files and directories they point to, so they get chunked as well
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// When a chunk is opened, read this many of the following chunks into memory in the background
+    #[arg(long, value_name = "CHUNKS", default_value_t = 0)]
+    readahead: u64,
+
+    /// Size of the buffer for reading chunks from the mirror, independent of the blocksize [default: 128K]
+    #[arg(long, value_name = "SIZE", value_parser = convert_symbolic_quantity)]
+    io_buffer: Option<u64>,
+
+    /// Present unsupported files like sockets as read-only text files describing them
+    #[arg(long)]
+    placeholder: bool,
+
+    /// List the entries of each directory sorted by name, including the synthetic files
+    #[arg(long)]
+    sort_entries: bool,
+
+    /// Leave out files and directories matching this glob pattern, relative to the mirror
+    #[arg(long, value_name = "GLOB", value_parser = convert_glob)]
+    exclude: Vec<String>,
+
+    /// Keep the metadata database in this file and reuse it on later mounts of the same mirror
+    #[arg(long, value_name = "FILE", conflicts_with = "pack_small")]
+    db_path: Option<PathBuf>,
+
+    /// Instead of mounting, print the chunks the mirror would be split into to stdout
+    #[arg(long, conflicts_with_all = ["mountpoint", "db_path", "to_stdout"])]
+    dry_run: bool,
+
+    /// Instead of mounting, write the chunks of the given file as a stream to stdout
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["mirror", "mountpoint", "compress", "parts", "overlap"])]
+    to_stdout: Option<PathBuf>,
+
     #[command(flatten)]
     args_common: ArgsCommon,
 }
 
 #[derive(Args, Debug)]
 struct ArgsCat {
+    /// Refuse to mount if the chunks do not match the blocksize of the mirror
+    #[arg(long)]
+    verify: bool,
+
+    /// Serve partly downloaded mirrors, reading a missing chunk fails with an I/O error
+    #[arg(long, conflicts_with = "verify")]
+    allow_missing: bool,
+
+    /// Refuse to mount if the chunks do not match the tree digest of the mirror
+    #[arg(long, conflicts_with = "allow_missing")]
+    strict: bool,
+
+    /// Instead of mounting, read a chunk stream from stdin and write the file to stdout
+    #[arg(long, conflicts_with_all = ["mirror", "mountpoint"])]
+    from_stdin: bool,
+
+    /// Instead of mounting, check the mirror for consistency and report the result for each file
+    #[arg(long, conflicts_with_all = ["mountpoint", "verify", "allow_missing", "from_stdin"])]
+    check: bool,
+
     #[command(flatten)]
     args_common: ArgsCommon,
 }
 
+#[derive(Args, Debug)]
+struct ArgsCheck {
+    /// Defines the directory that will be checked
+    mirror: PathBuf,
+
+    /// Name of the config file in the root of the mirror, defaults to .scfs_config
+    #[arg(long, value_name = "NAME", value_parser = convert_config_name)]
+    config_name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ArgsCatToStdout {
+    /// Defines the directory that contains the chunks of the file
+    mirror: PathBuf,
+
+    /// Path of the file relative to the root of the mirror
+    file: PathBuf,
+
+    /// Name of the config file in the root of the mirror, defaults to .scfs_config
+    #[arg(long, value_name = "NAME", value_parser = convert_config_name)]
+    config_name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ArgsSplitToDir {
+    /// Defines the directory that will be split
+    mirror: PathBuf,
+
+    /// Defines the directory the chunks will be written to, which must be empty
+    out_dir: PathBuf,
+
+    /// Sets the blocksize to split the files with
+    #[arg(long, short = 'b', value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
+    blocksize: u64,
+}
+
+#[derive(Args, Debug)]
+struct ArgsDump {
+    /// Defines the directory that will be indexed
+    mirror: PathBuf,
+
+    /// Index the mirror like the given file system would
+    #[arg(long, value_enum, default_value_t = DumpMode::Split)]
+    mode: DumpMode,
+
+    /// Sets the blocksize for indexing with SplitFS
+    #[arg(long, short = 'b', value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
+    blocksize: u64,
+}
+
+#[derive(Args, Debug)]
+struct ArgsPlan {
+    /// Defines the directory that will be planned
+    mirror: PathBuf,
+
+    /// Sets the blocksize to split the files with
+    #[arg(long, short = 'b', value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
+    blocksize: u64,
+}
+
+#[derive(Args, Debug)]
+struct ArgsMigrate {
+    /// Defines the directory whose config will be migrated
+    mirror: PathBuf,
+
+    /// Name of the config file in the root of the mirror, defaults to .scfs_config
+    #[arg(long, value_name = "NAME", value_parser = convert_config_name)]
+    config_name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ArgsManifest {
+    /// Defines the directory whose chunks will be listed
+    mirror: PathBuf,
+
+    /// List the chunks the given file system would present
+    #[arg(long, value_enum, default_value_t = ManifestMode::Split)]
+    mode: ManifestMode,
+
+    /// Sets the blocksize to split the files with
+    #[arg(long, short = 'b', value_parser = convert_symbolic_quantity, default_value_t = CONFIG_DEFAULT_BLOCKSIZE)]
+    blocksize: u64,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ManifestMode {
+    Split,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DumpMode {
+    Split,
+    Cat,
+}
+
 impl Cli {
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
         let mode = match self {
-            Cli::SCFS => CommandScfs::parse().args.mode,
-            Cli::SplitFS => Mode::Split(CommandSplitFs::parse().args),
+            Cli::SCFS => {
+                let mut command = CommandScfs::command();
+                let matches = command.get_matches_mut();
+                if let Some(("split", matches)) = matches.subcommand() {
+                    check_parts(command.find_subcommand_mut("split").unwrap(), matches);
+                }
+                parsed::<CommandScfs>(&mut command, &matches).args.mode
+            }
+            Cli::SplitFS => {
+                let mut command = CommandSplitFs::command();
+                let matches = command.get_matches_mut();
+                check_parts(&mut command, &matches);
+                Mode::Split(parsed::<CommandSplitFs>(&mut command, &matches).args)
+            }
             Cli::CatFS => Mode::Cat(CommandCatFs::parse().args),
         };
 
         let args_common = match &mode {
             Mode::Split(args) => &args.args_common,
             Mode::Cat(args) => &args.args_common,
+            Mode::Check(args) => {
+                init_logging(LevelFilter::Warn);
+                return check_mirror(&args.mirror, args.config_name.as_deref());
+            }
+            Mode::Dump(args) => {
+                init_logging(LevelFilter::Warn);
+                return dump_database(args);
+            }
+            Mode::Plan(args) => {
+                init_logging(LevelFilter::Warn);
+                return plan_mirror(args);
+            }
+            Mode::Migrate(args) => {
+                init_logging(LevelFilter::Warn);
+                return migrate_mirror(args);
+            }
+            Mode::Manifest(args) => {
+                init_logging(LevelFilter::Warn);
+                return write_manifest(args);
+            }
+            Mode::CatToStdout(args) => {
+                init_logging(LevelFilter::Warn);
+                return cat_to_stdout(args);
+            }
+            Mode::SplitToDir(args) => {
+                init_logging(LevelFilter::Warn);
+                return split_to_dir(args);
+            }
+            Mode::Doctor => {
+                init_logging(LevelFilter::Warn);
+                return run_doctor();
+            }
         };
 
+        init_logging(if args_common.verbose {
+            LevelFilter::Debug
+        } else if args_common.quiet {
+            LevelFilter::Error
+        } else {
+            LevelFilter::Warn
+        });
+
+        match &mode {
+            Mode::Split(ArgsSplit {
+                to_stdout: Some(file),
+                blocksize,
+                ..
+            }) => {
+                let config = Config::default().blocksize(*blocksize).map_err(CliError)?;
+                split_to_writer(file, config, io::stdout().lock())?;
+                return Ok(());
+            }
+
+            Mode::Split(args @ ArgsSplit { dry_run: true, .. }) => {
+                let mirror = args.args_common.mirror.as_ref().unwrap();
+                if !mirror.exists() {
+                    error!("Mirror path does not exist: {:?}", mirror);
+                    panic!("Mirror path does not exist: {:?}", mirror)
+                }
+
+                let config = Config::default()
+                    .blocksize(args.blocksize)
+                    .map_err(CliError)?
+                    .parts(args.parts)
+                    .map_err(CliError)?
+                    .overlap(args.overlap)
+                    .compression(args.compress)
+                    .chunk_format(args.chunk_format.clone())
+                    .name_with_offset(args.name_with_offset)
+                    .min_split_size(args.min_split_size)
+                    .chunked_suffix(args.mark_chunked.clone());
+                let settings = Settings::default()
+                    .checksums(args.checksums)
+                    .pack_small(args.pack_small)
+                    .exclude(args.exclude.clone())
+                    .follow_symlinks(args.follow_symlinks);
+                let fs = SplitFS::new(
+                    mirror.canonicalize().unwrap().as_os_str(),
+                    config,
+                    settings,
+                    Box::new(|| ()),
+                )
+                .map_err(mirror_error)?;
+                fs.write_layout(io::stdout().lock())?;
+                return Ok(());
+            }
+
+            Mode::Cat(ArgsCat {
+                from_stdin: true, ..
+            }) => {
+                cat_from_reader(io::stdin().lock(), io::stdout().lock())?;
+                return Ok(());
+            }
+
+            Mode::Cat(ArgsCat { check: true, .. }) => {
+                return check_mirror(
+                    args_common.mirror.as_ref().unwrap(),
+                    args_common.config_name.as_deref(),
+                );
+            }
+
+            _ => {}
+        }
+
         let (mirror, mountpoint) = {
-            let mirror = &args_common.mirror;
-            let mountpoint = &args_common.mountpoint;
+            let mirror = args_common.mirror.as_ref().unwrap();
+            let mountpoint = args_common.mountpoint.as_ref().unwrap();
 
             if !mirror.exists() {
+                error!("Mirror path does not exist: {:?}", mirror);
                 panic!("Mirror path does not exist: {:?}", mirror)
             }
 
@@ -123,6 +604,7 @@ impl Cli {
                 if args_common.mkdir {
                     fs::create_dir_all(mountpoint).unwrap();
                 } else {
+                    error!("Mountpoint path does not exist: {:?}", mountpoint);
                     panic!("Mountpoint path does not exist: {:?}", mountpoint)
                 }
             }
@@ -131,6 +613,10 @@ impl Cli {
             let mountpoint = mountpoint.canonicalize().unwrap();
 
             if mirror.starts_with(&mountpoint) {
+                error!(
+                    "Mirror must not be in a subfolder of mountpoint: {:?}",
+                    mountpoint
+                );
                 panic!(
                     "Mirror must not be in a subfolder of mountpoint: {:?}",
                     mountpoint
@@ -157,40 +643,790 @@ impl Cli {
         let fuse_options = &args_common.fuse_options;
         let fuse_options_extra = &args_common.fuse_options_extra;
 
-        let fuse_options = fuse_options
-            .iter()
-            .chain(fuse_options_extra.iter())
-            .flat_map(|option| vec![OsStr::new("-o"), &option]);
+        // Given first, so that an fsname passed down to FUSE still takes precedence
+        let fsname = OsString::from(format!("fsname={}", args_common.fsname));
 
-        if args_common.daemon {
-            Daemonize::new().start().expect("Failed to daemonize.");
-        }
+        let fuse_options = mount_options(
+            iter::once(&fsname)
+                .chain(fuse_options.iter())
+                .chain(fuse_options_extra.iter()),
+            args_common.lenient_writes,
+            args_common.special_files,
+        )?;
+
+        let read_threads = args_common.read_threads.map(NonZeroUsize::get);
+        let max_open_files = args_common.max_open_files.map(NonZeroUsize::get);
+        let attr_ttl = Some(Duration::from_secs(args_common.attr_ttl));
+        let idle_timeout = args_common.idle_timeout.map(Duration::from_secs);
+
+        // Like mirror and mountpoint, the database path has to be resolved before daemonizing
+        let db_path = match &mode {
+            Mode::Split(args) => args
+                .db_path
+                .as_ref()
+                .map(|db_path| path::absolute(db_path).unwrap()),
+            Mode::Cat(_)
+            | Mode::Check(_)
+            | Mode::Dump(_)
+            | Mode::Plan(_)
+            | Mode::Migrate(_)
+            | Mode::Manifest(_)
+            | Mode::CatToStdout(_)
+            | Mode::SplitToDir(_)
+            | Mode::Doctor => None,
+        };
+
+        let mount_timeout = Duration::from_secs(args_common.mount_timeout);
+
+        let (pid_file, log_file) = if args_common.daemon {
+            let name = match &mode {
+                Mode::Cat(_) => "catfs",
+                _ => "splitfs",
+            };
+            (
+                daemon_file(args_common.pid_file.as_deref(), name, &mountpoint, "pid")?,
+                daemon_file(args_common.log_file.as_deref(), name, &mountpoint, "log")?,
+            )
+        } else {
+            (None, None)
+        };
+
+        // With --wait-ready, the daemon reports back through this socket once it is mounted
+        let daemon_socket = if args_common.daemon && args_common.wait_ready {
+            let (parent_socket, daemon_socket) = UnixStream::pair()?;
+            match daemonize(pid_file.as_deref(), log_file.as_deref())?.execute() {
+                Outcome::Parent(Ok(_)) => {
+                    drop(daemon_socket);
+                    return wait_for_daemon(parent_socket, Path::new(&mountpoint), mount_timeout);
+                }
+                Outcome::Child(Ok(_)) => Some(daemon_socket),
+                Outcome::Parent(Err(e)) | Outcome::Child(Err(e)) => {
+                    panic!("Failed to daemonize.: {:?}", e)
+                }
+            }
+        } else {
+            if args_common.daemon {
+                daemonize(pid_file.as_deref(), log_file.as_deref())?
+                    .start()
+                    .expect("Failed to daemonize.");
+            }
+            None
+        };
+
+        let mount_deadline = Instant::now() + mount_timeout;
+        let (tx_ready, rx_ready) = channel();
+
+        // A daemon has no terminal to show the progress on
+        let progress = if args_common.daemon || args_common.quiet {
+            None
+        } else {
+            let (tx_progress, rx_progress) = channel::<PopulateProgress>();
+            thread::spawn(move || {
+                let mut reported = false;
+                for progress in rx_progress {
+                    eprint!("\r{} files indexed", progress.files);
+                    reported = true;
+                }
+                if reported {
+                    eprintln!();
+                }
+            });
+            Some(tx_progress)
+        };
+
+        let mount_error = |e| {
+            CliError(format!(
+                "failed to mount at {}: {}",
+                Path::new(&mountpoint).display(),
+                e
+            ))
+        };
 
         let _session = match &mode {
             Mode::Split(args) => {
                 let blocksize = args.blocksize;
-                let config = Config::default().blocksize(blocksize);
-                let fs = SplitFS::new(&mirror, config, drop_hook);
-                mount(fs, &mountpoint, fuse_options)
+                let config = Config::default()
+                    .blocksize(blocksize)
+                    .map_err(CliError)?
+                    .parts(args.parts)
+                    .map_err(CliError)?
+                    .overlap(args.overlap)
+                    .compression(args.compress)
+                    .chunk_format(args.chunk_format.clone())
+                    .name_with_offset(args.name_with_offset)
+                    .min_split_size(args.min_split_size)
+                    .chunked_suffix(args.mark_chunked.clone())
+                    .flat(args.flat);
+                let settings = Settings::default()
+                    .emit_count(args.emit_count)
+                    .emit_meta(args.emit_meta)
+                    .emit_reads(args.emit_reads)
+                    .emit_whole(args.emit_whole)
+                    .detect_changes(args.mirror_change_detection)
+                    .checksums(args.checksums)
+                    .pack_small(args.pack_small)
+                    .exclude(args.exclude.clone())
+                    .follow_symlinks(args.follow_symlinks)
+                    .special_files(args_common.special_files)
+                    .noatime(args_common.noatime)
+                    .uid_map(IdMap(args_common.uid_map.clone()))
+                    .gid_map(IdMap(args_common.gid_map.clone()))
+                    .read_limit(args_common.read_limit)
+                    .readahead(args.readahead)
+                    .io_buffer(args.io_buffer.map(|io_buffer| io_buffer as usize))
+                    .placeholder(args.placeholder)
+                    .sort_entries(args.sort_entries)
+                    .config_name(args_common.config_name.clone())
+                    .ino_offset(args_common.ino_offset)
+                    .preallocate_inodes(args.preallocate_inodes)
+                    .lenient_writes(args_common.lenient_writes)
+                    .subdir(args_common.subdir.clone())
+                    .read_threads(read_threads)
+                    .single_threaded(args_common.single_threaded)
+                    .max_open_files(max_open_files)
+                    .attr_ttl(attr_ttl)
+                    .idle_timeout(idle_timeout)
+                    .progress(progress)
+                    .ready(Some(tx_ready))
+                    .db_path(db_path);
+                let fs = SplitFS::new(&mirror, config.clone(), settings, drop_hook)
+                    .map_err(mirror_error)?;
+                // The verifier samples the files indexed by SplitFS, which is moved into the
+                // mount, relative to the mounted subdirectory
+                let verifier = args.self_verify.then(|| {
+                    let root = match &args_common.subdir {
+                        Some(subdir) => Path::new(&mirror).join(subdir),
+                        None => PathBuf::from(&mirror),
+                    };
+                    SelfVerifier::new(root, &mountpoint, config, fs.chunked_files())
+                });
+                let session = mount(
+                    fs,
+                    &mountpoint,
+                    fuse_options.iter().map(OsString::as_os_str),
+                )
+                .map_err(mount_error)?;
+
+                if let Some(verifier) = verifier {
+                    verifier.spawn(Duration::from_secs(args.self_verify_interval));
+                }
+
+                session
             }
 
-            Mode::Cat(_args) => {
-                let fs = CatFS::new(&mirror, drop_hook);
-                mount(fs, &mountpoint, fuse_options)
+            Mode::Cat(args) => {
+                let settings = Settings::default()
+                    .special_files(args_common.special_files)
+                    .noatime(args_common.noatime)
+                    .uid_map(IdMap(args_common.uid_map.clone()))
+                    .gid_map(IdMap(args_common.gid_map.clone()))
+                    .read_limit(args_common.read_limit)
+                    .config_name(args_common.config_name.clone())
+                    .ino_offset(args_common.ino_offset)
+                    .lenient_writes(args_common.lenient_writes)
+                    .subdir(args_common.subdir.clone())
+                    .read_threads(read_threads)
+                    .single_threaded(args_common.single_threaded)
+                    .max_open_files(max_open_files)
+                    .attr_ttl(attr_ttl)
+                    .idle_timeout(idle_timeout)
+                    .progress(progress)
+                    .ready(Some(tx_ready))
+                    .verify(args.verify)
+                    .strict(args.strict)
+                    .allow_missing(args.allow_missing);
+                let fs = CatFS::new(&mirror, settings, drop_hook).map_err(mirror_error)?;
+                mount(
+                    fs,
+                    &mountpoint,
+                    fuse_options.iter().map(OsString::as_os_str),
+                )
+                .map_err(mount_error)?
             }
+
+            // Checking, dumping, planning, listing and writing files or chunks as well as the
+            // self-diagnostic have already returned before mounting
+            Mode::Check(_)
+            | Mode::Dump(_)
+            | Mode::Plan(_)
+            | Mode::Migrate(_)
+            | Mode::Manifest(_)
+            | Mode::CatToStdout(_)
+            | Mode::SplitToDir(_)
+            | Mode::Doctor => unreachable!(),
         };
 
+        if args_common.wait_ready {
+            wait_ready(&rx_ready, Path::new(&mountpoint), mount_deadline)?;
+            if let Some(mut daemon_socket) = daemon_socket {
+                daemon_socket.write_all(b"\n")?;
+            }
+        }
+
         rx_quitter.recv().expect("Could not join quitter channel.");
 
+        // Init scripts would otherwise take the stale file for a running daemon
+        if let Some(pid_file) = pid_file {
+            fs::remove_file(pid_file).unwrap_or(());
+        }
+
+        Ok(())
+    }
+}
+
+// Turns the matches into the arguments, failing with a usage error just like parse does.
+fn parsed<C: FromArgMatches>(command: &mut clap::Command, matches: &ArgMatches) -> C {
+    C::from_arg_matches(matches).unwrap_or_else(|e| e.format(command).exit())
+}
+
+// A fixed number of parts determines the blocksize of each file, so it only conflicts with a
+// blocksize given on the command line. One from SCFS_BLOCKSIZE or the default is overridden.
+fn check_parts(command: &mut clap::Command, matches: &ArgMatches) {
+    if matches.value_source("parts").is_some()
+        && matches.value_source("blocksize") == Some(ValueSource::CommandLine)
+    {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                "the argument '--parts <N>' cannot be used with '--blocksize <BLOCKSIZE>'",
+            )
+            .exit();
+    }
+}
+
+// The device all FUSE file systems talk to the kernel through.
+const FUSE_DEVICE: &str = "/dev/fuse";
+
+// Options that cannot be given together.
+const OPPOSITE_MOUNT_OPTIONS: [(&str, &str); 6] = [
+    ("ro", "rw"),
+    ("dev", "nodev"),
+    ("suid", "nosuid"),
+    ("exec", "noexec"),
+    ("atime", "noatime"),
+    ("sync", "async"),
+];
+
+// Splits the options passed down to FUSE into single options and drops repeated ones. Options
+// that contradict each other or the read-only mount are rejected before mounting, instead of
+// leaving FUSE with a confusing conflict.
+fn mount_options<'a, I: IntoIterator<Item = &'a OsString>>(
+    options: I,
+    lenient_writes: bool,
+    special_files: bool,
+) -> Result<Vec<OsString>, CliError> {
+    let mut result: Vec<OsString> = Vec::new();
+    for option in options {
+        for option in option.as_bytes().split(|byte| *byte == b',') {
+            let option = OsStr::from_bytes(option);
+
+            // Options after "--" may be given with their own -o
+            if option.is_empty() || option == "-o" {
+                continue;
+            }
+            if !result.iter().any(|known| known == option) {
+                result.push(option.to_os_string());
+            }
+        }
+    }
+
+    let has = |name: &str| result.iter().any(|option| option == name);
+
+    if has("rw") && !lenient_writes {
+        return Err(CliError(String::from(
+            "Option rw contradicts the read-only mount, use --lenient-writes to accept new times \
+             and permissions",
+        )));
+    }
+    if has("ro") && lenient_writes {
+        return Err(CliError(String::from(
+            "Option ro contradicts --lenient-writes, which needs a writable mount",
+        )));
+    }
+    for (option, opposite) in OPPOSITE_MOUNT_OPTIONS {
+        if has(option) && has(opposite) {
+            return Err(CliError(format!(
+                "Options {} and {} contradict each other",
+                option, opposite
+            )));
+        }
+    }
+
+    if has("nodev") && special_files {
+        warn!("Device files presented with --special-files cannot be opened with option nodev");
+    }
+
+    Ok(result)
+}
+
+// Prepares the daemon with its PID file and the file its output goes to.
+fn daemonize(
+    pid_file: Option<&Path>,
+    log_file: Option<&Path>,
+) -> Result<Daemonize<()>, Box<dyn Error>> {
+    let mut daemonize = Daemonize::new();
+    if let Some(pid_file) = pid_file {
+        daemonize = daemonize.pid_file(pid_file);
+    }
+    if let Some(log_file) = log_file {
+        let log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .map_err(|e| CliError(format!("cannot open {}: {}", log_file.display(), e)))?;
+        daemonize = daemonize.stdout(log.try_clone()?).stderr(log);
+    }
+
+    Ok(daemonize)
+}
+
+// Resolves a file of the daemon before it changes its working directory. Without an explicit
+// path, the file is put into the runtime directory of the user, if there is one, and named after
+// the mountpoint, so that several daemons do not share their files.
+fn daemon_file(
+    path: Option<&Path>,
+    name: &str,
+    mountpoint: &OsStr,
+    extension: &str,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if let Some(path) = path {
+        return Ok(Some(path::absolute(path)?));
+    }
+
+    let runtime_dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) if !runtime_dir.is_empty() => PathBuf::from(runtime_dir).join("scfs"),
+        _ => return Ok(None),
+    };
+    fs::create_dir_all(&runtime_dir)?;
+
+    let mountpoint = mountpoint.to_string_lossy();
+    let mountpoint = mountpoint.trim_start_matches('/').replace('/', "-");
+    Ok(Some(
+        runtime_dir.join(format!("{}-{}.{}", name, mountpoint, extension)),
+    ))
+}
+
+fn init_logging(level: LevelFilter) {
+    // The details of other crates, like each single FUSE request, are too much even for verbose
+    env_logger::Builder::new()
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("scfs", level)
+        .parse_default_env()
+        .init();
+}
+
+// Waits until the kernel has initialized the file system and its root can be stat'ed.
+fn wait_ready(
+    rx_ready: &Receiver<()>,
+    mountpoint: &Path,
+    deadline: Instant,
+) -> Result<(), CliError> {
+    let not_ready = || {
+        CliError(format!(
+            "mountpoint {} did not get ready in time",
+            mountpoint.display()
+        ))
+    };
+
+    rx_ready
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| not_ready())?;
+
+    while !fs::metadata(mountpoint).is_ok_and(|meta| meta.ino() == INO_ROOT) {
+        if Instant::now() >= deadline {
+            return Err(not_ready());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(())
+}
+
+// In the parent process, waits for the daemon to report its mountpoint as ready. A daemon that
+// fails before closes the socket without a report.
+fn wait_for_daemon(
+    mut socket: UnixStream,
+    mountpoint: &Path,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    // The daemon gives up at the same time, so leave it a moment to report
+    socket.set_read_timeout(Some(timeout + Duration::from_secs(1)))?;
+
+    let mut report = [0];
+    match socket.read(&mut report) {
+        Ok(1) => Ok(()),
+        _ => Err(CliError(format!(
+            "mountpoint {} did not get ready in time",
+            mountpoint.display()
+        ))
+        .into()),
+    }
+}
+
+// Prints the consistency report of the mirror and fails if any of the checks failed.
+fn check_mirror(mirror: &Path, config_name: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let config_name = config_name.unwrap_or(CONFIG_FILE_NAME);
+    if CatFS::check(mirror.as_os_str(), config_name, io::stdout().lock())? {
         Ok(())
+    } else {
+        Err(CliError(format!("mirror {} is inconsistent", mirror.display())).into())
+    }
+}
+
+// Indexes the mirror like CatFS would and writes the concatenated chunks of a single file to
+// stdout.
+fn cat_to_stdout(args: &ArgsCatToStdout) -> Result<(), Box<dyn Error>> {
+    let mirror = args.mirror.canonicalize().map_err(|e| {
+        CliError(format!(
+            "mirror {} is not accessible: {}",
+            args.mirror.display(),
+            e
+        ))
+    })?;
+
+    let settings = Settings::default().config_name(args.config_name.clone());
+    CatFS::new(mirror.as_os_str(), settings, Box::new(|| ()))
+        .map_err(mirror_error)?
+        .write_file(&args.file, io::stdout().lock())
+        .map_err(|e| CliError(e.to_string()))?;
+
+    Ok(())
+}
+
+// Indexes the mirror like SplitFS would and writes the chunks it would present to the output
+// directory.
+fn split_to_dir(args: &ArgsSplitToDir) -> Result<(), Box<dyn Error>> {
+    let mirror = args.mirror.canonicalize().map_err(|e| {
+        CliError(format!(
+            "mirror {} is not accessible: {}",
+            args.mirror.display(),
+            e
+        ))
+    })?;
+
+    // Chunks written into the mirror would be split again on the next run
+    let out_dir = path::absolute(&args.out_dir)?;
+    if out_dir.starts_with(&mirror) {
+        return Err(CliError(format!(
+            "output directory {} must not be inside of the mirror",
+            args.out_dir.display()
+        ))
+        .into());
+    }
+    if fs::read_dir(&out_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(CliError(format!(
+            "output directory {} is not empty",
+            args.out_dir.display()
+        ))
+        .into());
+    }
+
+    let config = Config::default()
+        .blocksize(args.blocksize)
+        .map_err(CliError)?;
+    SplitFS::new(
+        mirror.as_os_str(),
+        config,
+        Settings::default(),
+        Box::new(|| ()),
+    )
+    .map_err(mirror_error)?
+    .write_to_dir(&out_dir)
+    .map_err(|e| {
+        CliError(format!(
+            "failed to write chunks to {}: {}",
+            args.out_dir.display(),
+            e
+        ))
+    })?;
+
+    Ok(())
+}
+
+// Indexes the mirror like SplitFS or CatFS would and prints the resulting database.
+fn dump_database(args: &ArgsDump) -> Result<(), Box<dyn Error>> {
+    let mirror = args.mirror.canonicalize().map_err(|e| {
+        CliError(format!(
+            "mirror {} is not accessible: {}",
+            args.mirror.display(),
+            e
+        ))
+    })?;
+
+    match args.mode {
+        DumpMode::Split => {
+            let config = Config::default()
+                .blocksize(args.blocksize)
+                .map_err(CliError)?;
+            SplitFS::new(
+                mirror.as_os_str(),
+                config,
+                Settings::default(),
+                Box::new(|| ()),
+            )
+            .map_err(mirror_error)?
+            .write_dump(io::stdout().lock())?
+        }
+        DumpMode::Cat => CatFS::new(mirror.as_os_str(), Settings::default(), Box::new(|| ()))
+            .map_err(mirror_error)?
+            .write_dump(io::stdout().lock())?,
     }
+
+    Ok(())
 }
 
+// Indexes the mirror like SplitFS would and prints how many chunks its files are split into.
+fn plan_mirror(args: &ArgsPlan) -> Result<(), Box<dyn Error>> {
+    let mirror = args.mirror.canonicalize().map_err(|e| {
+        CliError(format!(
+            "mirror {} is not accessible: {}",
+            args.mirror.display(),
+            e
+        ))
+    })?;
+
+    let config = Config::default()
+        .blocksize(args.blocksize)
+        .map_err(CliError)?;
+    SplitFS::new(
+        mirror.as_os_str(),
+        config,
+        Settings::default(),
+        Box::new(|| ()),
+    )
+    .map_err(mirror_error)?
+    .write_plan(io::stdout().lock())?;
+
+    Ok(())
+}
+
+// Indexes the mirror like SplitFS would and prints every chunk with the range of its file.
+fn write_manifest(args: &ArgsManifest) -> Result<(), Box<dyn Error>> {
+    let mirror = args.mirror.canonicalize().map_err(|e| {
+        CliError(format!(
+            "mirror {} is not accessible: {}",
+            args.mirror.display(),
+            e
+        ))
+    })?;
+
+    match args.mode {
+        ManifestMode::Split => {
+            let config = Config::default()
+                .blocksize(args.blocksize)
+                .map_err(CliError)?;
+            SplitFS::new(
+                mirror.as_os_str(),
+                config,
+                Settings::default(),
+                Box::new(|| ()),
+            )
+            .map_err(mirror_error)?
+            .write_manifest(io::stdout().lock())?
+        }
+    }
+
+    Ok(())
+}
+
+// Rewrites the config of the mirror in the current format and prints each field that changed.
+// The new config replaces the old one at once, so an interrupted migration leaves either of them.
+fn migrate_mirror(args: &ArgsMigrate) -> Result<(), Box<dyn Error>> {
+    let config_name = args.config_name.as_deref().unwrap_or(CONFIG_FILE_NAME);
+    let path = args.mirror.join(config_name);
+    let config = Config::from_file(&path)
+        .and_then(|config| config.migrate().map_err(ConfigError::Invalid))
+        .map_err(|e| {
+            CliError(format!(
+                "config {} cannot be migrated: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+    // The fields as they are written in the file, only to show what changes
+    let old = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(
+        &fs::read_to_string(&path)?,
+    )?;
+
+    let new = match serde_json::to_value(&config)? {
+        serde_json::Value::Object(new) => new,
+        _ => unreachable!("Config is serialized as an object"),
+    };
+    if new == old {
+        println!("{} is up to date", config_name);
+        return Ok(());
+    }
+
+    let mut fields = old.keys().chain(new.keys()).collect::<Vec<_>>();
+    fields.sort();
+    fields.dedup();
+    let show = |value: Option<&serde_json::Value>| value.map_or("unset".into(), |v| v.to_string());
+    for field in fields {
+        if old.get(field) != new.get(field) {
+            println!(
+                "{}: {} -> {}",
+                field,
+                show(old.get(field)),
+                show(new.get(field))
+            );
+        }
+    }
+
+    let migrated = args.mirror.join(format!("{}.migrated", config_name));
+    fs::write(&migrated, serde_json::to_string(&config)?)?;
+    fs::rename(&migrated, &path)?;
+
+    Ok(())
+}
+
+// Mounts SplitFS over a temporary mirror with a single file and CatFS over that, then compares the
+// restored file with the original. Before that, the prerequisites for mounting are reported, so
+// that a failing mount can be told apart from a missing FUSE installation.
+fn run_doctor() -> Result<(), Box<dyn Error>> {
+    let device = Path::new(FUSE_DEVICE);
+    let device_usable = access(device, AccessFlags::R_OK | AccessFlags::W_OK).is_ok();
+    println!(
+        "FUSE device: {}",
+        if !device.exists() {
+            "missing"
+        } else if device_usable {
+            "ok"
+        } else {
+            "not accessible"
+        }
+    );
+
+    let fusermount = find_fusermount();
+    println!(
+        "fusermount: {}",
+        fusermount.as_ref().map_or_else(
+            || String::from("not found, only root can mount"),
+            |path| path.display().to_string()
+        )
+    );
+
+    match doctor_round_trip() {
+        Ok(()) => {
+            println!("PASS");
+            Ok(())
+        }
+        Err(e) => {
+            println!("FAIL: {}", e);
+            if !device.exists() {
+                println!("Hint: load the fuse kernel module, for example with modprobe fuse");
+            } else if !device_usable {
+                println!("Hint: make sure you may read and write {}", FUSE_DEVICE);
+            }
+            if fusermount.is_none() && !geteuid().is_root() {
+                println!("Hint: install FUSE, which provides fusermount3 or fusermount");
+            }
+            Err(CliError(String::from("self-diagnostic failed")).into())
+        }
+    }
+}
+
+// The steps of the self-diagnostic that need a mount. Each one is reported once it succeeded.
+fn doctor_round_trip() -> Result<(), Box<dyn Error>> {
+    let temp = DoctorDir::new()
+        .map_err(|e| CliError(format!("cannot create temporary directory: {}", e)))?;
+    let mirror = temp.0.join("mirror");
+    let split = temp.0.join("split");
+    let cat = temp.0.join("cat");
+    for dir in [&mirror, &split, &cat] {
+        fs::create_dir(dir)?;
+    }
+
+    // Several chunks, the last one shorter than the others
+    let content = (0..10000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs::write(mirror.join("file"), &content)?;
+    println!("Temporary directory: {}", temp.0.display());
+
+    let config = Config::default().blocksize(4096).map_err(CliError)?;
+    let session_split = mount_split(&mirror, config, &split, iter::empty::<&str>())
+        .map_err(|e| CliError(format!("cannot mount SplitFS: {}", e)))?;
+    println!("Mount SplitFS: ok");
+
+    let session_cat = mount_cat(&split, &cat, iter::empty::<&str>())
+        .map_err(|e| CliError(format!("cannot mount CatFS: {}", e)))?;
+    println!("Mount CatFS: ok");
+
+    let restored = fs::read(cat.join("file"));
+
+    session_cat
+        .unmount()
+        .map_err(|e| CliError(format!("cannot unmount CatFS: {}", e)))?;
+    session_split
+        .unmount()
+        .map_err(|e| CliError(format!("cannot unmount SplitFS: {}", e)))?;
+
+    match restored {
+        Ok(restored) if restored == content => {
+            println!("Round trip: ok");
+            Ok(())
+        }
+        Ok(_) => Err(CliError(String::from("restored file differs from the original")).into()),
+        Err(e) => Err(CliError(format!("cannot read restored file: {}", e)).into()),
+    }
+}
+
+// Looks for the helper that unprivileged users need to mount FUSE file systems.
+fn find_fusermount() -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .flat_map(|dir| ["fusermount3", "fusermount"].map(|name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+// A directory below the temporary directory of the system, which is removed when dropped.
+struct DoctorDir(PathBuf);
+
+impl DoctorDir {
+    fn new() -> io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let path =
+            std::env::temp_dir().join(format!("scfs-doctor.{}.{}", std::process::id(), nanos));
+        fs::create_dir(&path)?;
+        Ok(DoctorDir(path))
+    }
+}
+
+impl Drop for DoctorDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Reports a mirror that SplitFS or CatFS cannot be created for.
+fn mirror_error(e: MountError) -> CliError {
+    CliError(format!("failed to index mirror: {}", e))
+}
+
+// An error that is meant to be read by the user. Since errors returned from main are printed with
+// their Debug representation, it only consists of the plain message.
+struct CliError(String);
+
+impl fmt::Debug for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CliError {}
+
 fn convert_symbolic_quantity(s: &str) -> Result<u64, String> {
     let s = s.trim();
     let digits = String::from_iter(s.chars().take_while(|c| c.is_ascii_digit()).fuse());
 
-    if digits.len() == 0 {
+    if digits.is_empty() {
         return Err(String::from("No digits given"));
     }
 
@@ -202,16 +1438,89 @@ fn convert_symbolic_quantity(s: &str) -> Result<u64, String> {
 
     let quantifier = s[digits.len()..].trim();
 
-    let exp = match quantifier {
-        "" => 0,
-        "K" => 1,
-        "M" => 2,
-        "G" => 3,
-        "T" => 4,
-        _ => return Err(String::from("Unkown quantifier")),
+    // The bare quantifiers are binary ones for backwards compatibility
+    let (factor, exp) = match quantifier {
+        "" => (1, 0),
+        "K" | "Ki" => (1024, 1),
+        "M" | "Mi" => (1024, 2),
+        "G" | "Gi" => (1024, 3),
+        "T" | "Ti" => (1024, 4),
+        "KB" => (1000, 1),
+        "MB" => (1000, 2),
+        "GB" => (1000, 3),
+        "TB" => (1000, 4),
+        _ => return Err(format!("Unknown quantifier: {:?}", quantifier)),
     };
 
-    Ok(digits.parse::<u64>().unwrap() * 1024_u64.pow(exp))
+    base.checked_mul(u64::pow(factor, exp))
+        .ok_or_else(|| String::from("Quantity is too large"))
+}
+
+fn convert_suffix(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        Err(String::from("Suffix may not be empty"))
+    } else if s.contains('/') {
+        Err(String::from("Suffix may not contain a slash"))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+fn convert_config_name(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        Err(String::from("Config name may not be empty"))
+    } else if s.contains('/') {
+        Err(String::from("Config name may not contain a slash"))
+    } else if s == "." || s == ".." {
+        Err(String::from("Config name may not be . or .."))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+// FUSE separates its options by commas, so the name cannot contain any.
+fn convert_fsname(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        Err(String::from("File system name may not be empty"))
+    } else if s.contains(',') {
+        Err(String::from("File system name may not contain a comma"))
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+// SplitFS marks its synthetic files with the high bits of their inodes, which the offset must not
+// reach.
+fn convert_ino_offset(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(offset) if offset <= INO_OFFSET_MAX => Ok(offset),
+        Ok(_) => Err(format!("Inode offset may be at most {}", INO_OFFSET_MAX)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn convert_id_mapping(s: &str) -> Result<(Option<u32>, u32), String> {
+    let (from, to) = s
+        .split_once(':')
+        .ok_or_else(|| String::from("Mapping must have the form FROM:TO"))?;
+
+    let parse = |id: &str| {
+        id.parse::<u32>()
+            .map_err(|_| format!("Invalid ID: {:?}", id))
+    };
+    let from = if from == "*" {
+        None
+    } else {
+        Some(parse(from)?)
+    };
+
+    Ok((from, parse(to)?))
+}
+
+fn convert_glob(s: &str) -> Result<String, String> {
+    Exclude::glob(s)
+        .map(|_| s.to_string())
+        .map_err(|e| e.to_string())
 }
 
 #[cfg(test)]
@@ -236,16 +1545,113 @@ mod tests {
         CommandCatFs::command().debug_assert()
     }
 
+    #[test]
+    fn test_id_mapping_converter() {
+        assert_eq!(convert_id_mapping("1000:0"), Ok((Some(1000), 0)));
+        assert_eq!(convert_id_mapping("*:100"), Ok((None, 100)));
+
+        for s in ["1000", "1000:", ":0", "a:0", "*:*", "-1:0", "1:2:3"] {
+            assert!(convert_id_mapping(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_config_name_converter() {
+        assert_eq!(
+            convert_config_name("scfs.json"),
+            Ok(String::from("scfs.json"))
+        );
+
+        for s in ["", "dir/scfs.json", ".", ".."] {
+            assert!(convert_config_name(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_fsname_converter() {
+        assert_eq!(convert_fsname("backup"), Ok(String::from("backup")));
+
+        for s in ["", "back,up"] {
+            assert!(convert_fsname(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_ino_offset_converter() {
+        assert_eq!(convert_ino_offset("0"), Ok(0));
+        assert_eq!(convert_ino_offset("1000000"), Ok(1000000));
+
+        for s in ["", "-1", "x", &(INO_OFFSET_MAX + 1).to_string()] {
+            assert!(convert_ino_offset(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_mount_options() {
+        let options = |options: &[&str], lenient_writes| {
+            let options = options.iter().map(OsString::from).collect::<Vec<_>>();
+            mount_options(&options, lenient_writes, false)
+        };
+
+        assert_eq!(
+            options(
+                &["fsname=scfs", "noexec,allow_other", "-o", "noexec"],
+                false
+            )
+            .unwrap(),
+            ["fsname=scfs", "noexec", "allow_other"]
+        );
+        assert_eq!(options(&["rw"], true).unwrap(), ["rw"]);
+
+        for (given, lenient_writes) in [
+            (&["rw"][..], false),
+            (&["ro"], true),
+            (&["exec", "noexec"], false),
+            (&["atime,noatime"], false),
+        ] {
+            assert!(options(given, lenient_writes).is_err(), "{:?}", given);
+        }
+    }
+
     #[test]
     fn test_symbolic_quantity_converter() {
-        let sym_exp = vec![("", 0), ("K", 1), ("M", 2), ("G", 3), ("T", 4)];
-        for (sym, exp) in sym_exp {
+        let sym_exp = vec![
+            ("", 1024, 0),
+            ("K", 1024, 1),
+            ("M", 1024, 2),
+            ("G", 1024, 3),
+            ("T", 1024, 4),
+            ("Ki", 1024, 1),
+            ("Mi", 1024, 2),
+            ("Gi", 1024, 3),
+            ("Ti", 1024, 4),
+            ("KB", 1000, 1),
+            ("MB", 1000, 2),
+            ("GB", 1000, 3),
+            ("TB", 1000, 4),
+        ];
+        for (sym, factor, exp) in sym_exp {
             println!("Testing 1{}", sym);
             assert_eq!(
                 convert_symbolic_quantity(format!("1{}", sym).as_str()).unwrap(),
-                1024_u64.pow(exp)
+                u64::pow(factor, exp)
             );
         }
+
+        assert_eq!(convert_symbolic_quantity("3MB").unwrap(), 3_000_000);
+        assert_eq!(convert_symbolic_quantity("3Mi").unwrap(), 3 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_symbolic_quantity_converter_fail_on_mixed_quantifier() {
+        for s in ["1KiB9", "1KiB", "1Kb", "1kB", "1KB5", "1MiB"] {
+            assert!(convert_symbolic_quantity(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_symbolic_quantity_converter_fail_on_overflow() {
+        assert!(convert_symbolic_quantity("16777216T").is_err());
     }
 
     #[test]
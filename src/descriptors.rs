@@ -0,0 +1,96 @@
+use std::sync::{Condvar, Mutex};
+
+/// Limits the number of files of the mirror that are open at the same time, so that many
+/// concurrent reads cannot exhaust the file descriptors of the process. Reads beyond the limit
+/// wait until another read has closed its file.
+pub(crate) struct OpenFileLimit {
+    max: Option<usize>,
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+// Closes the slot of a file once its work is done, even if the work panicked.
+struct OpenFile<'a>(&'a OpenFileLimit);
+
+impl Drop for OpenFile<'_> {
+    fn drop(&mut self) {
+        *self.0.open.lock().unwrap() -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
+impl OpenFileLimit {
+    /// Creates a new limit. A limit of `None` or zero lets all files be opened immediately.
+    pub(crate) fn new(max: Option<usize>) -> Self {
+        OpenFileLimit {
+            max: max.filter(|max| *max > 0),
+            open: Mutex::new(0),
+            closed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn is_limited(&self) -> bool {
+        self.max.is_some()
+    }
+
+    /// Runs `f` as soon as another file may be opened. `f` may keep at most one file of the
+    /// mirror open at a time and has to close it before returning.
+    pub(crate) fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let max = match self.max {
+            Some(max) => max,
+            None => return f(),
+        };
+
+        let mut open = self.open.lock().unwrap();
+        while *open >= max {
+            open = self.closed.wait(open).unwrap();
+        }
+        *open += 1;
+        drop(open);
+
+        let _file = OpenFile(self);
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_open_file_limit() {
+        let limit = Arc::new(OpenFileLimit::new(Some(2)));
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+
+        let threads = (0..8)
+            .map(|_| {
+                let limit = Arc::clone(&limit);
+                let running = Arc::clone(&running);
+                let most_running = Arc::clone(&most_running);
+                thread::spawn(move || {
+                    limit.run(|| {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most_running.fetch_max(now, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+        assert_eq!(*limit.open.lock().unwrap(), 0);
+
+        assert!(!OpenFileLimit::new(None).is_limited());
+        assert!(!OpenFileLimit::new(Some(0)).is_limited());
+    }
+}
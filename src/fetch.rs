@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Provides chunks that are not present in the mirror, for example by downloading them from a
+/// remote storage.
+///
+/// CatFS consults the source whenever a chunk it has indexed cannot be found in the mirror
+/// anymore, and for the gaps of missing chunks if these are allowed. The fetched files are
+/// remembered, so every chunk is only fetched once per mount.
+pub trait ChunkSource: Send + Sync {
+    /// Returns the path of a local file with the content of the chunk at `path` in the mirror.
+    fn fetch(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Lists the chunks that only exist in the source, with the paths they would have in the
+    /// mirror and the sizes of their content. CatFS indexes them while mounting, together with
+    /// the directories they are located in. Flat mirrors are only indexed from their root.
+    ///
+    /// By default, the source lists no chunks, so all chunks have to be found in the mirror
+    /// while mounting.
+    fn list(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        Ok(Vec::new())
+    }
+}
+
+/// The default source, which requires all chunks to exist in the mirror.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalChunks;
+
+impl ChunkSource for LocalChunks {
+    fn fetch(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::metadata(path)?;
+        Ok(path.to_path_buf())
+    }
+}
+
+/// Resolves chunk paths of the mirror to local files, fetching missing chunks from a
+/// [`ChunkSource`].
+pub(crate) struct ChunkFetcher {
+    source: Arc<dyn ChunkSource>,
+
+    // Maps chunk paths in the mirror to the files fetched for them
+    fetched: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl ChunkFetcher {
+    pub(crate) fn new(source: Arc<dyn ChunkSource>) -> Self {
+        ChunkFetcher {
+            source,
+            fetched: Default::default(),
+        }
+    }
+
+    /// Returns the path to read the chunk at `path` from, which is the path itself as long as the
+    /// chunk exists in the mirror.
+    pub(crate) fn locate(&self, path: &Path) -> io::Result<PathBuf> {
+        if fs::metadata(path).is_ok() {
+            return Ok(path.to_path_buf());
+        }
+
+        // Holding the lock while fetching makes sure that a chunk is not fetched twice
+        let mut fetched = self.fetched.lock().unwrap();
+        if let Some(local) = fetched.get(path).filter(|local| local.exists()) {
+            return Ok(local.clone());
+        }
+
+        let local = self.source.fetch(path)?;
+        fetched.insert(path.to_path_buf(), local.clone());
+        Ok(local)
+    }
+}
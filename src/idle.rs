@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::DropHookFn;
+
+/// Tracks the time of the last file system operation and calls a hook once nothing has happened
+/// for the configured timeout.
+pub(crate) struct IdleTimer {
+    start: Instant,
+
+    // Milliseconds between start and the last operation
+    last_access: Arc<AtomicU64>,
+}
+
+impl IdleTimer {
+    /// Creates a new timer, which calls `hook` after `timeout` without operations. A timeout of
+    /// `None` or zero disables the timer.
+    pub(crate) fn new(timeout: Option<Duration>, hook: Arc<DropHookFn>) -> Self {
+        let start = Instant::now();
+        let last_access = Arc::new(AtomicU64::new(0));
+
+        if let Some(timeout) = timeout.filter(|timeout| !timeout.is_zero()) {
+            // The thread only holds a weak reference, so it stops once the file system is gone
+            let last_access = Arc::downgrade(&last_access);
+            thread::spawn(move || {
+                while let Some(last_access) = last_access.upgrade() {
+                    let last_access = Duration::from_millis(last_access.load(Ordering::Relaxed));
+                    let idle = start.elapsed().saturating_sub(last_access);
+
+                    if idle >= timeout {
+                        hook();
+                        break;
+                    }
+
+                    thread::sleep(timeout - idle);
+                }
+            });
+        }
+
+        IdleTimer { start, last_access }
+    }
+
+    pub(crate) fn touch(&self) {
+        self.last_access
+            .store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_idle_timeout() {
+        let (tx, rx) = channel();
+        let hook: Arc<DropHookFn> = Arc::new(Box::new(move || tx.send(()).unwrap()));
+
+        let timer = IdleTimer::new(Some(Duration::from_millis(300)), hook);
+
+        // Operations postpone the timeout
+        for _ in 0..5 {
+            thread::sleep(Duration::from_millis(100));
+            timer.touch();
+        }
+        assert!(rx.try_recv().is_err());
+
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[test]
+    fn test_idle_timeout_disabled() {
+        let (tx, rx) = channel();
+        let hook: Arc<DropHookFn> = Arc::new(Box::new(move || tx.send(()).unwrap()));
+
+        let _timer = IdleTimer::new(Some(Duration::ZERO), hook);
+        assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    }
+}
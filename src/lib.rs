@@ -64,9 +64,17 @@
 //! Usage: scfs <COMMAND>
 //!
 //! Commands:
-//!   split  Create a splitting file system
-//!   cat    Create a concatenating file system
-//!   help   Print this message or the help of the given subcommand(s)
+//!   split          Create a splitting file system
+//!   cat            Create a concatenating file system
+//!   check          Check a mirror created by SplitFS for consistency without mounting it
+//!   dump           Print the internal database of a file system as JSON lines without mounting it
+//!   plan           Print how many chunks SplitFS would split the files of a directory into
+//!   migrate        Rewrite the config of a mirror created by an older SplitFS in the current format
+//!   manifest       Print the chunks of a directory with their byte ranges as JSON lines, to resume uploads
+//!   cat-to-stdout  Write a file of a mirror created by SplitFS to stdout without mounting the mirror
+//!   split-to-dir   Write the chunks SplitFS would present for a directory as real files to another directory
+//!   doctor         Check whether this system can mount SCFS by splitting and concatenating a temporary file
+//!   help           Print this message or the help of the given subcommand(s)
 //!
 //! Options:
 //!   -h, --help     Print help
@@ -84,12 +92,60 @@
 //!   [FUSE_OPTIONS_EXTRA]...  Additional options, which are passed down to FUSE
 //!
 //! Options:
-//!   -b, --blocksize <BLOCKSIZE>        Sets the desired blocksize [default: 2097152]
-//!   -o, --fuse-options <FUSE_OPTIONS>  Additional options, which are passed down to FUSE
-//!   -d, --daemon                       Run program in background
-//!       --mkdir                        Create mountpoint directory if it does not exist already
-//!   -h, --help                         Print help
-//!   -V, --version                      Print version
+//!   -b, --blocksize <BLOCKSIZE>           Sets the desired blocksize [env: SCFS_BLOCKSIZE=] [default: 2097152]
+//!       --parts <N>                       Split each file into at most this many chunks of equal size instead of using a blocksize
+//!       --overlap <SIZE>                  Repeat this many bytes from the end of the previous block at the start of each chunk
+//!       --preallocate-inodes              Derive the inode of each entry from its path, so that every mount presents the same inodes
+//!       --compress <CODEC>                Compress each chunk with the given codec [possible values: gzip]
+//!       --chunk-format <TEMPLATE>         Name the chunks after this template, where {index} or {index:0N} stands for the chunk number
+//!       --name-with-offset                Append the offset of each chunk in its file and its length to the chunk names
+//!       --min-split-size <SIZE>           Present files up to this size as they are, only larger files get split
+//!       --mark-chunked [<SUFFIX>]         Append a suffix to the names of chunked files in directory listings, ".scfs" by default
+//!       --flat                            List the chunks of all files in the root, named after the paths of their files
+//!       --self-verify                     Periodically compare random chunks against the mirror and report mismatches
+//!       --self-verify-interval <SECONDS>  Seconds between two self-verification samples [default: 60]
+//!       --emit-count                      Provide a .count file with the number of chunks in each chunked file's directory
+//!       --emit-meta                       Provide a .scfs_meta file with the original mode, owner and timestamps of each chunked file
+//!       --emit-reads                      Provide a .scfs_reads file with the number of reads of each chunk of each chunked file
+//!       --emit-whole                      Provide a .whole file with the original content of each chunked file next to its chunks
+//!       --mirror-change-detection         Fail with ESTALE on files whose size or modification time changed since they were indexed
+//!       --checksums                       Provide a .sha256 file with the SHA-256 checksum next to each chunk
+//!       --pack-small <SIZE>               Pack files smaller than this size into combined pack objects of at most this size
+//!       --follow-symlinks                 Present symlinks like the files and directories they point to, so they get chunked as well
+//!       --readahead <CHUNKS>              When a chunk is opened, read this many of the following chunks into memory in the background [default: 0]
+//!       --io-buffer <SIZE>                Size of the buffer for reading chunks from the mirror, independent of the blocksize [default: 128K]
+//!       --placeholder                     Present unsupported files like sockets as read-only text files describing them
+//!       --sort-entries                    List the entries of each directory sorted by name, including the synthetic files
+//!       --exclude <GLOB>                  Leave out files and directories matching this glob pattern, relative to the mirror
+//!       --db-path <FILE>                  Keep the metadata database in this file and reuse it on later mounts of the same mirror
+//!       --dry-run                         Instead of mounting, print the chunks the mirror would be split into to stdout
+//!       --to-stdout <FILE>                Instead of mounting, write the chunks of the given file as a stream to stdout
+//!   -o, --fuse-options <FUSE_OPTIONS>     Additional options, which are passed down to FUSE
+//!   -d, --daemon                          Run program in background
+//!       --pid-file <PATH>                 Write the process ID of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+//!       --log-file <PATH>                 Redirect the output of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+//!       --wait-ready                      Wait until the mountpoint can be used, with --daemon the parent process only exits then
+//!       --mount-timeout <SECONDS>         Seconds to wait for the mountpoint, including the time to index the mirror [default: 60]
+//!       --mkdir                           Create mountpoint directory if it does not exist already
+//!   -v, --verbose                         Log details about mounting and indexing the mirror
+//!   -q, --quiet                           Only log errors and do not show the indexing progress
+//!       --attr-ttl <SECONDS>              Seconds the kernel may cache file attributes and directory entries [default: 86400]
+//!       --subdir <PATH>                   Present only this directory inside of the mirror, given relative to the mirror
+//!       --lenient-writes                  Accept changes of permissions and times without applying them to the mirror
+//!       --ino-offset <N>                  Shift all inodes but the one of the root by this much, to tell apart stacked mounts [default: 0]
+//!       --idle-timeout <SECONDS>          Unmount after this many seconds without any access, zero disables the timeout
+//!       --special-files                   Present device files and FIFOs as they are instead of leaving them out
+//!       --noatime                         Do not update the access times of the mirrored files when reading them
+//!       --uid-map <FROM:TO>               Present files of the user ID FROM as owned by TO, a FROM of * maps all other IDs
+//!       --gid-map <FROM:TO>               Present files of the group ID FROM as owned by TO, a FROM of * maps all other IDs
+//!       --read-threads <THREADS>          Number of threads indexing the mirror and serving read requests, defaults to the number of CPUs
+//!       --single-threaded                 Serve one request after the other on a single thread, which trades throughput for determinism
+//!       --max-open-files <FILES>          Keep at most this many files of the mirror open at once, further reads wait for them
+//!       --read-limit <BYTES>              Serve at most this many bytes per second across all reads, for example 10M
+//!       --config-name <NAME>              Name of the config file in the root of the mirror, defaults to .scfs_config
+//!       --fsname <NAME>                   Name of the mounted file system in mount listings, for example to tell mirrors apart [default: scfs]
+//!   -h, --help                            Print help
+//!   -V, --version                         Print version
 //! ```
 //!
 //! To mount a directory with SplitFS, use the following form:
@@ -134,10 +190,414 @@
 //! splitfs --blocksize=1M <base directory> <mount point>
 //! ```
 //!
+//! To make the intent clearer, the binary quantifiers may also be written as
+//! "Ki", "Mi", "Gi", and "Ti". Additionally, the decimal quantifiers "KB", "MB",
+//! "GB", and "TB" multiply the base with 1000, so this sets the block size to
+//! exactly 1000000 bytes:
+//!
+//! ```shell script
+//! splitfs --blocksize=1MB <base directory> <mount point>
+//! ```
+//!
+//! Without `--blocksize`, the block size is taken from the `SCFS_BLOCKSIZE`
+//! environment variable if it is set, which accepts the same quantifiers. This
+//! comes in handy to set a default for orchestrated mounts, an explicit
+//! `--blocksize` always takes precedence:
+//!
+//! ```shell script
+//! SCFS_BLOCKSIZE=1M splitfs <base directory> <mount point>
+//! ```
+//!
 //! You can actually go as far as to set a block size of one byte, but be prepared
 //! for a ridiculous amount of overhead or maybe even a system freeze because the
 //! metadata table grows too large.
 //!
+//! #### Self-verification
+//!
+//! As a paranoia check, SplitFS can periodically compare a random chunk, read
+//! through the mount point, with the same byte range read directly from the
+//! mirror. Any mismatch is logged as an error:
+//!
+//! ```shell script
+//! splitfs --self-verify --self-verify-interval=10 <base directory> <mount point>
+//! ```
+//!
+//! This catches errors in the offset calculation as well as files that have
+//! changed in the mirror while being mounted.
+//!
+//! #### Chunk count files
+//!
+//! With `--emit-count`, each directory that represents a chunked file contains an
+//! additional file `.count` that holds the number of chunks:
+//!
+//! ```shell script
+//! cat <mount point>/path/to/file/.count
+//! ```
+//!
+//! This avoids listing possibly huge directories just to count their entries.
+//! CatFS ignores these files when concatenating.
+//!
+//! #### Metadata files
+//!
+//! With `--emit-meta`, each directory that represents a file additionally
+//! contains a virtual file `.scfs_meta`, which holds the mode, owner and
+//! timestamps of the original file as JSON. When chunks are copied, they usually
+//! get a new modification time and may get other permissions. If a `.scfs_meta`
+//! file is present, CatFS presents the restored file with the original metadata
+//! instead of taking it from the first chunk:
+//!
+//! ```shell script
+//! scfs split --emit-meta mirror mountpoint
+//! ```
+//!
+//! #### Read statistics
+//!
+//! With `--emit-reads`, each directory that represents a file additionally
+//! contains a virtual file `.scfs_reads`, which reports how often each chunk has
+//! been read and how many bytes have been served for the file as JSON. This helps
+//! to find a blocksize that suits the way an uploader reads the chunks. Reads that
+//! the kernel serves from its page cache do not reach SplitFS and are not counted:
+//!
+//! ```shell script
+//! scfs split --emit-reads mirror mountpoint
+//! cat mountpoint/file/.scfs_reads
+//! ```
+//!
+//! #### Whole files
+//!
+//! With `--emit-whole`, each directory that represents a file additionally
+//! contains a virtual file `.whole`, which presents the original file in one
+//! piece. This way, a file can be looked at while browsing the mount, without
+//! mounting CatFS on top of it. Its content is read directly from the file in the
+//! mirror, even if the chunks are compressed. CatFS ignores the `.whole` files,
+//! but they should be left out when uploading the chunks, since they double the
+//! size of the upload:
+//!
+//! ```shell script
+//! scfs split --emit-whole mirror mountpoint
+//! cmp mirror/file mountpoint/file/.whole
+//! ```
+//!
+//! #### Checksum files
+//!
+//! With `--checksums`, each chunk `scfs.NNNNNNNNNN` gets a sibling
+//! `scfs.NNNNNNNNNN.sha256`, which contains the SHA-256 checksum of the chunk in
+//! the format of `sha256sum`. This way, the integrity of downloaded chunks can be
+//! checked with:
+//!
+//! ```shell script
+//! sha256sum -c *.sha256
+//! ```
+//!
+//! CatFS ignores these files as well.
+//!
+//! #### Packing small files
+//!
+//! With `--pack-small <SIZE>`, regular files smaller than `SIZE` are not exposed as
+//! individual directories. Instead, consecutive small files of a directory are
+//! combined into pack objects `scfs.pack.0000000000`, `scfs.pack.0000000001` and so
+//! on, each of which is at most `SIZE` in size. A file `.scfs_packs` next to the
+//! packs records which file lives at which offset of which pack. CatFS reads
+//! this index and restores the original files from the packs.
+//!
+//! This is useful for mirrors containing lots of tiny files, which would otherwise
+//! result in lots of tiny objects.
+//!
+//! #### Persistent metadata database
+//!
+//! On startup, SplitFS walks the whole mirror to build its metadata database. For
+//! mirrors with lots of files, this can take a while. With `--db-path <FILE>`, the
+//! database is kept in the given file and reused on the next mount:
+//!
+//! ```shell script
+//! scfs split --db-path ~/.cache/scfs.db mirror mountpoint
+//! ```
+//!
+//! The database is rebuilt automatically if it was created for another mirror or
+//! with another blocksize, or if the modification time of the mirror directory
+//! itself changed. Note that changes further down in the mirror do not touch the
+//! modification time of the mirror directory, so in this case the database has to
+//! be deleted manually. This option cannot be combined with `--pack-small`.
+//!
+//! #### Detecting changes of the mirror
+//!
+//! SCFS expects that the mirror is not altered while mounted. If this cannot be
+//! guaranteed, `--mirror-change-detection` records the size and modification time
+//! of each file while indexing and compares them before serving it:
+//!
+//! ```shell script
+//! scfs split --mirror-change-detection mirror mountpoint
+//! ```
+//!
+//! Once a file has been changed, `stat` and `read` on the file or any of its
+//! chunks fail with `ESTALE` instead of returning a mix of old and new data. Note
+//! that contents which have already been read may still be served from the page
+//! cache of the kernel. Files packed with `--pack-small` are not checked.
+//!
+//! #### Compressing chunks
+//!
+//! With `--compress gzip`, each chunk contains the gzip-compressed bytes of its
+//! part of the original file, which makes uploads smaller. The codec is recorded
+//! in `.scfs_config`, so CatFS decompresses the chunks transparently:
+//!
+//! ```shell script
+//! scfs split --compress gzip mirror mountpoint
+//! ```
+//!
+//! Since the size of a compressed chunk is only known after compressing it, each
+//! chunk is compressed when its size is first queried and again when it is
+//! opened. Note that packs created with `--pack-small` are not compressed.
+//!
+//! #### Chunk names
+//!
+//! By default, the chunks are named `scfs.0000000000`, `scfs.0000000001` and so
+//! on. With `--chunk-format`, a different template can be given, where `{index}`
+//! stands for the chunk number and `{index:0N}` pads it with zeros to `N` digits:
+//!
+//! ```shell script
+//! scfs split --chunk-format 'part-{index:04}.bin' mirror mountpoint
+//! ```
+//!
+//! The template is stored in `.scfs_config`, so CatFS finds the chunks again
+//! without any further options.
+//!
+//! #### Chunk names with offsets
+//!
+//! Tools that upload the chunks one by one do not know where in the file a chunk
+//! belongs. With `--name-with-offset`, each chunk name ends with the offset of the
+//! chunk in the original file and its length, like
+//! `scfs.0000000003.00006291456.2097152`:
+//!
+//! ```shell script
+//! scfs split --name-with-offset mirror mountpoint
+//! ```
+//!
+//! This is recorded in `.scfs_config` as well. CatFS takes the index from the
+//! name, while the offset and the length only have to be numbers. Such mirrors
+//! need a CatFS that supports the fourth format version.
+//!
+//! #### Per-file blocksizes
+//!
+//! A single blocksize does not fit every file equally well, for example if a
+//! mirror mixes a few huge images with many medium files. An optional
+//! `.scfs_blocksizes` manifest in the mirror maps paths, relative to the mirror,
+//! to a blocksize in bytes:
+//!
+//! ```json
+//! {"images/disk.img": 67108864}
+//! ```
+//!
+//! Files listed in the manifest are split with their own blocksize, all others
+//! with the one given by `--blocksize`. The virtual directory of each listed file
+//! contains an additional `.scfs_blocksize` file with its blocksize, so that CatFS
+//! puts the chunks together correctly. The manifest itself is split like any other
+//! file.
+//!
+//! #### Fixed number of parts
+//!
+//! Some storage providers limit the number of objects rather than their size.
+//! With `--parts`, each file is split into at most the given number of chunks of
+//! equal size instead of chunks of a fixed blocksize:
+//!
+//! ```shell script
+//! scfs split --parts 8 mirror mountpoint
+//! ```
+//!
+//! Each file gets the smallest blocksize that results in at most that many
+//! chunks, so files smaller than the number of parts are split into chunks of a
+//! single byte, and empty files still get one empty chunk. Like with the manifest
+//! above, which still takes precedence for the files it lists, the virtual
+//! directory of each file contains a `.scfs_blocksize` file, so that CatFS puts
+//! the chunks together correctly. `--parts` cannot be combined with
+//! `--blocksize` or `--flat`, while a blocksize from `SCFS_BLOCKSIZE` is simply
+//! overridden.
+//!
+//! #### Overlapping chunks
+//!
+//! Some schemes to verify or resume transfers compare a few bytes on both sides of
+//! each chunk boundary. With `--overlap`, each chunk but the first starts with the
+//! given number of bytes from the end of the previous block:
+//!
+//! ```shell script
+//! scfs split --blocksize 4M --overlap 4K mirror mountpoint
+//! ```
+//!
+//! Chunks are thus larger than the blocksize by the overlap, and the
+//! `user.scfs.offset` and `user.scfs.length` attributes of each chunk include the
+//! repeated bytes. The overlap is stored in the config file, so that CatFS skips
+//! the repeated bytes when putting the files together again. For files whose
+//! blocksize is smaller than the overlap, each chunk repeats just the whole
+//! previous block.
+//!
+//! #### Marking chunked files
+//!
+//! In a mounted SplitFS, the virtual directories of chunked files look just like
+//! real directories. With `--mark-chunked`, they are listed with the additional
+//! suffix `.scfs`, or with the suffix given as `--mark-chunked=SUFFIX`:
+//!
+//! ```shell script
+//! scfs split --mark-chunked mirror mountpoint
+//! ```
+//!
+//! Both the marked and the original name can be used to access the chunks. The
+//! suffix is stored in `.scfs_config`, so CatFS presents the files under their
+//! original names again.
+//!
+//! #### Flat mirrors
+//!
+//! Some storage backends handle a single directory with many files better than a
+//! deep directory tree. With `--flat`, SplitFS lists the chunks of all files in
+//! its root directory, named after the path of their file:
+//!
+//! ```shell script
+//! scfs split --flat mirror mountpoint
+//! ```
+//!
+//! The chunk `scfs.0000000001` of the file `dir/my_file` is listed as
+//! `dir%2Fmy%5Ffile__scfs.0000000001`. All bytes of the path except ASCII letters,
+//! digits, `-`, `.` and `~` are percent-encoded, so the first `__` always
+//! separates the path from the chunk name. CatFS rebuilds the directory tree from
+//! these names.
+//!
+//! Only regular files are kept in a flat mirror. Symlinks, special files and empty
+//! directories get lost, and the directories presented by CatFS share the
+//! attributes of the mirror's root. As the whole path ends up in a single name,
+//! deeply nested files can exceed the name length limit of the filesystem, which
+//! is usually 255 bytes. `--flat` cannot be combined with the options that add
+//! further files to the chunks or leave files unsplit.
+//!
+//! #### Splitting only large files
+//!
+//! With `--min-split-size`, only files larger than the given size are split into
+//! chunks. Smaller files are presented unchanged as regular files:
+//!
+//! ```shell script
+//! scfs split --min-split-size 1M mirror mountpoint
+//! ```
+//!
+//! Small files that happen to be named like a chunk are still split, so CatFS can
+//! always tell the two apart. The threshold is stored in `.scfs_config`, CatFS
+//! needs no further options.
+//!
+//! #### Excluding files
+//!
+//! With `--exclude`, files and directories matching a glob pattern are left out
+//! of the mountpoint entirely. The option can be given multiple times. Patterns
+//! are matched against the path relative to the mirror, where `*` stays within a
+//! directory and `**` matches across directories:
+//!
+//! ```shell script
+//! scfs split --exclude '**/*.tmp' --exclude 'cache/**' mirror mountpoint
+//! ```
+//!
+//! #### Status file
+//!
+//! The root of the mountpoint contains a virtual file `.scfs_status` with runtime
+//! statistics as JSON: the number of files, the number of chunks, the number of
+//! currently open files and the bytes read from chunks and packs since mounting.
+//! The file is not listed in the directory, so copies of the mountpoint do not
+//! include it:
+//!
+//! ```shell script
+//! cat mountpoint/.scfs_status
+//! ```
+//!
+//! #### Reading ahead
+//!
+//! Sync tools usually read the chunks of a file one after the other. With
+//! `--readahead`, opening a chunk reads the given number of following chunks into
+//! memory in the background, so they can be served right away once they get
+//! opened:
+//!
+//! ```shell script
+//! scfs split --readahead 4 mirror mountpoint
+//! ```
+//!
+//! At most 64 MiB of chunks are kept in memory, the least recently used ones are
+//! dropped first. Chunks of files that changed in the meantime are read again. The
+//! status file reports how many chunks were served from memory as
+//! `readahead_hits`.
+//!
+//! #### Read buffer
+//!
+//! Reads of chunks are served from the files of the mirror through a buffer of
+//! 128 KiB, independent of the blocksize. With `--io-buffer`, the size of this
+//! buffer can be tuned to the storage of the mirror:
+//!
+//! ```shell script
+//! scfs split --io-buffer 1M mirror mountpoint
+//! ```
+//!
+//! The buffer never exceeds the size of the read request. Compressed chunks,
+//! packed files and chunks read ahead are always read in one go.
+//!
+//! #### Following symlinks
+//!
+//! Symlinks are presented as they are by default, so their targets are not part
+//! of the chunks. With `--follow-symlinks`, a symlink to a file is chunked like a
+//! regular file and a symlink to a directory is presented like a regular
+//! directory:
+//!
+//! ```shell script
+//! scfs split --follow-symlinks mirror mountpoint
+//! ```
+//!
+//! Broken symlinks and symlinks to one of their own parent directories, which
+//! would lead into an endless loop, are still presented as symlinks.
+//!
+//! #### Hard links
+//!
+//! Paths of the mirror that are hard links to the same file are still presented
+//! as separate directories, but they share their chunks. Each chunk is presented
+//! as one file with a link count of the number of paths, so a backup tool that
+//! preserves hard links, like `rsync -H`, stores it only once. CatFS presents the
+//! paths of such a file as hard links of each other again. Hard links to files
+//! that are not chunked, like packed files, and hard links in flat mirrors are
+//! still presented as independent files.
+//!
+//! #### Placeholders for unsupported files
+//!
+//! Sockets, and device files and FIFOs without `--special-files`, are left out of
+//! the mountpoint. With `--placeholder`, SplitFS presents each of them as a
+//! read-only text file with the additional extension `.unsupported`, which names
+//! the type and size of the original entry:
+//!
+//! ```shell script
+//! scfs split --placeholder mirror mountpoint
+//! ```
+//!
+//! This way, a backup of the mountpoint at least records that the entry existed.
+//! Placeholders are not chunked, so CatFS skips them with a warning, unless they
+//! are below the `--min-split-size` of the mirror.
+//!
+//! #### Sorted listings
+//!
+//! SplitFS lists the entries of a directory sorted by their names in the mirror,
+//! after the synthetic files like `.scfs_config` and `.count`. With
+//! `--mark-chunked`, the suffix is not taken into account. For tools that depend
+//! on listings sorted by the presented names, `--sort-entries` sorts each listing
+//! as a whole:
+//!
+//! ```shell script
+//! scfs split --sort-entries mirror mountpoint
+//! ```
+//!
+//! CatFS has no synthetic files, so its listings are always sorted by name.
+//!
+//! #### Dry run
+//!
+//! With `--dry-run`, SplitFS does not mount anything, but prints the chunks the
+//! mirror would be split into, one line per chunk. The tab-separated columns are
+//! the path of the file relative to the mirror, the size of the file, its number
+//! of chunks, the name of the chunk and the byte range of the chunk inside the
+//! file:
+//!
+//! ```shell script
+//! scfs split --dry-run --blocksize 4 mirror
+//! ```
+//!
+//! Files packed with `--pack-small` are not listed.
+//!
 //! ### CatFS
 //!
 //! ```text
@@ -149,9 +609,35 @@
 //!   [FUSE_OPTIONS_EXTRA]...  Additional options, which are passed down to FUSE
 //!
 //! Options:
+//!       --verify                       Refuse to mount if the chunks do not match the blocksize of the mirror
+//!       --allow-missing                Serve partly downloaded mirrors, reading a missing chunk fails with an I/O error
+//!       --strict                       Refuse to mount if the chunks do not match the tree digest of the mirror
+//!       --from-stdin                   Instead of mounting, read a chunk stream from stdin and write the file to stdout
+//!       --check                        Instead of mounting, check the mirror for consistency and report the result for each file
 //!   -o, --fuse-options <FUSE_OPTIONS>  Additional options, which are passed down to FUSE
 //!   -d, --daemon                       Run program in background
+//!       --pid-file <PATH>              Write the process ID of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+//!       --log-file <PATH>              Redirect the output of the daemon to this file, defaults to a file in $XDG_RUNTIME_DIR/scfs
+//!       --wait-ready                   Wait until the mountpoint can be used, with --daemon the parent process only exits then
+//!       --mount-timeout <SECONDS>      Seconds to wait for the mountpoint, including the time to index the mirror [default: 60]
 //!       --mkdir                        Create mountpoint directory if it does not exist already
+//!   -v, --verbose                      Log details about mounting and indexing the mirror
+//!   -q, --quiet                        Only log errors and do not show the indexing progress
+//!       --attr-ttl <SECONDS>           Seconds the kernel may cache file attributes and directory entries [default: 86400]
+//!       --subdir <PATH>                Present only this directory inside of the mirror, given relative to the mirror
+//!       --lenient-writes               Accept changes of permissions and times without applying them to the mirror
+//!       --ino-offset <N>               Shift all inodes but the one of the root by this much, to tell apart stacked mounts [default: 0]
+//!       --idle-timeout <SECONDS>       Unmount after this many seconds without any access, zero disables the timeout
+//!       --special-files                Present device files and FIFOs as they are instead of leaving them out
+//!       --noatime                      Do not update the access times of the mirrored files when reading them
+//!       --uid-map <FROM:TO>            Present files of the user ID FROM as owned by TO, a FROM of * maps all other IDs
+//!       --gid-map <FROM:TO>            Present files of the group ID FROM as owned by TO, a FROM of * maps all other IDs
+//!       --read-threads <THREADS>       Number of threads indexing the mirror and serving read requests, defaults to the number of CPUs
+//!       --single-threaded              Serve one request after the other on a single thread, which trades throughput for determinism
+//!       --max-open-files <FILES>       Keep at most this many files of the mirror open at once, further reads wait for them
+//!       --read-limit <BYTES>           Serve at most this many bytes per second across all reads, for example 10M
+//!       --config-name <NAME>           Name of the config file in the root of the mirror, defaults to .scfs_config
+//!       --fsname <NAME>                Name of the mounted file system in mount listings, for example to tell mirrors apart [default: scfs]
 //!   -h, --help                         Print help
 //!   -V, --version                      Print version
 //! ```
@@ -173,125 +659,700 @@
 //!
 //! The directory specified as `mount point` will now reflect the content of `base
 //! directory`, replacing each directory with chunked files in it as single files.
+//! Other files, which are not named like a chunk, like a `.DS_Store` or the
+//! leftovers of a download, are skipped with a warning.
 //!
-//! ### Additional FUSE mount options
+//! Relative symlinks pointing into a directory with chunked files, like to one of
+//! its chunks or with a trailing slash, point at the concatenated file instead.
+//! Absolute symlinks and symlinks leading out of the base directory are presented
+//! as they are.
 //!
-//! It is possible to pass additional mount options to the underlying FUSE
-//! library.
+//! #### Verifying chunks
 //!
-//! SCFS supports two ways of specifying options, either via the "-o" option, or
-//! via additional arguments after a "--" separator. This is in accordance to
-//! other FUSE based filesystems like EncFS.
+//! CatFS relies on all chunks but the last one of each file being exactly one
+//! blocksize in size. If chunks got truncated or lost, for example during a
+//! download, reading would return wrong data. With `--verify`, CatFS checks the
+//! sizes and the numbering of all chunks before mounting and refuses to mount an
+//! inconsistent mirror:
 //!
-//! These two calls are equivalent:
+//! ```shell script
+//! catfs --verify <base directory> <mount point>
+//! ```
+//!
+//! #### Tree digest
+//!
+//! The config file of a splitted mirror contains a digest of the relative paths
+//! and sizes of all chunks. CatFS computes the digest of the chunks it finds and
+//! warns if it differs, for example because chunks have been added, removed or
+//! renamed after the split. With `--strict`, CatFS refuses to mount such a mirror
+//! instead:
 //!
 //! ```shell script
-//! scfs split -o nonempty mirror mountpoint
-//! scfs split mirror mountpoint -- nonempty
+//! catfs --strict <base directory> <mount point>
 //! ```
 //!
-//! Of course, these methods also work in the `splitfs` and `catfs` binaries.
+//! The digest is not checked for partial mirrors and when mounting a
+//! subdirectory, since they do not contain all chunks. Configs of older mirrors do
+//! not contain a digest at all.
 //!
-//! ### Daemon mode
+//! #### Partial mirrors
 //!
-//! Originally, SCFS was meant to be run in the foreground. This proved to be
-//! annoying if one wants to use the same terminal for further work. Granted, one
-//! could always use features of their Shell to send the process to the
-//! background, but then you have a background process that might accidentally be
-//! killed if the user closes terminal. Furthermore, SCFS originally did not
-//! terminate cleanly if the user unmounted it by external means.
+//! Usually, CatFS expects all chunks to be present. With `--allow-missing`, a
+//! partly downloaded mirror can be mounted as well: a missing chunk leaves a gap
+//! in its file, reading from the gap fails with an I/O error, but all other parts
+//! of the file can be read as usual. This can not be combined with `--verify`.
 //!
-//! Since v0.9.0, SCFS natively supports daemon mode, in that the program changes
-//! its working directory to `"/"` and then forks itself into a true daemon
-//! process, independent of the running terminal.
+//! ```shell script
+//! scfs cat --allow-missing mirror mountpoint
+//! ```
+//!
+//! #### Sparse files
+//!
+//! Chunks that only contain zeros are reported as holes by CatFS, just like
+//! missing chunks in partial mirrors. Copy tools that look for holes with
+//! `SEEK_HOLE` and `SEEK_DATA`, like `cp --sparse=auto`, can thus make the copy of
+//! a disk image with large zero regions sparse again. The holes are detected in
+//! steps of whole chunks, so smaller blocksizes find more of them. Reading a hole
+//! still returns zeros.
+//!
+//! ### Self-diagnostic
+//!
+//! To find out whether a system can mount SCFS at all, run:
 //!
 //! ```shell script
-//! splitfs --daemon mirror mountpoint
+//! scfs doctor
 //! ```
 //!
-//! Note that `mirror` and `mountpoint` are resolved *before* changing the working
-//! directory, so they can still be given relative to the current working
-//! directory.
+//! This creates a small file in a temporary directory, mounts SplitFS over it and
+//! CatFS over the chunks, and compares the restored file with the original. Before
+//! that, it reports whether `/dev/fuse` is accessible and where `fusermount` is
+//! found. The result is printed as `PASS` or `FAIL` with the step that failed and,
+//! if possible, a hint on what is missing. The command fails if the file could not
+//! be restored.
 //!
-//! To unmount, `fusermount` can be used:
+//! ### Checking mirrors
+//!
+//! A mirror created by SplitFS can be checked for consistency without mounting
+//! it:
 //!
 //! ```shell script
-//! fusermount -u mountpoint
+//! scfs check mirror
 //! ```
 //!
-//! ## Limitations
+//! This is the same as `catfs --check mirror`. The config has to be valid and
+//! each chunked file has to consist of contiguously numbered chunks, all of them
+//! of the blocksize except the last one. The result is printed as `PASS` or
+//! `FAIL` for the config and for each chunked file, the command fails if any of
+//! the checks failed.
 //!
-//! I consider this project no longer a "raw prototype", and I am eating my own
-//! dog food, meaning I use it in my own backup strategies and create features
-//! based on my personal needs.
+//! ### Writing chunks to a directory
 //!
-//! However, this might not meet the needs of the typical user and without
-//! feedback I might not even think of some scenarios to begin with.
+//! Instead of presenting the chunks on a mount, they can also be written to a
+//! directory as real files, for example to feed them to an uploader that cannot
+//! read from a FUSE mount:
 //!
-//! Specifically, these are the current limitations of SCFS:
+//! ```shell script
+//! scfs split-to-dir --blocksize=1M mirror chunks
+//! ```
 //!
-//! -   It should work an all UNIX based systems, like Linux and maybe some MacOS
-//!     versions, however without MacOS specific file attributes. But definitely
-//!     not on Windows, since this would need special handling of system calls,
-//!     which I haven't had time to take care of yet.
+//! The output directory must be empty and must not be inside of the mirror. It
+//! then has exactly the layout SplitFS presents, including the config, the
+//! blocksize files, packs and files below the split size, so it can be mounted
+//! with CatFS or used just like a copy of a mounted SplitFS.
 //!
-//! -   It can only work with directories, regular files, and symlinks. Every
-//!     other file types (device files, pipes, and so on) will be silently
-//!     ignored.
+//! ### Restoring single files
 //!
-//! -   The base directory will be mounted read-only in the new mount point, and
-//!     SCFS expects that the base directory will not be altered while mounted.
-
-use std::ffi::{OsStr, OsString};
-use std::fs;
-use std::fs::Metadata;
-use std::os::unix::ffi::OsStrExt;
-use std::os::unix::ffi::OsStringExt;
-use std::os::unix::fs::MetadataExt;
-use std::path::Path;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-
-use fuser::{BackgroundSession, FileAttr, FileType, Filesystem, MountOption};
-use rusqlite::Row;
-use serde::{Deserialize, Serialize};
-
-pub use cli::Cli;
-
-pub(crate) use catfs::CatFS;
-pub(crate) use shared::Shared;
-pub(crate) use splitfs::SplitFS;
-
-mod catfs;
-mod cli;
-mod shared;
-mod splitfs;
-
-const TTL: Duration = Duration::from_secs(60 * 60 * 24);
-
-const STMT_CREATE: &str = "
-    CREATE TABLE Files (
-        ino INTEGER PRIMARY KEY,
-        parent_ino INTEGER,
-        path TEXT UNIQUE,
-        file_name TEXT,
-        part INTEGER,
-        vdir INTEGER,
-        symlink INTEGER
-    )
-";
-const STMT_CREATE_INDEX_PARENT_INO_FILE_NAME: &str = "
-    CREATE INDEX idx_parent_ino_file_name
-    ON Files (parent_ino, file_name)
-";
-const STMT_INSERT: &str = "
-    INSERT INTO Files (ino, parent_ino, path, file_name, part, vdir, symlink)
-    VALUES (?, ?, ?, ?, ?, ?, ?)
-";
-const STMT_QUERY_BY_INO: &str = "
-    SELECT *
-    FROM Files
-    WHERE ino = ?
-";
+//! A single file can be restored from a mirror created by SplitFS without
+//! mounting anything, which also works where FUSE is not available, like in CI
+//! pipelines:
+//!
+//! ```shell script
+//! scfs cat-to-stdout mirror path/to/file > file
+//! ```
+//!
+//! The path is relative to the root of the mirror. The mirror is indexed like
+//! CatFS would do it, and the chunks of the file are concatenated to stdout in
+//! order. The command fails if the file does not exist, is no regular file, or
+//! misses one of its chunks.
+//!
+//! ### Dumping the database
+//!
+//! For auditing and debugging, the internal database of a file system can be
+//! printed without mounting it. Each entry is printed as one JSON line with its
+//! inode, the inode of its parent, its path, its name, its part number and
+//! whether it is a virtual directory or a symlink:
+//!
+//! ```shell script
+//! scfs dump --blocksize 1M mirror
+//! scfs dump --mode cat mirror
+//! ```
+//!
+//! With `--mode split`, the default, the mirror is indexed like SplitFS would.
+//! With `--mode cat`, it is indexed like CatFS would.
+//!
+//! ### Planning the blocksize
+//!
+//! To find a good blocksize before mounting anything, `scfs plan` indexes a
+//! directory like SplitFS would and prints how many chunks its files are split
+//! into, so the number of parallel uploads can be estimated:
+//!
+//! ```shell script
+//! scfs plan --blocksize=1M mirror
+//! ```
+//!
+//! The output contains the number of files and chunks, the file with the most
+//! chunks and the minimum, median and maximum number of chunks per file:
+//!
+//! ```text
+//! Files: 4
+//! Chunks: 10
+//! Most chunks: 5 (b/d/e)
+//! Chunks per file: min 1, median 3, max 5
+//! ```
+//!
+//! ### Manifests for resuming uploads
+//!
+//! An uploader that copies the chunks from a SplitFS mountpoint can record its
+//! progress by chunk. `scfs manifest` indexes a directory like SplitFS would and
+//! prints one JSON line per chunk, with the path of the chunk relative to the
+//! mountpoint, the file it belongs to, its index and the byte range of the file it
+//! contains:
+//!
+//! ```shell script
+//! scfs manifest --mode=split --blocksize 1M mirror > manifest.jsonl
+//! ```
+//!
+//! The lines are ordered by file and index, so the same directory and blocksize
+//! always lead to the same manifest. After an aborted upload, the chunks that are
+//! missing at the destination can be looked up in the manifest and transferred
+//! again.
+//!
+//! ### Migrating mirrors
+//!
+//! Configs written by older versions of SplitFS lack some of the fields of the
+//! current format. `scfs migrate` fills in their defaults and rewrites the config
+//! of a mirror in the current format, printing each field that changed:
+//!
+//! ```shell script
+//! scfs migrate mirror
+//! ```
+//!
+//! A config that is already current is left as it is. Configs of a newer format
+//! than the installed version supports are rejected.
+//!
+//! Fields added to the config have defaults that describe older mirrors, and
+//! fields unknown to the installed version are ignored. The format version only
+//! increases for features that change how the chunks are put together, like
+//! `--overlap`, so that older versions of CatFS reject a mirror instead of
+//! presenting wrong files.
+//!
+//! ### Extended attributes
+//!
+//! The chunks of SplitFS carry extended attributes that tell where their content
+//! comes from: `user.scfs.source` is the path of the file in the mirror,
+//! `user.scfs.part` the index of the chunk, counting from zero like the chunk names,
+//! and `user.scfs.offset` and `user.scfs.length` the byte range of the file the
+//! chunk contains. In CatFS, reconstructed files have `user.scfs.parts` with the
+//! number of their chunks:
+//!
+//! ```shell script
+//! getfattr -d mountpoint/file/scfs.0000000001
+//! ```
+//!
+//! ### Special files
+//!
+//! Device files and FIFOs are left out by default. With `--special-files`, both
+//! SplitFS and CatFS present them as they are, without chunking them, so a backup
+//! of the mirror keeps them:
+//!
+//! ```shell script
+//! scfs split --special-files mirror mountpoint
+//! scfs cat --special-files mountpoint restored
+//! ```
+//!
+//! Device files can only be used on mountpoints with the `dev` FUSE option.
+//!
+//! ### Access times
+//!
+//! Reading through a mountpoint reads the mirrored files, which updates their
+//! access times. This causes writes during a backup and fails on read-only media.
+//! With `--noatime`, both SplitFS and CatFS open the mirrored files with
+//! `O_NOATIME`, so their access times stay untouched:
+//!
+//! ```shell script
+//! scfs split --noatime mirror mountpoint
+//! ```
+//!
+//! Only the owner of a file may do so. Files of other users are still read, but
+//! their access times are updated like before.
+//!
+//! ### Mapping owners
+//!
+//! Both file systems present the owners and groups of the mirrored files. When
+//! restoring a backup as another user, these may belong to accounts that do not
+//! exist. With `--uid-map` and `--gid-map`, an ID of the mirror is presented as
+//! another one, and `*` maps all IDs without a mapping of their own:
+//!
+//! ```shell script
+//! scfs cat --uid-map=1000:0 --gid-map='*:0' mirror mountpoint
+//! ```
+//!
+//! Both options can be given multiple times, IDs without any mapping are presented
+//! as they are.
+//!
+//! ### Limiting reads
+//!
+//! Reading a backup through a mountpoint can saturate a slow disk or a mirror on
+//! network storage. With `--read-limit`, both SplitFS and CatFS serve at most the
+//! given number of bytes per second, summed up over all open files:
+//!
+//! ```shell script
+//! scfs split --read-limit=10M mirror mountpoint
+//! ```
+//!
+//! Reads beyond the limit are delayed until it allows them again. Up to one second
+//! worth of bytes can be read at once after a pause, so short bursts are not
+//! slowed down.
+//!
+//! ### Limiting open files
+//!
+//! Every read opens the mirrored file it reads from. When a program reads
+//! thousands of files at once, the process can run out of file descriptors. With
+//! `--max-open-files`, both SplitFS and CatFS keep at most the given number of
+//! files of the mirror open at the same time:
+//!
+//! ```shell script
+//! scfs cat --max-open-files=256 mirror mountpoint
+//! ```
+//!
+//! Reads beyond the limit wait until another read has finished instead of
+//! failing. CatFS then closes each chunk after reading it, instead of keeping the
+//! recently used chunks of each open file open. Without the limit, reads that run
+//! out of file descriptors fail with `EMFILE`.
+//!
+//! ### Single-threaded mode
+//!
+//! Both SplitFS and CatFS serve reads on several threads, and the kernel may send
+//! several requests at once. For reproducible debugging, or for mirrors on
+//! storage that copes badly with concurrent access, `--single-threaded` serves
+//! each read on the thread of the FUSE session and indexes the mirror on one
+//! thread as well. Additionally, the kernel is asked to send only one
+//! asynchronous request at a time. This trades throughput for determinism and can
+//! not be combined with `--read-threads`:
+//!
+//! ```shell script
+//! scfs cat --single-threaded mirror mountpoint
+//! ```
+//!
+//! ### Config file name
+//!
+//! SplitFS presents its config as `.scfs_config` in the root of the mountpoint,
+//! and CatFS reads it from there. If that name is already taken on the backup
+//! target or gets lost there, choose another one with `--config-name`. The same
+//! name has to be given to CatFS and to the check of the mirror:
+//!
+//! ```shell script
+//! scfs split --config-name=scfs.json mirror mountpoint
+//! scfs cat --config-name=scfs.json mountpoint restored
+//! scfs check --config-name=scfs.json mountpoint
+//! ```
+//!
+//! ### File system name
+//!
+//! All mounts of SCFS are listed with the name `scfs` in `mount` and
+//! `/proc/mounts`. To tell several mounts apart, `--fsname` sets another name, for
+//! example the one of the mirror. The type of the mounts is `fuse.splitfs` or
+//! `fuse.catfs`, as long as they are mounted through `fusermount`:
+//!
+//! ```shell script
+//! scfs split --fsname photos-backup mirror mountpoint
+//! ```
+//!
+//! ### Presenting a subdirectory
+//!
+//! To only present a part of a large mirror, `--subdir` takes a directory relative
+//! to the mirror, which then becomes the root of the mount:
+//!
+//! ```shell script
+//! catfs --subdir home/user backup mountpoint
+//! ```
+//!
+//! The mirror itself stays the same, so CatFS still reads the config file from the
+//! root of the mirror, and the blocksize manifest and the `--exclude` patterns of
+//! SplitFS still refer to paths relative to the mirror. Flat mirrors keep all
+//! chunks in their root and cannot be mounted from a subdirectory.
+//!
+//! ### Lenient writes
+//!
+//! SCFS mounts are read-only, so archive tools that restore the times or
+//! permissions of extracted files fail on them. With `--lenient-writes`, the file
+//! system is mounted read-write and accepts such changes, for example through
+//! `touch` or `chmod`. They are only kept in memory until unmounting and never
+//! touch the mirror:
+//!
+//! ```shell script
+//! catfs --lenient-writes mirror mountpoint
+//! touch -d 2020-01-01 mountpoint/file
+//! ```
+//!
+//! Everything that would change the content or the entries, like writing,
+//! creating, renaming, or removing files, still fails with `EROFS`, and so do
+//! changes of the owner.
+//!
+//! ### Inode offset
+//!
+//! SplitFS and CatFS number the entries of their mounts starting from the same
+//! inode. When stacking mounts, for example CatFS on top of a SplitFS mount,
+//! tools that traverse across the mountpoints may mix up the entries of both. With
+//! `--ino-offset`, all inodes are shifted by the given amount, so that the stacked
+//! mounts occupy disjoint ranges:
+//!
+//! ```shell script
+//! splitfs mirror split
+//! catfs --ino-offset 1000000000 split cat
+//! ```
+//!
+//! The root of a mount always keeps inode 1, which the kernel expects for it, and
+//! the config file presented by SplitFS keeps its fixed inode as well.
+//!
+//! ### Preallocated inodes
+//!
+//! SplitFS numbers its entries in the order it indexes them, which depends on the
+//! order the directories of the mirror list their entries. With
+//! `--preallocate-inodes`, the inode of each entry and each chunk is derived from
+//! its path relative to the mirror instead, so that mounts of the same mirror on
+//! different runs or machines present the same inodes:
+//!
+//! ```shell script
+//! scfs split --preallocate-inodes mirror mountpoint
+//! ```
+//!
+//! The inodes are hashes, which stay clear of the fixed inodes of the root and the
+//! config file and of the inodes of synthetic files. In the rare case that two
+//! entries hash to the same inode, the one indexed later takes the next free
+//! inode. `--preallocate-inodes` cannot be combined with `--ino-offset`.
+//!
+//! ### Additional FUSE mount options
+//!
+//! It is possible to pass additional mount options to the underlying FUSE
+//! library.
+//!
+//! SCFS supports two ways of specifying options, either via the "-o" option, or
+//! via additional arguments after a "--" separator. This is in accordance to
+//! other FUSE based filesystems like EncFS.
+//!
+//! These two calls are equivalent:
+//!
+//! ```shell script
+//! scfs split -o nonempty mirror mountpoint
+//! scfs split mirror mountpoint -- nonempty
+//! ```
+//!
+//! Of course, these methods also work in the `splitfs` and `catfs` binaries.
+//!
+//! Several options can be given at once, separated by commas, and repeated
+//! options are only passed once. Options that contradict each other, like `exec`
+//! and `noexec`, are rejected before mounting. Since SCFS mounts read-only, `rw`
+//! is only accepted together with `--lenient-writes`, which in turn does not
+//! accept `ro`. Passing `nodev` together with `--special-files` only gives a
+//! warning, since device files are still listed, but cannot be opened.
+//!
+//! ### Daemon mode
+//!
+//! Originally, SCFS was meant to be run in the foreground. This proved to be
+//! annoying if one wants to use the same terminal for further work. Granted, one
+//! could always use features of their Shell to send the process to the
+//! background, but then you have a background process that might accidentally be
+//! killed if the user closes terminal. Furthermore, SCFS originally did not
+//! terminate cleanly if the user unmounted it by external means.
+//!
+//! Since v0.9.0, SCFS natively supports daemon mode, in that the program changes
+//! its working directory to `"/"` and then forks itself into a true daemon
+//! process, independent of the running terminal.
+//!
+//! ```shell script
+//! splitfs --daemon mirror mountpoint
+//! ```
+//!
+//! Note that `mirror` and `mountpoint` are resolved *before* changing the working
+//! directory, so they can still be given relative to the current working
+//! directory.
+//!
+//! By default, the daemon is started before the mirror is indexed and mounted, so
+//! the mountpoint may not be usable yet when the command returns. For scripts,
+//! `--wait-ready` makes the command only return once the file system is mounted
+//! and its root can be accessed. If that takes longer than `--mount-timeout`
+//! seconds, 60 by default, the command fails:
+//!
+//! ```shell script
+//! splitfs --daemon --wait-ready mirror mountpoint
+//! cat mountpoint/file/scfs.0000000000
+//! ```
+//!
+//! Without `--daemon`, the option fails the same way if the mountpoint does not
+//! get ready in time.
+//!
+//! To unmount, `fusermount` can be used:
+//!
+//! ```shell script
+//! fusermount -u mountpoint
+//! ```
+//!
+//! The daemon writes its process ID to a PID file and redirects its output,
+//! including the log, to a log file. By default, both are put into
+//! `$XDG_RUNTIME_DIR/scfs` and named after the mountpoint, for example
+//! `splitfs-home-user-mountpoint.pid`. Without a runtime directory, no PID file is
+//! written and the output is discarded. Init scripts can pass their own paths
+//! with `--pid-file` and `--log-file`:
+//!
+//! ```shell script
+//! splitfs --daemon --pid-file /run/splitfs.pid --log-file /var/log/splitfs.log mirror mountpoint
+//! kill "$(cat /run/splitfs.pid)"
+//! ```
+//!
+//! The PID file is removed once the file system has been unmounted.
+//!
+//! ### Logging
+//!
+//! SCFS logs warnings to stderr, for example about files in the mirror that
+//! cannot be presented, like pipes or device files. With `--verbose`, it
+//! additionally logs details about indexing and mounting, while `--quiet` only
+//! leaves errors and hides the indexing progress. For finer control, the
+//! `RUST_LOG` environment variable is respected as well.
+//!
+//! ### Idle timeout
+//!
+//! With `--idle-timeout`, SCFS unmounts itself once the file system has not been
+//! accessed for the given number of seconds. This comes in handy for scripted
+//! workflows, where the mountpoint is only needed while another program runs:
+//!
+//! ```shell script
+//! scfs split --daemon --idle-timeout 600 mirror mountpoint
+//! ```
+//!
+//! ### Streaming mode
+//!
+//! For pipelines, where the chunks are consumed right away, a single file can be
+//! split without mounting anything. With `--to-stdout`, the chunks are written
+//! back-to-back to stdout, and `--from-stdin` reverses this:
+//!
+//! ```shell script
+//! scfs split --to-stdout big.img | uploader
+//! downloader | scfs cat --from-stdin > big.img
+//! ```
+//!
+//! All lengths in the stream are unsigned 64 bit big-endian integers. The stream
+//! starts with the magic bytes `SCFS`, followed by the length of the config and
+//! the config itself as JSON, the same as in `.scfs_config`. Then, for each chunk,
+//! the length of the chunk is followed by the chunk data. A length of zero marks
+//! the end of the stream.
+//!
+//! ### Library usage
+//!
+//! SCFS can also be embedded into other programs. The functions `mount_split` and
+//! `mount_cat` mount the respective file system and return a session handle. The
+//! file system stays mounted as long as the handle is alive. Dropping the handle
+//! unmounts the file system, which then shuts down in the background, while
+//! `unmount` waits for the shutdown and returns the error that ended the session:
+//!
+//! ```rust,no_run
+//! let session = scfs::mount_split("mirror", scfs::Config::default(), "mountpoint", ["allow_other"])
+//!     .expect("Failed to mount");
+//! session.unmount().expect("Failed to unmount");
+//! ```
+//!
+//! For chunks kept in a remote storage, `mount_cat_with_source` takes an
+//! implementation of the `ChunkSource` trait. Whenever an indexed chunk is missing
+//! in the mirror, CatFS calls its `fetch` method with the path of the chunk, which
+//! returns the path of a local copy, for example after downloading it. Each chunk
+//! is only fetched once per mount, and only when it is read. Chunks that are not in
+//! the mirror at all are indexed from the `list` method, which returns their paths
+//! in the mirror together with the sizes of their content.
+//!
+//! Without mounting anything, `chunk_ranges` returns the byte ranges of the chunks
+//! SplitFS would split a file into, so that programs like custom uploaders can
+//! read the chunks directly from the file:
+//!
+//! ```rust,no_run
+//! for range in scfs::chunk_ranges("file", 1024).expect("Failed to read file") {
+//!     println!("chunk {}: {} bytes at {}", range.index, range.len, range.offset);
+//! }
+//! ```
+//!
+//! The config of a mirror is read and checked by `Config::from_mirror`, just like
+//! CatFS does before mounting. Its `ConfigError` tells a missing config apart from
+//! one that cannot be read, contains invalid JSON or cannot be used:
+//!
+//! ```rust,no_run
+//! match scfs::Config::from_mirror("mirror") {
+//!     Ok(config) => println!("{:?}", config),
+//!     Err(scfs::ConfigError::Missing(path)) => println!("no mirror, {} is missing", path.display()),
+//!     Err(e) => panic!("{}", e),
+//! }
+//! ```
+//!
+//! SplitFS can also split a mirror that does not exist on disk. The function
+//! `mount_split_with_source` takes an implementation of the `MirrorSource` trait,
+//! which lists directories, returns the metadata of entries and opens files. The
+//! bundled `MemoryMirror` keeps the whole mirror in memory, which is handy for tests
+//! and for splitting data that comes from somewhere else:
+//!
+//! ```rust,no_run
+//! let mut mirror = scfs::MemoryMirror::new("/mirror");
+//! mirror.add_file("/mirror/file", vec![0; 4096]);
+//! let config = scfs::Config::default().blocksize(1024).unwrap();
+//! let session = scfs::mount_split_with_source("/mirror", config, "mountpoint", ["ro"], mirror)
+//!     .expect("Failed to mount");
+//! ```
+//!
+//! ## Limitations
+//!
+//! I consider this project no longer a "raw prototype", and I am eating my own
+//! dog food, meaning I use it in my own backup strategies and create features
+//! based on my personal needs.
+//!
+//! However, this might not meet the needs of the typical user and without
+//! feedback I might not even think of some scenarios to begin with.
+//!
+//! Specifically, these are the current limitations of SCFS:
+//!
+//! -   It should work an all UNIX based systems, like Linux and maybe some MacOS
+//!     versions, where the birthtime and file flags are passed through. But
+//!     definitely not on Windows yet. Mounting goes through a backend of the
+//!     platform, which is FUSE on UNIX, but the WinFsp backend for Windows is only
+//!     a placeholder so far and refuses to mount.
+//!
+//! -   It can only work with directories, regular files, and symlinks, and with
+//!     device files and FIFOs if `--special-files` is given. Every other file
+//!     type (sockets and so on) will be skipped with a warning.
+//!
+//! -   The base directory will be mounted read-only in the new mount point, and
+//!     SCFS expects that the base directory will not be altered while mounted.
+//!     Since there is nothing to write back, `fsync` and `flush` always succeed.
+//!     With `--mirror-change-detection`, SplitFS at least refuses to serve files
+//!     that have been changed since they were indexed.
+//!
+//! -   Operations beyond reading are answered deliberately: `fallocate` fails
+//!     with `EROFS`, `ioctl` with `ENOTTY` and `bmap` with `EOPNOTSUPP`. Polling
+//!     is not supported by the underlying FUSE library, so the kernel always
+//!     reports the files as ready, just like regular files on disk.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::fs::{File, Metadata, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use fuser::{KernelConfig, ReplyDirectory};
+use log::debug;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+pub use cli::Cli;
+pub use fetch::{ChunkSource, LocalChunks};
+pub use fuser::{BackgroundSession, FileAttr, FileType};
+pub use source::{LocalMirror, MemoryMirror, MirrorFile, MirrorMetadata, MirrorSource};
+
+pub(crate) use backend::{Backend, Mount, Platform};
+pub(crate) use catfs::CatFS;
+pub(crate) use descriptors::OpenFileLimit;
+pub(crate) use fetch::ChunkFetcher;
+pub(crate) use idle::IdleTimer;
+pub(crate) use overrides::AttrOverrides;
+pub(crate) use pool::ThreadPool;
+pub(crate) use prefetch::PrefetchedMirror;
+pub(crate) use readahead::{ChunkCache, ChunkStamp};
+pub(crate) use reads::ReadStats;
+pub(crate) use shared::Shared;
+pub(crate) use splitfs::{Exclude, SplitFS};
+pub(crate) use throttle::ReadLimiter;
+pub(crate) use verify::SelfVerifier;
+
+mod backend;
+mod catfs;
+mod cli;
+mod descriptors;
+mod fetch;
+mod idle;
+mod overrides;
+mod pool;
+mod prefetch;
+mod readahead;
+mod reads;
+mod shared;
+mod source;
+mod splitfs;
+mod stream;
+mod throttle;
+mod verify;
+
+const TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+const STMT_CREATE: &str = "
+    CREATE TABLE Files (
+        ino INTEGER PRIMARY KEY,
+        parent_ino INTEGER,
+        path TEXT UNIQUE,
+        file_name TEXT,
+        part INTEGER,
+        vdir INTEGER,
+        symlink INTEGER
+    )
+";
+const STMT_CREATE_INDEX_PARENT_INO_FILE_NAME: &str = "
+    CREATE INDEX idx_parent_ino_file_name
+    ON Files (parent_ino, file_name)
+";
+const STMT_INSERT: &str = "
+    INSERT INTO Files (ino, parent_ino, path, file_name, part, vdir, symlink)
+    VALUES (?, ?, ?, ?, ?, ?, ?)
+";
+const STMT_CREATE_LINKS: &str = "
+    CREATE TABLE Links (
+        ino INTEGER PRIMARY KEY,
+        target_ino INTEGER
+    )
+";
+const STMT_INSERT_LINK: &str = "
+    INSERT INTO Links (ino, target_ino)
+    VALUES (?, ?)
+";
+const STMT_QUERY_LINK_TARGET: &str = "
+    SELECT target_ino
+    FROM Links
+    WHERE ino = ?
+";
+const STMT_COUNT_LINKS: &str = "
+    SELECT COUNT(*)
+    FROM Links
+    WHERE target_ino = ?
+";
+const STMT_QUERY_LINKS: &str = "
+    SELECT ino
+    FROM Links
+    WHERE target_ino = ?
+    ORDER BY ino
+";
+const STMT_QUERY_BY_INO: &str = "
+    SELECT *
+    FROM Files
+    WHERE ino = ?
+";
 const STMT_QUERY_BY_PARENT_INO: &str = "
     SELECT *
     FROM Files
@@ -304,241 +1365,2063 @@ const STMT_QUERY_BY_PARENT_INO_AND_FILENAME: &str = "
     WHERE parent_ino = ?
     AND file_name = ?
 ";
+const STMT_COUNT_BY_PARENT_INO: &str = "
+    SELECT COUNT(*)
+    FROM Files
+    WHERE parent_ino = ?
+    AND CAST(file_name AS TEXT) NOT LIKE '%.sha256'
+";
+const STMT_COUNT_ALL: &str = "
+    SELECT COUNT(*)
+    FROM Files
+";
+const STMT_COUNT_FILES: &str = "
+    SELECT COUNT(*)
+    FROM Files
+    WHERE vdir
+";
+const STMT_COUNT_CHUNKS: &str = "
+    SELECT COUNT(*)
+    FROM Files
+    WHERE part != 0
+    AND CAST(file_name AS TEXT) NOT LIKE '%.sha256'
+";
+const STMT_QUERY_ALL: &str = "
+    SELECT *
+    FROM Files
+    ORDER BY ino
+";
+const STMT_QUERY_PARTS: &str = "
+    SELECT *
+    FROM Files
+    WHERE part != 0
+    ORDER BY parent_ino, part
+";
+const STMT_CREATE_STAMPS: &str = "
+    CREATE TABLE Stamps (
+        ino INTEGER PRIMARY KEY,
+        size INTEGER,
+        mtime INTEGER,
+        mtime_nsec INTEGER
+    )
+";
+const STMT_INSERT_STAMP: &str = "
+    INSERT INTO Stamps (ino, size, mtime, mtime_nsec)
+    VALUES (?, ?, ?, ?)
+";
+const STMT_QUERY_STAMP: &str = "
+    SELECT size, mtime, mtime_nsec
+    FROM Stamps
+    WHERE ino = ?
+";
+const STMT_DROP: &str = "
+    DROP TABLE IF EXISTS Files;
+    DROP TABLE IF EXISTS Links;
+    DROP TABLE IF EXISTS Meta;
+    DROP TABLE IF EXISTS Stamps;
+";
+const STMT_CREATE_META: &str = "
+    CREATE TABLE Meta (
+        mirror BLOB,
+        mtime INTEGER,
+        mtime_nsec INTEGER,
+        config TEXT,
+        checksums INTEGER,
+        exclude TEXT,
+        follow_symlinks INTEGER,
+        special_files INTEGER,
+        placeholder INTEGER,
+        blocksizes TEXT,
+        ino_offset INTEGER,
+        preallocate_inodes INTEGER,
+        tree_digest TEXT
+    )
+";
+const STMT_INSERT_META: &str = "
+    INSERT INTO Meta (
+        mirror, mtime, mtime_nsec, config, checksums, exclude, follow_symlinks, special_files,
+        placeholder, blocksizes, ino_offset, preallocate_inodes
+    )
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+";
+const STMT_QUERY_META: &str = "
+    SELECT
+        mirror, mtime, mtime_nsec, config, checksums, exclude, follow_symlinks, special_files,
+        placeholder, blocksizes, ino_offset, preallocate_inodes
+    FROM Meta
+";
+const STMT_QUERY_TREE_DIGEST: &str = "
+    SELECT tree_digest
+    FROM Meta
+";
+const STMT_UPDATE_TREE_DIGEST: &str = "
+    UPDATE Meta
+    SET tree_digest = ?
+";
+
+const CONFIG_FILE_NAME: &str = ".scfs_config";
+const COUNT_FILE_NAME: &str = ".count";
+const STATUS_FILE_NAME: &str = ".scfs_status";
+const META_FILE_NAME: &str = ".scfs_meta";
+const READS_FILE_NAME: &str = ".scfs_reads";
+const WHOLE_FILE_NAME: &str = ".whole";
+const BLOCKSIZE_FILE_NAME: &str = ".scfs_blocksize";
+const BLOCKSIZES_MANIFEST_NAME: &str = ".scfs_blocksizes";
+const CHECKSUM_SUFFIX: &str = ".sha256";
+const PLACEHOLDER_SUFFIX: &str = ".unsupported";
+const FLAT_SEPARATOR: &str = "__";
+const PACK_INDEX_FILE_NAME: &str = ".scfs_packs";
+const PACK_FILE_PREFIX: &str = "scfs.pack.";
+
+// The name of the mounted file system in mount listings, unless another one is given
+const DEFAULT_FSNAME: &str = "scfs";
+
+// Extended attributes of chunks in SplitFS and of reconstructed files in CatFS
+const XATTR_SOURCE: &str = "user.scfs.source";
+const XATTR_PART: &str = "user.scfs.part";
+const XATTR_OFFSET: &str = "user.scfs.offset";
+const XATTR_LENGTH: &str = "user.scfs.length";
+const XATTR_PARTS: &str = "user.scfs.parts";
+
+const CONFIG_DEFAULT_BLOCKSIZE: u64 = 2 * 1024 * 1024;
+
+// Number of files between two progress reports while populating the database.
+const PROGRESS_INTERVAL: u64 = 1000;
+
+// Upper bound for the bytes of chunks that have been read ahead.
+const READAHEAD_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Capacity of the buffer SplitFS reads chunks from the mirror with, unless set otherwise.
+const IO_BUFFER_DEFAULT_SIZE: usize = 128 * 1024;
+
+// Has to be increased whenever the layout of a splitted mirror changes in a way that older
+// versions of CatFS cannot handle, see Config for the rules. The third format adds overlapping
+// chunks, whose repeated bytes older versions would not skip. The fourth format adds chunk names
+// with offsets, which older versions would not recognize as chunks.
+const CONFIG_FORMAT_VERSION: u32 = 4;
+
+const INO_OUTSIDE: u64 = 0;
+const INO_ROOT: u64 = 1;
+const INO_CONFIG: u64 = 2;
+const INO_STATUS: u64 = 3;
+
+const INO_FIRST_FREE: u64 = 10;
+
+// Upper bound for --ino-offset, which keeps the inodes of entries clear of the flag bits below.
+const INO_OFFSET_MAX: u64 = 1 << 52;
+
+// Lets the kernel send only one asynchronous request at a time, like reads ahead of the
+// current position. It is negotiated when the session is initialized, there is no mount option
+// for it.
+fn limit_background_requests(config: &mut KernelConfig) {
+    config.set_max_background(1).unwrap_or(1);
+    config.set_congestion_threshold(1).unwrap_or(1);
+}
+
+// Synthetic files inside of virtual directories are not stored in the database. Their inode is
+// derived from the inode of the containing directory by setting a flag bit.
+const INO_FLAG_COUNT: u64 = 1 << 62;
+const INO_FLAG_META: u64 = 1 << 61;
+const INO_FLAG_BLOCKSIZE: u64 = 1 << 60;
+const INO_FLAG_READS: u64 = 1 << 59;
+const INO_FLAG_WHOLE: u64 = 1 << 58;
+
+// Preallocated inodes are derived from paths and stay below all of the flag bits.
+const INO_PREALLOCATED_END: u64 = INO_FLAG_WHOLE;
+
+type DropHookFn = Box<dyn Fn() + Send + Sync + 'static>;
+
+fn system_time_from_time(secs: i64, nsecs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32)
+    } else {
+        UNIX_EPOCH - Duration::new((-secs) as u64, nsecs as u32)
+    }
+}
+
+// The inverse of system_time_from_time.
+fn time_from_system_time(time: SystemTime) -> (i64, i64) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos() as i64),
+        Err(e) => (
+            -(e.duration().as_secs() as i64),
+            e.duration().subsec_nanos() as i64,
+        ),
+    }
+}
+
+fn convert_filetype(ft: fs::FileType) -> Option<FileType> {
+    if ft.is_dir() {
+        Some(FileType::Directory)
+    } else if ft.is_file() {
+        Some(FileType::RegularFile)
+    } else if ft.is_symlink() {
+        Some(FileType::Symlink)
+    } else if ft.is_char_device() {
+        Some(FileType::CharDevice)
+    } else if ft.is_block_device() {
+        Some(FileType::BlockDevice)
+    } else if ft.is_fifo() {
+        Some(FileType::NamedPipe)
+    } else {
+        None
+    }
+}
+
+// Device files and FIFOs are only presented with the special_files setting, as they are and
+// without being chunked.
+fn is_special_file(kind: FileType) -> bool {
+    matches!(
+        kind,
+        FileType::CharDevice | FileType::BlockDevice | FileType::NamedPipe
+    )
+}
+
+// The file type of a special file, without looking at the database, which does not know about it.
+fn special_file_kind<P: AsRef<Path>>(path: P) -> Option<FileType> {
+    let kind = convert_filetype(fs::metadata(path).ok()?.file_type())?;
+    is_special_file(kind).then_some(kind)
+}
+
+// Returns None for file types FUSE cannot present. Even if the type has been checked while
+// populating, the file may have been replaced by an unsupported one since.
+fn convert_metadata_to_attr(meta: Metadata, ino: Option<u64>) -> Option<FileAttr> {
+    Some(FileAttr {
+        ino: if let Some(ino) = ino { ino } else { meta.ino() },
+        size: meta.size(),
+        blocks: meta.blocks(),
+        atime: system_time_from_time(meta.atime(), meta.atime_nsec()),
+        mtime: system_time_from_time(meta.mtime(), meta.mtime_nsec()),
+        ctime: system_time_from_time(meta.ctime(), meta.ctime_nsec()),
+        // The birthtime, if the platform and the file system of the mirror provide it
+        crtime: meta.created().unwrap_or(system_time_from_time(0, 0)),
+        kind: convert_filetype(meta.file_type())?,
+        perm: meta.mode() as u16,
+        nlink: meta.nlink() as u32,
+        uid: meta.uid(),
+        gid: meta.gid(),
+        rdev: meta.rdev() as u32,
+        blksize: meta.blksize() as u32,
+        flags: file_flags(&meta),
+    })
+}
+
+// The BSD file flags like UF_HIDDEN, which only exist on macOS.
+#[cfg(target_os = "macos")]
+fn file_flags(meta: &Metadata) -> u32 {
+    std::os::macos::fs::MetadataExt::st_flags(meta)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn file_flags(_meta: &Metadata) -> u32 {
+    0
+}
+
+// Mounts through the backend of the platform, see Backend.
+fn mount<'b, FS, P, I>(
+    filesystem: FS,
+    mountpoint: &P,
+    options: I,
+) -> io::Result<<Platform as Backend>::Session>
+where
+    Platform: Mount<FS>,
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'b OsStr>,
+{
+    let options: Vec<_> = options.into_iter().collect();
+    Platform::mount(filesystem, mountpoint.as_ref(), &options)
+}
+
+/// The reasons why a file system cannot be created for a mirror.
+#[derive(Debug)]
+pub enum MountError {
+    /// The config file of the mirror does not exist.
+    MissingConfig(PathBuf),
+
+    /// The config file or the blocksize manifest of the mirror does not contain valid JSON.
+    InvalidJson(PathBuf, serde_json::Error),
+
+    /// The config cannot be used, for example because of an unsupported format version.
+    InvalidConfig(String),
+
+    /// The parts of a file in the mirror do not fit together.
+    Inconsistent(String),
+
+    /// The mirror cannot be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for MountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MountError::MissingConfig(path) => {
+                write!(f, "SCFS config file not found: {}", path.display())
+            }
+            MountError::InvalidJson(path, e) => {
+                write!(f, "{} contains invalid JSON: {}", path.display(), e)
+            }
+            MountError::InvalidConfig(message) => f.write_str(message),
+            MountError::Inconsistent(message) => {
+                write!(f, "SCFS mirror is inconsistent: {}", message)
+            }
+            MountError::Io(e) => write!(f, "SCFS mirror not readable: {}", e),
+        }
+    }
+}
+
+impl Error for MountError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MountError::InvalidJson(_, e) => Some(e),
+            MountError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MountError {
+    fn from(e: io::Error) -> Self {
+        MountError::Io(e)
+    }
+}
+
+// The mount functions report all errors as io::Error, the MountError stays available as its inner
+// error.
+impl From<MountError> for io::Error {
+    fn from(e: MountError) -> Self {
+        match e {
+            MountError::MissingConfig(_) => io::Error::new(io::ErrorKind::NotFound, e),
+            MountError::Io(e) => e,
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// The reasons why the config of a mirror cannot be read by [`Config::from_mirror`] or
+/// [`Config::from_file`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file does not exist.
+    Missing(PathBuf),
+
+    /// The config file cannot be read.
+    Io(PathBuf, io::Error),
+
+    /// The config file does not contain valid JSON.
+    InvalidJson(PathBuf, serde_json::Error),
+
+    /// The config cannot be used, for example because of an unsupported format version.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(path) => {
+                write!(f, "SCFS config file not found: {}", path.display())
+            }
+            ConfigError::Io(path, e) => {
+                write!(f, "SCFS config file {} not readable: {}", path.display(), e)
+            }
+            ConfigError::InvalidJson(path, e) => {
+                write!(f, "{} contains invalid JSON: {}", path.display(), e)
+            }
+            ConfigError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::InvalidJson(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for MountError {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            ConfigError::Missing(path) => MountError::MissingConfig(path),
+            ConfigError::Io(_, e) => MountError::Io(e),
+            ConfigError::InvalidJson(path, e) => MountError::InvalidJson(path, e),
+            ConfigError::Invalid(message) => MountError::InvalidConfig(message),
+        }
+    }
+}
+
+/// A file system mounted by [`mount_split`], [`mount_cat`] or [`mount_cat_with_source`].
+///
+/// Dropping the session unmounts the file system, which then shuts down in the background. To
+/// wait for the shutdown, for example before removing the mirror, use [`Session::unmount`].
+#[derive(Debug)]
+pub struct Session {
+    session: <Platform as Backend>::Session,
+}
+
+impl Session {
+    fn new(session: <Platform as Backend>::Session) -> Self {
+        Session { session }
+    }
+
+    /// The path the file system is mounted at.
+    pub fn mountpoint(&self) -> &Path {
+        Platform::mountpoint(&self.session)
+    }
+
+    /// Unmounts the file system and waits until it has shut down. Returns the error that ended
+    /// the session, if any.
+    pub fn unmount(self) -> io::Result<()> {
+        let mountpoint = self.mountpoint().to_path_buf();
+        let result = Platform::unmount(self.session);
+        debug!("Unmounted {:?}", mountpoint);
+        result
+    }
+}
+
+/// Mounts a splitting file system of `mirror` at `mountpoint`.
+///
+/// The file system stays mounted as long as the returned session is alive, dropping it unmounts
+/// the file system again. The `options` are passed down to FUSE, for example `allow_other`. An
+/// error is returned if the file system cannot be mounted, for example because FUSE is not
+/// available, or if the mirror cannot be used, in which case it contains a [`MountError`].
+///
+/// ```no_run
+/// use scfs::{mount_split, Config};
+///
+/// let config = Config::default().blocksize(1024).unwrap();
+/// let session = mount_split("mirror", config, "mountpoint", ["allow_other"]).unwrap();
+///
+/// // Work with the files in "mountpoint" ...
+///
+/// session.unmount().unwrap();
+/// ```
+pub fn mount_split<P, Q, I, S>(
+    mirror: P,
+    config: Config,
+    mountpoint: Q,
+    options: I,
+) -> io::Result<Session>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let options = options
+        .into_iter()
+        .map(|option| option.as_ref().to_os_string())
+        .collect::<Vec<_>>();
+
+    let fs = SplitFS::new(
+        mirror.as_ref().as_os_str(),
+        config,
+        Settings::default(),
+        Box::new(|| ()),
+    )?;
+    mount(fs, &mountpoint, options.iter().map(OsString::as_os_str)).map(Session::new)
+}
+
+/// Like [`mount_split`], but splits the entries provided by `source` instead of the ones on disk.
+///
+/// The `mirror` is the path of the root directory in the source. This way, data that is not
+/// stored in a directory can be split, for example a [`MemoryMirror`]:
+///
+/// ```no_run
+/// use scfs::{mount_split_with_source, Config, MemoryMirror};
+///
+/// let mut mirror = MemoryMirror::new("/mirror");
+/// mirror.add_file("/mirror/file", vec![0; 4096]);
+///
+/// let config = Config::default().blocksize(1024).unwrap();
+/// let session =
+///     mount_split_with_source("/mirror", config, "mountpoint", Vec::<String>::new(), mirror)
+///         .unwrap();
+/// ```
+pub fn mount_split_with_source<P, Q, I, S, M>(
+    mirror: P,
+    config: Config,
+    mountpoint: Q,
+    options: I,
+    source: M,
+) -> io::Result<Session>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+    M: MirrorSource + 'static,
+{
+    let options = options
+        .into_iter()
+        .map(|option| option.as_ref().to_os_string())
+        .collect::<Vec<_>>();
+
+    let fs = SplitFS::new(
+        mirror.as_ref().as_os_str(),
+        config,
+        Settings::default().mirror_source(Some(Arc::new(source))),
+        Box::new(|| ()),
+    )?;
+    mount(fs, &mountpoint, options.iter().map(OsString::as_os_str)).map(Session::new)
+}
+
+/// Mounts a concatenating file system of `mirror` at `mountpoint`.
+///
+/// The `mirror` has to be a splitted directory, or a copy of it, containing the `.scfs_config`
+/// file. Like with [`mount_split`], dropping the returned session unmounts the file system. If the
+/// mirror cannot be used, the returned error contains a [`MountError`] with the reason.
+///
+/// ```no_run
+/// use scfs::mount_cat;
+///
+/// let session = mount_cat("mirror", "mountpoint", Vec::<String>::new()).unwrap();
+///
+/// // Work with the files in "mountpoint" ...
+///
+/// drop(session);
+/// ```
+pub fn mount_cat<P, Q, I, S>(mirror: P, mountpoint: Q, options: I) -> io::Result<Session>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let options = options
+        .into_iter()
+        .map(|option| option.as_ref().to_os_string())
+        .collect::<Vec<_>>();
+
+    let fs = CatFS::new(
+        mirror.as_ref().as_os_str(),
+        Settings::default(),
+        Box::new(|| ()),
+    )?;
+    mount(fs, &mountpoint, options.iter().map(OsString::as_os_str)).map(Session::new)
+}
+
+/// Like [`mount_cat`], but fetches chunks that are missing in `mirror` from `source`.
+///
+/// This way, the chunks can be kept in a remote storage and are only downloaded once they are
+/// read. Chunks the mirror does not contain while mounting are indexed from
+/// [`ChunkSource::list`], and the sizes of all chunks are recorded then, so listing and stating
+/// files never fetches a chunk.
+///
+/// ```no_run
+/// use std::fs;
+/// use std::io;
+/// use std::path::{Path, PathBuf};
+///
+/// use scfs::{mount_cat_with_source, ChunkSource};
+///
+/// // Copies missing chunks from a staging directory, which stands in for the remote storage
+/// struct Staging {
+///     mirror: PathBuf,
+///     staging: PathBuf,
+///     cache: PathBuf,
+/// }
+///
+/// impl ChunkSource for Staging {
+///     fn fetch(&self, path: &Path) -> io::Result<PathBuf> {
+///         let relative = path.strip_prefix(&self.mirror).map_err(io::Error::other)?;
+///         let local = self.cache.join(relative);
+///         fs::create_dir_all(local.parent().unwrap())?;
+///         fs::copy(self.staging.join(relative), &local)?;
+///         Ok(local)
+///     }
+/// }
+///
+/// let source = Staging {
+///     mirror: PathBuf::from("mirror"),
+///     staging: PathBuf::from("staging"),
+///     cache: PathBuf::from("cache"),
+/// };
+/// let session =
+///     mount_cat_with_source("mirror", "mountpoint", Vec::<String>::new(), source).unwrap();
+/// ```
+pub fn mount_cat_with_source<P, Q, I, S, C>(
+    mirror: P,
+    mountpoint: Q,
+    options: I,
+    source: C,
+) -> io::Result<Session>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+    C: ChunkSource + 'static,
+{
+    let options = options
+        .into_iter()
+        .map(|option| option.as_ref().to_os_string())
+        .collect::<Vec<_>>();
+
+    let fs = CatFS::new(
+        mirror.as_ref().as_os_str(),
+        Settings::default().chunk_source(Some(Arc::new(source))),
+        Box::new(|| ()),
+    )?;
+    mount(fs, &mountpoint, options.iter().map(OsString::as_os_str)).map(Session::new)
+}
+
+// Builds the name of a chunk in a flat mirror: the path of its file relative to the mirror, with
+// all bytes except ASCII letters, digits, '-', '.' and '~' percent-encoded, followed by
+// FLAT_SEPARATOR and the name of the chunk. Since '_' is encoded as well, the first separator
+// always ends the path.
+fn flat_name(relative: &Path, chunk_name: &OsStr) -> OsString {
+    let mut name = Vec::new();
+    for byte in relative.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-.~".contains(byte) {
+            name.push(*byte);
+        } else {
+            name.extend(format!("%{:02X}", byte).into_bytes());
+        }
+    }
+    name.extend(FLAT_SEPARATOR.as_bytes());
+    name.extend(chunk_name.as_bytes());
+    OsString::from_vec(name)
+}
+
+// Splits the name of a chunk in a flat mirror into the relative path of its file and the name of
+// the chunk. Returns None for names that have not been built by flat_name, or whose path would
+// leave the mirror.
+fn parse_flat_name(name: &OsStr) -> Option<(PathBuf, &OsStr)> {
+    let name = name.as_bytes();
+    let separator = name
+        .windows(FLAT_SEPARATOR.len())
+        .position(|window| window == FLAT_SEPARATOR.as_bytes())?;
+    let (encoded, chunk_name) = (
+        &name[..separator],
+        &name[separator + FLAT_SEPARATOR.len()..],
+    );
+
+    let mut relative = Vec::new();
+    let mut bytes = encoded.iter();
+    while let Some(byte) = bytes.next() {
+        if *byte == b'%' {
+            let hex = [*bytes.next()?, *bytes.next()?];
+            relative.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else if byte.is_ascii_alphanumeric() || b"-.~".contains(byte) {
+            relative.push(*byte);
+        } else {
+            return None;
+        }
+    }
+
+    let relative = PathBuf::from(OsString::from_vec(relative));
+    let is_normal = relative
+        .as_os_str()
+        .as_bytes()
+        .split(|byte| *byte == b'/')
+        .all(|part| !part.is_empty() && part != b"." && part != b"..");
+
+    is_normal.then_some((relative, OsStr::from_bytes(chunk_name)))
+}
+
+/// The byte range of a single chunk inside of its original file, see [`chunk_ranges`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChunkRange {
+    /// The zero-based index of the chunk, which is part of its name.
+    pub index: u64,
+
+    /// The position of the first byte of the chunk inside of the file.
+    pub offset: u64,
+
+    /// The number of bytes of the chunk. Only the last chunk may be shorter than the blocksize.
+    pub len: u64,
+}
+
+/// Returns the ranges of the chunks that SplitFS splits the file at `path` into.
+///
+/// This way, the chunks can be read directly from the file without mounting anything. Just like
+/// in a mounted SplitFS, even an empty file has one chunk, whose length is zero. An error is
+/// returned if the size of the file cannot be determined or if the blocksize is zero.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{Read, Seek, SeekFrom};
+///
+/// for range in scfs::chunk_ranges("file", 1024).unwrap() {
+///     let mut file = File::open("file").unwrap();
+///     file.seek(SeekFrom::Start(range.offset)).unwrap();
+///
+///     let mut chunk = Vec::new();
+///     file.take(range.len).read_to_end(&mut chunk).unwrap();
+///
+///     // Upload the chunk ...
+/// }
+/// ```
+pub fn chunk_ranges<P: AsRef<Path>>(
+    path: P,
+    blocksize: u64,
+) -> io::Result<impl Iterator<Item = ChunkRange>> {
+    if blocksize == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Blocksize may not be zero",
+        ));
+    }
+
+    let size = fs::metadata(path)?.len();
+    Ok(split_ranges(size, blocksize))
+}
+
+// Even an empty file consists of one chunk. This way, we can differentiate between an empty file
+// and an empty directory.
+fn split_ranges(size: u64, blocksize: u64) -> impl Iterator<Item = ChunkRange> {
+    let chunks = 1.max(size.div_ceil(blocksize));
+    (0..chunks).map(move |index| {
+        let offset = index * blocksize;
+        ChunkRange {
+            index,
+            offset,
+            len: blocksize.min(size - offset),
+        }
+    })
+}
+
+// The directory that is presented as the root of the mount, which is the mirror itself unless the
+// subdir setting points to a directory inside of it.
+fn presented_root(
+    mirror: &Path,
+    subdir: Option<&Path>,
+    source: &dyn MirrorSource,
+) -> Result<PathBuf, MountError> {
+    let subdir = match subdir {
+        Some(subdir) => subdir,
+        None => return Ok(mirror.to_path_buf()),
+    };
+
+    if !subdir
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(MountError::InvalidConfig(format!(
+            "Subdirectory {} is no relative path inside of the mirror",
+            subdir.display()
+        )));
+    }
+
+    let root = mirror.join(subdir);
+    match source.symlink_metadata(&root)?.attr {
+        Some(attr) if attr.kind == FileType::Directory => Ok(root),
+        _ => Err(MountError::InvalidConfig(format!(
+            "Subdirectory {} is no directory",
+            root.display()
+        ))),
+    }
+}
+
+fn insert_file_info(file_db: &Connection, file_info: FileInfo) {
+    let file_info = FileInfoRow::from(file_info);
+
+    file_db
+        .prepare_cached(STMT_INSERT)
+        .unwrap()
+        .execute(params![
+            file_info.ino,
+            file_info.parent_ino,
+            file_info.path,
+            file_info.file_name,
+            file_info.part,
+            file_info.vdir,
+            file_info.symlink,
+        ])
+        .unwrap();
+}
+
+// Records that the file `ino` is a hard link of the file `target_ino`, which is the first path of
+// their inode in the mirror.
+fn insert_link(file_db: &Connection, ino: u64, target_ino: u64) {
+    file_db
+        .prepare_cached(STMT_INSERT_LINK)
+        .unwrap()
+        .execute(params![ino as i64, target_ino as i64])
+        .unwrap();
+}
+
+// Reads from a virtual file that consists of the given segments, laid out back to back.
+fn read_segments(
+    segments: &[FileHandle],
+    offset: u64,
+    size: u64,
+    source: &dyn MirrorSource,
+) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(size as usize);
+
+    let mut segment_offset = 0;
+    for segment in segments {
+        let len = segment.end - segment.start;
+        let wanted = size - bytes.len() as u64;
+
+        if wanted == 0 {
+            break;
+        }
+
+        if offset < segment_offset + len {
+            let skip = offset.saturating_sub(segment_offset);
+            let mut file = source.open(Path::new(&segment.file))?;
+            file.seek(SeekFrom::Start(segment.start + skip))?;
+            file.take(wanted.min(len - skip)).read_to_end(&mut bytes)?;
+        }
+
+        segment_offset += len;
+    }
+
+    Ok(bytes)
+}
+
+#[derive(Clone, Debug)]
+struct FileHandle {
+    file: OsString,
+    start: u64,
+    end: u64,
+
+    // Content that is computed on open, like compressed chunks or checksums
+    content: Option<Arc<Vec<u8>>>,
+}
+
+/// Entries of a directory, taken when the directory is opened. All readdir calls on the same
+/// handle iterate the same listing, regardless of how many calls it takes and of rows that are
+/// added to the database in the meantime.
+type DirListing = Vec<(u64, FileType, OsString)>;
+
+/// Starts the listing of a directory with its . and .. entries.
+fn dir_listing(file_info: &FileInfo) -> DirListing {
+    let parent_ino = if file_info.parent_ino == INO_OUTSIDE {
+        file_info.ino
+    } else {
+        file_info.parent_ino
+    };
+
+    vec![
+        (file_info.ino, FileType::Directory, ".".into()),
+        (parent_ino, FileType::Directory, "..".into()),
+    ]
+}
+
+/// Replies with the entries of the listing after the given offset. The offset of each entry is
+/// its index plus one, so the next call continues right after the last added entry.
+fn reply_dir_listing(listing: &DirListing, offset: i64, mut reply: ReplyDirectory) {
+    for (i, (ino, kind, name)) in listing.iter().enumerate().skip(offset as usize) {
+        if reply.add(*ino, i as i64 + 1, *kind, name) {
+            break;
+        }
+    }
+    reply.ok();
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+struct FileInfo {
+    ino: u64,
+    parent_ino: u64,
+    #[serde(serialize_with = "serialize_lossy")]
+    path: OsString,
+    #[serde(serialize_with = "serialize_lossy")]
+    file_name: OsString,
+    part: u64,
+    vdir: bool,
+    symlink: bool,
+}
+
+impl FileInfo {
+    fn with_ino(ino: u64) -> Self {
+        FileInfo {
+            ino,
+            parent_ino: Default::default(),
+            path: Default::default(),
+            file_name: Default::default(),
+            part: 0,
+            vdir: false,
+            symlink: false,
+        }
+    }
+
+    fn with_parent_ino(parent_ino: u64) -> Self {
+        FileInfo {
+            ino: Default::default(),
+            parent_ino,
+            path: Default::default(),
+            file_name: Default::default(),
+            part: 0,
+            vdir: false,
+            symlink: false,
+        }
+    }
+
+    fn file_name<S: Into<OsString>>(mut self, file_name: S) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    fn into_file_info_row(self) -> FileInfoRow {
+        FileInfoRow::from(self)
+    }
+}
+
+impl From<&Row<'_>> for FileInfo {
+    fn from(row: &Row) -> Self {
+        FileInfoRow::from(row).into()
+    }
+}
+
+// Paths are dumped as strings for readability, even if that loses non-UTF-8 bytes.
+fn serialize_lossy<S: Serializer>(s: &OsString, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&s.to_string_lossy())
+}
+
+#[derive(Debug)]
+struct FileInfoRow {
+    ino: i64,
+    parent_ino: i64,
+    path: Vec<u8>,
+    file_name: Vec<u8>,
+    part: i64,
+    vdir: bool,
+    symlink: bool,
+}
+
+impl From<&Row<'_>> for FileInfoRow {
+    fn from(row: &Row) -> Self {
+        FileInfoRow {
+            ino: row.get_unwrap(0),
+            parent_ino: row.get_unwrap(1),
+            path: row.get_unwrap(2),
+            file_name: row.get_unwrap(3),
+            part: row.get_unwrap(4),
+            vdir: row.get_unwrap(5),
+            symlink: row.get_unwrap(6),
+        }
+    }
+}
+
+impl From<FileInfoRow> for FileInfo {
+    fn from(f: FileInfoRow) -> Self {
+        FileInfo {
+            ino: f.ino as u64,
+            parent_ino: f.parent_ino as u64,
+            path: OsString::from_vec(f.path),
+            file_name: OsString::from_vec(f.file_name),
+            part: f.part as u64,
+            vdir: f.vdir,
+            symlink: f.symlink,
+        }
+    }
+}
+
+impl From<FileInfo> for FileInfoRow {
+    fn from(f: FileInfo) -> Self {
+        FileInfoRow {
+            ino: f.ino as i64,
+            parent_ino: f.parent_ino as i64,
+            path: f.path.as_bytes().to_vec(),
+            file_name: f.file_name.as_bytes().to_vec(),
+            part: f.part as i64,
+            vdir: f.vdir,
+            symlink: f.symlink,
+        }
+    }
+}
+
+/// The configuration of a splitted mirror, which is stored alongside the chunks.
+///
+/// The config file is read by versions of CatFS both older and newer than the SplitFS that wrote
+/// it, so its JSON stays compatible in both directions:
+///
+/// - Every field added after `blocksize` has a default, which describes mirrors written before
+///   the field existed. Configs of older mirrors thus read without any of the newer fields.
+/// - Fields that still have their default are left out when writing, so mirrors that do not use
+///   a newer feature get the same config as before.
+/// - Unknown fields are ignored when reading. Fields that only add information can therefore be
+///   added without changing the format.
+/// - A field that changes how the chunks have to be put together increases the format version,
+///   because older readers would ignore it and return wrong data. Readers reject configs of a
+///   newer format version than they support.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Config {
+    blocksize: u64,
+
+    // Config files from before the introduction of the format version have the first format
+    #[serde(default = "Config::legacy_format_version")]
+    format_version: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<Compression>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunk_format: Option<ChunkFormat>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    name_with_offset: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_split_size: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunked_suffix: Option<String>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    flat: bool,
+
+    // Only used by SplitFS, which records the resulting blocksize of each file in its directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parts: Option<u64>,
+
+    // Needed by CatFS to skip the repeated bytes, see chunk_overlap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    overlap: Option<u64>,
+
+    // Computed by SplitFS on each mount and checked by CatFS, see tree_digest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tree_digest: Option<String>,
+}
+
+impl Config {
+    /// Reads the config from the `.scfs_config` file in the root of a mirror created by SplitFS,
+    /// see [`Config::from_file`].
+    pub fn from_mirror<P: AsRef<Path>>(mirror: P) -> Result<Self, ConfigError> {
+        Config::from_file(mirror.as_ref().join(CONFIG_FILE_NAME))
+    }
+
+    /// Reads the config from the given file, like CatFS does before mounting. Configs with an
+    /// unsupported format version or a blocksize of zero are rejected.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ConfigError::Missing(path.to_path_buf()),
+            _ => ConfigError::Io(path.to_path_buf(), e),
+        })?;
+        let config: Config = serde_json::from_str(&config)
+            .map_err(|e| ConfigError::InvalidJson(path.to_path_buf(), e))?;
+
+        if !config.is_format_supported() {
+            return Err(ConfigError::Invalid(format!(
+                "SCFS mirror has format version {}, but only up to version {} is supported",
+                config.format_version, CONFIG_FORMAT_VERSION
+            )));
+        }
+
+        if config.blocksize == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "SCFS config file contains a blocksize of 0",
+            )));
+        }
+
+        Ok(config)
+    }
+
+    /// Sets the maximum size of a single chunk. Returns an error if the blocksize is zero.
+    pub fn blocksize(mut self, blocksize: u64) -> Result<Self, String> {
+        if blocksize == 0 {
+            return Err(String::from("Blocksize may not be zero"));
+        }
+
+        self.blocksize = blocksize;
+        Ok(self)
+    }
+
+    /// Sets the number of chunks each file is split into, or `None` to split all files into chunks
+    /// of the blocksize. Each file gets the smallest blocksize that results in at most this many
+    /// chunks. Returns an error if the number of parts is zero.
+    pub fn parts(mut self, parts: Option<u64>) -> Result<Self, String> {
+        if parts == Some(0) {
+            return Err(String::from("Number of parts may not be zero"));
+        }
+
+        self.parts = parts;
+        Ok(self)
+    }
+
+    /// Sets the number of bytes each chunk but the first repeats from the end of the previous
+    /// block, or `None` for chunks that do not overlap. An overlap larger than the blocksize
+    /// repeats just the whole previous block.
+    pub fn overlap(mut self, overlap: Option<u64>) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Sets the codec that is used to compress each chunk, or `None` for uncompressed chunks.
+    pub fn compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the format of the chunk names, or `None` for the default `scfs.{index:010}`.
+    pub fn chunk_format(mut self, chunk_format: Option<ChunkFormat>) -> Self {
+        self.chunk_format = chunk_format;
+        self
+    }
+
+    /// Sets whether the chunk names additionally contain the offset of the chunk in the original
+    /// file and its length, like `scfs.0000000003.00006291456.2097152`.
+    pub fn name_with_offset(mut self, name_with_offset: bool) -> Self {
+        self.name_with_offset = name_with_offset;
+        self
+    }
 
-const CONFIG_FILE_NAME: &str = ".scfs_config";
+    /// Sets the size up to which files are presented as they are instead of being split, or
+    /// `None` to split all files.
+    pub fn min_split_size(mut self, min_split_size: Option<u64>) -> Self {
+        self.min_split_size = min_split_size;
+        self
+    }
 
-const CONFIG_DEFAULT_BLOCKSIZE: u64 = 2 * 1024 * 1024;
+    /// Sets a suffix, which is appended to the names of chunked files in directory listings of
+    /// SplitFS, or `None` to list them under their original names. Both names can be used to
+    /// access them.
+    pub fn chunked_suffix(mut self, chunked_suffix: Option<String>) -> Self {
+        self.chunked_suffix = chunked_suffix;
+        self
+    }
 
-const INO_OUTSIDE: u64 = 0;
-const INO_ROOT: u64 = 1;
-const INO_CONFIG: u64 = 2;
+    /// Sets whether SplitFS lists all chunks directly in its root directory, named after the
+    /// relative path of their file and their own name, instead of in virtual directories.
+    pub fn flat(mut self, flat: bool) -> Self {
+        self.flat = flat;
+        self
+    }
 
-const INO_FIRST_FREE: u64 = 10;
+    // Small files are presented as they are, unless their name could be mistaken for a chunk.
+    fn is_plain_file(&self, name: &OsStr, size: u64) -> bool {
+        self.min_split_size
+            .is_some_and(|min_split_size| size <= min_split_size)
+            && self.chunk_index(name).is_none()
+    }
+
+    // With a fixed number of parts, the blocksize of a file follows from its size. Empty files
+    // still need a valid blocksize.
+    fn part_blocksize(&self, size: u64) -> Option<u64> {
+        self.parts.map(|parts| size.div_ceil(parts).max(1))
+    }
 
-type DropHookFn = Box<dyn Fn() + Send + 'static>;
+    // The number of bytes at the start of the chunk with the given index that repeat the end of
+    // the previous block, and that CatFS skips when putting the file together.
+    fn chunk_overlap(&self, index: u64) -> u64 {
+        match index {
+            0 => 0,
+            _ => self.overlap.unwrap_or(0).min(self.blocksize),
+        }
+    }
 
-fn system_time_from_time(secs: i64, nsecs: i64) -> SystemTime {
-    if secs >= 0 {
-        UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32)
-    } else {
-        UNIX_EPOCH - Duration::new((-secs) as u64, nsecs as u32)
+    // The range of the original file that the chunk with the given index contains. Chunks beyond
+    // the end of the file, which may have shrunk since mounting, are empty.
+    fn chunk_span(&self, index: u64, size: u64) -> (u64, u64) {
+        let end = size.min((index + 1) * self.blocksize);
+        let start = (index * self.blocksize - self.chunk_overlap(index)).min(end);
+        (start, end)
+    }
+
+    // Files with their own blocksize use a copy of the config with that blocksize.
+    fn with_blocksize(&self, blocksize: Option<u64>) -> Cow<'_, Config> {
+        match blocksize {
+            Some(blocksize) if blocksize != self.blocksize => Cow::Owned(Config {
+                blocksize,
+                ..self.clone()
+            }),
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    // The name of the chunk without its offset, which identifies the chunk in tree digests and
+    // messages.
+    fn chunk_name(&self, index: u64) -> OsString {
+        self.chunk_format.clone().unwrap_or_default().name(index)
+    }
+
+    // The name under which the chunk with the given index of a file of the given size is
+    // presented. Only names with offset depend on the size.
+    fn chunk_file_name(&self, index: u64, size: u64) -> OsString {
+        let mut name = self.chunk_name(index);
+        if self.name_with_offset {
+            let (start, end) = self.chunk_span(index, size);
+            name.push(format!(".{:011}.{}", start, end - start));
+        }
+        name
+    }
+
+    // The offset and the length in names with offset are only checked to be numbers, CatFS
+    // computes both from the chunks themselves.
+    fn chunk_index(&self, name: &OsStr) -> Option<u64> {
+        let mut name = name.as_bytes();
+        if self.name_with_offset {
+            for _ in 0..2 {
+                let dot = name.iter().rposition(|&byte| byte == b'.')?;
+                let digits = &name[dot + 1..];
+                if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                    return None;
+                }
+                name = &name[..dot];
+            }
+        }
+
+        self.chunk_format
+            .clone()
+            .unwrap_or_default()
+            .index(OsStr::from_bytes(name))
+    }
+
+    fn legacy_format_version() -> u32 {
+        1
+    }
+
+    // Brings a deserialized config of an older mirror to the current format. The fields added
+    // since then already got their defaults while deserializing, which match the layout of the
+    // older mirror.
+    fn migrate(self) -> Result<Self, String> {
+        if !self.is_format_supported() {
+            return Err(format!(
+                "format version {} is newer than the supported version {}",
+                self.format_version, CONFIG_FORMAT_VERSION
+            ));
+        }
+
+        let blocksize = self.blocksize;
+        let config = self.blocksize(blocksize)?;
+        Ok(Config {
+            format_version: CONFIG_FORMAT_VERSION,
+            ..config
+        })
+    }
+
+    fn is_format_supported(&self) -> bool {
+        self.format_version <= CONFIG_FORMAT_VERSION
     }
 }
 
-fn convert_filetype(ft: fs::FileType) -> Option<FileType> {
-    if ft.is_dir() {
-        Some(FileType::Directory)
-    } else if ft.is_file() {
-        Some(FileType::RegularFile)
-    } else if ft.is_symlink() {
-        Some(FileType::Symlink)
-    } else {
-        None
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            blocksize: CONFIG_DEFAULT_BLOCKSIZE,
+            format_version: CONFIG_FORMAT_VERSION,
+            compression: None,
+            chunk_format: None,
+            name_with_offset: false,
+            min_split_size: None,
+            chunked_suffix: None,
+            flat: false,
+            parts: None,
+            overlap: None,
+            tree_digest: None,
+        }
     }
 }
 
-fn convert_metadata_to_attr(meta: Metadata, ino: Option<u64>) -> FileAttr {
-    FileAttr {
-        ino: if let Some(ino) = ino { ino } else { meta.ino() },
-        size: meta.size(),
-        blocks: meta.blocks(),
-        atime: system_time_from_time(meta.atime(), meta.atime_nsec()),
-        mtime: system_time_from_time(meta.mtime(), meta.mtime_nsec()),
-        ctime: system_time_from_time(meta.ctime(), meta.ctime_nsec()),
-        crtime: meta.created().unwrap_or(system_time_from_time(0, 0)),
-        kind: convert_filetype(meta.file_type()).expect("Filetype not supported"),
-        perm: meta.mode() as u16,
-        nlink: meta.nlink() as u32,
-        uid: meta.uid(),
-        gid: meta.gid(),
-        rdev: meta.rdev() as u32,
-        blksize: meta.blksize() as u32,
-        flags: 0,
-    }
-}
-
-// Copied from fuser, mount_options.rs. When this becomes part of their public API, delete this function.
-fn mount_option_from_str(s: &str) -> MountOption {
-    match s {
-        "auto_unmount" => MountOption::AutoUnmount,
-        "allow_other" => MountOption::AllowOther,
-        "allow_root" => MountOption::AllowRoot,
-        "default_permissions" => MountOption::DefaultPermissions,
-        "dev" => MountOption::Dev,
-        "nodev" => MountOption::NoDev,
-        "suid" => MountOption::Suid,
-        "nosuid" => MountOption::NoSuid,
-        "ro" => MountOption::RO,
-        "rw" => MountOption::RW,
-        "exec" => MountOption::Exec,
-        "noexec" => MountOption::NoExec,
-        "atime" => MountOption::Atime,
-        "noatime" => MountOption::NoAtime,
-        "dirsync" => MountOption::DirSync,
-        "sync" => MountOption::Sync,
-        "async" => MountOption::Async,
-        x if x.starts_with("fsname=") => MountOption::FSName(x[7..].into()),
-        x if x.starts_with("subtype=") => MountOption::Subtype(x[8..].into()),
-        x => MountOption::CUSTOM(x.into()),
-    }
-}
-
-fn mount<'a, 'b, FS, P, I>(filesystem: FS, mountpoint: &P, fuse_options: I) -> BackgroundSession
-where
-    FS: Filesystem + Send + 'static + 'a,
-    P: AsRef<Path>,
-    I: IntoIterator<Item = &'b OsStr>,
-{
-    let fuse_options = fuse_options
-        .into_iter()
-        .map(|x| mount_option_from_str(x.to_str().unwrap()));
+/// The codecs that can be used to compress chunks.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+}
+
+/// The format of chunk names, given as a template with a single placeholder for the zero-based
+/// index of the chunk: either `{index}` for the bare number, or `{index:0N}` for the number padded
+/// with zeros to a width of `N`, for example `part-{index:04}.bin`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChunkFormat {
+    prefix: String,
+    width: usize,
+    suffix: String,
+}
+
+impl ChunkFormat {
+    fn name(&self, index: u64) -> OsString {
+        format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            index,
+            self.suffix,
+            width = self.width
+        )
+        .into()
+    }
+
+    // Works on the raw bytes, so names that are no valid UTF-8 are simply no chunk names.
+    fn index(&self, name: &OsStr) -> Option<u64> {
+        let digits = name
+            .as_bytes()
+            .strip_prefix(self.prefix.as_bytes())?
+            .strip_suffix(self.suffix.as_bytes())?;
 
-    let options = {
-        let mut options = vec![MountOption::RO, MountOption::FSName(String::from("scfs"))];
-        for opt in fuse_options {
-            options.push(opt);
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
         }
-        options
-    };
 
-    fuser::spawn_mount2(filesystem, &mountpoint, options.as_ref()).unwrap()
-}
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+}
+
+impl Default for ChunkFormat {
+    fn default() -> Self {
+        ChunkFormat {
+            prefix: String::from("scfs."),
+            width: 10,
+            suffix: String::new(),
+        }
+    }
+}
+
+impl FromStr for ChunkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let start = s
+            .find("{index")
+            .ok_or_else(|| String::from("Missing {index} placeholder"))?;
+        let end = start
+            + s[start..]
+                .find('}')
+                .ok_or_else(|| String::from("Unterminated placeholder"))?;
+
+        let width = match &s[start + "{index".len()..end] {
+            "" => 0,
+            spec => spec
+                .strip_prefix(":0")
+                .and_then(|width| width.parse().ok())
+                .ok_or_else(|| format!("Invalid placeholder: {:?}", &s[start..=end]))?,
+        };
+
+        let prefix = &s[..start];
+        let suffix = &s[end + 1..];
+
+        if prefix.contains(['{', '}']) || suffix.contains(['{', '}']) {
+            return Err(String::from("Only a single placeholder is allowed"));
+        }
+        if s.contains('/') {
+            return Err(String::from("Chunk names must not contain slashes"));
+        }
+
+        Ok(ChunkFormat {
+            prefix: prefix.to_string(),
+            width,
+            suffix: suffix.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for ChunkFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ChunkFormat> for String {
+    fn from(format: ChunkFormat) -> Self {
+        match format.width {
+            0 => format!("{}{{index}}{}", format.prefix, format.suffix),
+            width => format!("{}{{index:0{}}}{}", format.prefix, width, format.suffix),
+        }
+    }
+}
+
+// Reads a range of a file and compresses it as a whole into a chunk.
+fn compress_chunk<P: AsRef<Path>>(
+    file: P,
+    start: u64,
+    end: u64,
+    compression: Compression,
+    source: &dyn MirrorSource,
+) -> std::io::Result<Vec<u8>> {
+    let mut file = source.open(file.as_ref())?;
+    file.seek(SeekFrom::Start(start))?;
+
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            std::io::copy(&mut file.take(end - start), &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
+
+// Reads a file at its own position instead of the position of the file descriptor, so that
+// several threads can read the same open file at once.
+struct PositionedReader {
+    file: Arc<File>,
+    position: u64,
+}
+
+impl Read for PositionedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.file.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+// Opens a chunk for reading its content, starting at the given offset of the content.
+fn open_chunk<P: AsRef<Path>>(
+    path: P,
+    config: &Config,
+    offset: u64,
+) -> std::io::Result<Box<dyn Read>> {
+    read_chunk(Arc::new(File::open(path)?), config, offset)
+}
+
+// Reads the content of an already opened chunk, starting at the given offset of the content.
+fn read_chunk(file: Arc<File>, config: &Config, offset: u64) -> std::io::Result<Box<dyn Read>> {
+    match config.compression {
+        None => Ok(Box::new(PositionedReader {
+            file,
+            position: offset,
+        })),
+        Some(Compression::Gzip) => {
+            let mut decoder = GzDecoder::new(PositionedReader { file, position: 0 });
+            std::io::copy(&mut (&mut decoder).take(offset), &mut std::io::sink())?;
+            Ok(Box::new(decoder))
+        }
+    }
+}
+
+// Opens a file of the mirror for reading. With `noatime`, reads do not update the access time of
+// the file. Only the owner of a file may do so, for all other files the access time is updated
+// like before.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+fn open_mirror_file<P: AsRef<Path>>(path: P, noatime: bool) -> std::io::Result<File> {
+    #[cfg(target_os = "linux")]
+    if noatime {
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOATIME)
+            .open(path.as_ref())
+        {
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => {}
+            result => return result,
+        }
+    }
+
+    File::open(path)
+}
+
+// The error to reply for a failed read of the mirror. Running out of file descriptors is reported
+// as such, since it only lasts until other files have been closed.
+fn read_errno(error: &io::Error) -> libc::c_int {
+    match error.raw_os_error() {
+        Some(libc::EMFILE) | Some(libc::ENFILE) => libc::EMFILE,
+        _ => libc::EIO,
+    }
+}
+
+// Returns the size of a chunk's content, which differs from the file size for compressed chunks.
+fn chunk_content_size<P: AsRef<Path>>(path: P, config: &Config) -> std::io::Result<u64> {
+    let path = path.as_ref();
+
+    match config.compression {
+        None => Ok(fs::metadata(path)?.len()),
+
+        // The gzip trailer contains the size of the uncompressed data modulo 2^32, which is exact
+        // as long as no chunk, including its overlap, can reach 2^32 bytes
+        Some(Compression::Gzip)
+            if config
+                .blocksize
+                .checked_add(config.chunk_overlap(1))
+                .is_some_and(|size| size <= u32::MAX as u64) =>
+        {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::End(-4))?;
+            let mut size = [0; 4];
+            file.read_exact(&mut size)?;
+            Ok(u32::from_le_bytes(size) as u64)
+        }
+
+        Some(Compression::Gzip) => {
+            std::io::copy(&mut open_chunk(path, config, 0)?, &mut std::io::sink())
+        }
+    }
+}
+
+// Hashes the relative path and the content size of each chunk of a mirror, so that CatFS notices
+// chunks that have been added, removed or renamed after the split. A chunk is named by the relative
+// path of its file and its own name, which does not depend on the layout of the splitted mirror.
+fn tree_digest(mut chunks: Vec<(PathBuf, u64)>) -> String {
+    chunks.sort();
+
+    let mut hasher = Sha256::new();
+    for (path, size) in chunks {
+        hasher.update(path.as_os_str().as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{}\n", size));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// In contrast to Config, the settings only affect the running filesystem and are not persisted in
+// the mirror.
+#[derive(Clone, Default)]
+struct Settings {
+    emit_count: bool,
+    pack_small: Option<u64>,
+    read_threads: Option<usize>,
+    db_path: Option<PathBuf>,
+    verify: bool,
+    checksums: bool,
+    emit_meta: bool,
+    emit_reads: bool,
+    emit_whole: bool,
+    follow_symlinks: bool,
+    attr_ttl: Option<Duration>,
+    exclude: Vec<String>,
+    idle_timeout: Option<Duration>,
+    progress: Option<Sender<PopulateProgress>>,
+
+    // Notified once the kernel has initialized the mounted file system
+    ready: Option<Sender<()>>,
+
+    allow_missing: bool,
+    readahead: u64,
+
+    // Capacity of the buffer for reading chunks from the mirror, see IO_BUFFER_DEFAULT_SIZE
+    io_buffer: Option<usize>,
+
+    special_files: bool,
+    placeholder: bool,
+
+    // Opens the files of the mirror with O_NOATIME
+    noatime: bool,
+
+    // Rewrite the presented owners and groups
+    uid_map: IdMap,
+    gid_map: IdMap,
+
+    // Provides the chunks missing in a mirror, see LocalChunks for the default
+    chunk_source: Option<Arc<dyn ChunkSource>>,
+
+    // Provides the entries SplitFS splits, see LocalMirror for the default
+    mirror_source: Option<Arc<dyn MirrorSource>>,
+
+    // Caps the bytes served by all reads per second
+    read_limit: Option<u64>,
+
+    // Caps the number of files of the mirror that are open at the same time
+    max_open_files: Option<usize>,
+
+    // Lists synthetic files among the others, sorted by their presented names
+    sort_entries: bool,
+
+    // Replaces CONFIG_FILE_NAME as the name of the config file in the root of the mirror
+    config_name: Option<String>,
+
+    // Shifts the inodes of all entries but the root, so that stacked mounts do not share inodes
+    ino_offset: u64,
+
+    // Derives the inodes of SplitFS from the paths of the entries instead of the indexing order
+    preallocate_inodes: bool,
+
+    // Mounts read-write and accepts new permissions and times, without touching the mirror
+    lenient_writes: bool,
+
+    // Presents this directory inside of the mirror as the root, see presented_root
+    subdir: Option<PathBuf>,
+
+    // Refuses to mount mirrors whose chunks do not match the tree digest of their config
+    strict: bool,
+
+    // Records the size and mtime of each file while indexing and fails with ESTALE once they
+    // changed, see SplitFS::is_stale
+    detect_changes: bool,
+
+    // Serves reads on the thread of the FUSE session and lets the kernel send one request at a
+    // time, see thread_pool
+    single_threaded: bool,
+}
+
+impl Settings {
+    fn emit_count(mut self, emit_count: bool) -> Self {
+        self.emit_count = emit_count;
+        self
+    }
+
+    fn pack_small(mut self, pack_small: Option<u64>) -> Self {
+        self.pack_small = pack_small;
+        self
+    }
+
+    fn read_threads(mut self, read_threads: Option<usize>) -> Self {
+        self.read_threads = read_threads;
+        self
+    }
+
+    fn db_path(mut self, db_path: Option<PathBuf>) -> Self {
+        self.db_path = db_path;
+        self
+    }
+
+    fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    fn emit_meta(mut self, emit_meta: bool) -> Self {
+        self.emit_meta = emit_meta;
+        self
+    }
+
+    fn emit_reads(mut self, emit_reads: bool) -> Self {
+        self.emit_reads = emit_reads;
+        self
+    }
+
+    fn emit_whole(mut self, emit_whole: bool) -> Self {
+        self.emit_whole = emit_whole;
+        self
+    }
+
+    fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    fn attr_ttl(mut self, attr_ttl: Option<Duration>) -> Self {
+        self.attr_ttl = attr_ttl;
+        self
+    }
+
+    fn exclude(mut self, exclude: Vec<String>) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    fn progress(mut self, progress: Option<Sender<PopulateProgress>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    fn ready(mut self, ready: Option<Sender<()>>) -> Self {
+        self.ready = ready;
+        self
+    }
+
+    fn allow_missing(mut self, allow_missing: bool) -> Self {
+        self.allow_missing = allow_missing;
+        self
+    }
+
+    fn readahead(mut self, readahead: u64) -> Self {
+        self.readahead = readahead;
+        self
+    }
+
+    fn io_buffer(mut self, io_buffer: Option<usize>) -> Self {
+        self.io_buffer = io_buffer;
+        self
+    }
+
+    fn special_files(mut self, special_files: bool) -> Self {
+        self.special_files = special_files;
+        self
+    }
+
+    fn placeholder(mut self, placeholder: bool) -> Self {
+        self.placeholder = placeholder;
+        self
+    }
+
+    fn noatime(mut self, noatime: bool) -> Self {
+        self.noatime = noatime;
+        self
+    }
+
+    fn read_limit(mut self, read_limit: Option<u64>) -> Self {
+        self.read_limit = read_limit;
+        self
+    }
+
+    fn max_open_files(mut self, max_open_files: Option<usize>) -> Self {
+        self.max_open_files = max_open_files;
+        self
+    }
+
+    fn sort_entries(mut self, sort_entries: bool) -> Self {
+        self.sort_entries = sort_entries;
+        self
+    }
+
+    fn config_name(mut self, config_name: Option<String>) -> Self {
+        self.config_name = config_name;
+        self
+    }
+
+    fn config_file_name(&self) -> &str {
+        self.config_name.as_deref().unwrap_or(CONFIG_FILE_NAME)
+    }
+
+    fn ino_offset(mut self, ino_offset: u64) -> Self {
+        self.ino_offset = ino_offset;
+        self
+    }
+
+    fn preallocate_inodes(mut self, preallocate_inodes: bool) -> Self {
+        self.preallocate_inodes = preallocate_inodes;
+        self
+    }
+
+    fn lenient_writes(mut self, lenient_writes: bool) -> Self {
+        self.lenient_writes = lenient_writes;
+        self
+    }
+
+    fn subdir(mut self, subdir: Option<PathBuf>) -> Self {
+        self.subdir = subdir;
+        self
+    }
+
+    fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
 
-struct FileHandle {
-    file: OsString,
-    start: u64,
-    end: u64,
-}
+    fn detect_changes(mut self, detect_changes: bool) -> Self {
+        self.detect_changes = detect_changes;
+        self
+    }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
-struct FileInfo {
-    ino: u64,
-    parent_ino: u64,
-    path: OsString,
-    file_name: OsString,
-    part: u64,
-    vdir: bool,
-    symlink: bool,
-}
+    fn single_threaded(mut self, single_threaded: bool) -> Self {
+        self.single_threaded = single_threaded;
+        self
+    }
 
-impl FileInfo {
-    fn with_ino(ino: u64) -> Self {
-        FileInfo {
-            ino,
-            parent_ino: Default::default(),
-            path: Default::default(),
-            file_name: Default::default(),
-            part: 0,
-            vdir: false,
-            symlink: false,
+    // The threads that read the mirror while indexing it, see PrefetchedMirror
+    fn index_threads(&self) -> Option<usize> {
+        if self.single_threaded {
+            Some(1)
+        } else {
+            self.read_threads
         }
     }
 
-    fn with_parent_ino(parent_ino: u64) -> Self {
-        FileInfo {
-            ino: Default::default(),
-            parent_ino,
-            path: Default::default(),
-            file_name: Default::default(),
-            part: 0,
-            vdir: false,
-            symlink: false,
+    // The threads that serve reads. Single-threaded mounts read on the thread of the FUSE session,
+    // so requests are answered one after the other in the order they arrive.
+    fn thread_pool(&self) -> ThreadPool {
+        if self.single_threaded {
+            ThreadPool::inline()
+        } else {
+            ThreadPool::new(self.read_threads)
         }
     }
 
-    fn file_name<S: Into<OsString>>(mut self, file_name: S) -> Self {
-        self.file_name = file_name.into();
+    // The root keeps INO_ROOT, which the kernel expects for the root of every mount.
+    fn first_free_ino(&self) -> u64 {
+        INO_FIRST_FREE + self.ino_offset
+    }
+
+    fn uid_map(mut self, uid_map: IdMap) -> Self {
+        self.uid_map = uid_map;
         self
     }
 
-    fn into_file_info_row(self) -> FileInfoRow {
-        FileInfoRow::from(self)
+    fn gid_map(mut self, gid_map: IdMap) -> Self {
+        self.gid_map = gid_map;
+        self
+    }
+
+    fn chunk_source(mut self, chunk_source: Option<Arc<dyn ChunkSource>>) -> Self {
+        self.chunk_source = chunk_source;
+        self
+    }
+
+    fn mirror_source(mut self, mirror_source: Option<Arc<dyn MirrorSource>>) -> Self {
+        self.mirror_source = mirror_source;
+        self
     }
 }
 
-impl From<&Row<'_>> for FileInfo {
-    fn from(row: &Row) -> Self {
-        FileInfoRow::from(row).into()
+// Maps user or group IDs of the mirror to the presented ones. A mapping from None applies to all
+// IDs without a mapping of their own, all other IDs are presented as they are.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct IdMap(Vec<(Option<u32>, u32)>);
+
+impl IdMap {
+    fn map(&self, id: u32) -> u32 {
+        let find = |from| self.0.iter().find(|(other, _)| *other == from);
+        find(Some(id))
+            .or_else(|| find(None))
+            .map_or(id, |(_, to)| *to)
     }
 }
 
-#[derive(Debug)]
-struct FileInfoRow {
-    ino: i64,
-    parent_ino: i64,
-    path: Vec<u8>,
-    file_name: Vec<u8>,
-    part: i64,
-    vdir: bool,
-    symlink: bool,
+// The number of files and their bytes seen so far while populating the database.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct PopulateProgress {
+    files: u64,
+    bytes: u64,
 }
 
-impl From<&Row<'_>> for FileInfoRow {
-    fn from(row: &Row) -> Self {
-        FileInfoRow {
-            ino: row.get_unwrap(0),
-            parent_ino: row.get_unwrap(1),
-            path: row.get_unwrap(2),
-            file_name: row.get_unwrap(3),
-            part: row.get_unwrap(4),
-            vdir: row.get_unwrap(5),
-            symlink: row.get_unwrap(6),
+// Sends the progress of populating the database every PROGRESS_INTERVAL files and once more when
+// it is done. Dropping the reporter closes the channel.
+struct ProgressReporter {
+    sink: Option<Sender<PopulateProgress>>,
+    progress: PopulateProgress,
+}
+
+impl ProgressReporter {
+    fn new(sink: Option<Sender<PopulateProgress>>) -> Self {
+        ProgressReporter {
+            sink,
+            progress: Default::default(),
         }
     }
-}
 
-impl From<FileInfoRow> for FileInfo {
-    fn from(f: FileInfoRow) -> Self {
-        FileInfo {
-            ino: f.ino as u64,
-            parent_ino: f.parent_ino as u64,
-            path: OsString::from_vec(f.path),
-            file_name: OsString::from_vec(f.file_name),
-            part: f.part as u64,
-            vdir: f.vdir,
-            symlink: f.symlink,
+    fn add(&mut self, bytes: u64) {
+        self.progress.files += 1;
+        self.progress.bytes += bytes;
+
+        if self.progress.files.is_multiple_of(PROGRESS_INTERVAL) {
+            self.send();
         }
     }
-}
 
-impl From<FileInfo> for FileInfoRow {
-    fn from(f: FileInfo) -> Self {
-        FileInfoRow {
-            ino: f.ino as i64,
-            parent_ino: f.parent_ino as i64,
-            path: f.path.as_bytes().to_vec(),
-            file_name: f.file_name.as_bytes().to_vec(),
-            part: f.part as i64,
-            vdir: f.vdir,
-            symlink: f.symlink,
+    fn finish(mut self) {
+        debug!(
+            "Indexed {} files with {} bytes",
+            self.progress.files, self.progress.bytes
+        );
+
+        if self.progress.files == 0 || !self.progress.files.is_multiple_of(PROGRESS_INTERVAL) {
+            self.send();
+        }
+    }
+
+    fn send(&mut self) {
+        if let Some(sink) = &self.sink {
+            // Nobody listening anymore is no reason to stop populating
+            if sink.send(self.progress).is_err() {
+                self.sink = None;
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
-struct Config {
-    blocksize: u64,
+// One entry of a pack index, describing where a packed file lives inside of its pack.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct PackEntry {
+    file_name: OsString,
+    pack: u64,
+    offset: u64,
+    size: u64,
 }
 
-impl Config {
-    fn blocksize(mut self, blocksize: u64) -> Self {
-        self.blocksize = blocksize;
-        self
-    }
+// The metadata of an original file, which is lost when chunks are copied around. SplitFS provides
+// it in .scfs_meta, CatFS applies it to the restored file.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct FileMeta {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    atime: i64,
+    atime_nsec: i64,
+    mtime: i64,
+    mtime_nsec: i64,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            blocksize: CONFIG_DEFAULT_BLOCKSIZE,
+impl FileMeta {
+    fn from_attr(attr: &FileAttr) -> Self {
+        let (atime, atime_nsec) = time_from_system_time(attr.atime);
+        let (mtime, mtime_nsec) = time_from_system_time(attr.mtime);
+        FileMeta {
+            mode: attr.perm as u32 & 0o7777,
+            uid: attr.uid,
+            gid: attr.gid,
+            atime,
+            atime_nsec,
+            mtime,
+            mtime_nsec,
         }
     }
+
+    fn apply(&self, attr: &mut FileAttr) {
+        attr.perm = (self.mode & 0o7777) as u16;
+        attr.uid = self.uid;
+        attr.gid = self.gid;
+        attr.atime = system_time_from_time(self.atime, self.atime_nsec);
+        attr.mtime = system_time_from_time(self.mtime, self.mtime_nsec);
+    }
+}
+
+fn pack_file_name(pack: u64) -> OsString {
+    format!("{}{:010}", PACK_FILE_PREFIX, pack).into()
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+
     use super::*;
 
+    fn ranges_of_file(size: usize, blocksize: u64) -> Vec<(u64, u64, u64)> {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, vec![0; size]).unwrap();
+
+        chunk_ranges(&file, blocksize)
+            .unwrap()
+            .map(|range| (range.index, range.offset, range.len))
+            .collect()
+    }
+
+    #[test]
+    fn chunk_ranges_of_empty_file() {
+        assert_eq!(ranges_of_file(0, 4), vec![(0, 0, 0)]);
+    }
+
+    #[test]
+    fn chunk_ranges_of_exact_multiple() {
+        assert_eq!(ranges_of_file(8, 4), vec![(0, 0, 4), (1, 4, 4)]);
+    }
+
+    #[test]
+    fn chunk_ranges_with_partial_last_chunk() {
+        assert_eq!(ranges_of_file(10, 4), vec![(0, 0, 4), (1, 4, 4), (2, 8, 2)]);
+    }
+
+    #[test]
+    fn chunk_ranges_reject_zero_blocksize() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"content").unwrap();
+
+        let error = chunk_ranges(&file, 0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn convert_metadata_of_unsupported_file_type() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"content").unwrap();
+
+        let attr = convert_metadata_to_attr(fs::metadata(&file).unwrap(), Some(42)).unwrap();
+        assert_eq!(attr.ino, 42);
+        assert_eq!(attr.kind, FileType::RegularFile);
+
+        // Sockets cannot be presented by FUSE
+        let socket = dir.path().join("socket");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        assert!(convert_metadata_to_attr(fs::metadata(&socket).unwrap(), None).is_none());
+    }
+
+    #[test]
+    fn parse_and_format_chunk_format() {
+        let format: ChunkFormat = "chunk_{index:06}.dat".parse().unwrap();
+        assert_eq!(format.name(42), "chunk_000042.dat");
+        assert_eq!(format.index(OsStr::new("chunk_000042.dat")), Some(42));
+        assert_eq!(format.index(OsStr::new("chunk_000042.sha256")), None);
+        assert_eq!(String::from(format), "chunk_{index:06}.dat");
+
+        let format: ChunkFormat = "{index}".parse().unwrap();
+        assert_eq!(format.name(1234), "1234");
+        assert_eq!(format.index(OsStr::new("1234")), Some(1234));
+
+        let default = ChunkFormat::default();
+        assert_eq!(default.name(1), "scfs.0000000001");
+        assert_eq!(String::from(default), "scfs.{index:010}");
+
+        for invalid in [
+            "scfs.",
+            "{index:10}",
+            "{index}.{index}",
+            "{index",
+            "dir/{index}",
+        ] {
+            assert!(invalid.parse::<ChunkFormat>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn build_and_parse_names_with_offset() {
+        let config = Config::default()
+            .blocksize(2097152)
+            .unwrap()
+            .name_with_offset(true);
+        assert_eq!(
+            config.chunk_file_name(3, 10 * 1024 * 1024),
+            "scfs.0000000003.00006291456.2097152"
+        );
+        assert_eq!(
+            config.chunk_file_name(4, 9 * 1024 * 1024),
+            "scfs.0000000004.00008388608.1048576"
+        );
+        assert_eq!(
+            config.chunk_index(OsStr::new("scfs.0000000003.00006291456.2097152")),
+            Some(3)
+        );
+
+        for invalid in [
+            "scfs.0000000003",
+            "scfs.0000000003.00006291456",
+            "scfs.0000000003.00006291456.",
+            "scfs.0000000003.00006291456.2097152.sha256",
+            "scfs.x.00006291456.2097152",
+        ] {
+            assert!(
+                config.chunk_index(OsStr::new(invalid)).is_none(),
+                "{}",
+                invalid
+            );
+        }
+
+        // Without offsets, only the bare names are chunk names
+        let config = config.name_with_offset(false);
+        assert_eq!(
+            config.chunk_file_name(3, 10 * 1024 * 1024),
+            "scfs.0000000003"
+        );
+        assert!(config
+            .chunk_index(OsStr::new("scfs.0000000003.00006291456.2097152"))
+            .is_none());
+    }
+
+    #[test]
+    fn build_and_parse_flat_names() {
+        for file in ["file", "dir/file", "my_file__x", "a b/%20", "dir/\u{e4}"] {
+            let name = flat_name(Path::new(file), OsStr::new("scfs.0000000001"));
+            assert_eq!(
+                parse_flat_name(&name),
+                Some((PathBuf::from(file), OsStr::new("scfs.0000000001")))
+            );
+        }
+
+        let name = flat_name(Path::new("dir/my_file"), OsStr::new("scfs.0000000001"));
+        assert_eq!(name, "dir%2Fmy%5Ffile__scfs.0000000001");
+
+        let non_utf8 = OsStr::from_bytes(b"\xff");
+        let name = flat_name(Path::new(non_utf8), OsStr::new("scfs.0000000001"));
+        assert_eq!(parse_flat_name(&name).unwrap().0, Path::new(non_utf8));
+
+        for invalid in [
+            "file",
+            "__scfs.0000000001",
+            "..__scfs.0000000001",
+            "dir%2F..%2Ffile__scfs.0000000001",
+            "dir%2F%2Ffile__scfs.0000000001",
+            "dir/file__scfs.0000000001",
+            "file%2__scfs.0000000001",
+        ] {
+            assert!(
+                parse_flat_name(OsStr::new(invalid)).is_none(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
     #[test]
     fn convert_fileinfo_to_fileinforow_and_back() {
         let file_info = FileInfo {
@@ -555,4 +3438,397 @@ mod tests {
 
         assert_eq!(file_info, file_info_row.into());
     }
+
+    #[test]
+    fn serialize_config_with_format_version() {
+        let config = Config::default().blocksize(1024).unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"blocksize":1024,"format_version":{}}}"#,
+                CONFIG_FORMAT_VERSION
+            )
+        );
+
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.blocksize, 1024);
+        assert_eq!(config.format_version, CONFIG_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn reject_zero_blocksize() {
+        assert!(Config::default().blocksize(0).is_err());
+        assert_eq!(Config::default().blocksize(1).unwrap().blocksize, 1);
+    }
+
+    #[test]
+    fn deserialize_legacy_config() {
+        let config: Config = serde_json::from_str(r#"{"blocksize":1024}"#).unwrap();
+        assert_eq!(config.blocksize, 1024);
+        assert_eq!(config.format_version, 1);
+        assert!(config.is_format_supported());
+    }
+
+    #[test]
+    fn deserialize_config_without_newer_fields() {
+        let config: Config =
+            serde_json::from_str(r#"{"blocksize":1024,"format_version":1}"#).unwrap();
+        assert_eq!(config.blocksize, 1024);
+        assert!(config.compression.is_none());
+        assert!(config.chunk_format.is_none());
+        assert!(config.min_split_size.is_none());
+        assert!(config.chunked_suffix.is_none());
+        assert!(!config.flat);
+        assert!(config.parts.is_none());
+        assert!(config.overlap.is_none());
+        assert!(config.tree_digest.is_none());
+
+        let config = config.migrate().unwrap();
+        assert_eq!(config.format_version, CONFIG_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn deserialize_config_with_unknown_fields() {
+        // A newer SplitFS may add fields that only carry additional information
+        let json = format!(
+            r#"{{"blocksize":1024,"format_version":{},"future":{{"nested":[1,2]}},"other":null}}"#,
+            CONFIG_FORMAT_VERSION
+        );
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.blocksize, 1024);
+        assert!(config.is_format_supported());
+
+        // Unknown fields are not written again
+        assert_eq!(
+            serde_json::to_string(&config).unwrap(),
+            format!(
+                r#"{{"blocksize":1024,"format_version":{}}}"#,
+                CONFIG_FORMAT_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn read_config_from_mirror() {
+        let mirror = tempdir().unwrap();
+        let json = serde_json::to_string(&Config::default().blocksize(1024).unwrap()).unwrap();
+        fs::write(mirror.path().join(CONFIG_FILE_NAME), json).unwrap();
+
+        let config = Config::from_mirror(mirror.path()).unwrap();
+        assert_eq!(config.blocksize, 1024);
+        assert_eq!(config.format_version, CONFIG_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn read_missing_config_from_mirror() {
+        let mirror = tempdir().unwrap();
+
+        let error = Config::from_mirror(mirror.path()).unwrap_err();
+        assert!(
+            matches!(error, ConfigError::Missing(ref path) if path == &mirror.path().join(CONFIG_FILE_NAME))
+        );
+        assert!(matches!(
+            MountError::from(error),
+            MountError::MissingConfig(_)
+        ));
+    }
+
+    #[test]
+    fn read_invalid_config_from_mirror() {
+        let mirror = tempdir().unwrap();
+        let config = mirror.path().join(CONFIG_FILE_NAME);
+
+        fs::write(&config, "{").unwrap();
+        let error = Config::from_mirror(mirror.path()).unwrap_err();
+        assert!(matches!(error, ConfigError::InvalidJson(_, _)), "{}", error);
+
+        fs::write(&config, r#"{"blocksize":0}"#).unwrap();
+        let error = Config::from_mirror(mirror.path()).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid(_)), "{}", error);
+
+        fs::write(
+            &config,
+            format!(
+                r#"{{"blocksize":1024,"format_version":{}}}"#,
+                CONFIG_FORMAT_VERSION + 1
+            ),
+        )
+        .unwrap();
+        let error = Config::from_mirror(mirror.path()).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid(_)), "{}", error);
+
+        // A directory in place of the config cannot be read at all
+        fs::remove_file(&config).unwrap();
+        fs::create_dir(&config).unwrap();
+        let error = Config::from_mirror(mirror.path()).unwrap_err();
+        assert!(matches!(error, ConfigError::Io(_, _)), "{}", error);
+    }
+
+    #[test]
+    fn chunk_content_size_of_gzip_chunk_at_trailer_limit() {
+        let dir = tempdir().unwrap();
+        let chunk = dir.path().join("chunk");
+
+        // The decoder stops after the gzip member, while the trailing bytes claim a size of 3
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"content").unwrap();
+        let mut content = encoder.finish().unwrap();
+        content.extend_from_slice(&[3, 0, 0, 0]);
+        fs::write(&chunk, content).unwrap();
+
+        let config = Config::default()
+            .blocksize(u32::MAX as u64 - 1)
+            .unwrap()
+            .compression(Some(Compression::Gzip));
+
+        // Chunks of up to u32::MAX bytes take the size from the trailer
+        let trailer = config.clone().overlap(Some(1));
+        assert_eq!(chunk_content_size(&chunk, &trailer).unwrap(), 3);
+
+        // Larger chunks, including the overlap, are decompressed instead
+        let decompressed = config.clone().overlap(Some(2));
+        assert_eq!(chunk_content_size(&chunk, &decompressed).unwrap(), 7);
+        let overflowing = config.blocksize(u64::MAX).unwrap().overlap(Some(1));
+        assert_eq!(chunk_content_size(&chunk, &overflowing).unwrap(), 7);
+    }
+
+    #[test]
+    fn mount_split_and_cat() -> Result<(), std::io::Error> {
+        let mirror = tempfile::tempdir()?;
+        let mountpoint_split = tempfile::tempdir()?;
+        let mountpoint_cat = tempfile::tempdir()?;
+
+        let data = (0..100).collect::<Vec<u8>>();
+        fs::write(mirror.path().join("file"), &data)?;
+
+        let _session_split = mount_split(
+            mirror.path(),
+            Config::default().blocksize(7).unwrap(),
+            mountpoint_split.path(),
+            Vec::<String>::new(),
+        )?;
+        let _session_cat = mount_cat(mountpoint_split.path(), mountpoint_cat.path(), ["ro"])?;
+
+        assert_eq!(
+            fs::read_dir(mountpoint_split.path().join("file"))?.count(),
+            15
+        );
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mount_split_memory_mirror_and_cat() -> Result<(), std::io::Error> {
+        let mountpoint_split = tempfile::tempdir()?;
+        let mountpoint_cat = tempfile::tempdir()?;
+
+        let data = (0..100).collect::<Vec<u8>>();
+        let mut mirror = MemoryMirror::new("/mirror");
+        mirror.add_file("/mirror/dir/file", data.clone());
+        mirror.add_dir("/mirror/empty");
+
+        let _session_split = mount_split_with_source(
+            "/mirror",
+            Config::default().blocksize(7).unwrap(),
+            mountpoint_split.path(),
+            Vec::<String>::new(),
+            mirror,
+        )?;
+        let _session_cat = mount_cat(mountpoint_split.path(), mountpoint_cat.path(), ["ro"])?;
+
+        assert_eq!(
+            fs::read_dir(mountpoint_split.path().join("dir/file"))?.count(),
+            15
+        );
+        assert_eq!(
+            fs::read(mountpoint_split.path().join("dir/file/scfs.0000000000"))?,
+            &data[..7]
+        );
+        assert_eq!(fs::read(mountpoint_cat.path().join("dir/file"))?, data);
+        assert!(mountpoint_cat.path().join("empty").is_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mount_split_and_cat_empty_file_and_dir() -> Result<(), std::io::Error> {
+        let mirror = tempfile::tempdir()?;
+        let mountpoint_split = tempfile::tempdir()?;
+        let mountpoint_cat = tempfile::tempdir()?;
+
+        fs::create_dir_all(mirror.path().join("dir/empty_dir"))?;
+        fs::write(mirror.path().join("dir/empty_file"), b"")?;
+
+        let _session_split = mount_split(
+            mirror.path(),
+            Config::default(),
+            mountpoint_split.path(),
+            Vec::<String>::new(),
+        )?;
+        let _session_cat = mount_cat(mountpoint_split.path(), mountpoint_cat.path(), ["ro"])?;
+
+        // In SplitFS, both are directories, but only the file has a chunk
+        assert_eq!(
+            fs::read_dir(mountpoint_split.path().join("dir/empty_file"))?.count(),
+            1
+        );
+        assert_eq!(
+            fs::read_dir(mountpoint_split.path().join("dir/empty_dir"))?.count(),
+            0
+        );
+
+        let empty_file = mountpoint_cat.path().join("dir/empty_file");
+        assert!(empty_file.symlink_metadata()?.is_file());
+        assert!(fs::read(empty_file)?.is_empty());
+
+        let empty_dir = mountpoint_cat.path().join("dir/empty_dir");
+        assert!(empty_dir.symlink_metadata()?.is_dir());
+        assert_eq!(fs::read_dir(empty_dir)?.count(), 0);
+
+        Ok(())
+    }
+
+    fn is_mounted(mountpoint: &Path) -> bool {
+        let mountpoint = mountpoint.canonicalize().unwrap();
+        fs::read_to_string("/proc/self/mountinfo")
+            .unwrap()
+            .lines()
+            .any(|line| line.split(' ').nth(4) == Some(mountpoint.to_str().unwrap()))
+    }
+
+    #[test]
+    fn unmount_session() -> Result<(), std::io::Error> {
+        let mirror = tempfile::tempdir()?;
+        let mountpoint = tempfile::tempdir()?;
+
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let session = mount_split(
+            mirror.path(),
+            Config::default(),
+            mountpoint.path(),
+            Vec::<String>::new(),
+        )?;
+        assert_eq!(session.mountpoint(), mountpoint.path());
+        assert!(is_mounted(mountpoint.path()));
+        assert_eq!(
+            fs::read(mountpoint.path().join("file/scfs.0000000000"))?,
+            b"content"
+        );
+
+        session.unmount()?;
+        assert!(!is_mounted(mountpoint.path()));
+        assert!(!mountpoint.path().join("file").exists());
+
+        // The drop hook of the file system has run once unmount returns
+        let (tx, rx) = std::sync::mpsc::channel();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(move || tx.send(()).unwrap()),
+        )
+        .unwrap();
+        let session = Session::new(mount(fs, &mountpoint, Vec::new())?);
+        assert!(rx.try_recv().is_err());
+        session.unmount()?;
+        rx.try_recv().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn mount_flat_split_and_cat() -> Result<(), std::io::Error> {
+        let mirror = tempfile::tempdir()?;
+        let mountpoint_split = tempfile::tempdir()?;
+        let mountpoint_cat = tempfile::tempdir()?;
+
+        let data = (0..100).collect::<Vec<u8>>();
+        fs::create_dir_all(mirror.path().join("dir/sub_dir"))?;
+        fs::write(mirror.path().join("file"), &data)?;
+        fs::write(mirror.path().join("dir/sub_dir/my file"), &data[..10])?;
+        fs::write(mirror.path().join("dir/empty"), b"")?;
+
+        let _session_split = mount_split(
+            mirror.path(),
+            Config::default().blocksize(7).unwrap().flat(true),
+            mountpoint_split.path(),
+            Vec::<String>::new(),
+        )?;
+
+        let mut names = fs::read_dir(mountpoint_split.path())?
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names.len(), 1 + 15 + 2 + 1);
+        assert_eq!(names[0], CONFIG_FILE_NAME);
+        assert!(names.contains(&OsString::from("dir%2Fempty__scfs.0000000000")));
+        assert_eq!(
+            fs::read(
+                mountpoint_split
+                    .path()
+                    .join("dir%2Fsub%5Fdir%2Fmy%20file__scfs.0000000001")
+            )?,
+            &data[7..10]
+        );
+        assert!(!mountpoint_split.path().join("file").exists());
+
+        let _session_cat = mount_cat(mountpoint_split.path(), mountpoint_cat.path(), ["ro"])?;
+
+        assert_eq!(fs::read(mountpoint_cat.path().join("file"))?, data);
+        assert_eq!(
+            fs::read(mountpoint_cat.path().join("dir/sub_dir/my file"))?,
+            &data[..10]
+        );
+        assert_eq!(fs::read(mountpoint_cat.path().join("dir/empty"))?, b"");
+        assert!(mountpoint_cat.path().join("dir/sub_dir").is_dir());
+
+        Ok(())
+    }
+
+    // Not run by default, since timings are unreliable on busy machines
+    #[test]
+    #[ignore]
+    fn test_expensive_insert_transaction() {
+        use std::time::Instant;
+
+        let insert_all = |transaction: bool| {
+            let file_db = Connection::open_in_memory().unwrap();
+            file_db.execute(STMT_CREATE, []).unwrap();
+
+            let start = Instant::now();
+            if transaction {
+                file_db.execute_batch("BEGIN").unwrap();
+            }
+            for ino in INO_FIRST_FREE..INO_FIRST_FREE + 200_000 {
+                insert_file_info(
+                    &file_db,
+                    FileInfo {
+                        ino,
+                        parent_ino: INO_ROOT,
+                        path: OsString::from(format!("/mirror/file_{}", ino)),
+                        file_name: OsString::from(format!("file_{}", ino)),
+                        part: 0,
+                        vdir: false,
+                        symlink: false,
+                    },
+                );
+            }
+            if transaction {
+                file_db.execute_batch("COMMIT").unwrap();
+            }
+            start.elapsed()
+        };
+
+        let single = insert_all(false);
+        let batched = insert_all(true);
+        assert!(
+            batched < single,
+            "single inserts: {:?}, one transaction: {:?}",
+            single,
+            batched
+        );
+    }
 }
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use fuser::FileAttr;
+
+/// Attributes that have been set on a mount with lenient writes. They are only kept in memory and
+/// presented instead of the ones derived from the mirror, which is never touched.
+#[derive(Debug, Default)]
+pub(crate) struct AttrOverrides {
+    enabled: bool,
+    attrs: HashMap<u64, AttrOverride>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct AttrOverride {
+    perm: Option<u16>,
+    atime: Option<SystemTime>,
+    mtime: Option<SystemTime>,
+    ctime: Option<SystemTime>,
+}
+
+impl AttrOverrides {
+    /// Creates an empty set of overrides. Only enabled overrides accept new attributes.
+    pub(crate) fn new(enabled: bool) -> Self {
+        AttrOverrides {
+            enabled,
+            attrs: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Replaces the attributes that have been set for the inode of `attr`.
+    pub(crate) fn apply(&self, attr: &mut FileAttr) {
+        if let Some(set) = self.attrs.get(&attr.ino) {
+            attr.perm = set.perm.unwrap_or(attr.perm);
+            attr.atime = set.atime.unwrap_or(attr.atime);
+            attr.mtime = set.mtime.unwrap_or(attr.mtime);
+            attr.ctime = set.ctime.unwrap_or(attr.ctime);
+        }
+    }
+
+    /// Sets the given attributes of the inode. Like on a real file system, this changes the
+    /// status change time as well.
+    pub(crate) fn set(
+        &mut self,
+        ino: u64,
+        perm: Option<u16>,
+        atime: Option<SystemTime>,
+        mtime: Option<SystemTime>,
+    ) {
+        let set = self.attrs.entry(ino).or_default();
+        set.perm = perm.or(set.perm);
+        set.atime = atime.or(set.atime);
+        set.mtime = mtime.or(set.mtime);
+        set.ctime = Some(SystemTime::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use fuser::FileType;
+
+    use super::*;
+
+    #[test]
+    fn test_attr_overrides() {
+        let attr = FileAttr {
+            ino: 10,
+            size: 0,
+            blocks: 0,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        };
+        let later = UNIX_EPOCH + Duration::from_secs(1000);
+
+        let mut overrides = AttrOverrides::new(true);
+        overrides.set(10, None, None, Some(later));
+        overrides.set(10, Some(0o600), None, None);
+
+        // Attributes set earlier are kept, all others stay as they are
+        let mut presented = attr;
+        overrides.apply(&mut presented);
+        assert_eq!(presented.perm, 0o600);
+        assert_eq!(presented.mtime, later);
+        assert_eq!(presented.atime, UNIX_EPOCH);
+        assert!(presented.ctime > later);
+
+        let mut other = FileAttr { ino: 11, ..attr };
+        overrides.apply(&mut other);
+        assert_eq!(other.perm, 0o644);
+        assert_eq!(other.mtime, UNIX_EPOCH);
+    }
+}
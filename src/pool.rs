@@ -0,0 +1,125 @@
+use std::num::NonZeroUsize;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads, which execute the jobs sent to them in order of arrival.
+pub(crate) struct ThreadPool {
+    // Without a sender, the jobs are executed on the calling thread
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Creates a new pool with the given number of threads, or one thread per CPU if `None`.
+    pub(crate) fn new(size: Option<usize>) -> Self {
+        let size =
+            size.unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        // A panicking job must not take down its worker
+                        Ok(job) => catch_unwind(AssertUnwindSafe(job)).unwrap_or(()),
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Creates a pool without threads, which executes each job on the calling thread before
+    /// returning from `execute`.
+    pub(crate) fn inline() -> Self {
+        ThreadPool {
+            sender: None,
+            workers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        match &self.sender {
+            Some(sender) => sender.send(Box::new(job)).unwrap(),
+            None => catch_unwind(AssertUnwindSafe(job)).unwrap_or(()),
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers finish the remaining jobs and then stop
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap_or(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_execute_jobs() {
+        let pool = ThreadPool::new(Some(3));
+        let (tx, rx) = channel();
+
+        for i in 0..100 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                if i == 50 {
+                    panic!("Jobs may panic without breaking the pool");
+                }
+                tx.send(i).unwrap();
+            });
+        }
+        drop(pool);
+        drop(tx);
+
+        let mut results = rx.iter().collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(results, (0..100).filter(|&i| i != 50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_execute_jobs_inline() {
+        let pool = ThreadPool::inline();
+        let caller = thread::current().id();
+        let (tx, rx) = channel();
+
+        for i in 0..3 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                if i == 1 {
+                    panic!("Jobs may panic without breaking the pool");
+                }
+                tx.send((i, thread::current().id())).unwrap();
+            });
+        }
+        drop(tx);
+
+        // The jobs are done before execute returns, in the order they were given
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [(0, caller), (2, caller)]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use fuser::FileType;
+
+use crate::{MirrorFile, MirrorMetadata, MirrorSource};
+
+/// Walks a mirror on several threads before indexing it and keeps the listings and metadata of
+/// all entries, so that the sequential walk of populate only has to look them up. Since populate
+/// still visits the entries one after the other, the inodes are allocated just like without the
+/// prefetched results.
+///
+/// Only real directories are descended into. Entries that have not been visited, like the targets
+/// of symlinks, and entries that failed are passed on to the source.
+pub(crate) struct PrefetchedMirror<'a> {
+    source: &'a dyn MirrorSource,
+    listings: HashMap<PathBuf, Vec<PathBuf>>,
+    metadata: HashMap<PathBuf, MirrorMetadata>,
+
+    // Only filled if symlinks are followed
+    followed: HashMap<PathBuf, MirrorMetadata>,
+}
+
+// The directories that still have to be listed, and how many of them are being listed right now
+struct WalkQueue {
+    dirs: VecDeque<PathBuf>,
+    busy: usize,
+}
+
+#[derive(Default)]
+struct WalkResults {
+    listings: HashMap<PathBuf, Vec<PathBuf>>,
+    metadata: HashMap<PathBuf, MirrorMetadata>,
+    followed: HashMap<PathBuf, MirrorMetadata>,
+}
+
+impl<'a> PrefetchedMirror<'a> {
+    /// Walks the tree below `root` with the given number of threads, or one thread per CPU if
+    /// `None`. Entries for which `skip` returns true are neither looked at nor descended into.
+    pub(crate) fn walk(
+        source: &'a dyn MirrorSource,
+        root: &Path,
+        follow_symlinks: bool,
+        threads: Option<usize>,
+        skip: &(dyn Fn(&Path) -> bool + Sync),
+    ) -> Self {
+        let threads = threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+            .max(1);
+
+        let queue = Mutex::new(WalkQueue {
+            dirs: VecDeque::new(),
+            busy: 0,
+        });
+        let changed = Condvar::new();
+        let results = Mutex::new(WalkResults::default());
+
+        let stat = |path: &Path| -> bool {
+            let meta = source.symlink_metadata(path).ok();
+            let followed = follow_symlinks
+                .then(|| source.metadata(path).ok())
+                .flatten();
+
+            let mut results = results.lock().unwrap();
+            if let Some(followed) = followed {
+                results.followed.insert(path.to_path_buf(), followed);
+            }
+            match meta {
+                Some(meta) => {
+                    results.metadata.insert(path.to_path_buf(), meta);
+                    meta.attr
+                        .is_some_and(|attr| attr.kind == FileType::Directory)
+                }
+                None => false,
+            }
+        };
+
+        if stat(root) {
+            queue.lock().unwrap().dirs.push_back(root.to_path_buf());
+        }
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let dir = {
+                        let mut queue = queue.lock().unwrap();
+                        loop {
+                            if let Some(dir) = queue.dirs.pop_front() {
+                                queue.busy += 1;
+                                break Some(dir);
+                            }
+                            if queue.busy == 0 {
+                                break None;
+                            }
+                            queue = changed.wait(queue).unwrap();
+                        }
+                    };
+                    let dir = match dir {
+                        Some(dir) => dir,
+                        None => {
+                            changed.notify_all();
+                            return;
+                        }
+                    };
+
+                    let mut subdirs = Vec::new();
+                    if let Ok(entries) = source.read_dir(&dir) {
+                        for entry in &entries {
+                            if !skip(entry) && stat(entry) {
+                                subdirs.push(entry.clone());
+                            }
+                        }
+                        results.lock().unwrap().listings.insert(dir, entries);
+                    }
+
+                    let mut queue = queue.lock().unwrap();
+                    queue.dirs.extend(subdirs);
+                    queue.busy -= 1;
+                    changed.notify_all();
+                });
+            }
+        });
+
+        let results = results.into_inner().unwrap();
+        PrefetchedMirror {
+            source,
+            listings: results.listings,
+            metadata: results.metadata,
+            followed: results.followed,
+        }
+    }
+}
+
+impl MirrorSource for PrefetchedMirror<'_> {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        match self.listings.get(path) {
+            Some(entries) => Ok(entries.clone()),
+            None => self.source.read_dir(path),
+        }
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        match self.metadata.get(path) {
+            Some(meta) => Ok(*meta),
+            None => self.source.symlink_metadata(path),
+        }
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn MirrorFile>> {
+        self.source.open(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        match self.followed.get(path) {
+            Some(meta) => Ok(*meta),
+            None => self.source.metadata(path),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.source.read_link(path)
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use fuser::FileAttr;
+
+/// Identifies the state of the mirrored file a chunk has been read from. A cached chunk is only
+/// served as long as its file has not changed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ChunkStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl From<&FileAttr> for ChunkStamp {
+    fn from(attr: &FileAttr) -> Self {
+        ChunkStamp {
+            len: attr.size,
+            modified: Some(attr.mtime),
+        }
+    }
+}
+
+/// Chunks that have been read ahead of their first request, keyed by their inode. Once the
+/// contained chunks exceed the capacity in bytes, the least recently used ones are dropped.
+pub(crate) struct ChunkCache {
+    capacity: u64,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    chunks: HashMap<u64, (ChunkStamp, Arc<Vec<u8>>)>,
+
+    // Inodes from the least to the most recently used chunk
+    order: VecDeque<u64>,
+
+    size: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, ino: u64) -> Option<(ChunkStamp, Arc<Vec<u8>>)> {
+        let chunk = self.chunks.remove(&ino)?;
+        self.order.retain(|other| *other != ino);
+        self.size -= chunk.1.len() as u64;
+        Some(chunk)
+    }
+}
+
+impl ChunkCache {
+    pub(crate) fn new(capacity: u64) -> Self {
+        ChunkCache {
+            capacity,
+            state: Default::default(),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns true if the chunk is cached and its file has not changed since.
+    pub(crate) fn contains(&self, ino: u64, source: ChunkStamp) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .chunks
+            .get(&ino)
+            .is_some_and(|(cached, _)| *cached == source)
+    }
+
+    /// Returns the cached chunk, if its file has not changed since it has been read.
+    pub(crate) fn get(&self, ino: u64, source: ChunkStamp) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+
+        let (cached, content) = state.remove(ino)?;
+        if cached != source {
+            return None;
+        }
+
+        state.size += content.len() as u64;
+        state.order.push_back(ino);
+        state.chunks.insert(ino, (cached, Arc::clone(&content)));
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        Some(content)
+    }
+
+    pub(crate) fn insert(&self, ino: u64, source: ChunkStamp, content: Vec<u8>) {
+        let size = content.len() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.remove(ino);
+
+        while state.size + size > self.capacity {
+            match state.order.front() {
+                Some(&oldest) => state.remove(oldest),
+                None => break,
+            };
+        }
+
+        state.size += size;
+        state.order.push_back(ino);
+        state.chunks.insert(ino, (source, Arc::new(content)));
+    }
+
+    /// Drops the chunk of the given inode, if it is cached.
+    pub(crate) fn remove(&self, ino: u64) {
+        self.state.lock().unwrap().remove(ino);
+    }
+
+    /// Number of chunks that have been served from the cache.
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn source(len: u64) -> ChunkStamp {
+        ChunkStamp {
+            len,
+            modified: Some(SystemTime::UNIX_EPOCH),
+        }
+    }
+
+    #[test]
+    fn test_chunk_cache() {
+        let cache = ChunkCache::new(10);
+
+        cache.insert(1, source(100), vec![1; 4]);
+        cache.insert(2, source(100), vec![2; 4]);
+        assert_eq!(cache.get(1, source(100)).unwrap().as_slice(), &[1; 4]);
+
+        // The least recently used chunk makes room for the new one
+        cache.insert(3, source(100), vec![3; 4]);
+        assert!(cache.contains(1, source(100)));
+        assert!(!cache.contains(2, source(100)));
+        assert!(cache.contains(3, source(100)));
+
+        // Chunks larger than the whole cache are not kept at all
+        cache.insert(4, source(100), vec![4; 11]);
+        assert!(!cache.contains(4, source(100)));
+        assert!(cache.contains(1, source(100)));
+
+        // Chunks of files that changed since must be read again
+        let changed = ChunkStamp {
+            len: 100,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1)),
+        };
+        assert!(cache.get(3, changed).is_none());
+        assert!(!cache.contains(3, source(100)));
+
+        assert_eq!(cache.hits(), 1);
+
+        cache.remove(1);
+        assert!(!cache.contains(1, source(100)));
+
+        // The freed space is available again
+        cache.insert(5, source(100), vec![5; 10]);
+        assert!(cache.contains(5, source(100)));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counts the reads of each chunk and the bytes they returned, which SplitFS presents in the
+/// `.scfs_reads` file of each chunked file.
+#[derive(Debug, Default)]
+pub(crate) struct ReadStats {
+    chunks: Mutex<HashMap<u64, ChunkReads>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ChunkReads {
+    pub(crate) reads: u64,
+    pub(crate) bytes: u64,
+}
+
+impl ReadStats {
+    /// Records a read of the chunk with the given inode, which returned `bytes` bytes.
+    pub(crate) fn record(&self, ino: u64, bytes: u64) {
+        let mut chunks = self.chunks.lock().unwrap();
+        let chunk = chunks.entry(ino).or_default();
+        chunk.reads += 1;
+        chunk.bytes += bytes;
+    }
+
+    /// Returns the reads of the chunk so far, which are zero for chunks that have not been read.
+    pub(crate) fn get(&self, ino: u64) -> ChunkReads {
+        self.chunks
+            .lock()
+            .unwrap()
+            .get(&ino)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_stats() {
+        let stats = ReadStats::default();
+        stats.record(10, 4);
+        stats.record(10, 6);
+        stats.record(11, 0);
+
+        assert_eq!(
+            stats.get(10),
+            ChunkReads {
+                reads: 2,
+                bytes: 10
+            }
+        );
+        assert_eq!(stats.get(11), ChunkReads { reads: 1, bytes: 0 });
+        assert_eq!(stats.get(12), ChunkReads::default());
+    }
+}
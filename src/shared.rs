@@ -1,15 +1,62 @@
 use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use fuser::{FileAttr, ReplyAttr, ReplyData, ReplyEntry, Request};
-use libc::ENOENT;
+use fuser::{
+    FileAttr, FileType, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyEmpty, ReplyEntry,
+    ReplyIoctl, ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow,
+};
+use libc::{
+    EACCES, EIO, ENODATA, ENOENT, ENOTTY, EOPNOTSUPP, ERANGE, EROFS, O_ACCMODE, O_RDONLY, R_OK,
+    W_OK, X_OK,
+};
+use nix::sys::statvfs::statvfs;
 use rusqlite::{params, Connection, Error};
 
-use crate::{FileInfo, FileInfoRow, STMT_QUERY_BY_INO, STMT_QUERY_BY_PARENT_INO_AND_FILENAME, TTL};
+use crate::{
+    AttrOverrides, FileInfo, FileInfoRow, INO_ROOT, STMT_COUNT_ALL, STMT_COUNT_LINKS,
+    STMT_QUERY_ALL, STMT_QUERY_BY_INO, STMT_QUERY_BY_PARENT_INO_AND_FILENAME,
+    STMT_QUERY_LINK_TARGET,
+};
 
 pub(crate) trait Shared {
+    // The database is only ever accessed from the FUSE session loop, which dispatches one request
+    // after the other with exclusive access to the file system, even if the kernel sends them
+    // concurrently. Work handed to the read threads is given everything it needs up front and
+    // never touches the database, so a single connection without any locking is enough. Since
+    // Connection is not Sync, the compiler keeps it that way.
     fn file_db(&self) -> &Connection;
 
+    // How long the kernel may cache attributes and entries
+    fn ttl(&self) -> Duration;
+
+    // The type of the mounted file system shown in mount listings, after "fuse."
+    const SUBTYPE: &'static str;
+
+    // Attributes set through lenient writes, which are presented instead of the derived ones
+    fn attr_overrides(&self) -> &AttrOverrides;
+    fn attr_overrides_mut(&mut self) -> &mut AttrOverrides;
+
+    /// Writes every entry of the database as one JSON line to `out`, ordered by inode.
+    fn write_dump<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut stmt = self.file_db().prepare(STMT_QUERY_ALL).unwrap();
+        let file_infos = stmt.query_map([], |row| Ok(FileInfo::from(row))).unwrap();
+
+        for file_info in file_infos {
+            writeln!(
+                out,
+                "{}",
+                serde_json::to_string(&file_info.unwrap()).unwrap()
+            )?;
+        }
+
+        out.flush()
+    }
+
     fn get_file_info_from_ino(&self, ino: u64) -> Result<FileInfo, Error> {
         let ino = FileInfoRow::from(FileInfo::with_ino(ino)).ino;
 
@@ -52,37 +99,289 @@ pub(crate) trait Shared {
         }
     }
 
-    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> FileAttr;
+    // Hard links of a file in the mirror refer to the first path of their inode, which is
+    // returned for them. All other inodes are returned as they are.
+    fn get_link_target(&self, ino: u64) -> u64 {
+        self.file_db()
+            .prepare_cached(STMT_QUERY_LINK_TARGET)
+            .unwrap()
+            .query_row(params![ino as i64], |row| row.get::<_, i64>(0))
+            .map_or(ino, |target_ino| target_ino as u64)
+    }
 
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let file_info =
-            self.get_file_info_from_parent_ino_and_file_name(parent, OsString::from(name));
-        if let Ok(file_info) = file_info {
-            let attr = self.get_attr_from_file_info(&file_info);
-            reply.entry(&TTL, &attr, 0);
+    // The number of paths of the given file in the mirror, counting the file itself
+    fn count_links(&self, target_ino: u64) -> u32 {
+        let links: i64 = self
+            .file_db()
+            .prepare_cached(STMT_COUNT_LINKS)
+            .unwrap()
+            .query_row(params![target_ino as i64], |row| row.get(0))
+            .unwrap();
+        links as u32 + 1
+    }
+
+    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> Option<FileAttr>;
+
+    fn get_attr_from_ino(&self, ino: u64) -> Option<FileAttr> {
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        self.get_attr_from_file_info(&file_info)
+    }
+
+    // The block size reported by statfs, defaults to the one of the mirror's file system
+    fn preferred_block_size(&self) -> Option<u64> {
+        None
+    }
+
+    // The attributes of the entry `name` in the directory `parent`. Since readdir lists the parent
+    // of the root as the root itself, lookups of . and .. in the root return the root as well.
+    fn lookup_attr(&self, parent: u64, name: &OsStr) -> Option<FileAttr> {
+        let mut attr = if parent == INO_ROOT && (name == "." || name == "..") {
+            self.get_attr_from_ino(INO_ROOT)
         } else {
-            reply.error(ENOENT);
+            self.get_file_info_from_parent_ino_and_file_name(parent, OsString::from(name))
+                .ok()
+                .and_then(|file_info| self.get_attr_from_file_info(&file_info))
+        }?;
+        self.attr_overrides().apply(&mut attr);
+        Some(attr)
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.lookup_attr(parent, name) {
+            Some(attr) => reply.entry(&self.ttl(), &attr, 0),
+            None => reply.error(ENOENT),
         }
     }
 
     fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        let file_info = self.get_file_info_from_ino(ino);
-        if let Ok(file_info) = file_info {
-            let attr = self.get_attr_from_file_info(&file_info);
-            reply.attr(&TTL, &attr)
+        if let Some(mut attr) = self.get_attr_from_ino(ino) {
+            self.attr_overrides().apply(&mut attr);
+            reply.attr(&self.ttl(), &attr)
         } else {
             reply.error(ENOENT)
         }
     }
 
+    // Only mounts with lenient writes are writable at all. They accept new permissions and times
+    // and keep them in memory, everything that would change the content or the owner still fails.
+    #[allow(clippy::too_many_arguments)]
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        reply: ReplyAttr,
+    ) {
+        if !self.attr_overrides().is_enabled() || uid.is_some() || gid.is_some() || size.is_some() {
+            reply.error(EROFS);
+            return;
+        }
+
+        let mut attr = match self.get_attr_from_ino(ino) {
+            Some(attr) => attr,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let time = |time: TimeOrNow| match time {
+            TimeOrNow::SpecificTime(time) => time,
+            TimeOrNow::Now => SystemTime::now(),
+        };
+        let perm = mode.map(|mode| (mode & 0o7777) as u16);
+        self.attr_overrides_mut()
+            .set(attr.ino, perm, atime.map(time), mtime.map(time));
+
+        self.attr_overrides().apply(&mut attr);
+        reply.attr(&self.ttl(), &attr)
+    }
+
+    // Nothing can be written, even on mounts with lenient writes.
+    fn write(&mut self, _req: &Request, _ino: u64, reply: ReplyWrite) {
+        reply.error(EROFS);
+    }
+
+    fn create(&mut self, _req: &Request, _parent: u64, _name: &OsStr, reply: ReplyCreate) {
+        reply.error(EROFS);
+    }
+
+    // Creating directories, device files, symlinks and hard links
+    fn make_entry(&mut self, _req: &Request, _parent: u64, _name: &OsStr, reply: ReplyEntry) {
+        reply.error(EROFS);
+    }
+
+    // Removing entries and renaming them
+    fn remove_entry(&mut self, _req: &Request, _parent: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(EROFS);
+    }
+
+    // Preallocating space or punching holes would change the content of a file.
+    fn fallocate(&mut self, _req: &Request, _ino: u64, reply: ReplyEmpty) {
+        reply.error(EROFS);
+    }
+
+    // Only file systems on block devices can map the blocks of a file, which the presented files
+    // are not.
+    fn bmap(&mut self, _req: &Request, _ino: u64, reply: ReplyBmap) {
+        reply.error(EOPNOTSUPP);
+    }
+
+    // No ioctl commands are supported, which is reported to the caller just like for any other
+    // file that does not know a command.
+    fn ioctl(&mut self, _req: &Request, _ino: u64, reply: ReplyIoctl) {
+        reply.error(ENOTTY);
+    }
+
+    // Writing is never possible, reading and executing depend on the presented attributes.
+    fn access(&mut self, req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        if mask & W_OK != 0 {
+            reply.error(EROFS);
+            return;
+        }
+
+        match self.get_attr_from_ino(ino) {
+            Some(attr) if is_access_allowed(&attr, req.uid(), req.gid(), mask) => reply.ok(),
+            Some(_) => reply.error(EACCES),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    // The extended attributes of an inode with their values, None if the inode does not exist
+    fn get_xattrs(&self, ino: u64) -> Option<Vec<(&'static str, Vec<u8>)>>;
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let xattrs = match self.get_xattrs(ino) {
+            Some(xattrs) => xattrs,
+            None => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match xattrs.into_iter().find(|(xattr, _)| name == *xattr) {
+            Some((_, value)) => reply_xattr(&value, size, reply),
+            None => reply.error(ENODATA),
+        }
+    }
+
+    // The names are listed one after the other, each terminated by a null byte.
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        match self.get_xattrs(ino) {
+            Some(xattrs) => {
+                let names = xattrs
+                    .iter()
+                    .flat_map(|(xattr, _)| xattr.bytes().chain([0]))
+                    .collect::<Vec<_>>();
+                reply_xattr(&names, size, reply);
+            }
+            None => reply.error(ENOENT),
+        }
+    }
+
+    // Where a symlink of the mirror points to
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    // Targets are passed on as raw bytes, they do not have to be valid UTF-8.
     fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
-        let path = self.get_file_info_from_ino(ino).unwrap().path;
-        let target = fs::read_link(path).unwrap();
-        let target = target.to_str().unwrap().as_bytes();
-        reply.data(target);
+        let path = match self.get_file_info_from_ino(ino) {
+            Ok(file_info) => file_info.path,
+            Err(_) => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        match self.read_link(Path::new(&path)) {
+            Ok(target) => reply.data(target.as_os_str().as_bytes()),
+            Err(_) => reply.error(EIO),
+        }
+    }
+
+    // Space figures come from the file system of the mirror, the number of files is the number of
+    // entries in the database.
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        let mirror = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+
+        let stat = match statvfs(Path::new(&mirror)) {
+            Ok(stat) => stat,
+            Err(errno) => {
+                reply.error(errno as i32);
+                return;
+            }
+        };
+
+        let files: i64 = self
+            .file_db()
+            .query_row(STMT_COUNT_ALL, [], |row| row.get(0))
+            .unwrap();
+
+        let block_size = self
+            .preferred_block_size()
+            .map_or(stat.block_size() as u32, |block_size| {
+                block_size.min(u32::MAX as u64) as u32
+            });
+
+        reply.statfs(
+            stat.blocks(),
+            stat.blocks_free(),
+            stat.blocks_available(),
+            files as u64,
+            stat.files_free(),
+            block_size,
+            stat.name_max() as u32,
+            stat.fragment_size() as u32,
+        );
+    }
+}
+
+// Opening a file for writing fails, even on mounts with lenient writes.
+pub(crate) fn is_writing_open(flags: i32) -> bool {
+    flags & O_ACCMODE != O_RDONLY
+}
+
+// A size of zero asks for the size of the value, which is then requested with a large enough
+// buffer.
+fn reply_xattr(value: &[u8], size: u32, reply: ReplyXattr) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(ERANGE);
+    } else {
+        reply.data(value);
     }
 }
 
+// Checks read and execute permissions like the kernel does. Root may read everything, but only
+// execute files that are executable for anybody. Supplementary groups are not known here, so only
+// the primary group counts.
+fn is_access_allowed(attr: &FileAttr, uid: u32, gid: u32, mask: i32) -> bool {
+    let mask = (mask & (R_OK | X_OK)) as u16;
+
+    if uid == 0 {
+        return mask & X_OK as u16 == 0
+            || attr.kind == FileType::Directory
+            || attr.perm & 0o111 != 0;
+    }
+
+    let perm = if uid == attr.uid {
+        attr.perm >> 6
+    } else if gid == attr.gid {
+        attr.perm >> 3
+    } else {
+        attr.perm
+    };
+
+    perm & mask == mask
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use std::collections::hash_map::RandomState;
@@ -90,8 +389,50 @@ pub(crate) mod tests {
     use std::fs;
     use std::fs::{DirEntry, File};
     use std::io::Write;
-    use std::os::unix::fs::symlink;
-    use std::path::Path;
+    use std::os::unix::fs::{symlink, MetadataExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, Once};
+
+    use libc::{F_OK, R_OK, X_OK};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+
+    use crate::{convert_metadata_to_attr, CatFS, Config, Settings, SplitFS, CONFIG_FILE_NAME};
+
+    use super::*;
+
+    // Records all log messages. Since the tests run in parallel, each test has to look for
+    // messages that only it can produce.
+    struct TestLogger;
+
+    static TEST_LOGGER: TestLogger = TestLogger;
+    static LOG_MESSAGES: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+    impl Log for TestLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            LOG_MESSAGES
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    pub(crate) fn init_test_logger() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&TEST_LOGGER).unwrap();
+            log::set_max_level(LevelFilter::Trace);
+        });
+    }
+
+    pub(crate) fn logged_messages() -> Vec<(Level, String)> {
+        LOG_MESSAGES.lock().unwrap().clone()
+    }
 
     pub(crate) fn create_files_and_symlinks(
         path: &Path,
@@ -102,16 +443,87 @@ pub(crate) mod tests {
             let path = path.join(file_name);
             fs::create_dir_all(path.parent().unwrap())?;
             let mut file = File::create(&path)?;
-            file.write_all(&data)?;
+            file.write_all(data)?;
         }
 
         for (link_name, target) in symlinks {
-            symlink(&target, path.join(&link_name))?;
+            symlink(target, path.join(link_name))?;
         }
 
         Ok(())
     }
 
+    #[test]
+    fn test_is_access_allowed() -> Result<(), std::io::Error> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, b"")?;
+
+        let mut attr = convert_metadata_to_attr(fs::metadata(&file)?, None).unwrap();
+        attr.uid = 1000;
+        attr.gid = 100;
+        attr.perm = 0o640;
+
+        assert!(is_access_allowed(&attr, 1000, 1000, R_OK));
+        assert!(!is_access_allowed(&attr, 1000, 1000, X_OK));
+        assert!(is_access_allowed(&attr, 1001, 100, R_OK));
+        assert!(!is_access_allowed(&attr, 1001, 1001, R_OK));
+        assert!(is_access_allowed(&attr, 1001, 1001, F_OK));
+
+        // Root may read anything, but not execute a file without any execute bit
+        assert!(is_access_allowed(&attr, 0, 0, R_OK));
+        assert!(!is_access_allowed(&attr, 0, 0, X_OK));
+        attr.perm = 0o641;
+        assert!(is_access_allowed(&attr, 0, 0, R_OK | X_OK));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_root_dot_entries() -> Result<(), std::io::Error> {
+        // Lookups of . and .. in the root find the root itself, just like readdir lists them
+
+        let mirror = tempfile::tempdir()?;
+        fs::create_dir(mirror.path().join("dir"))?;
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let check = |fs: &dyn Fn(&str) -> Option<FileAttr>| {
+            assert_eq!(fs(".").map(|attr| attr.ino), Some(INO_ROOT));
+            assert_eq!(fs("..").map(|attr| attr.ino), Some(INO_ROOT));
+        };
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        check(&|name| fs.lookup_attr(INO_ROOT, OsStr::new(name)));
+
+        fs::write(
+            mirror.path().join(CONFIG_FILE_NAME),
+            serde_json::to_vec(&Config::default()).unwrap(),
+        )?;
+        fs::remove_file(mirror.path().join("file"))?;
+        fs::create_dir(mirror.path().join("file"))?;
+        fs::write(mirror.path().join("file/scfs.0000000000"), b"content")?;
+
+        let fs = CatFS::new(
+            mirror.path().as_os_str(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        check(&|name| fs.lookup_attr(INO_ROOT, OsStr::new(name)));
+
+        // Below the root, the kernel never asks for them
+        let dir = fs.lookup_attr(INO_ROOT, OsStr::new("dir")).unwrap();
+        assert!(fs.lookup_attr(dir.ino, OsStr::new("..")).is_none());
+
+        Ok(())
+    }
+
     pub(crate) fn check_symlinks(
         symlink_map: &mut HashMap<String, String, RandomState>,
         symlinks_found: &Vec<&DirEntry>,
@@ -131,4 +543,60 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    // Collects the inodes of all entries below the given directory, with their paths.
+    pub(crate) fn collect_inos(path: &Path) -> Result<Vec<(PathBuf, u64)>, std::io::Error> {
+        let mut inos = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let meta = fs::symlink_metadata(&path)?;
+            inos.push((path.clone(), meta.ino()));
+            if meta.is_dir() {
+                inos.extend(collect_inos(&path)?);
+            }
+        }
+        Ok(inos)
+    }
+
+    // Reads an extended attribute of a file, the name of a missing one is returned as error.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn get_xattr(path: &Path, name: &str) -> Result<Vec<u8>, std::io::Error> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let name = CString::new(name).unwrap();
+        let mut value = vec![0u8; 4096];
+        let len = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        value.truncate(len as usize);
+        Ok(value)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn list_xattrs(path: &Path) -> Result<Vec<String>, std::io::Error> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let mut names = vec![0u8; 4096];
+        let len = unsafe { libc::listxattr(path.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(names[..len as usize]
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8(name.to_vec()).unwrap())
+            .collect())
+    }
 }
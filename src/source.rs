@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::{Cursor, Read, Seek};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use fuser::{FileAttr, FileType};
+
+use crate::{convert_metadata_to_attr, open_mirror_file};
+
+/// The metadata of an entry of a [`MirrorSource`].
+#[derive(Clone, Copy, Debug)]
+pub struct MirrorMetadata {
+    /// The device of the entry. Together with the inode in `attr`, it identifies hard links and
+    /// symlinks to directories that have already been visited.
+    pub dev: u64,
+
+    /// The attributes of the entry, with the inode it has in the source. `None` for entries FUSE
+    /// cannot present, like sockets.
+    pub attr: Option<FileAttr>,
+}
+
+/// A file opened by a [`MirrorSource`].
+pub trait MirrorFile: Read + Seek {}
+
+impl<T: Read + Seek> MirrorFile for T {}
+
+/// Provides the entries SplitFS splits, which are the files and directories of the mirror on disk
+/// by default, see [`LocalMirror`].
+///
+/// The paths passed to the source are the path of the mirror given when mounting, joined with the
+/// paths of the entries inside of it.
+pub trait MirrorSource: Send + Sync {
+    /// Returns the paths of all entries of the directory at `path`.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Returns the metadata of the entry at `path`, without following symlinks.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<MirrorMetadata>;
+
+    /// Opens the regular file at `path` for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn MirrorFile>>;
+
+    /// Returns the metadata of the entry at `path`, following symlinks. Sources without symlinks
+    /// do not need to implement it.
+    fn metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        self.symlink_metadata(path)
+    }
+
+    /// Returns the target of the symlink at `path`. Sources without symlinks do not need to
+    /// implement it.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not a symlink", path),
+        ))
+    }
+}
+
+/// The default source, which reads the mirror from disk.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalMirror {
+    noatime: bool,
+}
+
+impl LocalMirror {
+    // With `noatime`, reads do not update the access times of the files, see open_mirror_file.
+    pub(crate) fn new(noatime: bool) -> Self {
+        LocalMirror { noatime }
+    }
+}
+
+fn local_metadata(meta: fs::Metadata) -> MirrorMetadata {
+    MirrorMetadata {
+        dev: meta.dev(),
+        attr: convert_metadata_to_attr(meta, None),
+    }
+}
+
+impl MirrorSource for LocalMirror {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?.map(|entry| Ok(entry?.path())).collect()
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        fs::symlink_metadata(path).map(local_metadata)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn MirrorFile>> {
+        Ok(Box::new(open_mirror_file(path, self.noatime)?))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        fs::metadata(path).map(local_metadata)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+}
+
+/// A mirror that only exists in memory, for example to split data that is not stored in a
+/// directory, or in tests.
+///
+/// The mirror consists of directories and regular files, which are owned by the current user and
+/// have the time of the creation of the mirror. Adding an entry adds its missing parents as well.
+///
+/// ```
+/// use scfs::MemoryMirror;
+///
+/// let mut mirror = MemoryMirror::new("/mirror");
+/// mirror.add_file("/mirror/dir/file", "content");
+/// mirror.add_dir("/mirror/empty");
+/// ```
+#[derive(Debug)]
+pub struct MemoryMirror {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, MemoryEntry>,
+    created: SystemTime,
+    uid: u32,
+    gid: u32,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    ino: u64,
+    node: MemoryNode,
+}
+
+#[derive(Debug)]
+enum MemoryNode {
+    Dir(BTreeSet<OsString>),
+    File(Arc<Vec<u8>>),
+}
+
+// Lets cursors share the content of a file with the mirror.
+struct SharedContent(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedContent {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl MemoryMirror {
+    /// Creates an empty mirror, whose root directory is at `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let mut mirror = MemoryMirror {
+            root: root.as_ref().to_path_buf(),
+            entries: BTreeMap::new(),
+            created: SystemTime::now(),
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+        };
+        mirror.insert(root.as_ref(), MemoryNode::Dir(BTreeSet::new()));
+        mirror
+    }
+
+    /// Adds an empty directory at `path`, which has to be inside of the root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` is outside of the root, or if it or one of its parents is a file.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let path = path.as_ref();
+        match self.entries.get(path) {
+            Some(MemoryEntry {
+                node: MemoryNode::Dir(_),
+                ..
+            }) => {}
+            Some(_) => panic!("{:?} is already a file", path),
+            None => self.add_entry(path, MemoryNode::Dir(BTreeSet::new())),
+        }
+        self
+    }
+
+    /// Adds a file at `path` with the given content, replacing a file that is already there.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` is outside of the root or a directory, or if one of its parents is a file.
+    pub fn add_file<P: AsRef<Path>, C: Into<Vec<u8>>>(&mut self, path: P, content: C) -> &mut Self {
+        let path = path.as_ref();
+        let content = MemoryNode::File(Arc::new(content.into()));
+        match self.entries.get_mut(path) {
+            Some(MemoryEntry {
+                node: MemoryNode::Dir(_),
+                ..
+            }) => panic!("{:?} is already a directory", path),
+            Some(entry) => entry.node = content,
+            None => self.add_entry(path, content),
+        }
+        self
+    }
+
+    fn add_entry(&mut self, path: &Path, node: MemoryNode) {
+        let parent = match path.parent() {
+            Some(parent) if path.starts_with(&self.root) && path != self.root => parent,
+            _ => panic!("{:?} is not inside of {:?}", path, self.root),
+        };
+        self.add_dir(parent);
+        match &mut self.entries.get_mut(parent).unwrap().node {
+            MemoryNode::Dir(children) => children.insert(path.file_name().unwrap().into()),
+            MemoryNode::File(_) => unreachable!("Parents are directories"),
+        };
+        self.insert(path, node);
+    }
+
+    fn insert(&mut self, path: &Path, node: MemoryNode) {
+        let ino = self.entries.len() as u64 + 1;
+        self.entries
+            .insert(path.to_path_buf(), MemoryEntry { ino, node });
+    }
+
+    fn get(&self, path: &Path) -> io::Result<&MemoryEntry> {
+        self.entries.get(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} does not exist", path),
+            )
+        })
+    }
+}
+
+impl MirrorSource for MemoryMirror {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        match &self.get(path)?.node {
+            MemoryNode::Dir(children) => Ok(children.iter().map(|name| path.join(name)).collect()),
+            MemoryNode::File(_) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{:?} is not a directory", path),
+            )),
+        }
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<MirrorMetadata> {
+        let entry = self.get(path)?;
+        let (kind, size, perm, nlink) = match &entry.node {
+            MemoryNode::Dir(children) => (FileType::Directory, 0, 0o755, 2 + children.len()),
+            MemoryNode::File(content) => (FileType::RegularFile, content.len(), 0o644, 1),
+        };
+
+        Ok(MirrorMetadata {
+            dev: 0,
+            attr: Some(FileAttr {
+                ino: entry.ino,
+                size: size as u64,
+                blocks: (size as u64).div_ceil(512),
+                atime: self.created,
+                mtime: self.created,
+                ctime: self.created,
+                crtime: self.created,
+                kind,
+                perm,
+                nlink: nlink as u32,
+                uid: self.uid,
+                gid: self.gid,
+                rdev: 0,
+                blksize: 4096,
+                flags: 0,
+            }),
+        })
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn MirrorFile>> {
+        match &self.get(path)?.node {
+            MemoryNode::File(content) => {
+                Ok(Box::new(Cursor::new(SharedContent(Arc::clone(content)))))
+            }
+            MemoryNode::Dir(_) => Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{:?} is a directory", path),
+            )),
+        }
+    }
+}
@@ -1,1025 +1,5395 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
-use std::{fs, thread};
-
+use std::fs;
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::consts::FOPEN_DIRECT_IO;
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
-    ReplyOpen, Request,
+    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyIoctl, ReplyOpen, ReplyStatfs, ReplyWrite,
+    ReplyXattr, Request, TimeOrNow,
 };
-use libc::ENOENT;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use libc::{c_int, EBADF, EIO, ENOENT, EROFS, ESTALE};
+use log::{debug, warn};
 use rusqlite::{params, Connection};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
+use crate::shared::is_writing_open;
 use crate::{
-    convert_filetype, convert_metadata_to_attr, Config, DropHookFn, FileHandle, FileInfo,
-    FileInfoRow, Shared, CONFIG_FILE_NAME, INO_CONFIG, INO_FIRST_FREE, INO_OUTSIDE, INO_ROOT,
-    STMT_CREATE, STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, STMT_INSERT, STMT_QUERY_BY_PARENT_INO,
-    TTL,
+    compress_chunk, dir_listing, flat_name, insert_file_info, insert_link, is_special_file,
+    limit_background_requests, pack_file_name, parse_flat_name, presented_root, read_errno,
+    read_segments, reply_dir_listing, split_ranges, time_from_system_time, tree_digest,
+    AttrOverrides, ChunkCache, ChunkStamp, Compression, Config, DirListing, DropHookFn, FileHandle,
+    FileInfo, FileInfoRow, FileMeta, IdleTimer, LocalMirror, MirrorMetadata, MirrorSource,
+    MountError, OpenFileLimit, PackEntry, PrefetchedMirror, ProgressReporter, ReadLimiter,
+    ReadStats, Settings, Shared, ThreadPool, BLOCKSIZES_MANIFEST_NAME, BLOCKSIZE_FILE_NAME,
+    CHECKSUM_SUFFIX, COUNT_FILE_NAME, INO_CONFIG, INO_FIRST_FREE, INO_FLAG_BLOCKSIZE,
+    INO_FLAG_COUNT, INO_FLAG_META, INO_FLAG_READS, INO_FLAG_WHOLE, INO_OUTSIDE,
+    INO_PREALLOCATED_END, INO_ROOT, INO_STATUS, IO_BUFFER_DEFAULT_SIZE, META_FILE_NAME,
+    PACK_INDEX_FILE_NAME, PLACEHOLDER_SUFFIX, READAHEAD_CACHE_SIZE, READS_FILE_NAME,
+    STATUS_FILE_NAME, STMT_COUNT_BY_PARENT_INO, STMT_COUNT_CHUNKS, STMT_COUNT_FILES, STMT_CREATE,
+    STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, STMT_CREATE_LINKS, STMT_CREATE_META,
+    STMT_CREATE_STAMPS, STMT_DROP, STMT_INSERT_META, STMT_INSERT_STAMP, STMT_QUERY_ALL,
+    STMT_QUERY_BY_INO, STMT_QUERY_BY_PARENT_INO, STMT_QUERY_LINKS, STMT_QUERY_LINK_TARGET,
+    STMT_QUERY_META, STMT_QUERY_PARTS, STMT_QUERY_STAMP, STMT_QUERY_TREE_DIGEST,
+    STMT_UPDATE_TREE_DIGEST, TTL, WHOLE_FILE_NAME, XATTR_LENGTH, XATTR_OFFSET, XATTR_PART,
+    XATTR_SOURCE,
 };
 
 pub(crate) struct SplitFS {
     file_db: Connection,
     file_handles: HashMap<u64, FileHandle>,
+    dir_handles: HashMap<u64, DirListing>,
     next_fh: u64,
     config: Config,
     config_json: String,
-    drop_hook: DropHookFn,
+
+    // Blocksizes from the blocksize manifest, keyed by the path of the file
+    blocksizes: HashMap<OsString, u64>,
+
+    settings: Settings,
+
+    // Provides the entries of the mirror, shared with the read threads
+    source: Arc<dyn MirrorSource>,
+
+    packs: Packs,
+    compressed_sizes: RefCell<HashMap<u64, u64>>,
+
+    // Bytes of chunks and packs read since mounting, updated from the read threads
+    bytes_read: Arc<AtomicU64>,
+
+    // Only kept with emit_reads
+    read_stats: Option<Arc<ReadStats>>,
+
+    // Chunks read ahead of their first request, only used with the readahead setting
+    readahead: Option<Arc<ChunkCache>>,
+
+    // Shared by the reads of all file handles, so the read limit applies to the whole mount
+    limiter: Arc<ReadLimiter>,
+    open_limit: Arc<OpenFileLimit>,
+
+    attr_overrides: AttrOverrides,
+
+    pool: ThreadPool,
+    idle: IdleTimer,
+    drop_hook: Arc<DropHookFn>,
 }
 
-impl Shared for SplitFS {
-    fn file_db(&self) -> &Connection {
-        &self.file_db
-    }
+// Identifies a database built for a mirror, see SplitFS::is_db_current.
+type DbSnapshot = (
+    Vec<u8>,
+    i64,
+    i64,
+    String,
+    bool,
+    String,
+    bool,
+    bool,
+    bool,
+    String,
+    u64,
+    bool,
+);
+
+fn is_checksum(file_info: &FileInfo) -> bool {
+    file_info.part > 0
+        && file_info
+            .file_name
+            .as_bytes()
+            .ends_with(CHECKSUM_SUFFIX.as_bytes())
+}
 
-    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> FileAttr {
-        if file_info.symlink {
-            let attr = convert_metadata_to_attr(
-                fs::symlink_metadata(&file_info.path).unwrap(),
-                Some(file_info.ino),
-            );
-            attr
-        } else if file_info.part == 0 {
-            let mut attr = convert_metadata_to_attr(
-                fs::symlink_metadata(&file_info.path).unwrap(),
-                Some(file_info.ino),
-            );
-            attr.kind = FileType::Directory;
-            attr.blocks = 0;
-            attr.perm = 0o755;
-            attr
-        } else {
-            let mut attr = convert_metadata_to_attr(
-                fs::symlink_metadata(
-                    self.get_file_info_from_ino(file_info.parent_ino)
-                        .unwrap()
-                        .path,
-                )
-                .unwrap(),
-                Some(file_info.ino),
-            );
-            attr.size = u64::min(
-                self.config.blocksize,
-                attr.size - (file_info.part - 1) * self.config.blocksize,
-            );
-            attr
+// The name of the chunk that a checksum file belongs to, which is its own name without the suffix.
+fn checksummed_chunk_name(file_info: &FileInfo) -> &OsStr {
+    let name = file_info.file_name.as_bytes();
+    OsStr::from_bytes(&name[..name.len() - CHECKSUM_SUFFIX.len()])
+}
+
+// Reads the content of a chunk like it is presented, which is compressed with the compression
+// setting.
+fn load_chunk(
+    file: &OsStr,
+    start: u64,
+    end: u64,
+    compression: Option<Compression>,
+    source: &dyn MirrorSource,
+) -> io::Result<Vec<u8>> {
+    match compression {
+        Some(compression) => compress_chunk(file, start, end, compression, source),
+        None => {
+            let mut file = source.open(Path::new(file))?;
+            file.seek(SeekFrom::Start(start))?;
+            let mut content = Vec::new();
+            file.take(end - start).read_to_end(&mut content)?;
+            Ok(content)
         }
     }
 }
 
-impl SplitFS {
-    pub(crate) fn new(mirror: &OsStr, config: Config, drop_hook: DropHookFn) -> Self {
-        let file_db = Connection::open_in_memory().unwrap();
-
-        file_db.execute(STMT_CREATE, []).unwrap();
+// Synthetic files take their attributes from real ones, which may have been replaced by an
+// unsupported file type.
+fn reply_entry(attr: Option<FileAttr>, ttl: Duration, reply: ReplyEntry) {
+    match attr {
+        Some(attr) => reply.entry(&ttl, &attr, 0),
+        None => reply.error(ENOENT),
+    }
+}
 
-        SplitFS::populate(&file_db, &mirror, &config, INO_OUTSIDE, INO_FIRST_FREE);
+// Sockets are the only file type FUSE cannot present at all.
+fn describe_file_type(kind: Option<FileType>) -> &'static str {
+    match kind {
+        None => "socket",
+        Some(FileType::NamedPipe) => "FIFO",
+        Some(FileType::CharDevice) => "character device",
+        Some(FileType::BlockDevice) => "block device",
+        Some(_) => "unknown",
+    }
+}
 
-        file_db
-            .execute(STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, [])
-            .unwrap();
+// Reads the blocksize manifest of the mirror, if there is one. The paths in the manifest are
+// relative to the mirror, the returned map is keyed by the full paths. The manifest is returned
+// as well, so that a changed manifest leads to a new database.
+fn read_blocksizes(
+    mirror: &OsStr,
+    source: &dyn MirrorSource,
+) -> Result<(HashMap<OsString, u64>, String), MountError> {
+    let manifest = Path::new(mirror).join(BLOCKSIZES_MANIFEST_NAME);
+    if !source.metadata(&manifest).is_ok_and(|meta| {
+        meta.attr
+            .is_some_and(|attr| attr.kind == FileType::RegularFile)
+    }) {
+        return Ok(Default::default());
+    }
 
-        let file_handles = Default::default();
+    let mut json = String::new();
+    source.open(&manifest)?.read_to_string(&mut json)?;
+    let blocksizes: HashMap<PathBuf, u64> =
+        serde_json::from_str(&json).map_err(|e| MountError::InvalidJson(manifest, e))?;
+
+    let blocksizes = blocksizes
+        .into_iter()
+        .map(|(file, blocksize)| {
+            if blocksize == 0 {
+                return Err(MountError::InvalidConfig(format!(
+                    "Blocksize of {:?} may not be zero",
+                    file
+                )));
+            }
+            Ok((Path::new(mirror).join(file).into_os_string(), blocksize))
+        })
+        .collect::<Result<_, _>>()?;
 
-        let config_json = serde_json::to_string(&config).unwrap();
+    Ok((blocksizes, json))
+}
 
-        SplitFS {
-            file_db,
-            file_handles,
-            next_fh: 0,
-            config,
-            config_json,
-            drop_hook,
+// With the follow_symlinks setting, symlinks are presented like their targets. Broken symlinks and
+// symlinks to one of the ancestor directories, which would lead into an endless loop, are still
+// presented as they are.
+fn populate_metadata(
+    path: &Path,
+    settings: &Settings,
+    source: &dyn MirrorSource,
+    ancestors: &HashSet<(u64, u64)>,
+) -> MirrorMetadata {
+    if settings.follow_symlinks {
+        if let Ok(meta) = source.metadata(path) {
+            let is_ancestor = meta.attr.is_some_and(|attr| {
+                attr.kind == FileType::Directory && ancestors.contains(&(meta.dev, attr.ino))
+            });
+            if !is_ancestor {
+                return meta;
+            }
         }
     }
 
-    fn get_config_attr(&self) -> FileAttr {
-        let file_info = self.get_file_info_from_ino(INO_ROOT).unwrap();
-        let mut attr = self.get_attr_from_file_info(&file_info);
-        attr.ino = INO_CONFIG;
-        attr.size = self.config_json.len() as u64;
-        attr.blocks = 1;
-        attr.kind = FileType::RegularFile;
-        attr
+    source.symlink_metadata(path).unwrap()
+}
+
+// Records the size and the mtime of a regular file of the mirror, see SplitFS::is_stale.
+// Entries get consecutive inodes in the order they are indexed. With preallocated inodes, the
+// inode is a hash of the path relative to the root and the part instead, so that every mount of
+// the same mirror presents the same inodes. The rare collisions are resolved by probing the
+// following inodes.
+fn allocate_ino(
+    file_db: &Connection,
+    settings: &Settings,
+    path: &Path,
+    part: u64,
+    next_ino: &mut u64,
+) -> u64 {
+    if !settings.preallocate_inodes {
+        let ino = *next_ino;
+        *next_ino += 1;
+        return ino;
     }
 
-    fn populate<P: AsRef<Path>>(
-        file_db: &Connection,
-        path: P,
-        config: &Config,
-        parent_ino: u64,
-        mut next_ino: u64,
-    ) -> u64 {
-        let path = path.as_ref();
+    let mut query = file_db.prepare_cached(STMT_QUERY_BY_INO).unwrap();
+    let root = query
+        .query_row(params![INO_ROOT as i64], |row| Ok(FileInfo::from(row)))
+        .unwrap()
+        .path;
+    let relative = path.strip_prefix(&root).unwrap_or(path);
+
+    // Paths never contain a null byte, so it separates them from the part
+    let digest = Sha256::new()
+        .chain_update(relative.as_os_str().as_bytes())
+        .chain_update([0])
+        .chain_update(part.to_le_bytes())
+        .finalize();
+    let range = INO_PREALLOCATED_END - INO_FIRST_FREE;
+    let mut ino = u64::from_le_bytes(digest[..8].try_into().unwrap()) % range;
+    while query
+        .exists(params![(INO_FIRST_FREE + ino) as i64])
+        .unwrap()
+    {
+        ino = (ino + 1) % range;
+    }
+    INO_FIRST_FREE + ino
+}
 
-        let meta = path.symlink_metadata().unwrap();
+fn insert_stamp(file_db: &Connection, attr: &FileAttr) {
+    let (mtime, mtime_nsec) = time_from_system_time(attr.mtime);
+    file_db
+        .prepare_cached(STMT_INSERT_STAMP)
+        .unwrap()
+        .execute(params![
+            attr.ino as i64,
+            attr.size as i64,
+            mtime,
+            mtime_nsec
+        ])
+        .unwrap();
+}
 
-        if let None = convert_filetype(meta.file_type()) {
-            return next_ino;
-        }
+/// Decides which entries of the mirror are left out. The patterns are matched against the path
+/// relative to the mirror, `*` does not match across directories, but `**` does.
+pub(crate) struct Exclude {
+    mirror: PathBuf,
+    globs: GlobSet,
+}
 
-        let mut attr = convert_metadata_to_attr(meta, None);
+impl Exclude {
+    pub(crate) fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+        GlobBuilder::new(pattern).literal_separator(true).build()
+    }
 
-        attr.ino = if parent_ino == INO_OUTSIDE {
-            INO_ROOT
-        } else {
-            let ino = next_ino;
-            next_ino += 1;
-            ino
+    fn new<P: AsRef<Path>>(mirror: P, patterns: &[String]) -> Result<Self, MountError> {
+        let invalid = |e: globset::Error| {
+            MountError::InvalidConfig(format!("Invalid exclude pattern: {}", e))
         };
 
-        let file_info = FileInfoRow::from(FileInfo {
-            ino: attr.ino,
-            parent_ino,
-            path: OsString::from(path),
-            file_name: path.file_name().unwrap().into(),
-            part: 0,
-            vdir: attr.kind == FileType::RegularFile,
-            symlink: attr.kind == FileType::Symlink,
-        });
+        let mut globs = GlobSetBuilder::new();
+        for pattern in patterns {
+            globs.add(Exclude::glob(pattern).map_err(invalid)?);
+        }
 
-        file_db
-            .prepare_cached(STMT_INSERT)
-            .unwrap()
-            .execute(params![
-                file_info.ino,
-                file_info.parent_ino,
-                file_info.path,
-                file_info.file_name,
-                file_info.part,
-                file_info.vdir,
-                file_info.symlink,
-            ])
-            .unwrap();
+        Ok(Exclude {
+            mirror: mirror.as_ref().to_path_buf(),
+            globs: globs.build().map_err(invalid)?,
+        })
+    }
 
-        match attr.kind {
-            FileType::RegularFile => {
-                // Create at least one chunk, even if it is empty. This way, we can differentiate
-                // between an empty file and an empty directory.
-                let blocks = 1.max(f64::ceil(attr.size as f64 / config.blocksize as f64) as u64);
-                for i in 0..blocks {
-                    let file_name = format!("scfs.{:010}", i).into();
-                    let file_info = FileInfoRow::from(FileInfo {
-                        ino: {
-                            let ino = attr.ino + i + 1;
-                            next_ino = ino + 1;
-                            ino
-                        },
-                        parent_ino: attr.ino,
-                        path: OsString::from(path.join(&file_name)),
-                        file_name,
-                        part: i + 1,
-                        vdir: false,
-                        symlink: false,
-                    });
-
-                    file_db
-                        .prepare_cached(STMT_INSERT)
-                        .unwrap()
-                        .execute(params![
-                            file_info.ino,
-                            file_info.parent_ino,
-                            file_info.path,
-                            file_info.file_name,
-                            file_info.part,
-                            file_info.vdir,
-                            file_info.symlink,
-                        ])
-                        .unwrap();
-                }
-            }
+    fn is_excluded<P: AsRef<Path>>(&self, path: P) -> bool {
+        path.as_ref()
+            .strip_prefix(&self.mirror)
+            .is_ok_and(|path| self.globs.is_match(path))
+    }
+}
 
-            FileType::Directory => {
-                for entry in fs::read_dir(path).unwrap() {
-                    let entry = entry.unwrap();
-                    next_ino =
-                        SplitFS::populate(&file_db, entry.path(), &config, attr.ino, next_ino);
-                }
-            }
+// Runtime statistics, served as JSON in .scfs_status. Packed files count as files, but their
+// pack objects do not count as chunks.
+#[derive(Serialize)]
+struct Status {
+    files: u64,
+    chunks: u64,
+    open_handles: u64,
+    bytes_read: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readahead_hits: Option<u64>,
+}
 
-            _ => {}
-        }
+// The reads of the chunks of a file, served as JSON in .scfs_reads. Chunks that have not been read
+// yet are listed with zero reads.
+#[derive(Default, Serialize)]
+struct FileReads {
+    reads: BTreeMap<String, u64>,
+    bytes_read: u64,
+}
 
-        next_ino
-    }
+// A line of the manifest, see SplitFS::write_manifest.
+#[derive(Serialize)]
+struct ManifestEntry {
+    path: String,
+    file: String,
+    index: u64,
+    offset: u64,
+    length: u64,
 }
 
-impl Drop for SplitFS {
-    fn drop(&mut self) {
-        let _ = &(self.drop_hook)();
-    }
+// Pack objects and their indices, only used with the pack_small setting.
+#[derive(Default)]
+struct Packs {
+    // Maps the inode of a pack object to the files it consists of
+    objects: HashMap<u64, Vec<FileHandle>>,
+
+    // Maps the inode of a pack index to its JSON content
+    indices: HashMap<u64, String>,
 }
 
-impl Filesystem for SplitFS {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        if parent == INO_ROOT && name == CONFIG_FILE_NAME {
-            let attr = self.get_config_attr();
-            reply.entry(&TTL, &attr, 0);
-            return;
-        }
+impl Shared for SplitFS {
+    const SUBTYPE: &'static str = "splitfs";
 
-        Shared::lookup(self, _req, parent, name, reply);
+    fn file_db(&self) -> &Connection {
+        &self.file_db
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        if ino == INO_CONFIG {
-            let attr = self.get_config_attr();
-            reply.attr(&TTL, &attr);
-            return;
-        }
-
-        Shared::getattr(self, _req, ino, reply);
+    fn ttl(&self) -> Duration {
+        self.settings.attr_ttl.unwrap_or(TTL)
     }
 
-    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
-        Shared::readlink(self, _req, ino, reply);
+    fn attr_overrides(&self) -> &AttrOverrides {
+        &self.attr_overrides
     }
 
-    fn open(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
-        if ino == INO_CONFIG {
-            reply.opened(0, 0);
-            return;
-        }
+    fn attr_overrides_mut(&mut self) -> &mut AttrOverrides {
+        &mut self.attr_overrides
+    }
 
-        let file_info = self.get_file_info_from_ino(ino);
-        if let Ok(file_info) = file_info {
-            let file = self
-                .get_file_info_from_ino(file_info.parent_ino)
-                .unwrap()
-                .path;
+    fn preferred_block_size(&self) -> Option<u64> {
+        Some(self.config.blocksize)
+    }
 
-            let start = (file_info.part - 1) * self.config.blocksize;
-            let end = start + self.config.blocksize;
-            let fh = self.next_fh;
-            self.next_fh += 1;
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.source.read_link(path)
+    }
 
-            self.file_handles
-                .insert(fh, FileHandle { file, start, end });
+    // Chunks tell which range of which file of the mirror they contain. The part counts from zero,
+    // like the names of the chunks.
+    fn get_xattrs(&self, ino: u64) -> Option<Vec<(&'static str, Vec<u8>)>> {
+        if self.is_synthetic(ino)
+            || ino == INO_STATUS
+            || ino & (INO_FLAG_READS | INO_FLAG_WHOLE) != 0
+        {
+            return Some(Vec::new());
+        }
 
-            reply.opened(fh, 0);
-        } else {
-            reply.error(ENOENT)
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        if file_info.part == 0 || is_checksum(&file_info) {
+            return Some(Vec::new());
         }
+
+        let file = self
+            .get_file_info_from_ino(file_info.parent_ino)
+            .unwrap()
+            .path;
+        let size = self
+            .source
+            .metadata(Path::new(&file))
+            .ok()?
+            .attr
+            .map_or(0, |attr| attr.size);
+        let (start, end) = self.file_config(&file).chunk_span(file_info.part - 1, size);
+
+        Some(vec![
+            (XATTR_SOURCE, file.as_bytes().to_vec()),
+            (XATTR_PART, (file_info.part - 1).to_string().into_bytes()),
+            (XATTR_OFFSET, start.to_string().into_bytes()),
+            (XATTR_LENGTH, (end - start).to_string().into_bytes()),
+        ])
     }
 
-    fn read(
-        &mut self,
-        _req: &Request<'_>,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        size: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyData,
-    ) {
+    fn get_attr_from_ino(&self, ino: u64) -> Option<FileAttr> {
         if ino == INO_CONFIG {
-            reply.data(self.config_json.as_ref());
-            return;
+            return self.get_config_attr();
         }
 
-        let offset = offset as u64;
-        let size = size as u64;
+        if ino == INO_STATUS {
+            return self.get_status_attr();
+        }
 
-        let handle = self.file_handles.get(&fh).unwrap();
-        let file = handle.file.clone();
+        if ino & INO_FLAG_COUNT != 0 {
+            return self.get_count_attr(ino & !INO_FLAG_COUNT);
+        }
 
-        let offset = offset.min(handle.end - handle.start);
-        let size = size.min(handle.end - handle.start - offset);
-        let start = handle.start;
+        if ino & INO_FLAG_META != 0 {
+            return self.get_meta_attr(ino & !INO_FLAG_META);
+        }
 
-        thread::spawn(move || {
-            let mut file = BufReader::new(File::open(file).unwrap());
+        if ino & INO_FLAG_BLOCKSIZE != 0 {
+            return self.get_blocksize_attr(ino & !INO_FLAG_BLOCKSIZE);
+        }
 
-            file.seek(SeekFrom::Start(start + offset)).unwrap();
+        if ino & INO_FLAG_READS != 0 {
+            return self.get_reads_attr(ino & !INO_FLAG_READS);
+        }
 
-            let bytes = file
-                .take(size)
-                .bytes()
-                .map(|b| b.unwrap())
-                .collect::<Vec<_>>();
+        if ino & INO_FLAG_WHOLE != 0 {
+            return self.get_whole_attr(ino & !INO_FLAG_WHOLE);
+        }
 
-            reply.data(&bytes);
-        });
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        self.get_attr_from_file_info(&file_info)
     }
 
-    fn release(
-        &mut self,
-        _req: &Request<'_>,
-        ino: u64,
-        fh: u64,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        _flush: bool,
-        reply: ReplyEmpty,
-    ) {
-        if ino == INO_CONFIG {
-            reply.ok();
-            return;
-        }
+    fn get_attr_from_file_info(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        let mut attr = self.get_mirror_attr(file_info)?;
+        attr.uid = self.settings.uid_map.map(attr.uid);
+        attr.gid = self.settings.gid_map.map(attr.gid);
 
-        self.file_handles.remove(&fh);
-        reply.ok();
+        // The chunks of a split file are shared by all of its paths in the mirror
+        if file_info.vdir {
+            attr.nlink = self.count_links(self.get_link_target(file_info.ino));
+        } else if file_info.part > 0 {
+            attr.nlink = self.count_links(file_info.parent_ino);
+        }
+        Some(attr)
     }
+}
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
-        let file_info = self.get_file_info_from_ino(ino);
+impl SplitFS {
+    pub(crate) fn new(
+        mirror: &OsStr,
+        config: Config,
+        mut settings: Settings,
+        drop_hook: DropHookFn,
+    ) -> Result<Self, MountError> {
+        let invalid = |message: &str| Err(MountError::InvalidConfig(String::from(message)));
+
+        // Deserialized configs do not go through the builder
+        if config.blocksize == 0 {
+            return invalid("Blocksize may not be zero");
+        }
 
-        if let Ok(file_info) = file_info {
-            // . and .. make 2 and optionally 1 for .scfs_config
-            let additional_offset_max = 2 + if file_info.ino == 1 { 1 } else { 0 };
-
-            let mut additional_offset = 0;
-            if offset < 3 {
-                if offset < 1 {
-                    if reply.add(file_info.ino, 1, FileType::Directory, ".") {
-                        unreachable!()
-                    }
-                    additional_offset += 1;
-                }
+        if config.parts == Some(0) {
+            return invalid("Number of parts may not be zero");
+        }
 
-                if offset < 2 {
-                    if reply.add(
-                        if file_info.parent_ino == INO_OUTSIDE {
-                            file_info.ino
-                        } else {
-                            file_info.parent_ino
-                        },
-                        2,
-                        FileType::Directory,
-                        "..",
-                    ) {
-                        unreachable!()
-                    }
-                    additional_offset += 1;
-                }
+        if config.flat && config.min_split_size.is_some() {
+            return invalid("Flat mirrors cannot contain files below the split size");
+        }
 
-                if offset < 3 {
-                    if file_info.ino == INO_ROOT {
-                        if reply.add(INO_CONFIG, 3, FileType::RegularFile, CONFIG_FILE_NAME) {
-                            unreachable!()
-                        }
-                        additional_offset += 1;
-                    }
-                }
+        if config.flat && config.chunked_suffix.is_some() {
+            return invalid("Flat mirrors have no chunked files to mark");
+        }
+
+        let source = settings
+            .mirror_source
+            .take()
+            .unwrap_or_else(|| Arc::new(LocalMirror::new(settings.noatime)));
+
+        let (mut blocksizes, blocksizes_json) = read_blocksizes(mirror, source.as_ref())?;
+
+        if config.flat && (!blocksizes.is_empty() || config.parts.is_some()) {
+            return invalid("Flat mirrors cannot contain files with their own blocksize");
+        }
+
+        let file_db = match &settings.db_path {
+            Some(db_path) => Connection::open(db_path).unwrap(),
+            None => Connection::open_in_memory().unwrap(),
+        };
+
+        let mut packs = Packs::default();
+
+        // The manifest and the excludes still refer to the whole mirror
+        let root = presented_root(
+            Path::new(mirror),
+            settings.subdir.as_deref(),
+            source.as_ref(),
+        )?;
+
+        let mtime = source
+            .metadata(&root)?
+            .attr
+            .map_or(UNIX_EPOCH, |attr| attr.mtime);
+        let (mtime, mtime_nsec) = time_from_system_time(mtime);
+        let snapshot = (
+            root.as_os_str().as_bytes().to_vec(),
+            mtime,
+            mtime_nsec,
+            serde_json::to_string(&config).unwrap(),
+            settings.checksums,
+            settings.exclude.join("\n"),
+            settings.follow_symlinks,
+            settings.special_files,
+            settings.placeholder,
+            blocksizes_json,
+            settings.ino_offset,
+            settings.preallocate_inodes,
+        );
+
+        if settings.db_path.is_none()
+            || !SplitFS::is_db_current(&file_db, &snapshot, settings.detect_changes)
+        {
+            debug!("Indexing {:?}", mirror);
+
+            file_db.execute_batch(STMT_DROP).unwrap();
+            file_db.execute(STMT_CREATE, []).unwrap();
+            file_db.execute(STMT_CREATE_LINKS, []).unwrap();
+            if settings.detect_changes {
+                file_db.execute(STMT_CREATE_STAMPS, []).unwrap();
             }
 
-            let mut stmt = self
-                .file_db
-                .prepare_cached(STMT_QUERY_BY_PARENT_INO)
+            // A single transaction makes a huge difference for databases on disk
+            file_db.execute_batch("BEGIN").unwrap();
+            let mut progress = ProgressReporter::new(settings.progress.take());
+            let exclude = Exclude::new(mirror, &settings.exclude)?;
+            let prefetched = PrefetchedMirror::walk(
+                source.as_ref(),
+                &root,
+                settings.follow_symlinks,
+                settings.index_threads(),
+                &|path| exclude.is_excluded(path),
+            );
+            SplitFS::populate(
+                &file_db,
+                &root,
+                &config,
+                &blocksizes,
+                &settings,
+                &prefetched,
+                &exclude,
+                &mut HashSet::new(),
+                &mut HashMap::new(),
+                &mut packs,
+                &mut progress,
+                INO_OUTSIDE,
+                settings.first_free_ino(),
+            );
+            progress.finish();
+            file_db.execute_batch("COMMIT").unwrap();
+
+            file_db
+                .execute(STMT_CREATE_INDEX_PARENT_INO_FILE_NAME, [])
                 .unwrap();
-            let items = stmt
-                .query_map(
+
+            file_db.execute(STMT_CREATE_META, []).unwrap();
+            file_db
+                .execute(
+                    STMT_INSERT_META,
                     params![
-                        FileInfoRow::from(FileInfo::with_parent_ino(file_info.ino)).parent_ino,
-                        // The offset includes . and .., both which are not included in the
-                        // database, so the SELECT offset must be adjusted. Since the offset could
-                        // be negative, set it to 0 in that case.
-                        0.max(offset - additional_offset_max)
+                        snapshot.0,
+                        snapshot.1,
+                        snapshot.2,
+                        snapshot.3,
+                        snapshot.4,
+                        snapshot.5,
+                        snapshot.6,
+                        snapshot.7,
+                        snapshot.8,
+                        snapshot.9,
+                        snapshot.10,
+                        snapshot.11
                     ],
-                    |row| Ok(FileInfo::from(row)),
                 )
                 .unwrap();
-            for (off, item) in items.enumerate() {
-                let item = item.unwrap();
-
-                // Here the item is added to the directory listing. It is important to note that
-                // the offset parameter is quite crucial for correct function. The offset parameter
-                // is used for succeeding calls to start with the next item after the last item
-                // from the previous call. So, the offset parameter has to be one more than the
-                // index of the current item. Furthermore, since "." and ".." have been added
-                // manually as to not pollute the database with them, they also have to be handled
-                // properly. They always get inserted in positions 0 and 1 respectively. If the
-                // call starts at offset 0, then both of the directory hardlinks are included and
-                // the offset must be increased by 2. If the starting offset is 1, then only "."
-                // has been already added. For the additional "..", the offset has to be increased
-                // by 1. If the offset is greater than 1, then the hardlinks have been taken care
-                // of and the offset is already correct.
-                let is_full = reply.add(
-                    item.ino,
-                    offset + additional_offset + off as i64 + 1,
-                    if item.symlink {
-                        FileType::Symlink
-                    } else if item.part > 0 {
-                        FileType::RegularFile
-                    } else {
-                        FileType::Directory
-                    },
-                    Path::new(&item.path).file_name().unwrap(),
-                );
-
-                if is_full {
-                    break;
-                }
-            }
-            reply.ok();
         } else {
-            reply.error(ENOENT);
+            debug!("Reusing database for {:?}", mirror);
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs::{read, DirEntry};
-    use std::path::PathBuf;
+        if config.parts.is_some() {
+            SplitFS::insert_part_blocksizes(&file_db, &config, source.as_ref(), &mut blocksizes);
+        }
 
-    use fuser::BackgroundSession;
-    use rand::{Rng, RngCore};
-    use tempfile::{tempdir, TempDir};
+        let file_handles = Default::default();
 
-    use crate::mount;
-    use crate::shared::tests::{check_symlinks, create_files_and_symlinks};
+        let pool = settings.thread_pool();
 
-    use super::*;
+        let readahead =
+            (settings.readahead > 0).then(|| Arc::new(ChunkCache::new(READAHEAD_CACHE_SIZE)));
 
-    // Helper struct to keep necessary variables in scope. To not make the compiler complain,
-    // prefix them with an underscore. If for example the TempDir variables are not kept in scope
-    // this way, the directories would be deleted before the tests can be run.
-    struct TempSession {
-        _session: BackgroundSession,
-        _mirror: TempDir,
-        pub(crate) mountpoint: TempDir,
-    }
+        let limiter = Arc::new(ReadLimiter::new(settings.read_limit));
+        let open_limit = Arc::new(OpenFileLimit::new(settings.max_open_files));
+        let attr_overrides = AttrOverrides::new(settings.lenient_writes);
+        let read_stats = settings.emit_reads.then(|| Arc::new(ReadStats::default()));
 
-    fn mount_and_create_files_with_symlinks<'a>(
-        files: Vec<(String, Vec<u8>)>,
-        symlinks: Vec<(String, String)>,
-        config: Option<Config>,
-    ) -> Result<TempSession, std::io::Error> {
-        let mirror = tempdir()?;
-        let mountpoint = tempdir()?;
+        let drop_hook = Arc::new(drop_hook);
+        let idle = IdleTimer::new(settings.idle_timeout, Arc::clone(&drop_hook));
 
-        create_files_and_symlinks(mirror.path(), &files, &symlinks)?;
+        let mut fs = SplitFS {
+            file_db,
+            file_handles,
+            dir_handles: Default::default(),
+            next_fh: 0,
+            config,
+            config_json: String::new(),
+            blocksizes,
+            settings,
+            source,
+            packs,
+            compressed_sizes: Default::default(),
+            bytes_read: Default::default(),
+            read_stats,
+            readahead,
+            limiter,
+            open_limit,
+            attr_overrides,
+            pool,
+            idle,
+            drop_hook,
+        };
+
+        // Computing the digest looks at every file of the mirror, so it is kept in the database
+        // and only computed again together with the index
+        let tree_digest = fs
+            .file_db
+            .query_row(STMT_QUERY_TREE_DIGEST, [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .unwrap();
+        let tree_digest = match tree_digest {
+            Some(tree_digest) => tree_digest,
+            None => {
+                let tree_digest = fs.get_tree_digest();
+                fs.file_db
+                    .execute(STMT_UPDATE_TREE_DIGEST, params![tree_digest])
+                    .unwrap();
+                tree_digest
+            }
+        };
+        fs.config.tree_digest = Some(tree_digest);
+        fs.config_json = serde_json::to_string(&fs.config).unwrap();
+
+        Ok(fs)
+    }
+
+    // The attributes like they are derived from the mirror, before mapping their owner and group.
+    fn get_mirror_attr(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        if self.packs.objects.contains_key(&file_info.ino)
+            || self.packs.indices.contains_key(&file_info.ino)
+        {
+            self.get_pack_attr(file_info)
+        } else if self.is_placeholder(file_info) {
+            self.get_placeholder_attr(file_info)
+        } else if file_info.symlink {
+            self.get_source_attr(&file_info.path, false, file_info.ino)
+        } else if file_info.part == 0 {
+            let mut attr = self.get_source_attr(&file_info.path, true, file_info.ino)?;
+            if (attr.kind == FileType::RegularFile && !file_info.vdir) || is_special_file(attr.kind)
+            {
+                // A file below the split size or a special file
+                return Some(attr);
+            }
+            attr.kind = FileType::Directory;
+            attr.blocks = 0;
+            attr.perm = 0o755;
+            Some(attr)
+        } else {
+            let file = self
+                .get_file_info_from_ino(file_info.parent_ino)
+                .unwrap()
+                .path;
+            let config = self.file_config(&file);
+            let mut attr = self.get_source_attr(&file, true, file_info.ino)?;
+            // The file may have shrunk since mounting, parts beyond its end are empty
+            let (start, end) = config.chunk_span(file_info.part - 1, attr.size);
+            attr.size = end - start;
+            if is_checksum(file_info) {
+                // The hex digest, two spaces, the chunk's name and a newline
+                attr.size = 64 + 2 + checksummed_chunk_name(file_info).len() as u64 + 1;
+                attr.blocks = 1;
+            } else if self.config.compression.is_some() {
+                attr.size = self.get_compressed_size(file_info);
+                attr.blocks = attr.size.div_ceil(512);
+            }
+            Some(attr)
+        }
+    }
+
+    // The attributes of an entry of the mirror, presented with the given inode. Like the metadata
+    // of the file system, following symlinks or not.
+    fn get_source_attr<P: AsRef<Path>>(&self, path: P, follow: bool, ino: u64) -> Option<FileAttr> {
+        let meta = if follow {
+            self.source.metadata(path.as_ref())
+        } else {
+            self.source.symlink_metadata(path.as_ref())
+        };
+        // Entries may have vanished from the mirror since indexing
+        let mut attr = meta.ok()?.attr?;
+        attr.ino = ino;
+        Some(attr)
+    }
+
+    // Once the kernel forgets an inode, everything cached about it in memory is dropped. It can be
+    // computed again if the inode is looked up later, while the database entries stay.
+    fn forget_inode(&mut self, ino: u64) {
+        self.compressed_sizes.borrow_mut().remove(&ino);
+        if let Some(cache) = &self.readahead {
+            cache.remove(ino);
+        }
+    }
+
+    // Reads the chunks following the given one in the background, so that they are already in
+    // memory when they get opened.
+    fn read_ahead(&self, file_info: &FileInfo, file: &OsStr, source: ChunkStamp, len: u64) {
+        let cache = match &self.readahead {
+            Some(cache) => cache,
+            None => return,
+        };
+
+        let config = self.file_config(file);
+        for part in file_info.part + 1..=file_info.part + self.settings.readahead {
+            let (start, end) = config.chunk_span(part - 1, len);
+            if start >= end {
+                break;
+            }
+
+            let ino = match self.get_file_info_from_parent_ino_and_file_name(
+                file_info.parent_ino,
+                config.chunk_file_name(part - 1, len),
+            ) {
+                Ok(chunk_info) => chunk_info.ino,
+                Err(_) => break,
+            };
+            if cache.contains(ino, source) {
+                continue;
+            }
+
+            let cache = Arc::clone(cache);
+            let file = file.to_os_string();
+            let compression = self.config.compression;
+            let mirror = Arc::clone(&self.source);
+            let open_limit = Arc::clone(&self.open_limit);
+            self.pool.execute(move || {
+                let content =
+                    open_limit.run(|| load_chunk(&file, start, end, compression, mirror.as_ref()));
+                if let Ok(content) = content {
+                    cache.insert(ino, source, content);
+                }
+            });
+        }
+    }
+
+    // A database is only reused if it was created for the same mirror with the same blocksize and
+    // the mirror's top-level mtime did not change since. Databases from before hard links were
+    // recorded lack their table and are created again, just like databases without a tree digest
+    // or without the stamps needed for change detection.
+    fn is_db_current(file_db: &Connection, snapshot: &DbSnapshot, detect_changes: bool) -> bool {
+        file_db.prepare(STMT_QUERY_LINK_TARGET).is_ok()
+            && file_db.prepare(STMT_QUERY_TREE_DIGEST).is_ok()
+            && (!detect_changes || file_db.prepare(STMT_QUERY_STAMP).is_ok())
+            && file_db
+                .query_row(STMT_QUERY_META, [], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                        row.get(7)?,
+                        row.get(8)?,
+                        row.get(9)?,
+                        row.get(10)?,
+                        row.get(11)?,
+                    ))
+                })
+                .is_ok_and(|stored: DbSnapshot| &stored == snapshot)
+    }
+
+    // The checksum is built over the chunk as it is presented, so possibly compressed. The format
+    // is the same as the one of sha256sum, so the sidecars can be checked with "sha256sum -c".
+    fn get_checksum(
+        &self,
+        file_info: &FileInfo,
+        file: &OsStr,
+        start: u64,
+        end: u64,
+    ) -> std::io::Result<String> {
+        let chunk = self.open_limit.run(|| {
+            load_chunk(
+                file,
+                start,
+                end,
+                self.config.compression,
+                self.source.as_ref(),
+            )
+        })?;
+
+        Ok(format!(
+            "{:x}  {}\n",
+            Sha256::digest(chunk),
+            checksummed_chunk_name(file_info).to_string_lossy()
+        ))
+    }
+
+    // The size of a compressed chunk is only known after compressing it, so remember it for
+    // subsequent calls.
+    fn get_compressed_size(&self, file_info: &FileInfo) -> u64 {
+        if let Some(size) = self.compressed_sizes.borrow().get(&file_info.ino) {
+            return *size;
+        }
+
+        let file = self
+            .get_file_info_from_ino(file_info.parent_ino)
+            .unwrap()
+            .path;
+        // Reading stops at the end of the file anyway
+        let (start, end) = self
+            .file_config(&file)
+            .chunk_span(file_info.part - 1, u64::MAX);
+        let compression = self.config.compression.unwrap();
+        let size = self
+            .open_limit
+            .run(|| compress_chunk(file, start, end, compression, self.source.as_ref()))
+            .map_or(0, |compressed| compressed.len() as u64);
+
+        self.compressed_sizes
+            .borrow_mut()
+            .insert(file_info.ino, size);
+        size
+    }
+
+    fn get_config_attr(&self) -> Option<FileAttr> {
+        let file_info = self.get_file_info_from_ino(INO_ROOT).unwrap();
+        let mut attr = self.get_attr_from_file_info(&file_info)?;
+        attr.ino = INO_CONFIG;
+        attr.size = self.config_json.len() as u64;
+        attr.blocks = 1;
+        attr.kind = FileType::RegularFile;
+        Some(attr)
+    }
+
+    fn get_status_json(&self) -> String {
+        let count = |stmt| {
+            self.file_db
+                .query_row(stmt, [], |row| row.get::<_, i64>(0))
+                .unwrap() as u64
+        };
+        let packed = self.packs.objects.values().map(Vec::len).sum::<usize>() as u64;
+
+        serde_json::to_string(&Status {
+            files: count(STMT_COUNT_FILES) + packed,
+            chunks: count(STMT_COUNT_CHUNKS),
+            open_handles: self.file_handles.len() as u64,
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            readahead_hits: self.readahead.as_ref().map(|cache| cache.hits()),
+        })
+        .unwrap()
+    }
+
+    // The status changes all the time, so its size is only a hint. Reads of an opened status file
+    // bypass the page cache and return the snapshot taken when it was opened.
+    fn get_status_attr(&self) -> Option<FileAttr> {
+        let mut attr = self.get_config_attr()?;
+        attr.ino = INO_STATUS;
+        attr.size = self.get_status_json().len() as u64;
+        Some(attr)
+    }
+
+    fn get_count_json(&self, ino: u64) -> String {
+        let parent_ino =
+            FileInfoRow::from(FileInfo::with_parent_ino(self.get_link_target(ino))).parent_ino;
+
+        let count: u64 = self
+            .file_db
+            .prepare_cached(STMT_COUNT_BY_PARENT_INO)
+            .unwrap()
+            .query_row(params![parent_ino], |row| row.get(0))
+            .unwrap();
+
+        format!("{}\n", count)
+    }
+
+    fn get_count_attr(&self, ino: u64) -> Option<FileAttr> {
+        let file_info = self.get_file_info_from_ino(ino).unwrap();
+        let mut attr = self.get_attr_from_file_info(&file_info)?;
+        attr.ino = ino | INO_FLAG_COUNT;
+        attr.size = self.get_count_json(ino).len() as u64;
+        attr.blocks = 1;
+        attr.kind = FileType::RegularFile;
+        Some(attr)
+    }
+
+    fn get_meta_json(&self, ino: u64) -> Option<String> {
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        let attr = self.get_source_attr(&file_info.path, true, ino)?;
+        Some(serde_json::to_string(&FileMeta::from_attr(&attr)).unwrap())
+    }
+
+    fn get_meta_attr(&self, ino: u64) -> Option<FileAttr> {
+        let mut attr = self.get_count_attr(ino)?;
+        attr.ino = ino | INO_FLAG_META;
+        attr.size = self.get_meta_json(ino)?.len() as u64;
+        Some(attr)
+    }
+
+    fn get_reads_json(&self, ino: u64) -> String {
+        let stats = self.read_stats.as_ref().unwrap();
+        let parent_ino =
+            FileInfoRow::from(FileInfo::with_parent_ino(self.get_link_target(ino))).parent_ino;
+
+        let mut file_reads = FileReads::default();
+        let mut stmt = self
+            .file_db
+            .prepare_cached(STMT_QUERY_BY_PARENT_INO)
+            .unwrap();
+        for chunk in stmt
+            .query_map(params![parent_ino, 0], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|chunk| chunk.part > 0 && !is_checksum(chunk))
+        {
+            let reads = stats.get(chunk.ino);
+            file_reads
+                .reads
+                .insert(chunk.file_name.to_string_lossy().into_owned(), reads.reads);
+            file_reads.bytes_read += reads.bytes;
+        }
+
+        serde_json::to_string(&file_reads).unwrap()
+    }
+
+    // Like the status, the read statistics change all the time, so their size is only a hint.
+    fn get_reads_attr(&self, ino: u64) -> Option<FileAttr> {
+        let mut attr = self.get_count_attr(ino)?;
+        attr.ino = ino | INO_FLAG_READS;
+        attr.size = self.get_reads_json(ino).len() as u64;
+        Some(attr)
+    }
+
+    // The whole file is presented with the attributes of the file in the mirror, just like a file
+    // below the split size.
+    fn get_whole_attr(&self, ino: u64) -> Option<FileAttr> {
+        let file_info = self.get_file_info_from_ino(ino).ok()?;
+        self.get_source_attr(&file_info.path, true, ino | INO_FLAG_WHOLE)
+    }
+
+    fn is_whole_file(&self, parent: u64, name: &OsStr) -> bool {
+        self.settings.emit_whole
+            && name == WHOLE_FILE_NAME
+            && self
+                .get_file_info_from_ino(parent)
+                .is_ok_and(|file_info| file_info.vdir)
+    }
+
+    // The config of a file, which only differs from the global one if the file is listed in the
+    // blocksize manifest.
+    fn file_config(&self, file: &OsStr) -> Cow<'_, Config> {
+        self.config
+            .with_blocksize(self.blocksizes.get(file).copied())
+    }
+
+    // Only virtual directories of files with their own blocksize contain a blocksize file, so
+    // that CatFS knows how to put the chunks together again.
+    fn has_blocksize_file(&self, file_info: &FileInfo) -> bool {
+        file_info.vdir && self.file_config(&file_info.path).blocksize != self.config.blocksize
+    }
+
+    fn get_blocksize_json(&self, ino: u64) -> String {
+        let file_info = self.get_file_info_from_ino(ino).unwrap();
+        format!("{}\n", self.file_config(&file_info.path).blocksize)
+    }
+
+    fn get_blocksize_attr(&self, ino: u64) -> Option<FileAttr> {
+        let mut attr = self.get_count_attr(ino)?;
+        attr.ino = ino | INO_FLAG_BLOCKSIZE;
+        attr.size = self.get_blocksize_json(ino).len() as u64;
+        Some(attr)
+    }
+
+    fn is_blocksize_file(&self, parent: u64, name: &OsStr) -> bool {
+        name == BLOCKSIZE_FILE_NAME
+            && self
+                .get_file_info_from_ino(parent)
+                .is_ok_and(|file_info| self.has_blocksize_file(&file_info))
+    }
+
+    // Returns the original name of a chunked file listed with the chunked suffix. Names of actual
+    // entries always take precedence.
+    fn unmark_chunked(&self, parent: u64, name: &OsStr) -> Option<OsString> {
+        let suffix = self.config.chunked_suffix.as_ref()?;
+        let unmarked = OsStr::from_bytes(name.as_bytes().strip_suffix(suffix.as_bytes())?);
+
+        if self
+            .get_file_info_from_parent_ino_and_file_name(parent, name.to_os_string())
+            .is_ok()
+        {
+            return None;
+        }
+
+        self.get_file_info_from_parent_ino_and_file_name(parent, unmarked.to_os_string())
+            .ok()
+            .filter(|file_info| file_info.vdir)
+            .map(|file_info| file_info.file_name)
+    }
+
+    // The entries of a directory like they are presented, including the synthetic files.
+    fn get_dir_listing(&self, file_info: &FileInfo) -> DirListing {
+        let mut listing = dir_listing(file_info);
+
+        // The root directory additionally contains the config file, virtual directories may
+        // contain .count, .scfs_meta, .scfs_reads and .whole
+        if file_info.ino == INO_ROOT {
+            let config_name = self.settings.config_file_name().into();
+            listing.push((INO_CONFIG, FileType::RegularFile, config_name));
+        } else if file_info.vdir {
+            if self.settings.emit_count {
+                listing.push((
+                    file_info.ino | INO_FLAG_COUNT,
+                    FileType::RegularFile,
+                    COUNT_FILE_NAME.into(),
+                ));
+            }
+            if self.settings.emit_meta {
+                listing.push((
+                    file_info.ino | INO_FLAG_META,
+                    FileType::RegularFile,
+                    META_FILE_NAME.into(),
+                ));
+            }
+            if self.has_blocksize_file(file_info) {
+                listing.push((
+                    file_info.ino | INO_FLAG_BLOCKSIZE,
+                    FileType::RegularFile,
+                    BLOCKSIZE_FILE_NAME.into(),
+                ));
+            }
+            if self.settings.emit_reads {
+                listing.push((
+                    file_info.ino | INO_FLAG_READS,
+                    FileType::RegularFile,
+                    READS_FILE_NAME.into(),
+                ));
+            }
+            if self.settings.emit_whole {
+                listing.push((
+                    file_info.ino | INO_FLAG_WHOLE,
+                    FileType::RegularFile,
+                    WHOLE_FILE_NAME.into(),
+                ));
+            }
+        }
+
+        // Flat mirrors list all chunks in their root, there are no other directories
+        if self.config.flat && file_info.ino == INO_ROOT {
+            listing.extend(self.get_flat_listing());
+        } else {
+            let mut stmt = self
+                .file_db
+                .prepare_cached(STMT_QUERY_BY_PARENT_INO)
+                .unwrap();
+            let mut items = stmt
+                .query_map(
+                    params![
+                        FileInfoRow::from(FileInfo::with_parent_ino(
+                            self.get_link_target(file_info.ino)
+                        ))
+                        .parent_ino,
+                        0
+                    ],
+                    |row| Ok(FileInfo::from(row)),
+                )
+                .unwrap()
+                .map(|item| item.unwrap())
+                .collect::<Vec<_>>();
+            items.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+            for item in items {
+                let kind = if item.symlink {
+                    FileType::Symlink
+                } else if item.part > 0
+                    || self.is_pack(item.ino)
+                    || self.is_plain(&item)
+                    || self.is_placeholder(&item)
+                {
+                    FileType::RegularFile
+                } else if let Some(kind) = self.special_file_kind(&item) {
+                    kind
+                } else {
+                    FileType::Directory
+                };
+                let mut name = item.file_name;
+                if let Some(suffix) = self.config.chunked_suffix.as_ref().filter(|_| item.vdir) {
+                    name.push(suffix);
+                }
+                listing.push((item.ino, kind, name));
+            }
+            drop(stmt);
+        }
+
+        // The entries are sorted by the names in the database, which lack the chunked suffix, and
+        // follow the synthetic files. Sorting the whole listing orders them like they are shown.
+        if self.settings.sort_entries {
+            listing[2..].sort_by(|a, b| a.2.cmp(&b.2));
+        }
+
+        listing
+    }
+
+    // The chunks of all files in a flat mirror, named after the relative path of their file.
+    fn get_flat_listing(&self) -> DirListing {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+
+        let mut stmt = self.file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|part| !is_checksum(part));
+
+        let mut files = HashMap::new();
+        let mut listing = parts
+            .map(|part| {
+                let file = files.entry(part.parent_ino).or_insert_with(|| {
+                    let file = self.get_file_info_from_ino(part.parent_ino).unwrap().path;
+                    let file = Path::new(&file);
+                    file.strip_prefix(&root).unwrap_or(file).to_path_buf()
+                });
+                (
+                    part.ino,
+                    FileType::RegularFile,
+                    flat_name(file, &part.file_name),
+                )
+            })
+            .collect::<DirListing>();
+        listing.sort_by(|a, b| a.2.cmp(&b.2));
+        listing
+    }
+
+    // Finds the chunk listed under the given name in the root of a flat mirror.
+    fn get_flat_chunk(&self, name: &OsStr) -> Option<FileInfo> {
+        let (relative, chunk_name) = parse_flat_name(name)?;
+
+        let mut file_info = self.get_file_info_from_ino(INO_ROOT).ok()?;
+        for component in relative.iter() {
+            file_info = self
+                .get_file_info_from_parent_ino_and_file_name(file_info.ino, component.into())
+                .ok()?;
+        }
+
+        self.get_file_info_from_parent_ino_and_file_name(file_info.ino, chunk_name.into())
+            .ok()
+            .filter(|chunk| file_info.vdir && chunk.part > 0 && !is_checksum(chunk))
+    }
+
+    fn is_reads_file(&self, parent: u64, name: &OsStr) -> bool {
+        self.settings.emit_reads
+            && name == READS_FILE_NAME
+            && self
+                .get_file_info_from_ino(parent)
+                .is_ok_and(|file_info| file_info.vdir)
+    }
+
+    // With change detection, a file of the mirror and its chunks are stale once the size or the
+    // mtime of the file differ from the ones recorded while indexing. The chunks would not match
+    // the file anymore, so nothing of it is served instead.
+    fn is_stale(&self, ino: u64) -> bool {
+        if !self.settings.detect_changes {
+            return false;
+        }
+
+        // The whole file is stale together with the chunks of its file
+        let file_info = match self.get_file_info_from_ino(ino & !INO_FLAG_WHOLE) {
+            Ok(file_info) if file_info.part > 0 => {
+                self.get_file_info_from_ino(file_info.parent_ino).unwrap()
+            }
+            Ok(file_info) => file_info,
+            Err(_) => return false,
+        };
+
+        // Only regular files have been stamped
+        let stamp = self
+            .file_db
+            .prepare_cached(STMT_QUERY_STAMP)
+            .unwrap()
+            .query_row(params![file_info.ino as i64], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get(1)?, row.get(2)?))
+            });
+        let (size, mtime, mtime_nsec) = match stamp {
+            Ok(stamp) => stamp,
+            Err(_) => return false,
+        };
+
+        match self.source.metadata(Path::new(&file_info.path)) {
+            Ok(MirrorMetadata {
+                attr: Some(attr), ..
+            }) => attr.size != size || time_from_system_time(attr.mtime) != (mtime, mtime_nsec),
+            _ => true,
+        }
+    }
+
+    fn is_meta_file(&self, parent: u64, name: &OsStr) -> bool {
+        self.settings.emit_meta
+            && name == META_FILE_NAME
+            && self
+                .get_file_info_from_ino(parent)
+                .is_ok_and(|file_info| file_info.vdir)
+    }
+
+    // Synthetic files, which are backed neither by a chunk nor by a pack
+    fn is_synthetic(&self, ino: u64) -> bool {
+        ino == INO_CONFIG || ino & (INO_FLAG_COUNT | INO_FLAG_META | INO_FLAG_BLOCKSIZE) != 0
+    }
+
+    // Files below the split size cannot be told apart from directories by the database alone.
+    fn is_plain(&self, file_info: &FileInfo) -> bool {
+        self.config.min_split_size.is_some()
+            && file_info.part == 0
+            && !file_info.vdir
+            && !file_info.symlink
+            && self.get_source_kind(&file_info.path) == Some(FileType::RegularFile)
+    }
+
+    fn special_file_kind(&self, file_info: &FileInfo) -> Option<FileType> {
+        if self.settings.special_files && file_info.part == 0 && !file_info.vdir {
+            self.get_source_kind(&file_info.path)
+                .filter(|kind| is_special_file(*kind))
+        } else {
+            None
+        }
+    }
+
+    // The current file type of an entry of the mirror, following symlinks.
+    fn get_source_kind(&self, path: &OsStr) -> Option<FileType> {
+        Some(self.source.metadata(Path::new(path)).ok()?.attr?.kind)
+    }
+
+    // A placeholder has a different name than the unsupported entry it stands for.
+    fn is_placeholder(&self, file_info: &FileInfo) -> bool {
+        self.settings.placeholder
+            && file_info.part == 0
+            && Path::new(&file_info.path).file_name() != Some(&file_info.file_name)
+    }
+
+    fn get_placeholder_content(&self, file_info: &FileInfo) -> String {
+        match self.source.symlink_metadata(Path::new(&file_info.path)) {
+            Ok(meta) => format!(
+                "Unsupported file type: {}\nOriginal size: {} bytes\n",
+                describe_file_type(meta.attr.map(|attr| attr.kind)),
+                meta.attr.map_or(0, |attr| attr.size)
+            ),
+            Err(_) => String::from("Unsupported file, which does not exist anymore\n"),
+        }
+    }
+
+    fn get_placeholder_attr(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        let parent = self.get_file_info_from_ino(file_info.parent_ino).unwrap();
+        let mut attr = self.get_mirror_attr(&parent)?;
+        attr.ino = file_info.ino;
+        attr.size = self.get_placeholder_content(file_info).len() as u64;
+        attr.blocks = 1;
+        attr.kind = FileType::RegularFile;
+        attr.perm = 0o444;
+        attr.nlink = 1;
+        Some(attr)
+    }
+
+    fn is_pack(&self, ino: u64) -> bool {
+        self.packs.objects.contains_key(&ino) || self.packs.indices.contains_key(&ino)
+    }
+
+    fn is_count_file(&self, parent: u64, name: &OsStr) -> bool {
+        self.settings.emit_count
+            && name == COUNT_FILE_NAME
+            && self
+                .get_file_info_from_ino(parent)
+                .is_ok_and(|file_info| file_info.vdir)
+    }
+
+    /// Writes one tab-separated line per chunk to `out`: the path of the file relative to the
+    /// mirror, the size of the file, its number of chunks, the name of the chunk and the byte
+    /// range of the chunk inside the file.
+    pub(crate) fn write_layout<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+
+        let mut stmt = self.file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|part| !is_checksum(part));
+
+        for part in parts {
+            let file = self.get_file_info_from_ino(part.parent_ino).unwrap().path;
+            let size = self
+                .source
+                .metadata(Path::new(&file))?
+                .attr
+                .map_or(0, |attr| attr.size);
+            let config = self.file_config(&file);
+            let chunks = 1.max(size.div_ceil(config.blocksize));
+            let (start, end) = config.chunk_span(part.part - 1, size);
+
+            let file = Path::new(&file);
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                file.strip_prefix(&root).unwrap_or(file).display(),
+                size,
+                chunks,
+                part.file_name.to_string_lossy(),
+                start,
+                end
+            )?;
+        }
+
+        out.flush()
+    }
+
+    /// Writes one JSON line per chunk to `out`: the path of the chunk relative to the mountpoint,
+    /// the path of its file relative to the mirror, the index of the chunk and the byte range of
+    /// the file it contains. The lines are ordered by the paths of the files and the indices of
+    /// the chunks, so the same mirror always leads to the same manifest. The chunks of hard links
+    /// are listed under each of their paths, like they are presented.
+    pub(crate) fn write_manifest<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+        let relative = |path: &OsStr| {
+            let path = Path::new(path);
+            path.strip_prefix(&root).unwrap_or(path).to_path_buf()
+        };
+
+        let mut stmt = self.file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|part| !is_checksum(part));
+
+        let mut entries = Vec::new();
+        for part in parts {
+            let file = self.get_file_info_from_ino(part.parent_ino).unwrap().path;
+            let size = self
+                .source
+                .metadata(Path::new(&file))?
+                .attr
+                .map_or(0, |attr| attr.size);
+            let (offset, end) = self.file_config(&file).chunk_span(part.part - 1, size);
+            let length = end - offset;
+
+            let mut files = vec![relative(&file)];
+            let mut links = self.file_db.prepare_cached(STMT_QUERY_LINKS).unwrap();
+            for link in links
+                .query_map(
+                    params![FileInfoRow::from(FileInfo::with_ino(part.parent_ino)).ino],
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap()
+            {
+                let link = self.get_file_info_from_ino(link.unwrap() as u64).unwrap();
+                files.push(relative(&link.path));
+            }
+
+            for file in files {
+                // The chunks of flat mirrors are all in the root
+                let path = if self.config.flat {
+                    PathBuf::from(&part.file_name)
+                } else {
+                    file.join(&part.file_name)
+                };
+                entries.push(ManifestEntry {
+                    path: path.to_string_lossy().into_owned(),
+                    file: file.to_string_lossy().into_owned(),
+                    index: part.part - 1,
+                    offset,
+                    length,
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| (&a.file, a.index).cmp(&(&b.file, b.index)));
+        for entry in entries {
+            writeln!(out, "{}", serde_json::to_string(&entry).unwrap())?;
+        }
+
+        out.flush()
+    }
+
+    /// Writes the mirror to `out_dir` like it is presented on a mount, so that CatFS can restore it
+    /// from there. The directories are written from the same listings as on a mount, so they
+    /// contain the config, the synthetic files, packs and files below the split size as well.
+    /// Files listed under more than one path, like the chunks of hard links, are hard links of
+    /// each other.
+    pub(crate) fn write_to_dir(&self, out_dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(out_dir)?;
+        self.write_dir(INO_ROOT, out_dir, &mut HashMap::new())
+    }
+
+    fn write_dir(
+        &self,
+        ino: u64,
+        dir: &Path,
+        written: &mut HashMap<u64, PathBuf>,
+    ) -> io::Result<()> {
+        let file_info = self
+            .get_file_info_from_ino(ino)
+            .map_err(|_| io::Error::from(io::ErrorKind::NotFound))?;
+
+        for (ino, kind, name) in self.get_dir_listing(&file_info).into_iter().skip(2) {
+            let path = dir.join(name);
+            match kind {
+                FileType::Directory => {
+                    fs::create_dir(&path)?;
+                    self.write_dir(ino, &path, written)?;
+                }
+                FileType::Symlink => {
+                    let link = self
+                        .get_file_info_from_ino(ino)
+                        .map_err(|_| io::Error::from(io::ErrorKind::NotFound))?;
+                    std::os::unix::fs::symlink(self.read_link(Path::new(&link.path))?, path)?;
+                }
+                FileType::RegularFile => match written.entry(ino) {
+                    Entry::Occupied(target) => fs::hard_link(target.get(), path)?,
+                    Entry::Vacant(target) => {
+                        fs::write(&path, self.get_content(ino)?)?;
+                        target.insert(path);
+                    }
+                },
+                _ => warn!("Skipping {:?}, its file type is not supported", path),
+            }
+        }
+
+        Ok(())
+    }
+
+    // The whole content of a file like it is read on a mount, see open and read.
+    fn get_content(&self, ino: u64) -> io::Result<Vec<u8>> {
+        let not_found = || io::Error::from(io::ErrorKind::NotFound);
+
+        if ino == INO_CONFIG {
+            return Ok(self.config_json.clone().into_bytes());
+        }
+        if ino & INO_FLAG_COUNT != 0 {
+            return Ok(self.get_count_json(ino & !INO_FLAG_COUNT).into_bytes());
+        }
+        if ino & INO_FLAG_META != 0 {
+            return self
+                .get_meta_json(ino & !INO_FLAG_META)
+                .map(String::into_bytes)
+                .ok_or_else(not_found);
+        }
+        if ino & INO_FLAG_BLOCKSIZE != 0 {
+            return Ok(self
+                .get_blocksize_json(ino & !INO_FLAG_BLOCKSIZE)
+                .into_bytes());
+        }
+        if ino & INO_FLAG_READS != 0 {
+            return Ok(self.get_reads_json(ino & !INO_FLAG_READS).into_bytes());
+        }
+        if let Some(index) = self.packs.indices.get(&ino) {
+            return Ok(index.clone().into_bytes());
+        }
+        if let Some(segments) = self.packs.objects.get(&ino) {
+            let size = segments
+                .iter()
+                .map(|segment| segment.end - segment.start)
+                .sum();
+            return self
+                .open_limit
+                .run(|| read_segments(segments, 0, size, self.source.as_ref()));
+        }
+
+        let file_info = self
+            .get_file_info_from_ino(ino & !INO_FLAG_WHOLE)
+            .map_err(|_| not_found())?;
+        if self.is_placeholder(&file_info) {
+            return Ok(self.get_placeholder_content(&file_info).into_bytes());
+        }
+
+        // Whole files and files below the split size are read like they are
+        let is_plain = ino & INO_FLAG_WHOLE != 0 || file_info.part == 0;
+        let file = if is_plain {
+            file_info.path.clone()
+        } else {
+            self.get_file_info_from_ino(file_info.parent_ino)
+                .map_err(|_| not_found())?
+                .path
+        };
+        let len = self
+            .source
+            .metadata(Path::new(&file))?
+            .attr
+            .map_or(0, |attr| attr.size);
+
+        if is_plain {
+            return self
+                .open_limit
+                .run(|| load_chunk(&file, 0, len, None, self.source.as_ref()));
+        }
+
+        let (start, end) = self.file_config(&file).chunk_span(file_info.part - 1, len);
+        if is_checksum(&file_info) {
+            return Ok(self
+                .get_checksum(&file_info, &file, start, end)?
+                .into_bytes());
+        }
+        self.open_limit.run(|| {
+            load_chunk(
+                &file,
+                start,
+                end,
+                self.config.compression,
+                self.source.as_ref(),
+            )
+        })
+    }
+
+    /// The files that are presented as chunks, relative to the presented root, together with
+    /// their sizes. Files below the split size, packed and excluded files are not part of it, so
+    /// the self-verifier only samples files that actually have chunks on the mount.
+    pub(crate) fn chunked_files(&self) -> Vec<(PathBuf, u64)> {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+
+        let mut stmt = self
+            .file_db
+            .prepare("SELECT DISTINCT parent_ino FROM Files WHERE part != 0")
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, i64>(0))
+            .unwrap()
+            .map(|ino| {
+                let file = self
+                    .get_file_info_from_ino(ino.unwrap() as u64)
+                    .unwrap()
+                    .path;
+                let size = self
+                    .source
+                    .metadata(Path::new(&file))
+                    .ok()
+                    .and_then(|meta| meta.attr)
+                    .map_or(0, |attr| attr.size);
+                let file = Path::new(&file);
+                (file.strip_prefix(&root).unwrap_or(file).to_path_buf(), size)
+            })
+            .collect()
+    }
+
+    // The digest of the chunks like CatFS finds them in the splitted mirror, see tree_digest. Like
+    // in the manifest, the chunks of hard links count under each of their paths.
+    fn get_tree_digest(&self) -> String {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+        let relative = |path: &OsStr| {
+            let path = Path::new(path);
+            path.strip_prefix(&root).unwrap_or(path).to_path_buf()
+        };
+
+        let mut stmt = self.file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let parts = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|part| !is_checksum(part));
+
+        let mut files = HashMap::new();
+        let mut chunks = Vec::new();
+        for part in parts {
+            let (paths, size, config) = files.entry(part.parent_ino).or_insert_with(|| {
+                let file = self.get_file_info_from_ino(part.parent_ino).unwrap().path;
+                let size = self
+                    .source
+                    .metadata(Path::new(&file))
+                    .ok()
+                    .and_then(|meta| meta.attr)
+                    .map_or(0, |attr| attr.size);
+                let config = self.file_config(&file).into_owned();
+
+                let mut paths = vec![relative(&file)];
+                let mut links = self.file_db.prepare_cached(STMT_QUERY_LINKS).unwrap();
+                for link in links
+                    .query_map(
+                        params![FileInfoRow::from(FileInfo::with_ino(part.parent_ino)).ino],
+                        |row| row.get::<_, i64>(0),
+                    )
+                    .unwrap()
+                {
+                    let link = self.get_file_info_from_ino(link.unwrap() as u64).unwrap();
+                    paths.push(relative(&link.path));
+                }
+                (paths, size, config)
+            });
+
+            let (start, end) = config.chunk_span(part.part - 1, *size);
+            let length = end - start;
+            for path in paths.iter() {
+                chunks.push((path.join(self.config.chunk_name(part.part - 1)), length));
+            }
+        }
+
+        tree_digest(chunks)
+    }
+
+    /// Writes a summary of the chunks to `out`: the number of files and chunks, the file with the
+    /// most chunks and the minimum, median and maximum number of chunks per file.
+    pub(crate) fn write_plan<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let root = self.get_file_info_from_ino(INO_ROOT).unwrap().path;
+
+        let mut stmt = self.file_db.prepare(STMT_QUERY_PARTS).unwrap();
+        let mut chunks = HashMap::<u64, u64>::new();
+        for part in stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|part| !is_checksum(part))
+        {
+            *chunks.entry(part.parent_ino).or_default() += 1;
+        }
+
+        let mut counts = chunks.values().copied().collect::<Vec<_>>();
+        counts.sort_unstable();
+
+        writeln!(out, "Files: {}", counts.len())?;
+        writeln!(out, "Chunks: {}", counts.iter().sum::<u64>())?;
+
+        // Of several files with the most chunks, the one with the lowest inode is reported
+        if let Some((ino, count)) = chunks
+            .iter()
+            .max_by_key(|(ino, count)| (**count, std::cmp::Reverse(**ino)))
+        {
+            let file = self.get_file_info_from_ino(*ino).unwrap().path;
+            let file = Path::new(&file);
+            writeln!(
+                out,
+                "Most chunks: {} ({})",
+                count,
+                file.strip_prefix(&root).unwrap_or(file).display()
+            )?;
+            writeln!(
+                out,
+                "Chunks per file: min {}, median {}, max {}",
+                counts[0],
+                counts[counts.len() / 2],
+                counts[counts.len() - 1]
+            )?;
+        }
+
+        out.flush()
+    }
+
+    // Files split into a fixed number of parts get their own blocksize, unless the manifest lists
+    // them. Since the database does not keep the sizes, they are looked up again on each mount.
+    fn insert_part_blocksizes(
+        file_db: &Connection,
+        config: &Config,
+        source: &dyn MirrorSource,
+        blocksizes: &mut HashMap<OsString, u64>,
+    ) {
+        let mut stmt = file_db.prepare(STMT_QUERY_ALL).unwrap();
+        let file_infos = stmt
+            .query_map([], |row| Ok(FileInfo::from(row)))
+            .unwrap()
+            .map(|res| res.unwrap())
+            .filter(|file_info| file_info.part == 0 && file_info.vdir);
+        for file_info in file_infos {
+            let size = source
+                .metadata(Path::new(&file_info.path))
+                .ok()
+                .and_then(|meta| meta.attr)
+                .map_or(0, |attr| attr.size);
+            if let Some(blocksize) = config.part_blocksize(size) {
+                blocksizes.entry(file_info.path).or_insert(blocksize);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn populate<P: AsRef<Path>>(
+        file_db: &Connection,
+        path: P,
+        config: &Config,
+        blocksizes: &HashMap<OsString, u64>,
+        settings: &Settings,
+        source: &dyn MirrorSource,
+        exclude: &Exclude,
+        ancestors: &mut HashSet<(u64, u64)>,
+        links: &mut HashMap<(u64, u64, u64), u64>,
+        packs: &mut Packs,
+        progress: &mut ProgressReporter,
+        parent_ino: u64,
+        mut next_ino: u64,
+    ) -> u64 {
+        let path = path.as_ref();
+
+        let meta = populate_metadata(path, settings, source, ancestors);
+
+        match meta.attr.map(|attr| attr.kind) {
+            Some(kind) if settings.special_files || !is_special_file(kind) => {}
+
+            // The placeholder keeps the path of the original entry, only its name differs
+            _ if settings.placeholder => {
+                let mut file_name = path.file_name().unwrap().to_os_string();
+                file_name.push(PLACEHOLDER_SUFFIX);
+                let ino = allocate_ino(file_db, settings, path, 0, &mut next_ino);
+                insert_file_info(
+                    file_db,
+                    FileInfo {
+                        ino,
+                        parent_ino,
+                        path: OsString::from(path),
+                        file_name,
+                        part: 0,
+                        vdir: false,
+                        symlink: false,
+                    },
+                );
+                return next_ino;
+            }
+
+            _ => {
+                warn!("Skipping {:?}, its file type is not supported", path);
+                return next_ino;
+            }
+        }
+
+        let mut attr = meta.attr.expect("File type has been checked above");
+        let dev_ino = (meta.dev, attr.ino);
+
+        attr.ino = if parent_ino == INO_OUTSIDE {
+            INO_ROOT
+        } else {
+            allocate_ino(file_db, settings, path, 0, &mut next_ino)
+        };
+
+        let is_split = attr.kind == FileType::RegularFile
+            && !config.is_plain_file(path.file_name().unwrap(), attr.size);
+
+        insert_file_info(
+            file_db,
+            FileInfo {
+                ino: attr.ino,
+                parent_ino,
+                path: OsString::from(path),
+                file_name: path.file_name().unwrap().into(),
+                part: 0,
+                vdir: is_split,
+                symlink: attr.kind == FileType::Symlink,
+            },
+        );
+        if settings.detect_changes && attr.kind == FileType::RegularFile {
+            insert_stamp(file_db, &attr);
+        }
+        progress.add(if attr.kind == FileType::RegularFile {
+            attr.size
+        } else {
+            0
+        });
+
+        match attr.kind {
+            FileType::RegularFile if is_split => {
+                let config = config.with_blocksize(
+                    blocksizes
+                        .get(path.as_os_str())
+                        .copied()
+                        .or_else(|| config.part_blocksize(attr.size)),
+                );
+
+                // Further hard links of a file share the chunks of its first path, as long as
+                // they are split alike. Flat mirrors need the chunks of each path in their root.
+                if attr.nlink > 1 && !config.flat {
+                    match links.entry((dev_ino.0, dev_ino.1, config.blocksize)) {
+                        Entry::Occupied(target) => {
+                            insert_link(file_db, attr.ino, *target.get());
+                            return next_ino;
+                        }
+                        Entry::Vacant(target) => {
+                            target.insert(attr.ino);
+                        }
+                    }
+                }
+
+                for range in split_ranges(attr.size, config.blocksize) {
+                    let chunk_name = config.chunk_file_name(range.index, attr.size);
+                    let mut file_names = vec![chunk_name.clone()];
+                    if settings.checksums {
+                        let mut checksum_name = chunk_name;
+                        checksum_name.push(CHECKSUM_SUFFIX);
+                        file_names.push(checksum_name);
+                    }
+
+                    for file_name in file_names {
+                        let chunk = path.join(&file_name);
+                        let part = range.index + 1;
+                        insert_file_info(
+                            file_db,
+                            FileInfo {
+                                ino: allocate_ino(file_db, settings, &chunk, part, &mut next_ino),
+                                parent_ino: attr.ino,
+                                path: chunk.into_os_string(),
+                                file_name,
+                                part,
+                                vdir: false,
+                                symlink: false,
+                            },
+                        );
+                    }
+                }
+            }
+
+            FileType::Directory => {
+                let mut small_files = Vec::new();
+
+                ancestors.insert(dev_ino);
+
+                for entry in source.read_dir(path).unwrap() {
+                    if exclude.is_excluded(&entry) {
+                        continue;
+                    }
+
+                    if let Some(pack_small) = settings.pack_small {
+                        let meta = populate_metadata(&entry, settings, source, ancestors);
+                        if let Some(attr) = meta.attr.filter(|attr| {
+                            attr.kind == FileType::RegularFile && attr.size < pack_small
+                        }) {
+                            small_files.push((entry, attr.size));
+                            progress.add(attr.size);
+                            continue;
+                        }
+                    }
+
+                    next_ino = SplitFS::populate(
+                        file_db, entry, config, blocksizes, settings, source, exclude, ancestors,
+                        links, packs, progress, attr.ino, next_ino,
+                    );
+                }
+
+                ancestors.remove(&dev_ino);
+
+                if let Some(pack_small) = settings.pack_small {
+                    next_ino = SplitFS::populate_packs(
+                        file_db,
+                        path,
+                        small_files,
+                        pack_small,
+                        settings,
+                        packs,
+                        attr.ino,
+                        next_ino,
+                    );
+                }
+            }
+
+            _ => {}
+        }
+
+        next_ino
+    }
+
+    // Groups consecutive small files into pack objects that do not exceed the given size. Each
+    // directory with packed files gets an index that records the location of each file.
+    #[allow(clippy::too_many_arguments)]
+    fn populate_packs(
+        file_db: &Connection,
+        path: &Path,
+        small_files: Vec<(PathBuf, u64)>,
+        pack_size: u64,
+        settings: &Settings,
+        packs: &mut Packs,
+        parent_ino: u64,
+        mut next_ino: u64,
+    ) -> u64 {
+        if small_files.is_empty() {
+            return next_ino;
+        }
+
+        let mut objects: Vec<Vec<FileHandle>> = Vec::new();
+        let mut index = Vec::new();
+        let mut offset = 0;
+
+        for (file, size) in small_files {
+            if objects.is_empty() || offset + size > pack_size {
+                objects.push(Vec::new());
+                offset = 0;
+            }
+
+            index.push(PackEntry {
+                file_name: file.file_name().unwrap().into(),
+                pack: objects.len() as u64 - 1,
+                offset,
+                size,
+            });
+
+            objects.last_mut().unwrap().push(FileHandle {
+                file: file.into_os_string(),
+                start: 0,
+                end: size,
+                content: None,
+            });
+
+            offset += size;
+        }
+
+        for (pack, segments) in objects.into_iter().enumerate() {
+            let file_name = pack_file_name(pack as u64);
+            let pack = path.join(&file_name);
+            let ino = allocate_ino(file_db, settings, &pack, 0, &mut next_ino);
+            insert_file_info(
+                file_db,
+                FileInfo {
+                    ino,
+                    parent_ino,
+                    path: pack.into_os_string(),
+                    file_name,
+                    part: 0,
+                    vdir: false,
+                    symlink: false,
+                },
+            );
+            packs.objects.insert(ino, segments);
+        }
+
+        let index_path = path.join(PACK_INDEX_FILE_NAME);
+        let ino = allocate_ino(file_db, settings, &index_path, 0, &mut next_ino);
+        insert_file_info(
+            file_db,
+            FileInfo {
+                ino,
+                parent_ino,
+                path: index_path.into_os_string(),
+                file_name: PACK_INDEX_FILE_NAME.into(),
+                part: 0,
+                vdir: false,
+                symlink: false,
+            },
+        );
+        packs
+            .indices
+            .insert(ino, serde_json::to_string(&index).unwrap());
+
+        next_ino
+    }
+
+    // Pack objects take their metadata from their first file, pack indices from their directory.
+    fn get_pack_attr(&self, file_info: &FileInfo) -> Option<FileAttr> {
+        let (file, size, perm) = if let Some(segments) = self.packs.objects.get(&file_info.ino) {
+            (
+                segments.first().unwrap().file.clone(),
+                segments
+                    .iter()
+                    .map(|segment| segment.end - segment.start)
+                    .sum(),
+                None,
+            )
+        } else if let Some(index) = self.packs.indices.get(&file_info.ino) {
+            let parent = self.get_file_info_from_ino(file_info.parent_ino).unwrap();
+            (parent.path, index.len() as u64, Some(0o644))
+        } else {
+            return None;
+        };
+
+        let mut attr = self.get_source_attr(&file, true, file_info.ino)?;
+        attr.kind = FileType::RegularFile;
+        attr.size = size;
+        attr.blocks = size.div_ceil(512);
+        if let Some(perm) = perm {
+            attr.perm = perm;
+        }
+        Some(attr)
+    }
+}
+
+impl Drop for SplitFS {
+    fn drop(&mut self) {
+        debug!("Unmounted SplitFS");
+        let _ = &(self.drop_hook)();
+    }
+}
+
+impl Filesystem for SplitFS {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        if self.settings.single_threaded {
+            limit_background_requests(config);
+        }
+        if let Some(ready) = self.settings.ready.take() {
+            ready.send(()).unwrap_or(());
+        }
+        Ok(())
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        self.idle.touch();
+
+        if parent == INO_ROOT && name == self.settings.config_file_name() {
+            reply_entry(self.get_config_attr(), self.ttl(), reply);
+            return;
+        }
+
+        if parent == INO_ROOT && name == STATUS_FILE_NAME {
+            reply_entry(self.get_status_attr(), self.ttl(), reply);
+            return;
+        }
+
+        if self.is_count_file(parent, name) {
+            reply_entry(self.get_count_attr(parent), self.ttl(), reply);
+            return;
+        }
+
+        if self.is_meta_file(parent, name) {
+            reply_entry(self.get_meta_attr(parent), self.ttl(), reply);
+            return;
+        }
+
+        if self.is_blocksize_file(parent, name) {
+            reply_entry(self.get_blocksize_attr(parent), self.ttl(), reply);
+            return;
+        }
+
+        if self.is_reads_file(parent, name) {
+            reply_entry(self.get_reads_attr(parent), self.ttl(), reply);
+            return;
+        }
+
+        if self.is_whole_file(parent, name) {
+            reply_entry(self.get_whole_attr(parent), self.ttl(), reply);
+            return;
+        }
+
+        // The chunks of a hard link are found in the first path of the file
+        let parent = self.get_link_target(parent);
+
+        if self.config.flat && parent == INO_ROOT && name != "." && name != ".." {
+            let attr = self
+                .get_flat_chunk(name)
+                .and_then(|chunk| self.get_attr_from_file_info(&chunk));
+            reply_entry(attr, self.ttl(), reply);
+            return;
+        }
+
+        if let Some(name) = self.unmark_chunked(parent, name) {
+            Shared::lookup(self, _req, parent, &name, reply);
+            return;
+        }
+
+        Shared::lookup(self, _req, parent, name, reply);
+    }
+
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, _nlookup: u64) {
+        self.forget_inode(ino);
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        if self.is_stale(ino) {
+            reply.error(ESTALE);
+            return;
+        }
+
+        Shared::getattr(self, _req, ino, reply);
+    }
+
+    fn access(&mut self, req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        Shared::access(self, req, ino, mask, reply);
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        Shared::readlink(self, _req, ino, reply);
+    }
+
+    fn statfs(&mut self, _req: &Request, ino: u64, reply: ReplyStatfs) {
+        Shared::statfs(self, _req, ino, reply);
+    }
+
+    fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        Shared::getxattr(self, req, ino, name, size, reply);
+    }
+
+    fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        Shared::listxattr(self, req, ino, size, reply);
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        Shared::setattr(self, req, ino, mode, uid, gid, size, atime, mtime, reply);
+    }
+
+    fn write(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        Shared::write(self, req, ino, reply);
+    }
+
+    fn create(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        Shared::create(self, req, parent, name, reply);
+    }
+
+    fn mknod(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _target: &Path,
+        reply: ReplyEntry,
+    ) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn link(&mut self, req: &Request, _ino: u64, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        Shared::make_entry(self, req, parent, name, reply);
+    }
+
+    fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _newparent: u64,
+        _newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        Shared::remove_entry(self, req, parent, name, reply);
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        if is_writing_open(flags) {
+            reply.error(EROFS);
+            return;
+        }
+
+        if ino == INO_STATUS || ino & INO_FLAG_READS != 0 {
+            let status = if ino == INO_STATUS {
+                self.get_status_json().into_bytes()
+            } else {
+                self.get_reads_json(ino & !INO_FLAG_READS).into_bytes()
+            };
+
+            let fh = self.next_fh;
+            self.next_fh += 1;
+
+            self.file_handles.insert(
+                fh,
+                FileHandle {
+                    file: OsString::new(),
+                    start: 0,
+                    end: status.len() as u64,
+                    content: Some(Arc::new(status)),
+                },
+            );
+
+            reply.opened(fh, FOPEN_DIRECT_IO);
+            return;
+        }
+
+        // The whole file is read directly from the file in the mirror, like a file below the
+        // split size
+        if ino & INO_FLAG_WHOLE != 0 {
+            let file = match self.get_file_info_from_ino(ino & !INO_FLAG_WHOLE) {
+                Ok(file_info) => file_info.path,
+                Err(_) => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let len = match self.source.metadata(Path::new(&file)) {
+                Ok(MirrorMetadata {
+                    attr: Some(attr), ..
+                }) => attr.size,
+                _ => {
+                    reply.error(EIO);
+                    return;
+                }
+            };
+
+            let fh = self.next_fh;
+            self.next_fh += 1;
+
+            self.file_handles.insert(
+                fh,
+                FileHandle {
+                    file,
+                    start: 0,
+                    end: len,
+                    content: None,
+                },
+            );
+
+            reply.opened(fh, 0);
+            return;
+        }
+
+        if self.is_synthetic(ino) || self.is_pack(ino) {
+            reply.opened(0, 0);
+            return;
+        }
+
+        let file_info = self.get_file_info_from_ino(ino);
+        if let Ok(file_info) = file_info {
+            if self.is_placeholder(&file_info) {
+                let content = self.get_placeholder_content(&file_info).into_bytes();
+
+                let fh = self.next_fh;
+                self.next_fh += 1;
+
+                self.file_handles.insert(
+                    fh,
+                    FileHandle {
+                        file: OsString::new(),
+                        start: 0,
+                        end: content.len() as u64,
+                        content: Some(Arc::new(content)),
+                    },
+                );
+
+                reply.opened(fh, 0);
+                return;
+            }
+
+            // Only regular files below the split size are opened without being a chunk
+            let is_plain = file_info.part == 0;
+
+            let file = if is_plain {
+                file_info.path.clone()
+            } else {
+                self.get_file_info_from_ino(file_info.parent_ino)
+                    .unwrap()
+                    .path
+            };
+
+            let attr = match self.source.metadata(Path::new(&file)) {
+                Ok(MirrorMetadata {
+                    attr: Some(attr), ..
+                }) => attr,
+                _ => {
+                    reply.error(EIO);
+                    return;
+                }
+            };
+            let len = attr.size;
+
+            // The last chunk of a file ends with the file, not with the block
+            let (start, end) = if is_plain {
+                (0, len)
+            } else {
+                self.file_config(&file).chunk_span(file_info.part - 1, len)
+            };
+
+            let is_chunk = !is_plain && !is_checksum(&file_info);
+            let source = ChunkStamp::from(&attr);
+            let cached = self
+                .readahead
+                .as_ref()
+                .filter(|_| is_chunk)
+                .and_then(|cache| cache.get(ino, source));
+
+            let content = if is_plain {
+                None
+            } else if is_checksum(&file_info) {
+                match self.get_checksum(&file_info, &file, start, end) {
+                    Ok(checksum) => Some(Arc::new(checksum.into_bytes())),
+                    Err(e) => {
+                        reply.error(read_errno(&e));
+                        return;
+                    }
+                }
+            } else if let Some(content) = cached {
+                if self.config.compression.is_some() {
+                    self.compressed_sizes
+                        .borrow_mut()
+                        .insert(ino, content.len() as u64);
+                }
+                Some(content)
+            } else if let Some(compression) = self.config.compression {
+                let compressed = self
+                    .open_limit
+                    .run(|| compress_chunk(&file, start, end, compression, self.source.as_ref()));
+                match compressed {
+                    Ok(compressed) => {
+                        self.compressed_sizes
+                            .borrow_mut()
+                            .insert(ino, compressed.len() as u64);
+                        Some(Arc::new(compressed))
+                    }
+                    Err(e) => {
+                        reply.error(read_errno(&e));
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
+
+            if is_chunk {
+                self.read_ahead(&file_info, &file, source, len);
+            }
+
+            let fh = self.next_fh;
+            self.next_fh += 1;
+
+            self.file_handles.insert(
+                fh,
+                FileHandle {
+                    file,
+                    start,
+                    end,
+                    content,
+                },
+            );
+
+            reply.opened(fh, 0);
+        } else {
+            reply.error(ENOENT)
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        self.idle.touch();
+
+        if ino == INO_CONFIG {
+            reply.data(self.config_json.as_ref());
+            return;
+        }
+
+        if ino & INO_FLAG_COUNT != 0 {
+            reply.data(self.get_count_json(ino & !INO_FLAG_COUNT).as_ref());
+            return;
+        }
+
+        if ino & INO_FLAG_META != 0 {
+            match self.get_meta_json(ino & !INO_FLAG_META) {
+                Some(json) => reply.data(json.as_ref()),
+                None => reply.error(ENOENT),
+            }
+            return;
+        }
+
+        if ino & INO_FLAG_BLOCKSIZE != 0 {
+            reply.data(self.get_blocksize_json(ino & !INO_FLAG_BLOCKSIZE).as_ref());
+            return;
+        }
+
+        if self.is_stale(ino) {
+            reply.error(ESTALE);
+            return;
+        }
+
+        let offset = offset as u64;
+        let size = size as u64;
+
+        if let Some(index) = self.packs.indices.get(&ino) {
+            let start = (offset as usize).min(index.len());
+            let end = (start + size as usize).min(index.len());
+            self.bytes_read
+                .fetch_add((end - start) as u64, Ordering::Relaxed);
+            reply.data(&index.as_bytes()[start..end]);
+            return;
+        }
+
+        let bytes_read = Arc::clone(&self.bytes_read);
+        let limiter = Arc::clone(&self.limiter);
+        let open_limit = Arc::clone(&self.open_limit);
+
+        let source = Arc::clone(&self.source);
+
+        if let Some(segments) = self.packs.objects.get(&ino) {
+            let segments = segments.clone();
+            self.pool.execute(move || {
+                match open_limit.run(|| read_segments(&segments, offset, size, source.as_ref())) {
+                    Ok(bytes) => {
+                        bytes_read.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                        limiter.throttle(bytes.len() as u64);
+                        reply.data(&bytes)
+                    }
+                    Err(e) => reply.error(read_errno(&e)),
+                }
+            });
+            return;
+        }
+
+        let handle = self.file_handles.get(&fh).unwrap();
+
+        if let Some(content) = &handle.content {
+            let start = (offset as usize).min(content.len());
+            let end = (start + size as usize).min(content.len());
+            if ino == INO_STATUS || ino & INO_FLAG_READS != 0 {
+                reply.data(&content[start..end]);
+                return;
+            }
+
+            // Throttled reads must wait in a read thread instead of blocking all other requests
+            bytes_read.fetch_add((end - start) as u64, Ordering::Relaxed);
+            if let Some(stats) = &self.read_stats {
+                stats.record(ino, (end - start) as u64);
+            }
+            let bytes = content[start..end].to_vec();
+            self.pool.execute(move || {
+                limiter.throttle(bytes.len() as u64);
+                reply.data(&bytes)
+            });
+            return;
+        }
+
+        let file = handle.file.clone();
+        let read_stats = self.read_stats.clone();
+
+        let offset = offset.min(handle.end - handle.start);
+        let size = size.min(handle.end - handle.start - offset);
+        let start = handle.start;
+
+        // A buffer larger than the request would only read bytes that are thrown away
+        let io_buffer =
+            (size as usize).min(self.settings.io_buffer.unwrap_or(IO_BUFFER_DEFAULT_SIZE));
+
+        self.pool.execute(move || {
+            let read = || -> std::io::Result<Vec<u8>> {
+                let mut file = BufReader::with_capacity(io_buffer, source.open(Path::new(&file))?);
+
+                file.seek(SeekFrom::Start(start + offset))?;
+
+                file.take(size).bytes().collect()
+            };
+
+            match open_limit.run(read) {
+                Ok(bytes) => {
+                    bytes_read.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    if let Some(stats) = read_stats {
+                        stats.record(ino, bytes.len() as u64);
+                    }
+                    limiter.throttle(bytes.len() as u64);
+                    reply.data(&bytes)
+                }
+                Err(e) => reply.error(read_errno(&e)),
+            }
+        });
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        if self.is_synthetic(ino) || self.is_pack(ino) {
+            reply.ok();
+            return;
+        }
+
+        self.file_handles.remove(&fh);
+        reply.ok();
+    }
+
+    // The file system is read-only, so there is never anything to write back
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn fallocate(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _offset: i64,
+        _length: i64,
+        _mode: i32,
+        reply: ReplyEmpty,
+    ) {
+        Shared::fallocate(self, req, ino, reply);
+    }
+
+    fn bmap(&mut self, req: &Request<'_>, ino: u64, _blocksize: u32, _idx: u64, reply: ReplyBmap) {
+        Shared::bmap(self, req, ino, reply);
+    }
+
+    fn ioctl(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: u32,
+        _cmd: u32,
+        _in_data: &[u8],
+        _out_size: u32,
+        reply: ReplyIoctl,
+    ) {
+        Shared::ioctl(self, req, ino, reply);
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        self.idle.touch();
+
+        let file_info = match self.get_file_info_from_ino(ino) {
+            Ok(file_info) => file_info,
+            Err(_) => {
+                reply.error(ENOENT);
+                return;
+            }
+        };
+
+        let listing = self.get_dir_listing(&file_info);
+
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        self.dir_handles.insert(fh, listing);
+
+        reply.opened(fh, 0);
+    }
+
+    fn readdir(&mut self, _req: &Request, _ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        self.idle.touch();
+
+        match self.dir_handles.get(&fh) {
+            Some(listing) => reply_dir_listing(listing, offset, reply),
+            None => reply.error(EBADF),
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: ReplyEmpty,
+    ) {
+        self.dir_handles.remove(&fh);
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::fs;
+    use std::fs::{read, DirEntry, File};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Instant;
+
+    use flate2::read::GzDecoder;
+    use fuser::BackgroundSession;
+    use log::Level;
+    use nix::errno::Errno;
+    use nix::fcntl::FallocateFlags;
+    use nix::sys::stat::Mode;
+    use nix::unistd::{access, mkfifo, AccessFlags};
+    use rand::{Rng, RngCore};
+    use tempfile::{tempdir, TempDir};
+
+    use crate::shared::tests::{
+        check_symlinks, create_files_and_symlinks, init_test_logger, logged_messages,
+    };
+    use crate::{mount, CatFS, Compression, IdMap, CONFIG_FILE_NAME};
+
+    use super::*;
+
+    // Helper struct to keep necessary variables in scope. To not make the compiler complain,
+    // prefix them with an underscore. If for example the TempDir variables are not kept in scope
+    // this way, the directories would be deleted before the tests can be run.
+    struct TempSession {
+        _session: BackgroundSession,
+        _mirror: TempDir,
+        pub(crate) mountpoint: TempDir,
+    }
+
+    fn mount_and_create_files_with_settings(
+        files: Vec<(String, Vec<u8>)>,
+        symlinks: Vec<(String, String)>,
+        config: Option<Config>,
+        settings: Settings,
+    ) -> Result<TempSession, std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        create_files_and_symlinks(mirror.path(), &files, &symlinks)?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.unwrap_or_default(),
+            settings,
+            Box::new(|| ()),
+        )
+        .unwrap();
+
+        let session = mount(fs, &mountpoint, Vec::new())?;
+
+        Ok(TempSession {
+            _mirror: mirror,
+            mountpoint,
+            _session: session,
+        })
+    }
+
+    fn mount_and_create_files_with_symlinks(
+        files: Vec<(String, Vec<u8>)>,
+        symlinks: Vec<(String, String)>,
+        config: Option<Config>,
+    ) -> Result<TempSession, std::io::Error> {
+        mount_and_create_files_with_settings(files, symlinks, config, Settings::default())
+    }
+
+    fn mount_and_create_files(
+        files: Vec<(String, Vec<u8>)>,
+        config: Option<Config>,
+    ) -> Result<TempSession, std::io::Error> {
+        mount_and_create_files_with_symlinks(files, Vec::new(), config)
+    }
+
+    fn mount_and_create_seq_files(
+        num_files: usize,
+        config: Option<Config>,
+    ) -> Result<TempSession, std::io::Error> {
+        let files = (0..num_files)
+            .map(|i| {
+                let i_as_string = format!("{}", i);
+                (i_as_string.clone(), i_as_string.into_bytes())
+            })
+            .collect::<Vec<_>>();
+
+        mount_and_create_files(files, config)
+    }
+
+    fn list_files_in_path(path: PathBuf) -> Vec<PathBuf> {
+        fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>()
+    }
+
+    fn list_files_in_directories(dirs: &Vec<&DirEntry>) -> Vec<(String, Vec<PathBuf>)> {
+        dirs.iter()
+            .map(|item| {
+                let file_name = item.file_name().into_string().unwrap();
+                let files = list_files_in_path(item.path());
+                (file_name, files)
+            })
+            .collect::<Vec<_>>()
+    }
+
+    fn check_files(num_files: usize, dirs: &[&DirEntry], files: &[(String, Vec<PathBuf>)]) {
+        assert_eq!(dirs.len(), num_files);
+
+        assert_eq!(
+            files.iter().filter(|&(_, files)| files.len() == 1).count(),
+            num_files
+        );
+
+        assert_eq!(
+            files
+                .iter()
+                .filter(|&(file_name, files)| {
+                    let contents = files
+                        .iter()
+                        .flat_map(|entry| fs::read(entry).unwrap())
+                        .collect::<Vec<_>>();
+                    file_name.clone().into_bytes() == contents
+                })
+                .count(),
+            num_files
+        );
+    }
+
+    #[test]
+    fn test_empty_mirror() -> Result<(), std::io::Error> {
+        // Even with an empty mirror, there will be at least one file, namely the virtual config
+        // file, with a default Config struct and the digest of no chunks as content
+
+        let session = mount_and_create_seq_files(0, None)?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 1);
+
+        let file = entries.first().unwrap();
+
+        assert_eq!(file.file_name(), CONFIG_FILE_NAME);
+
+        let config = Config {
+            tree_digest: Some(tree_digest(Vec::new())),
+            ..Config::default()
+        };
+        assert_eq!(
+            fs::read_to_string(file.path())?,
+            serde_json::to_string(&config)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_mirror_custom_config() -> Result<(), std::io::Error> {
+        // Even with an empty mirror, there will be at least one file, namely the virtual config
+        // file, with the custom Config struct and the digest of no chunks as content
+
+        let config = Config::default().blocksize(1).unwrap();
+
+        let session = mount_and_create_seq_files(0, Some(config.clone()))?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 1);
+
+        let file = entries.first().unwrap();
+
+        assert_eq!(file.file_name(), CONFIG_FILE_NAME);
+
+        let config = Config {
+            tree_digest: Some(tree_digest(Vec::new())),
+            ..config
+        };
+        assert_eq!(
+            fs::read_to_string(file.path())?,
+            serde_json::to_string(&config)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_file() -> Result<(), std::io::Error> {
+        let num_files = 1;
+
+        let files = vec![("empty_file".to_string(), vec![])];
+
+        let session = mount_and_create_files(files, None)?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), num_files + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let dirs = entries
+            .iter()
+            .filter(|item| item.path().is_dir())
+            .collect::<Vec<_>>();
+
+        assert_eq!(dirs.len(), num_files);
+
+        let files = list_files_in_directories(&dirs);
+
+        assert_eq!(
+            files.iter().filter(|&(_, files)| files.len() == 1).count(),
+            num_files
+        );
+
+        assert_eq!(
+            files
+                .iter()
+                .filter(|&(_, files)| {
+                    let contents = files
+                        .iter()
+                        .flat_map(|entry| fs::read(entry).unwrap())
+                        .collect::<Vec<_>>();
+                    contents.is_empty()
+                })
+                .count(),
+            num_files
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_100_small_files() -> Result<(), std::io::Error> {
+        // With an mirror containing 100 small files, there should be 101 entries in the
+        // mountpoint: The config file and 100 folders representing the files. Each folder should
+        // contain a single part with the file contents, which are equal to the file name.
+
+        let num_files = 100;
+
+        let session = mount_and_create_seq_files(num_files, None)?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), num_files + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let dirs = entries
+            .iter()
+            .filter(|item| item.path().is_dir())
+            .collect::<Vec<_>>();
+
+        let files = list_files_in_directories(&dirs);
+
+        check_files(num_files, &dirs, &files);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_100_small_files_in_subfolders() -> Result<(), std::io::Error> {
+        // Same as above, but with multiple nested folders.
+
+        let num_files = 100;
+
+        let mut rng = rand::thread_rng();
+        let files = (0..num_files)
+            .map(|i| {
+                let file_name = format!(
+                    "{}/{}/{}/{}/{}/{}",
+                    i,
+                    rng.gen::<u32>(),
+                    rng.gen::<u32>(),
+                    rng.gen::<u32>(),
+                    rng.gen::<u32>(),
+                    rng.gen::<u32>()
+                );
+                let content = format!("{}", i,);
+                (file_name, content.into_bytes())
+            })
+            .collect::<Vec<_>>();
+
+        let session = mount_and_create_files(files, None)?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), num_files + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let dirs = entries
+            .iter()
+            .filter(|item| item.path().is_dir())
+            .collect::<Vec<_>>();
+
+        let files = dirs
+            .iter()
+            .map(|item| {
+                let file_name = item.file_name().into_string().unwrap();
+                let files = fs::read_dir(item.path())
+                    .unwrap()
+                    .map(|entry| {
+                        let mut path = entry.unwrap().path();
+                        while path.is_dir() {
+                            path = path.read_dir().unwrap().next().unwrap().unwrap().path();
+                        }
+                        path.to_path_buf()
+                    })
+                    .collect::<Vec<_>>();
+                (file_name, files)
+            })
+            .collect::<Vec<_>>();
+
+        check_files(num_files, &dirs, &files);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_inodes() -> Result<(), std::io::Error> {
+        // A few thousand tiny files must all be mounted with distinct inodes.
+
+        let num_dirs = 30;
+        let num_files = 3000;
+
+        let files = (0..num_files)
+            .map(|i| {
+                (
+                    format!("{}/{}", i % num_dirs, i),
+                    format!("{}", i).into_bytes(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let session = mount_and_create_files(files, None)?;
+
+        fn collect_inodes(path: &Path, inodes: &mut Vec<u64>) -> Result<(), std::io::Error> {
+            for entry in fs::read_dir(path)? {
+                let path = entry?.path();
+                inodes.push(fs::symlink_metadata(&path)?.ino());
+                if path.is_dir() {
+                    collect_inodes(&path, inodes)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut inodes = Vec::new();
+        collect_inodes(session.mountpoint.path(), &mut inodes)?;
+
+        // Each file is a directory with a single chunk, plus the directories and the config file.
+        assert_eq!(inodes.len(), 2 * num_files + num_dirs + 1);
+        assert_eq!(inodes.iter().collect::<HashSet<_>>().len(), inodes.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_big_file_bytewise() -> Result<(), std::io::Error> {
+        // A big file, with a block size of 1 byte, should be splitted in as many parts as bytes.
+        // By concatenating all parts together, the original content should be created.
+
+        let config = Config::default().blocksize(1).unwrap();
+
+        let mut data = [0u8; 100];
+        rand::thread_rng().fill_bytes(&mut data);
+        let data = data.to_vec();
+
+        let files = vec![("huge_file".to_string(), data.clone())];
+
+        let session = mount_and_create_files(files, Some(config))?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 1 + 1);
+
+        let dirs = entries
+            .iter()
+            .filter(|item| item.path().is_dir())
+            .collect::<Vec<_>>();
+
+        assert_eq!(dirs.len(), 1);
+
+        let dir = dirs.first().unwrap();
+
+        let files = list_files_in_path(dir.path());
+
+        assert_eq!(files.len(), data.len());
+
+        assert_eq!(
+            files
+                .iter()
+                .flat_map(|file| fs::read(file).unwrap())
+                .collect::<Vec<_>>(),
+            data
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_big_file_blockwise() -> Result<(), std::io::Error> {
+        // A big file, with a custom block size, should be splitted in as many parts as needed so
+        // that the parts are no larger than the block size. By concatenating all parts together,
+        // the original content should be created.
+
+        let blocksize = 37;
+
+        let config = Config::default().blocksize(blocksize).unwrap();
+
+        let mut data = [0u8; 100];
+        rand::thread_rng().fill_bytes(&mut data);
+        let data = data.to_vec();
+
+        let files = vec![("huge_file".to_string(), data.clone())];
+
+        let session = mount_and_create_files(files, Some(config))?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), 1 + 1);
+
+        let dirs = entries
+            .iter()
+            .filter(|item| item.path().is_dir())
+            .collect::<Vec<_>>();
+
+        assert_eq!(dirs.len(), 1);
+
+        let dir = dirs.first().unwrap();
+
+        let files = list_files_in_path(dir.path());
+
+        assert_eq!(
+            files.len(),
+            (data.len() as f32 / blocksize as f32).ceil() as usize
+        );
+
+        assert_eq!(
+            files
+                .iter()
+                .filter(|item| fs::read(item).unwrap().len() == blocksize as usize)
+                .count(),
+            files.len() - 1
+        );
+
+        assert_eq!(
+            fs::read(files.last().unwrap()).unwrap().len(),
+            data.len() % blocksize as usize
+        );
+
+        assert_eq!(
+            files
+                .iter()
+                .flat_map(|file| fs::read(file).unwrap())
+                .collect::<Vec<_>>(),
+            data
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_chunk_twice() -> Result<(), std::io::Error> {
+        // Two handles to the same chunk must be independent of each other, also if one of them
+        // is closed while the other one is still in use.
+
+        let blocksize = 37;
+
+        let config = Config::default().blocksize(blocksize).unwrap();
+
+        let mut data = [0u8; 100];
+        rand::thread_rng().fill_bytes(&mut data);
+        let data = data.to_vec();
+
+        let files = vec![("huge_file".to_string(), data.clone())];
+
+        let session = mount_and_create_files(files, Some(config))?;
+
+        let chunk = session
+            .mountpoint
+            .path()
+            .join("huge_file")
+            .join("scfs.0000000001");
+        let expected = &data[blocksize as usize..2 * blocksize as usize];
+
+        let mut first = File::open(&chunk)?;
+        let mut second = File::open(&chunk)?;
+
+        let mut head = [0u8; 10];
+        first.read_exact(&mut head)?;
+        assert_eq!(head, expected[..10]);
+
+        let mut buf = Vec::new();
+        second.read_to_end(&mut buf)?;
+        assert_eq!(buf, expected);
+        drop(second);
+
+        let mut tail = Vec::new();
+        first.read_to_end(&mut tail)?;
+        assert_eq!(tail, expected[10..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_reads() -> Result<(), std::io::Error> {
+        // Many concurrent reads, served by only a few threads, must all yield the correct data.
+
+        let blocksize = 100;
+
+        let config = Config::default().blocksize(blocksize).unwrap();
+        let settings = Settings::default().read_threads(Some(2));
+
+        let mut data = vec![0u8; 10_000];
+        rand::thread_rng().fill_bytes(&mut data);
+
+        let files = vec![("huge_file".to_string(), data.clone())];
+
+        let session =
+            mount_and_create_files_with_settings(files, Vec::new(), Some(config), settings)?;
+
+        let dir = session.mountpoint.path().join("huge_file");
+
+        let readers = (0..16)
+            .map(|_| {
+                let dir = dir.clone();
+                let data = data.clone();
+                std::thread::spawn(move || {
+                    for (part, expected) in data.chunks(blocksize as usize).enumerate() {
+                        let chunk = fs::read(dir.join(format!("scfs.{:010}", part))).unwrap();
+                        assert_eq!(chunk, expected);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_deleted_file() -> Result<(), std::io::Error> {
+        // If a mirror file vanishes after its chunk has been opened, reading must fail with an
+        // I/O error.
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let mut chunk = File::open(mountpoint.path().join("file").join("scfs.0000000000"))?;
+        fs::remove_file(mirror.path().join("file"))?;
+
+        let error = chunk.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(EIO));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_warn_unsupported_file_type() -> Result<(), std::io::Error> {
+        init_test_logger();
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let fifo = mirror.path().join("fifo");
+        mkfifo(&fifo, Mode::S_IRWXU)?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert!(!mountpoint.path().join("fifo").exists());
+        assert!(logged_messages()
+            .iter()
+            .any(|(level, message)| *level == Level::Warn
+                && message.contains(&format!("{:?}", fifo))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_special_files() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        mkfifo(&mirror.path().join("fifo"), Mode::S_IRWXU)?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default().special_files(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let fifo = mountpoint.path().join("fifo");
+        assert!(fs::symlink_metadata(&fifo)?.file_type().is_fifo());
+        assert_eq!(
+            fs::symlink_metadata(&fifo)?.permissions().mode() & 0o777,
+            0o700
+        );
+
+        let entries = list_files_in_path(mountpoint.path().to_path_buf());
+        assert!(entries.contains(&fifo));
+        let entry = fs::read_dir(mountpoint.path())?
+            .map(|entry| entry.unwrap())
+            .find(|entry| entry.file_name() == "fifo")
+            .unwrap();
+        assert!(entry.file_type()?.is_fifo());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_stats() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = (0..200)
+            .map(|i| (format!("dir_{}/file_{}", i % 10, i), vec![0u8; i % 30]))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        // Without caching, every stat reaches the file system
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(7).unwrap(),
+            Settings::default().attr_ttl(Some(Duration::ZERO)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        thread::scope(|scope| {
+            for t in 0..8 {
+                let files = &files;
+                let mountpoint = mountpoint.path();
+                scope.spawn(move || {
+                    for round in 0..5 {
+                        // Each thread walks the files in its own order
+                        for i in 0..files.len() {
+                            let (file_name, content) =
+                                &files[(i * 7 + t * 31 + round) % files.len()];
+                            let file = mountpoint.join(file_name);
+                            assert!(fs::metadata(&file).unwrap().is_dir());
+
+                            let last = content.len().saturating_sub(1) / 7;
+                            let chunk = file.join(format!("scfs.{:010}", last));
+                            let expected = if content.is_empty() {
+                                0
+                            } else {
+                                content.len() - last * 7
+                            };
+                            assert_eq!(fs::metadata(chunk).unwrap().len(), expected as u64);
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_id_maps() -> Result<(), std::io::Error> {
+        let files = vec![("a".to_string(), vec![0u8; 30])];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default()
+                .uid_map(IdMap(vec![(None, 1234)]))
+                .gid_map(IdMap(vec![(None, 4321)])),
+        )?;
+
+        let chunk = fs::metadata(session.mountpoint.path().join("a/scfs.0000000001"))?;
+        assert_eq!((chunk.uid(), chunk.gid()), (1234, 4321));
+
+        let dir = fs::metadata(session.mountpoint.path().join("a"))?;
+        assert_eq!((dir.uid(), dir.gid()), (1234, 4321));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_noatime() -> Result<(), std::io::Error> {
+        let data = (0..30).collect::<Vec<u8>>();
+        let files = vec![("a".to_string(), data.clone())];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default().noatime(true),
+        )?;
+
+        // An access time before the modification time would be updated by any read
+        let file = session._mirror.path().join("a");
+        let accessed = std::time::UNIX_EPOCH + Duration::from_secs(1000);
+        File::options()
+            .write(true)
+            .open(&file)?
+            .set_times(fs::FileTimes::new().set_accessed(accessed))?;
+
+        for part in 0..3 {
+            let chunk = fs::read(
+                session
+                    .mountpoint
+                    .path()
+                    .join(format!("a/scfs.{:010}", part)),
+            )?;
+            assert_eq!(chunk, data[part * 10..part * 10 + 10]);
+        }
+        assert_eq!(fs::metadata(&file)?.accessed()?, accessed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_entries() -> Result<(), std::io::Error> {
+        let files = vec![
+            (".hidden".to_string(), b"x".to_vec()),
+            ("a/x".to_string(), b"x".to_vec()),
+            ("b".to_string(), b"0123456789".to_vec()),
+            ("b-c".to_string(), b"x".to_vec()),
+        ];
+        let config = Config::default()
+            .blocksize(5)
+            .unwrap()
+            .min_split_size(Some(5))
+            .chunked_suffix(Some(".scfs".to_string()));
+
+        let names = |path: &Path| {
+            fs::read_dir(path)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let session = mount_and_create_files_with_settings(
+            files.clone(),
+            Vec::new(),
+            Some(config.clone()),
+            Settings::default().emit_count(true),
+        )?;
+        assert_eq!(
+            names(session.mountpoint.path()),
+            [CONFIG_FILE_NAME, ".hidden", "a", "b.scfs", "b-c"]
+        );
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(config),
+            Settings::default().emit_count(true).sort_entries(true),
+        )?;
+        assert_eq!(
+            names(session.mountpoint.path()),
+            [".hidden", CONFIG_FILE_NAME, "a", "b-c", "b.scfs"]
+        );
+        assert_eq!(
+            names(&session.mountpoint.path().join("b.scfs")),
+            [COUNT_FILE_NAME, "scfs.0000000000", "scfs.0000000001"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_open_files() -> Result<(), std::io::Error> {
+        let data = (0..=255).cycle().take(640).collect::<Vec<u8>>();
+        let files = vec![("a".to_string(), data.clone())];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default()
+                .max_open_files(Some(2))
+                .read_threads(Some(8)),
+        )?;
+
+        // All chunks are open at once and read concurrently, but only two at a time get through
+        let chunks = (0..64)
+            .map(|part| {
+                File::open(
+                    session
+                        .mountpoint
+                        .path()
+                        .join(format!("a/scfs.{:010}", part)),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let threads = chunks
+            .into_iter()
+            .map(|mut chunk| {
+                thread::spawn(move || {
+                    let mut content = Vec::new();
+                    chunk.read_to_end(&mut content).map(|_| content)
+                })
+            })
+            .collect::<Vec<_>>();
+        for (part, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap()?, data[part * 10..part * 10 + 10]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_limit() -> Result<(), std::io::Error> {
+        let data = (0..=255).cycle().take(1000).collect::<Vec<u8>>();
+        let files = vec![("a".to_string(), data.clone())];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(300).unwrap()),
+            Settings::default().read_limit(Some(1 << 30)),
+        )?;
+
+        for part in 0..4 {
+            let chunk = fs::read(
+                session
+                    .mountpoint
+                    .path()
+                    .join(format!("a/scfs.{:010}", part)),
+            )?;
+            assert_eq!(chunk, data[part * 300..data.len().min(part * 300 + 300)]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_limit_slows_reads() -> Result<(), std::io::Error> {
+        let data = vec![7; 30_000];
+        let files = vec![("a".to_string(), data.clone())];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(15_000).unwrap()),
+            Settings::default().read_limit(Some(10_000)),
+        )?;
+
+        // The first second worth of bytes passes right away, the rest has to wait for the limit
+        let start = Instant::now();
+        for part in 0..2 {
+            let chunk = fs::read(
+                session
+                    .mountpoint
+                    .path()
+                    .join(format!("a/scfs.{:010}", part)),
+            )?;
+            assert_eq!(chunk, data[..15_000]);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(1500));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_forget_drops_cached_state() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+
+        let files = (0..20)
+            .map(|i| (format!("file_{}", i), vec![i as u8; 100]))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let mut fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(10)
+                .unwrap()
+                .compression(Some(Compression::Gzip)),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+
+        let chunks = fs
+            .file_db
+            .prepare("SELECT ino FROM Files WHERE part > 0")
+            .unwrap()
+            .query_map([], |row| row.get::<_, u64>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 200);
+
+        // Stating the chunks remembers their compressed sizes
+        let sizes = chunks
+            .iter()
+            .map(|ino| fs.get_attr_from_ino(*ino).unwrap().size)
+            .collect::<Vec<_>>();
+        assert_eq!(fs.compressed_sizes.borrow().len(), chunks.len());
+
+        for ino in &chunks {
+            fs.forget_inode(*ino);
+        }
+        assert!(fs.compressed_sizes.borrow().is_empty());
+
+        // Forgotten inodes can still be looked up again
+        for (ino, size) in chunks.iter().zip(sizes) {
+            assert_eq!(fs.get_attr_from_ino(*ino).unwrap().size, size);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readdir_large_directory() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        // Far more entries than fit into a single readdir reply
+        let files = (0..3000)
+            .map(|i| (format!("file_with_a_long_name_{}", i), Vec::new()))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let names = fs::read_dir(mountpoint.path())?
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+
+        // The config file comes first, followed by the sorted entries of the mirror
+        assert_eq!(names[0], CONFIG_FILE_NAME);
+        let mut expected = files.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(names[1..], expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocksize_manifest() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![
+            ("big".to_string(), vec![1; 100]),
+            ("dir/small".to_string(), vec![2; 100]),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        fs::write(
+            mirror.path().join(BLOCKSIZES_MANIFEST_NAME),
+            r#"{"big": 30}"#,
+        )?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(7).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // Listed files get their own blocksize and announce it, all others use the global one
+        let big = mountpoint.path().join("big");
+        assert_eq!(fs::read_to_string(big.join(BLOCKSIZE_FILE_NAME))?, "30\n");
+        let chunks = fs::read_dir(&big)?
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != BLOCKSIZE_FILE_NAME)
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(fs::read(big.join("scfs.0000000003"))?.len(), 10);
+
+        let small = mountpoint.path().join("dir/small");
+        assert!(!small.join(BLOCKSIZE_FILE_NAME).exists());
+        assert_eq!(fs::read_dir(&small)?.count(), 15);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocksize_manifest_with_zero_blocksize() {
+        let mirror = tempdir().unwrap();
+        fs::write(
+            mirror.path().join(BLOCKSIZES_MANIFEST_NAME),
+            r#"{"big": 0}"#,
+        )
+        .unwrap();
+
+        let result = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        );
+        match result {
+            Err(MountError::InvalidConfig(message)) => {
+                assert_eq!(message, "Blocksize of \"big\" may not be zero")
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("SplitFS accepted the manifest"),
+        }
+
+        fs::write(mirror.path().join(BLOCKSIZES_MANIFEST_NAME), "{").unwrap();
+        let result = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        );
+        assert!(matches!(result, Err(MountError::InvalidJson(_, _))));
+    }
+
+    #[test]
+    fn test_mark_chunked() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![("dir/big".to_string(), (0..10).collect::<Vec<u8>>())];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(4)
+                .unwrap()
+                .chunked_suffix(Some(".scfs".to_string())),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // Only the chunked file is marked, real directories are not
+        let names = |dir: PathBuf| -> Result<HashSet<String>, std::io::Error> {
+            Ok(fs::read_dir(dir)?
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect())
+        };
+        assert!(names(mountpoint.path().to_path_buf())?.contains("dir"));
+        assert_eq!(
+            names(mountpoint.path().join("dir"))?,
+            HashSet::from(["big.scfs".to_string()])
+        );
+
+        // Both names lead to the chunks
+        for name in ["big.scfs", "big"] {
+            let chunked = mountpoint.path().join("dir").join(name);
+            assert!(chunked.is_dir());
+            assert_eq!(names(chunked.clone())?.len(), 3);
+            assert_eq!(fs::read(chunked.join("scfs.0000000002"))?, vec![8, 9]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placeholder() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let _listener = UnixListener::bind(mirror.path().join("sock"))?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default().placeholder(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let placeholder = mountpoint.path().join("sock.unsupported");
+        let meta = fs::symlink_metadata(&placeholder)?;
+        assert!(meta.is_file());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o444);
+
+        let content = fs::read_to_string(&placeholder)?;
+        assert_eq!(
+            content,
+            "Unsupported file type: socket\nOriginal size: 0 bytes\n"
+        );
+        assert_eq!(meta.len(), content.len() as u64);
+
+        assert!(!mountpoint.path().join("sock").exists());
+        let entries = list_files_in_path(mountpoint.path().to_path_buf());
+        assert!(entries.contains(&placeholder));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_populate_progress() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+
+        let files = (0..2500)
+            .map(|i| (format!("dir_{}/file_{}", i % 10, i), vec![0u8; i % 7]))
+            .collect::<Vec<_>>();
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let (tx, rx) = channel();
+        let _fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default().progress(Some(tx)),
+            Box::new(|| ()),
+        )
+        .unwrap();
+
+        // The channel is closed once populating is done
+        let progress = rx.iter().collect::<Vec<_>>();
+
+        assert!(!progress.is_empty());
+        assert!(progress
+            .windows(2)
+            .all(|w| w[0].files < w[1].files && w[0].bytes <= w[1].bytes));
+
+        // The root, the directories and the files
+        let last = progress.last().unwrap();
+        assert_eq!(last.files, 1 + 10 + 2500);
+        assert_eq!(
+            last.bytes,
+            files.iter().map(|(_, c)| c.len() as u64).sum::<u64>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_access() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let chunk = mountpoint.path().join("file").join("scfs.0000000000");
+        assert_eq!(access(&chunk, AccessFlags::R_OK), Ok(()));
+        assert_eq!(access(&chunk, AccessFlags::W_OK), Err(Errno::EROFS));
+        assert_eq!(
+            access(&mountpoint.path().join(CONFIG_FILE_NAME), AccessFlags::R_OK),
+            Ok(())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_split_size() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![
+            ("small".to_string(), vec![1u8; 10]),
+            ("big".to_string(), vec![2u8; 11]),
+            // Would be mistaken for a chunk if it was not split
+            ("dir/scfs.0000000000".to_string(), vec![3u8; 5]),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default()
+                .blocksize(4)
+                .unwrap()
+                .min_split_size(Some(10)),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let kinds = fs::read_dir(mountpoint.path())?
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.file_name(), entry.file_type().unwrap().is_file())
+            })
+            .collect::<HashMap<_, _>>();
+        assert!(kinds[OsStr::new("small")]);
+        assert!(!kinds[OsStr::new("big")]);
+
+        let small = mountpoint.path().join("small");
+        assert!(small.is_file());
+        assert_eq!(fs::metadata(&small)?.len(), 10);
+        assert_eq!(fs::read(&small)?, vec![1u8; 10]);
+
+        let big = mountpoint.path().join("big");
+        assert!(big.is_dir());
+        assert_eq!(fs::read_dir(&big)?.count(), 3);
+
+        assert!(mountpoint.path().join("dir/scfs.0000000000").is_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fsync() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let chunk = File::open(mountpoint.path().join("file").join("scfs.0000000000"))?;
+        chunk.sync_all()?;
+        chunk.sync_data()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_operations() -> Result<(), std::io::Error> {
+        // Operations that are not supported fail cleanly and leave the mount usable
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("file"), b"content")?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let chunk_path = mountpoint.path().join("file").join("scfs.0000000000");
+        let chunk = File::open(&chunk_path)?;
+
+        // Read-only file descriptors are already refused by the kernel
+        let error =
+            nix::fcntl::fallocate(chunk.as_raw_fd(), FallocateFlags::empty(), 0, 10).unwrap_err();
+        assert!([Errno::EBADF, Errno::EROFS].contains(&error), "{}", error);
+
+        let mut flags = 0;
+        assert_eq!(
+            unsafe { libc::ioctl(chunk.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) },
+            -1
+        );
+        assert_eq!(Errno::last(), Errno::ENOTTY);
+
+        assert_eq!(fs::read(&chunk_path)?, b"content");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_io_buffer() -> Result<(), std::io::Error> {
+        // The buffer size only changes how chunks are read from the mirror, not what is read
+
+        let mut rng = rand::thread_rng();
+        let mut content = vec![0u8; 2500];
+        rng.fill_bytes(&mut content);
+
+        for io_buffer in [1, 7, 1024 * 1024] {
+            let session = mount_and_create_files_with_settings(
+                vec![(String::from("file"), content.clone())],
+                Vec::new(),
+                Some(Config::default().blocksize(1000).unwrap()),
+                Settings::default().io_buffer(Some(io_buffer)),
+            )?;
+
+            let file = session.mountpoint.path().join("file");
+            for (part, expected) in content.chunks(1000).enumerate() {
+                let chunk = file.join(format!("scfs.{:010}", part));
+                assert_eq!(fs::read(&chunk)?, expected, "{}", io_buffer);
+
+                let mut buf = vec![0; 300];
+                let read = File::open(&chunk)?.read_at(&mut buf, 450)?;
+                assert_eq!(&buf[..read], &expected[450.min(expected.len())..][..read]);
+                assert_eq!(read, 300.min(expected.len().saturating_sub(450)));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_oversized_request() -> Result<(), std::io::Error> {
+        // Reading more than a partial chunk contains must return only the bytes of the chunk.
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let content = (0..10).collect::<Vec<u8>>();
+        fs::write(mirror.path().join("file"), &content)?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(2 * 1024 * 1024).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let chunk = File::open(mountpoint.path().join("file").join("scfs.0000000000"))?;
+        assert_eq!(chunk.metadata()?.len(), 10);
+
+        let mut buf = vec![0xff; 4 * 1024 * 1024];
+        let read = chunk.read_at(&mut buf, 0)?;
+        assert_eq!(&buf[..read], content.as_slice());
+        assert_eq!(chunk.read_at(&mut buf, 5)?, 5);
+        assert_eq!(chunk.read_at(&mut buf, 10)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrunk_file() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("a"), (0..10).collect::<Vec<u8>>())?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(3).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        File::options()
+            .write(true)
+            .open(mirror.path().join("a"))?
+            .set_len(2)?;
+
+        let dir = mountpoint.path().join("a");
+        assert_eq!(fs::metadata(dir.join("scfs.0000000000"))?.len(), 2);
+        assert_eq!(fs::metadata(dir.join("scfs.0000000003"))?.len(), 0);
+        assert!(fs::read(dir.join("scfs.0000000003"))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_db_path_keeps_tree_digest() -> Result<(), std::io::Error> {
+        // A reused database brings its tree digest along, the mirror is not looked at again
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+        let db_dir = tempdir()?;
+
+        let settings = Settings::default().db_path(Some(db_dir.path().join("scfs.db")));
+
+        let mounted_digest = || {
+            let fs = SplitFS::new(
+                mirror.path().as_os_str(),
+                Config::default(),
+                settings.clone(),
+                Box::new(|| ()),
+            )
+            .unwrap();
+            let _session = mount(fs, &mountpoint, Vec::new()).unwrap();
+
+            Config::from_mirror(mountpoint.path())
+                .unwrap()
+                .tree_digest
+                .unwrap()
+        };
+
+        fs::create_dir(mirror.path().join("a"))?;
+        fs::write(mirror.path().join("a/file"), b"1")?;
+        let digest = mounted_digest();
+
+        // Changes below the top-level are not detected, so the stored digest still applies
+        fs::write(mirror.path().join("a/file"), b"12")?;
+        assert_eq!(mounted_digest(), digest);
+
+        fs::write(mirror.path().join("b"), b"b")?;
+        assert_ne!(mounted_digest(), digest);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_exclude_pattern() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+
+        let error = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default().exclude(vec![String::from("a{")]),
+            Box::new(|| ()),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(error, MountError::InvalidConfig(_)), "{}", error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_db_path() -> Result<(), std::io::Error> {
+        // A database on disk is reused as long as the mirror's top-level mtime and the blocksize
+        // do not change, otherwise it is rebuilt.
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+        let db_dir = tempdir()?;
+
+        let settings = Settings::default().db_path(Some(db_dir.path().join("scfs.db")));
+
+        let list_mounted = |config: Config| {
+            let fs = SplitFS::new(
+                mirror.path().as_os_str(),
+                config,
+                settings.clone(),
+                Box::new(|| ()),
+            )
+            .unwrap();
+            let _session = mount(fs, &mountpoint, Vec::new()).unwrap();
+
+            let mut entries = fs::read_dir(mountpoint.path().join("a"))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            entries.sort();
+            entries
+        };
+
+        fs::create_dir(mirror.path().join("a"))?;
+        fs::write(mirror.path().join("a/file1"), b"1")?;
+        assert_eq!(list_mounted(Config::default()), vec!["file1"]);
+
+        // Changes below the top-level are not detected
+        fs::write(mirror.path().join("a/file2"), b"2")?;
+        assert_eq!(list_mounted(Config::default()), vec!["file1"]);
+
+        fs::write(mirror.path().join("b"), b"b")?;
+        assert_eq!(list_mounted(Config::default()), vec!["file1", "file2"]);
+
+        fs::write(mirror.path().join("a/file3"), b"3")?;
+        assert_eq!(
+            list_mounted(Config::default().blocksize(1).unwrap()),
+            vec!["file1", "file2", "file3"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_listing_is_snapshot() -> Result<(), std::io::Error> {
+        // Rows that are added to the database while a directory is being listed neither show up
+        // in nor shift the entries of the listing in progress
+
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+        let db_dir = tempdir()?;
+        let db_path = db_dir.path().join("scfs.db");
+
+        // Enough entries to take several readdir calls
+        let mut expected = (0..500).map(|i| format!("f{:03}", i)).collect::<Vec<_>>();
+        for name in &expected {
+            fs::write(mirror.path().join(name), b"")?;
+        }
 
         let fs = SplitFS::new(
             mirror.path().as_os_str(),
-            config.unwrap_or_default(),
+            Config::default(),
+            Settings::default().db_path(Some(db_path.clone())),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new()).unwrap();
+
+        let mut entries = fs::read_dir(mountpoint.path())?;
+        let mut listed = vec![entries.next().unwrap()?.file_name().into_string().unwrap()];
+
+        // Sorts before all other entries, so it would shift them if the listing was read from the
+        // database on every call
+        let added = mirror.path().join("added");
+        fs::write(&added, b"")?;
+        let file_db = Connection::open(&db_path).unwrap();
+        insert_file_info(
+            &file_db,
+            FileInfo {
+                ino: 1 << 40,
+                parent_ino: INO_ROOT,
+                path: added.clone().into_os_string(),
+                file_name: "added".into(),
+                part: 0,
+                vdir: false,
+                symlink: false,
+            },
+        );
+
+        for entry in entries {
+            listed.push(entry?.file_name().into_string().unwrap());
+        }
+        listed.sort();
+        expected.push(CONFIG_FILE_NAME.to_string());
+        expected.sort();
+        assert_eq!(listed, expected);
+
+        // Directories opened afterwards see the new row
+        assert!(fs::read_dir(mountpoint.path())?.any(|entry| entry.unwrap().file_name() == "added"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attr_ttl() -> Result<(), std::io::Error> {
+        // Without caching, the kernel asks for the attributes on every stat, so changes in the
+        // mirror show up immediately
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("a"), b"12345")?;
+
+        let settings = Settings::default().attr_ttl(Some(Duration::ZERO));
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            settings,
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let chunk = mountpoint.path().join("a").join("scfs.0000000000");
+        assert_eq!(fs::metadata(&chunk)?.len(), 5);
+
+        fs::write(mirror.path().join("a"), b"1234567890")?;
+        assert_eq!(fs::metadata(&chunk)?.len(), 10);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn test_crtime() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        fs::write(mirror.path().join("a"), b"12345")?;
+
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default(),
+            Settings::default(),
             Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let created = fs::metadata(mountpoint.path().join("a"))?.created()?;
+        assert!(created > std::time::UNIX_EPOCH);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_gzip() -> Result<(), std::io::Error> {
+        // Each chunk must contain the gzip-compressed bytes of its part of the source file, and
+        // the reported size must match the compressed length.
+
+        let blocksize = 1000;
+
+        let config = Config::default()
+            .blocksize(blocksize)
+            .unwrap()
+            .compression(Some(Compression::Gzip));
+
+        let data = (0..2500).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+        let files = vec![("file".to_string(), data.clone())];
+
+        let session = mount_and_create_files(files, Some(config))?;
+
+        let chunks = list_files_in_path(session.mountpoint.path().join("file"));
+        assert_eq!(chunks.len(), 3);
+
+        for (chunk, expected) in chunks.iter().zip(data.chunks(blocksize as usize)) {
+            let compressed = fs::read(chunk)?;
+            assert_eq!(fs::metadata(chunk)?.len(), compressed.len() as u64);
+            assert!(compressed.len() < expected.len());
+
+            let mut decompressed = Vec::new();
+            GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, expected);
+        }
+
+        let config = fs::read_to_string(session.mountpoint.path().join(CONFIG_FILE_NAME))?;
+        assert!(config.contains(r#""compression":"gzip""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_relative_file() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such, no splitting or any other modification.
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_rel".into(), CONFIG_FILE_NAME.into());
+
+        let session = mount_and_create_files_with_symlinks(
+            Vec::new(),
+            symlink_map.clone().into_iter().collect(),
+            None,
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), symlink_map.len() + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            read(symlinks_found.first().unwrap().path())?,
+            serde_json::to_string(&Config {
+                tree_digest: Some(tree_digest(Vec::new())),
+                ..Config::default()
+            })
+            .unwrap()
+            .into_bytes()
+        );
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_symlink_relative_vdir() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such, no splitting or any other modification. A
+        // relative symlink pointing to a file inside the chunked directory should translate into
+        // the same chunked virtual directory as the real file's counterpart.
+
+        let files = vec![(String::from("a/b/c"), String::from("42").into_bytes())];
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_rel".into(), "a/b/c".into());
+
+        let session = mount_and_create_files_with_symlinks(
+            files.clone(),
+            symlink_map.clone().into_iter().collect(),
+            None,
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), files.len() + symlink_map.len() + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        let parts = symlinks_found
+            .first()
+            .unwrap()
+            .path()
+            .read_dir()
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(parts.len(), 1);
+
+        assert_eq!(
+            read(parts.first().unwrap().as_ref().unwrap().path())?,
+            files.first().unwrap().1
+        );
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_symlink_absolute_dir() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such, no splitting or any other modification.
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_abs".into(), "/".into());
+
+        let session = mount_and_create_files_with_symlinks(
+            Vec::new(),
+            symlink_map.clone().into_iter().collect(),
+            None,
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), symlink_map.len() + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
+        );
+
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
+
+        assert!(symlinks_found.first().unwrap().path().is_dir());
+
+        check_symlinks(&mut symlink_map, &symlinks_found)
+    }
+
+    #[test]
+    fn test_symlink_broken() -> Result<(), std::io::Error> {
+        // A symlink should just be presented as such, no splitting or any other modification. If
+        // the target does not exist, just show a broken symlink, do not panic out.
+
+        let mut symlink_map = HashMap::new();
+        symlink_map.insert("link_rel".into(), "a/b/c".into());
+        symlink_map.insert("link_abs".into(), "/home/nobody/nothing".into());
+
+        let session = mount_and_create_files_with_symlinks(
+            Vec::new(),
+            symlink_map.clone().into_iter().collect(),
+            None,
+        )?;
+
+        let entries = fs::read_dir(&session.mountpoint)?
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries.len(), symlink_map.len() + 1);
+
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
+                .count(),
+            1
         );
 
-        let session = mount(fs, &mountpoint, Vec::new());
+        let symlinks_found = entries
+            .iter()
+            .filter(|item| item.file_type().unwrap().is_symlink())
+            .collect::<Vec<_>>();
 
-        Ok(TempSession {
-            _mirror: mirror,
-            mountpoint,
-            _session: session,
-        })
+        check_symlinks(&mut symlink_map, &symlinks_found)
     }
 
-    fn mount_and_create_files<'a>(
-        files: Vec<(String, Vec<u8>)>,
-        config: Option<Config>,
-    ) -> Result<TempSession, std::io::Error> {
-        mount_and_create_files_with_symlinks(files, Vec::new(), config)
-    }
+    #[test]
+    fn test_follow_symlinks() -> Result<(), std::io::Error> {
+        // With follow_symlinks, symlinks are chunked like their targets, unless this would lead
+        // into an endless loop.
+
+        let files = vec![
+            ("a/b".to_string(), vec![1u8; 15]),
+            ("c".to_string(), vec![2u8; 5]),
+        ];
+
+        let symlinks = vec![
+            ("link_file".to_string(), "c".to_string()),
+            ("link_dir".to_string(), "a".to_string()),
+            ("a/loop".to_string(), "..".to_string()),
+            ("self".to_string(), "self".to_string()),
+        ];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            symlinks,
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default().follow_symlinks(true),
+        )?;
 
-    fn mount_and_create_seq_files<'a>(
-        num_files: usize,
-        config: Option<Config>,
-    ) -> Result<TempSession, std::io::Error> {
-        let files = (0..num_files)
-            .map(|i| {
-                let i_as_string = format!("{}", i);
-                (i_as_string.clone(), i_as_string.into_bytes())
-            })
-            .collect::<Vec<_>>();
+        let mountpoint = session.mountpoint.path();
 
-        mount_and_create_files(files, config)
-    }
+        let link_file = mountpoint.join("link_file");
+        assert!(!link_file.symlink_metadata()?.is_symlink());
+        assert_eq!(list_files_in_path(link_file.clone()).len(), 1);
+        assert_eq!(fs::read(link_file.join("scfs.0000000000"))?, vec![2u8; 5]);
 
-    fn list_files_in_path(path: PathBuf) -> Vec<PathBuf> {
-        fs::read_dir(path)
-            .unwrap()
-            .map(|entry| entry.unwrap().path())
-            .collect::<Vec<_>>()
-    }
+        let link_dir = mountpoint.join("link_dir");
+        assert!(link_dir.symlink_metadata()?.is_dir());
+        assert_eq!(list_files_in_path(link_dir.join("b")).len(), 2);
+        assert_eq!(fs::read(link_dir.join("b/scfs.0000000001"))?, vec![1u8; 5]);
 
-    fn list_files_in_directories(dirs: &Vec<&DirEntry>) -> Vec<(String, Vec<PathBuf>)> {
-        dirs.iter()
-            .map(|item| {
-                let file_name = item.file_name().into_string().unwrap();
-                let files = list_files_in_path(item.path());
-                (file_name, files)
-            })
-            .collect::<Vec<_>>()
+        // Symlinks to ancestors and broken symlinks stay as they are
+        for link in ["a/loop", "link_dir/loop", "self"] {
+            assert!(
+                mountpoint.join(link).symlink_metadata()?.is_symlink(),
+                "{}",
+                link
+            );
+        }
+        assert_eq!(fs::read_link(mountpoint.join("a/loop"))?, Path::new(".."));
+
+        Ok(())
     }
 
-    fn check_files(num_files: usize, dirs: &Vec<&DirEntry>, files: &Vec<(String, Vec<PathBuf>)>) {
-        assert_eq!(dirs.len(), num_files);
+    #[test]
+    fn test_emit_count() -> Result<(), std::io::Error> {
+        // With emit_count, every virtual directory contains a .count file next to the chunks,
+        // which holds the number of chunks.
+
+        let config = Config::default().blocksize(10).unwrap();
+
+        let files = vec![
+            ("a".to_string(), vec![0u8; 95]),
+            ("b/c".to_string(), vec![0u8; 0]),
+        ];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(config),
+            Settings::default().emit_count(true),
+        )?;
+
+        let mountpoint = session.mountpoint.path();
+
+        let entries = list_files_in_path(mountpoint.join("a"));
+        assert_eq!(entries.len(), 10 + 1);
+        assert!(entries.contains(&mountpoint.join("a").join(COUNT_FILE_NAME)));
 
         assert_eq!(
-            files.iter().filter(|&(_, files)| files.len() == 1).count(),
-            num_files
+            fs::read_to_string(mountpoint.join("a").join(COUNT_FILE_NAME))?,
+            "10\n"
         );
-
         assert_eq!(
-            files
-                .iter()
-                .filter(|&(file_name, files)| {
-                    let contents = files
-                        .iter()
-                        .flat_map(|entry| fs::read(entry).unwrap())
-                        .collect::<Vec<_>>();
-                    file_name.clone().into_bytes() == contents
-                })
-                .count(),
-            num_files
+            fs::read_to_string(mountpoint.join("b/c").join(COUNT_FILE_NAME))?,
+            "1\n"
         );
+
+        // Real directories do not get a .count file
+        assert!(!mountpoint.join("b").join(COUNT_FILE_NAME).exists());
+        assert!(!list_files_in_path(mountpoint.join("b"))
+            .contains(&mountpoint.join("b").join(COUNT_FILE_NAME)));
+
+        Ok(())
     }
 
     #[test]
-    fn test_empty_mirror() -> Result<(), std::io::Error> {
-        // Even with an empty mirror, there will be at least one file, namely the virtual config
-        // file, with a default Config struct as content
+    fn test_emit_meta() -> Result<(), std::io::Error> {
+        // With emit_meta, every virtual directory contains a .scfs_meta file next to the chunks,
+        // which holds the metadata of the original file.
 
-        let session = mount_and_create_seq_files(0, None)?;
+        let config = Config::default().blocksize(10).unwrap();
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let files = vec![("a".to_string(), vec![0u8; 95])];
 
-        assert_eq!(entries.len(), 1);
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(config),
+            Settings::default().emit_count(true).emit_meta(true),
+        )?;
 
-        let file = entries.get(0).unwrap();
+        let mountpoint = session.mountpoint.path();
 
-        assert_eq!(file.file_name(), CONFIG_FILE_NAME);
+        let entries = list_files_in_path(mountpoint.join("a"));
+        assert_eq!(entries.len(), 10 + 2);
+        assert!(entries.contains(&mountpoint.join("a").join(COUNT_FILE_NAME)));
+        assert!(entries.contains(&mountpoint.join("a").join(META_FILE_NAME)));
 
+        let meta: FileMeta = serde_json::from_str(&fs::read_to_string(
+            mountpoint.join("a").join(META_FILE_NAME),
+        )?)?;
+        // The virtual directory presents the mtime of the original file as well
+        let vdir = fs::metadata(mountpoint.join("a"))?;
         assert_eq!(
-            fs::read_to_string(file.path())?,
-            serde_json::to_string(&Config::default())?
+            (meta.mtime, meta.mtime_nsec),
+            (vdir.mtime(), vdir.mtime_nsec())
         );
+        assert_eq!(meta.uid, vdir.uid());
 
         Ok(())
     }
 
     #[test]
-    fn test_empty_mirror_custom_config() -> Result<(), std::io::Error> {
-        // Even with an empty mirror, there will be at least one file, namely the virtual config
-        // file, with the custom Config struct as content
+    fn test_emit_reads() -> Result<(), std::io::Error> {
+        // With emit_reads, every virtual directory contains a .scfs_reads file, which counts the
+        // reads of each chunk and the bytes they returned.
 
-        let config = Config::default().blocksize(1);
+        let config = Config::default().blocksize(10).unwrap();
 
-        let session = mount_and_create_seq_files(0, Some(config))?;
+        let files = vec![("a".to_string(), vec![0u8; 25])];
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(config),
+            Settings::default().emit_reads(true),
+        )?;
 
-        assert_eq!(entries.len(), 1);
+        let dir = session.mountpoint.path().join("a");
+        assert!(list_files_in_path(dir.clone()).contains(&dir.join(READS_FILE_NAME)));
 
-        let file = entries.get(0).unwrap();
+        let read_stats = || -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(dir.join(READS_FILE_NAME)).unwrap()).unwrap()
+        };
+        assert_eq!(read_stats()["bytes_read"], 0);
 
-        assert_eq!(file.file_name(), CONFIG_FILE_NAME);
+        assert_eq!(fs::read(dir.join("scfs.0000000000"))?.len(), 10);
+        assert_eq!(fs::read(dir.join("scfs.0000000002"))?.len(), 5);
+
+        let stats = read_stats();
+        assert_eq!(stats["reads"]["scfs.0000000000"], 1);
+        assert_eq!(stats["reads"]["scfs.0000000001"], 0);
+        assert_eq!(stats["reads"]["scfs.0000000002"], 1);
+        assert_eq!(stats["bytes_read"], 15);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_whole() -> Result<(), std::io::Error> {
+        // With emit_whole, every virtual directory contains a .whole file with the content of the
+        // original file, even if the chunks are compressed
+        let data = (0..25).collect::<Vec<u8>>();
+        let session = mount_and_create_files_with_settings(
+            vec![("a".to_string(), data.clone())],
+            Vec::new(),
+            Some(
+                Config::default()
+                    .blocksize(10)
+                    .unwrap()
+                    .compression(Some(Compression::Gzip)),
+            ),
+            Settings::default().emit_whole(true),
+        )?;
+        let dir = session.mountpoint.path().join("a");
 
+        let mut names = fs::read_dir(&dir)?
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
         assert_eq!(
-            fs::read_to_string(file.path())?,
-            serde_json::to_string(&config)?
+            names,
+            [
+                WHOLE_FILE_NAME,
+                "scfs.0000000000",
+                "scfs.0000000001",
+                "scfs.0000000002"
+            ]
         );
 
+        let whole = dir.join(WHOLE_FILE_NAME);
+        assert_eq!(fs::metadata(&whole)?.len(), 25);
+        assert_eq!(fs::read(&whole)?, data);
+
+        let mut buf = vec![0; 10];
+        File::open(&whole)?.read_exact_at(&mut buf, 8)?;
+        assert_eq!(buf, data[8..18]);
+
         Ok(())
     }
 
     #[test]
-    fn test_empty_file() -> Result<(), std::io::Error> {
-        let num_files = 1;
+    fn test_detect_changes() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
 
-        let files = vec![("empty_file".to_string(), vec![])];
+        fs::write(mirror.path().join("a"), vec![1u8; 25])?;
+        fs::write(mirror.path().join("b"), vec![2u8; 25])?;
 
-        let session = mount_and_create_files(files, None)?;
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default().detect_changes(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let mountpoint = mountpoint.path();
+        assert_eq!(
+            fs::read(mountpoint.join("a/scfs.0000000000"))?,
+            vec![1u8; 10]
+        );
 
-        assert_eq!(entries.len(), num_files + 1);
+        // The chunks that have not been read yet would now end at other offsets
+        fs::write(mirror.path().join("a"), vec![3u8; 30])?;
+        let error = fs::read(mountpoint.join("a/scfs.0000000001")).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(ESTALE));
 
+        // Other files are served as usual
         assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
+            fs::read(mountpoint.join("b/scfs.0000000002"))?,
+            vec![2u8; 5]
         );
 
-        let dirs = entries
-            .iter()
-            .filter(|item| item.path().is_dir())
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        assert_eq!(dirs.len(), num_files);
+    #[test]
+    fn test_statfs() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(10).unwrap();
 
-        let files = list_files_in_directories(&dirs);
+        let files = vec![
+            ("a".to_string(), vec![0u8; 95]),
+            ("b/c".to_string(), vec![0u8; 0]),
+        ];
 
-        assert_eq!(
-            files.iter().filter(|&(_, files)| files.len() == 1).count(),
-            num_files
-        );
+        let session = mount_and_create_files(files, Some(config))?;
 
-        assert_eq!(
-            files
-                .iter()
-                .filter(|&(_, files)| {
-                    let contents = files
-                        .iter()
-                        .flat_map(|entry| fs::read(entry).unwrap())
-                        .collect::<Vec<_>>();
-                    contents.is_empty()
-                })
-                .count(),
-            num_files
-        );
+        let stat = nix::sys::statvfs::statvfs(session.mountpoint.path())?;
+
+        // The root, a with its 10 chunks, b, and c with its single chunk
+        assert_eq!(stat.files(), 1 + 1 + 10 + 1 + 1 + 1);
+        assert_eq!(stat.block_size(), 10);
 
         Ok(())
     }
 
     #[test]
-    fn test_100_small_files() -> Result<(), std::io::Error> {
-        // With an mirror containing 100 small files, there should be 101 entries in the
-        // mountpoint: The config file and 100 folders representing the files. Each folder should
-        // contain a single part with the file contents, which are equal to the file name.
+    fn test_status() -> Result<(), std::io::Error> {
+        let config = Config::default().blocksize(10).unwrap();
 
-        let num_files = 100;
+        let files = vec![
+            ("a".to_string(), vec![0u8; 95]),
+            ("b/c".to_string(), vec![0u8; 0]),
+        ];
 
-        let session = mount_and_create_seq_files(num_files, None)?;
+        let session = mount_and_create_files(files, Some(config))?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let mountpoint = session.mountpoint.path();
+        let status_file = mountpoint.join(STATUS_FILE_NAME);
 
-        assert_eq!(entries.len(), num_files + 1);
+        let read_status = || -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(&status_file).unwrap()).unwrap()
+        };
 
-        assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
-        );
+        let status = read_status();
+        assert_eq!(status["files"], 2);
+        assert_eq!(status["chunks"], 11);
+        assert_eq!(status["open_handles"], 0);
+        assert_eq!(status["bytes_read"], 0);
 
-        let dirs = entries
-            .iter()
-            .filter(|item| item.path().is_dir())
-            .collect::<Vec<_>>();
+        let _open = File::open(mountpoint.join("a/scfs.0000000009"))?;
+        fs::read(mountpoint.join("a/scfs.0000000000"))?;
 
-        let files = list_files_in_directories(&dirs);
+        let status = read_status();
+        assert_eq!(status["open_handles"], 1);
+        assert_eq!(status["bytes_read"], 10);
 
-        check_files(num_files, &dirs, &files);
+        // The status is not listed, so copies of the mountpoint do not contain it
+        assert!(!list_files_in_path(mountpoint.to_path_buf()).contains(&status_file));
 
         Ok(())
     }
 
     #[test]
-    fn test_100_small_files_in_subfolders() -> Result<(), std::io::Error> {
-        // Same as above, but with multiple nested folders.
-
-        let num_files = 100;
+    fn test_readahead() -> Result<(), std::io::Error> {
+        let data = (0..50).collect::<Vec<u8>>();
+        let files = vec![("a".to_string(), data.clone())];
 
-        let mut rng = rand::thread_rng();
-        let files = (0..num_files)
-            .map(|i| {
-                let file_name = format!(
-                    "{}/{}/{}/{}/{}/{}",
-                    i,
-                    rng.gen::<u32>(),
-                    rng.gen::<u32>(),
-                    rng.gen::<u32>(),
-                    rng.gen::<u32>(),
-                    rng.gen::<u32>()
-                );
-                let content = format!("{}", i,);
-                (file_name, content.into_bytes())
-            })
-            .collect::<Vec<_>>();
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default().readahead(2),
+        )?;
+        let mountpoint = session.mountpoint.path();
 
-        let session = mount_and_create_files(files, None)?;
+        let read_hits = || -> serde_json::Value {
+            let status = fs::read_to_string(mountpoint.join(STATUS_FILE_NAME)).unwrap();
+            serde_json::from_str::<serde_json::Value>(&status).unwrap()["readahead_hits"].clone()
+        };
+        assert_eq!(read_hits(), 0);
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        for part in 0..5 {
+            let chunk = fs::read(mountpoint.join(format!("a/scfs.{:010}", part)))?;
+            assert_eq!(chunk, data[part * 10..part * 10 + 10]);
 
-        assert_eq!(entries.len(), num_files + 1);
+            // Give the background reads time to finish
+            thread::sleep(Duration::from_millis(200));
+        }
 
-        assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
-        );
+        // All chunks but the first have been read ahead
+        assert_eq!(read_hits(), 4);
 
-        let dirs = entries
-            .iter()
-            .filter(|item| item.path().is_dir())
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        let files = dirs
-            .iter()
-            .map(|item| {
-                let file_name = item.file_name().into_string().unwrap();
-                let files = fs::read_dir(item.path())
-                    .unwrap()
-                    .map(|entry| {
-                        let mut path = entry.unwrap().path();
-                        while path.is_dir() {
-                            path = path.read_dir().unwrap().next().unwrap().unwrap().path();
-                        }
-                        path.to_path_buf()
-                    })
-                    .collect::<Vec<_>>();
-                (file_name, files)
-            })
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_exclude() -> Result<(), std::io::Error> {
+        let files = vec![
+            ("a".to_string(), vec![0u8; 5]),
+            ("a.tmp".to_string(), vec![0u8; 5]),
+            ("b/c".to_string(), vec![0u8; 5]),
+            ("b/c.tmp".to_string(), vec![0u8; 5]),
+            ("b/d/e.tmp".to_string(), vec![0u8; 5]),
+            ("cache/f".to_string(), vec![0u8; 5]),
+            ("cache/g/h".to_string(), vec![0u8; 5]),
+            ("other/cache/i".to_string(), vec![0u8; 5]),
+        ];
+
+        let settings =
+            Settings::default().exclude(vec!["**/*.tmp".to_string(), "cache/**".to_string()]);
+        let session = mount_and_create_files_with_settings(files, Vec::new(), None, settings)?;
+
+        let mountpoint = session.mountpoint.path();
+
+        for excluded in ["a.tmp", "b/c.tmp", "b/d/e.tmp", "cache/f", "cache/g"] {
+            assert!(!mountpoint.join(excluded).exists(), "{}", excluded);
+        }
+        assert!(list_files_in_path(mountpoint.join("cache")).is_empty());
+        assert!(list_files_in_path(mountpoint.join("b/d")).is_empty());
 
-        check_files(num_files, &dirs, &files);
+        for included in ["a", "b/c", "other/cache/i"] {
+            assert!(mountpoint.join(included).join("scfs.0000000000").exists());
+        }
 
         Ok(())
     }
 
     #[test]
-    fn test_big_file_bytewise() -> Result<(), std::io::Error> {
-        // A big file, with a block size of 1 byte, should be splitted in as many parts as bytes.
-        // By concatenating all parts together, the original content should be created.
+    fn test_checksums() -> Result<(), std::io::Error> {
+        // With checksums, every chunk has a sidecar with its SHA-256 digest, which does not count
+        // as a chunk itself.
 
-        let config = Config::default().blocksize(1);
+        let config = Config::default().blocksize(10).unwrap();
 
-        let mut data = [0u8; 100];
+        let mut data = [0u8; 25];
         rand::thread_rng().fill_bytes(&mut data);
-        let data = data.to_vec();
 
-        let files = vec![("huge_file".to_string(), data.clone())];
+        let files = vec![("file".to_string(), data.to_vec())];
+
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(config),
+            Settings::default().checksums(true).emit_count(true),
+        )?;
+
+        let dir = session.mountpoint.path().join("file");
+
+        let mut entries = list_files_in_path(dir.clone());
+        entries.sort();
+        assert_eq!(entries.len(), 1 + 3 * 2);
+
+        assert_eq!(fs::read_to_string(dir.join(COUNT_FILE_NAME))?, "3\n");
+
+        for part in 0..3 {
+            let chunk_name = format!("scfs.{:010}", part);
+            let chunk = fs::read(dir.join(&chunk_name))?;
+            assert_eq!(chunk, data[part * 10..(part * 10 + 10).min(data.len())]);
 
-        let session = mount_and_create_files(files, Some(config))?;
+            let sidecar = dir.join(format!("{}{}", chunk_name, CHECKSUM_SUFFIX));
+            let checksum = fs::read_to_string(&sidecar)?;
+            assert_eq!(fs::metadata(&sidecar)?.len(), checksum.len() as u64);
+            assert_eq!(
+                checksum,
+                format!("{:x}  {}\n", Sha256::digest(&chunk), chunk_name)
+            );
+        }
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        assert_eq!(entries.len(), 1 + 1);
+    #[test]
+    fn test_pack_small() -> Result<(), std::io::Error> {
+        // Files smaller than the pack size are not presented as chunked directories, but are
+        // packed into pack objects, which are described by the pack index.
 
-        let dirs = entries
-            .iter()
-            .filter(|item| item.path().is_dir())
-            .collect::<Vec<_>>();
+        let pack_size = 32;
 
-        assert_eq!(dirs.len(), 1);
+        let files = vec![
+            ("a".to_string(), vec![1u8; 10]),
+            ("b".to_string(), vec![2u8; 20]),
+            ("c".to_string(), vec![3u8; 5]),
+            ("d".to_string(), vec![4u8; 31]),
+            ("big".to_string(), vec![5u8; 200]),
+        ];
 
-        let dir = dirs.get(0).unwrap();
+        let session = mount_and_create_files_with_settings(
+            files.clone(),
+            Vec::new(),
+            None,
+            Settings::default().pack_small(Some(pack_size)),
+        )?;
 
-        let files = list_files_in_path(dir.path());
+        let mountpoint = session.mountpoint.path();
 
-        assert_eq!(files.len(), data.len());
+        assert!(mountpoint.join("big").is_dir());
+        for (file_name, _) in files.iter().filter(|(_, content)| content.len() < 32) {
+            assert!(!mountpoint.join(file_name).exists());
+        }
 
-        assert_eq!(
-            files
+        let index: Vec<PackEntry> =
+            serde_json::from_slice(&fs::read(mountpoint.join(PACK_INDEX_FILE_NAME))?)?;
+        assert_eq!(index.len(), 4);
+
+        for entry in index {
+            let pack = fs::read(mountpoint.join(pack_file_name(entry.pack)))?;
+            assert!(pack.len() as u64 <= pack_size);
+
+            let (_, content) = files
                 .iter()
-                .flat_map(|file| fs::read(file).unwrap())
-                .collect::<Vec<_>>(),
-            data
-        );
+                .find(|(file_name, _)| OsString::from(file_name) == entry.file_name)
+                .unwrap();
+            assert_eq!(
+                &pack[entry.offset as usize..(entry.offset + entry.size) as usize],
+                content.as_slice()
+            );
+        }
 
         Ok(())
     }
 
     #[test]
-    fn test_big_file_blockwise() -> Result<(), std::io::Error> {
-        // A big file, with a custom block size, should be splitted in as many parts as needed so
-        // that the parts are no larger than the block size. By concatenating all parts together,
-        // the original content should be created.
-
-        let blocksize = 37;
+    fn test_hard_links() -> Result<(), std::io::Error> {
+        // All paths of a hard linked file list the same chunks, which are linked as often
 
-        let config = Config::default().blocksize(blocksize);
-
-        let mut data = [0u8; 100];
-        rand::thread_rng().fill_bytes(&mut data);
-        let data = data.to_vec();
-
-        let files = vec![("huge_file".to_string(), data.clone())];
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
 
-        let session = mount_and_create_files(files, Some(config))?;
+        let data = (0..25).collect::<Vec<u8>>();
+        fs::create_dir(mirror.path().join("dir"))?;
+        fs::write(mirror.path().join("file"), &data)?;
+        fs::hard_link(mirror.path().join("file"), mirror.path().join("dir/link"))?;
+        fs::write(mirror.path().join("other"), &data)?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(10).unwrap(),
+            Settings::default().emit_count(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let file = mountpoint.path().join("file");
+        let link = mountpoint.path().join("dir/link");
+        for part in 0..3 {
+            let chunk_name = format!("scfs.{:010}", part);
+            let chunk = fs::metadata(file.join(&chunk_name))?;
+            let linked_chunk = fs::metadata(link.join(&chunk_name))?;
+            assert_eq!(chunk.ino(), linked_chunk.ino());
+            assert_eq!(chunk.nlink(), 2);
+            assert_eq!(
+                fs::read(file.join(&chunk_name))?,
+                fs::read(link.join(&chunk_name))?
+            );
+        }
+        assert_eq!(fs::read_to_string(link.join(COUNT_FILE_NAME))?, "3\n");
+        assert_eq!(fs::read_dir(&link)?.count(), 4);
 
-        assert_eq!(entries.len(), 1 + 1);
+        // Each path still is a directory of its own
+        assert_ne!(fs::metadata(&file)?.ino(), fs::metadata(&link)?.ino());
+        assert_eq!(fs::metadata(&link)?.nlink(), 2);
 
-        let dirs = entries
-            .iter()
-            .filter(|item| item.path().is_dir())
-            .collect::<Vec<_>>();
+        let other = mountpoint.path().join("other");
+        assert_eq!(fs::metadata(&other)?.nlink(), 1);
+        assert_eq!(fs::metadata(other.join("scfs.0000000000"))?.nlink(), 1);
 
-        assert_eq!(dirs.len(), 1);
+        Ok(())
+    }
 
-        let dir = dirs.get(0).unwrap();
+    #[test]
+    fn test_write_to_dir() -> Result<(), std::io::Error> {
+        // The chunks written to a directory can be restored by CatFS just like the presented ones
 
-        let files = list_files_in_path(dir.path());
+        let mirror = tempdir()?;
+        let out_dir = tempdir()?;
+        let mountpoint = tempdir()?;
 
-        assert_eq!(
-            files.len(),
-            (data.len() as f32 / blocksize as f32).ceil() as usize
-        );
+        let mut rng = rand::thread_rng();
+        let mut data = vec![0u8; 1234];
+        rng.fill_bytes(&mut data);
+        fs::create_dir_all(mirror.path().join("dir/empty_dir"))?;
+        fs::write(mirror.path().join("dir/file"), &data)?;
+        fs::write(mirror.path().join("empty"), b"")?;
+        fs::hard_link(mirror.path().join("dir/file"), mirror.path().join("link"))?;
+        std::os::unix::fs::symlink("dir/file", mirror.path().join("symlink"))?;
 
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            Config::default().blocksize(100).unwrap(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        fs.write_to_dir(out_dir.path())?;
+        drop(fs);
+
+        let chunk = out_dir.path().join("dir/file/scfs.0000000012");
+        assert_eq!(fs::read(&chunk)?, data[1200..]);
+        assert_eq!(fs::metadata(&chunk)?.nlink(), 2);
         assert_eq!(
-            files
-                .iter()
-                .filter(|item| File::open(item).unwrap().bytes().count() == blocksize as usize)
-                .count(),
-            files.len() - 1
+            fs::read_dir(out_dir.path().join("empty"))?.count(),
+            1,
+            "an empty file has one empty chunk"
         );
 
+        // All chunks match the tree digest of the written config
+        let fs = CatFS::new(
+            out_dir.path().as_os_str(),
+            Settings::default().verify(true).strict(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert_eq!(fs::read(mountpoint.path().join("dir/file"))?, data);
+        assert_eq!(fs::read(mountpoint.path().join("link"))?, data);
+        assert_eq!(fs::read(mountpoint.path().join("empty"))?, b"");
+        assert!(mountpoint.path().join("dir/empty_dir").is_dir());
         assert_eq!(
-            File::open(files.last().unwrap()).unwrap().bytes().count(),
-            data.len() % blocksize as usize
+            fs::read_link(mountpoint.path().join("symlink"))?,
+            Path::new("dir/file")
         );
-
         assert_eq!(
-            files
-                .iter()
-                .flat_map(|file| fs::read(file).unwrap())
-                .collect::<Vec<_>>(),
-            data
+            fs::metadata(mountpoint.path().join("link"))?.ino(),
+            fs::metadata(mountpoint.path().join("dir/file"))?.ino()
         );
 
         Ok(())
     }
 
     #[test]
-    fn test_symlink_relative_file() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such, no splitting or any other modification.
-
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_rel".into(), CONFIG_FILE_NAME.into());
+    fn test_write_to_dir_like_mount() -> Result<(), std::io::Error> {
+        // The written directory must contain the same entries as the mount, including the
+        // synthetic files, packs and files below the split size
+
+        // Every entry with the content of the files
+        fn read_tree(
+            dir: &Path,
+            relative: &Path,
+            tree: &mut BTreeMap<PathBuf, Option<Vec<u8>>>,
+        ) -> std::io::Result<()> {
+            for entry in fs::read_dir(dir.join(relative))? {
+                let relative = relative.join(entry?.file_name());
+                let path = dir.join(&relative);
+                if path.is_dir() {
+                    tree.insert(relative.clone(), None);
+                    read_tree(dir, &relative, tree)?;
+                } else {
+                    tree.insert(relative, Some(fs::read(path)?));
+                }
+            }
+            Ok(())
+        }
 
-        let session = mount_and_create_files_with_symlinks(
-            Vec::new(),
-            symlink_map.clone().into_iter().collect(),
-            None,
+        let mirror = tempdir()?;
+        let out_dir = tempdir()?;
+        let mountpoint_split = tempdir()?;
+        let mountpoint_cat = tempdir()?;
+
+        let files = vec![
+            ("big".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("dir/file".to_string(), (0..20).rev().collect::<Vec<u8>>()),
+            ("dir/small".to_string(), vec![1; 5]),
+            ("dir/tiny".to_string(), vec![2; 2]),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+        fs::write(
+            mirror.path().join(BLOCKSIZES_MANIFEST_NAME),
+            r#"{"big": 30}"#,
         )?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let config = Config::default()
+            .blocksize(7)
+            .unwrap()
+            .min_split_size(Some(10));
+        let settings = || Settings::default().emit_meta(true).pack_small(Some(4));
 
-        assert_eq!(entries.len(), symlink_map.len() + 1);
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.clone(),
+            settings(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        fs.write_to_dir(out_dir.path())?;
+        drop(fs);
 
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config,
+            settings(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_split = mount(fs, &mountpoint_split, Vec::new())?;
+
+        let mut written = BTreeMap::new();
+        read_tree(out_dir.path(), Path::new(""), &mut written)?;
+        let mut mounted = BTreeMap::new();
+        read_tree(mountpoint_split.path(), Path::new(""), &mut mounted)?;
         assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
+            written.keys().collect::<Vec<_>>(),
+            mounted.keys().collect::<Vec<_>>()
         );
+        for (path, content) in &written {
+            // The metadata contains the access times, which writing the chunks has changed
+            if path.file_name() != Some(OsStr::new(META_FILE_NAME)) {
+                assert_eq!(content, &mounted[path], "{:?}", path);
+            }
+        }
+        assert!(written.contains_key(&Path::new("big").join(BLOCKSIZE_FILE_NAME)));
+        assert!(written.contains_key(&Path::new("big").join(META_FILE_NAME)));
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        let fs = CatFS::new(
+            out_dir.path().as_os_str(),
+            Settings::default().verify(true).strict(true),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let _session_cat = mount(fs, &mountpoint_cat, Vec::new())?;
 
+        for (file_name, content) in files {
+            assert_eq!(fs::read(mountpoint_cat.path().join(file_name))?, content);
+        }
         assert_eq!(
-            read(symlinks_found.first().unwrap().path())?,
-            serde_json::to_string(&Config::default())
-                .unwrap()
-                .into_bytes()
+            fs::read(mountpoint_cat.path().join(BLOCKSIZES_MANIFEST_NAME))?,
+            br#"{"big": 30}"#
         );
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_relative_vdir() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such, no splitting or any other modification. A
-        // relative symlink pointing to a file inside the chunked directory should translate into
-        // the same chunked virtual directory as the real file's counterpart.
-
-        let files = vec![(String::from("a/b/c"), String::from("42").into_bytes())];
-
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_rel".into(), "a/b/c".into());
-
-        let session = mount_and_create_files_with_symlinks(
-            files.clone(),
-            symlink_map.clone().into_iter().collect(),
-            None,
+    #[cfg(target_os = "linux")]
+    fn test_xattrs() -> Result<(), std::io::Error> {
+        use crate::shared::tests::{get_xattr, list_xattrs};
+
+        let blocksize = 10;
+        let data = (0..25).collect::<Vec<u8>>();
+        let session = mount_and_create_files_with_settings(
+            vec![(String::from("file"), data)],
+            Vec::new(),
+            Some(Config::default().blocksize(blocksize).unwrap()),
+            Settings::default().checksums(true),
         )?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+        let file = session.mountpoint.path().join("file");
+        for part in 0..3u64 {
+            let chunk = file.join(format!("scfs.{:010}", part));
+            assert_eq!(
+                list_xattrs(&chunk)?,
+                [XATTR_SOURCE, XATTR_PART, XATTR_OFFSET, XATTR_LENGTH]
+            );
 
-        assert_eq!(entries.len(), files.len() + symlink_map.len() + 1);
+            let xattr = |name| -> Result<String, std::io::Error> {
+                Ok(String::from_utf8(get_xattr(&chunk, name)?).unwrap())
+            };
+            assert!(xattr(XATTR_SOURCE)?.ends_with("/file"));
+            assert_eq!(xattr(XATTR_PART)?, part.to_string());
+            assert_eq!(xattr(XATTR_OFFSET)?, (part * blocksize).to_string());
+            assert_eq!(
+                xattr(XATTR_LENGTH)?,
+                blocksize.min(25 - part * blocksize).to_string()
+            );
 
+            let error = get_xattr(&chunk, "user.scfs.unknown").unwrap_err();
+            assert_eq!(error.raw_os_error(), Some(libc::ENODATA));
+        }
+
+        // Only the chunks themselves know their range
+        assert!(list_xattrs(&file)?.is_empty());
+        assert!(list_xattrs(&file.join("scfs.0000000000.sha256"))?.is_empty());
+        assert!(list_xattrs(&session.mountpoint.path().join(CONFIG_FILE_NAME))?.is_empty());
         assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
+            get_xattr(&file, XATTR_SOURCE).unwrap_err().raw_os_error(),
+            Some(libc::ENODATA)
         );
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        let parts = symlinks_found
-            .first()
-            .unwrap()
-            .path()
-            .read_dir()
-            .unwrap()
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_ino_offset() -> Result<(), std::io::Error> {
+        use crate::shared::tests::collect_inos;
 
-        assert_eq!(parts.len(), 1);
+        let offset = 1 << 40;
+        let files = vec![
+            ("a".to_string(), (0..25).collect()),
+            ("dir/b".to_string(), vec![1; 5]),
+        ];
 
-        assert_eq!(
-            read(parts.first().unwrap().as_ref().unwrap().path())?,
-            files.first().unwrap().1
-        );
+        let session = mount_and_create_files_with_settings(
+            files,
+            Vec::new(),
+            Some(Config::default().blocksize(10).unwrap()),
+            Settings::default().ino_offset(offset),
+        )?;
+        let mountpoint = session.mountpoint.path();
+
+        // Only the root and the config file keep their fixed inodes
+        assert_eq!(fs::metadata(mountpoint)?.ino(), INO_ROOT);
+        let inos = collect_inos(mountpoint)?;
+        assert_eq!(inos.len(), 8);
+        for (path, ino) in inos {
+            if path == mountpoint.join(CONFIG_FILE_NAME) {
+                assert_eq!(ino, INO_CONFIG);
+            } else {
+                assert!(ino > offset, "{:?} has inode {}", path, ino);
+            }
+        }
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_absolute_dir() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such, no splitting or any other modification.
+    fn test_preallocate_inodes() -> Result<(), std::io::Error> {
+        use crate::shared::tests::collect_inos;
+
+        let files = vec![
+            ("a".to_string(), (0..25).collect()),
+            ("dir/b".to_string(), vec![1; 5]),
+            ("dir/c".to_string(), vec![2; 15]),
+        ];
+
+        // Entries created in another order would be indexed in another order as well
+        let mut inos = Vec::new();
+        for files in [files.clone(), files.into_iter().rev().collect()] {
+            let session = mount_and_create_files_with_settings(
+                files,
+                Vec::new(),
+                Some(Config::default().blocksize(10).unwrap()),
+                Settings::default().preallocate_inodes(true),
+            )?;
+            let mountpoint = session.mountpoint.path();
+
+            let mut mount_inos = collect_inos(mountpoint)?
+                .into_iter()
+                .map(|(path, ino)| (path.strip_prefix(mountpoint).unwrap().to_path_buf(), ino))
+                .collect::<Vec<_>>();
+            mount_inos.sort();
+            for (path, ino) in &mount_inos {
+                if path != Path::new(CONFIG_FILE_NAME) {
+                    assert!(
+                        (INO_FIRST_FREE..INO_PREALLOCATED_END).contains(ino),
+                        "{:?} has inode {}",
+                        path,
+                        ino
+                    );
+                }
+            }
+            inos.push(mount_inos);
+        }
 
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_abs".into(), "/".into());
+        assert_eq!(inos[0].len(), 11);
+        assert_eq!(inos[0], inos[1]);
 
-        let session = mount_and_create_files_with_symlinks(
-            Vec::new(),
-            symlink_map.clone().into_iter().collect(),
-            None,
-        )?;
+        Ok(())
+    }
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_prefetched_populate() -> Result<(), std::io::Error> {
+        use crate::{LocalMirror, STMT_QUERY_ALL};
 
-        assert_eq!(entries.len(), symlink_map.len() + 1);
+        // Wide and deep at the same time, so that the walk runs on all threads
+        let mirror = tempdir()?;
+        for i in 0..16 {
+            let mut dir = mirror.path().join(format!("dir_{}", i));
+            for depth in 0..6 {
+                fs::create_dir_all(&dir)?;
+                for j in 0..4 {
+                    fs::write(dir.join(format!("file_{}", j)), vec![0; i * depth + j])?;
+                }
+                dir = dir.join(format!("sub_{}", depth));
+            }
+        }
 
-        assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
-        );
+        let config = Config::default().blocksize(8).unwrap();
+        let settings = Settings::default();
+        let exclude = Exclude::new(mirror.path(), &[]).unwrap();
+        let index = |source: &dyn MirrorSource| {
+            let file_db = Connection::open_in_memory().unwrap();
+            file_db.execute(STMT_CREATE, []).unwrap();
+            file_db.execute(STMT_CREATE_LINKS, []).unwrap();
+            SplitFS::populate(
+                &file_db,
+                mirror.path(),
+                &config,
+                &HashMap::new(),
+                &settings,
+                source,
+                &exclude,
+                &mut HashSet::new(),
+                &mut HashMap::new(),
+                &mut Packs::default(),
+                &mut ProgressReporter::new(None),
+                INO_OUTSIDE,
+                settings.first_free_ino(),
+            );
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+            let mut stmt = file_db.prepare(STMT_QUERY_ALL).unwrap();
+            stmt.query_map([], |row| Ok(FileInfo::from(row)))
+                .unwrap()
+                .map(|file_info| serde_json::to_string(&file_info.unwrap()).unwrap())
+                .collect::<Vec<_>>()
+        };
 
-        assert!(symlinks_found.first().unwrap().path().is_dir());
+        let local = LocalMirror::default();
+        let sequential = index(&local);
+        let prefetched = index(&PrefetchedMirror::walk(
+            &local,
+            mirror.path(),
+            false,
+            Some(8),
+            &|_| false,
+        ));
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        assert!(sequential.len() > 16 * 6 * 4);
+        assert_eq!(prefetched, sequential);
+
+        Ok(())
     }
 
     #[test]
-    fn test_symlink_broken() -> Result<(), std::io::Error> {
-        // A symlink should just be presented as such, no splitting or any other modification. If
-        // the target does not exist, just show a broken symlink, do not panic out.
-
-        let mut symlink_map = HashMap::new();
-        symlink_map.insert("link_rel".into(), "a/b/c".into());
-        symlink_map.insert("link_abs".into(), "/home/nobody/nothing".into());
-
-        let session = mount_and_create_files_with_symlinks(
+    fn test_subdir() -> Result<(), std::io::Error> {
+        let files = vec![
+            ("outside".to_string(), b"outside".to_vec()),
+            ("backup/other/file".to_string(), b"other".to_vec()),
+            ("backup/home/file".to_string(), b"home".to_vec()),
+            ("backup/home/dir/file".to_string(), b"nested".to_vec()),
+        ];
+
+        let session = mount_and_create_files_with_settings(
+            files,
             Vec::new(),
-            symlink_map.clone().into_iter().collect(),
             None,
+            Settings::default().subdir(Some(PathBuf::from("backup/home"))),
         )?;
 
-        let entries = fs::read_dir(&session.mountpoint)?
-            .map(|entry| entry.unwrap())
-            .collect::<Vec<_>>();
-
-        assert_eq!(entries.len(), symlink_map.len() + 1);
-
+        let mut names = fs::read_dir(session.mountpoint.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.sort();
+        assert_eq!(names, [CONFIG_FILE_NAME, "dir", "file"]);
         assert_eq!(
-            entries
-                .iter()
-                .filter(|item| item.file_name() == CONFIG_FILE_NAME)
-                .count(),
-            1
+            fs::read(session.mountpoint.path().join("dir/file/scfs.0000000000"))?,
+            b"nested"
         );
 
-        let symlinks_found = entries
-            .iter()
-            .filter(|item| item.file_type().unwrap().is_symlink())
-            .collect::<Vec<_>>();
+        let mirror = tempdir()?;
+        fs::write(mirror.path().join("file"), b"content")?;
+        for subdir in ["../backup", "/backup", "file", "missing"] {
+            let result = SplitFS::new(
+                mirror.path().as_os_str(),
+                Config::default(),
+                Settings::default().subdir(Some(PathBuf::from(subdir))),
+                Box::new(|| ()),
+            );
+            assert!(result.is_err(), "{}", subdir);
+        }
 
-        check_symlinks(&mut symlink_map, &symlinks_found)
+        Ok(())
     }
 }
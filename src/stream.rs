@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::Config;
+
+// Framing of a chunk stream, all lengths are unsigned 64 bit big-endian integers:
+//
+//  1. The magic bytes `SCFS`.
+//  2. The length of the config, followed by the config as JSON, same as in `.scfs_config`.
+//  3. For each chunk, the length of the chunk, followed by the chunk itself. Only the last chunk
+//     may be shorter than the blocksize, no chunk is empty.
+//  4. A length of zero, marking the end of the stream.
+const STREAM_MAGIC: &[u8; 4] = b"SCFS";
+
+fn write_frame<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u64).to_be_bytes())?;
+    out.write_all(data)
+}
+
+fn read_frame_len<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut len = [0; 8];
+    input.read_exact(&mut len)?;
+    Ok(u64::from_be_bytes(len))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Splits the given file into chunks of the configured blocksize and writes them as a chunk
+/// stream to `out`.
+pub(crate) fn split_to_writer<P: AsRef<Path>, W: Write>(
+    file: P,
+    config: Config,
+    mut out: W,
+) -> io::Result<()> {
+    let mut file = File::open(file)?;
+
+    out.write_all(STREAM_MAGIC)?;
+    write_frame(&mut out, &serde_json::to_vec(&config).unwrap())?;
+
+    let mut chunk = Vec::with_capacity(config.blocksize as usize);
+    loop {
+        chunk.clear();
+        (&mut file).take(config.blocksize).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        write_frame(&mut out, &chunk)?;
+    }
+
+    out.write_all(&0_u64.to_be_bytes())?;
+    out.flush()
+}
+
+/// Reads a chunk stream from `input` and writes the concatenated chunks to `out`.
+pub(crate) fn cat_from_reader<R: Read, W: Write>(mut input: R, mut out: W) -> io::Result<()> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != STREAM_MAGIC {
+        return Err(invalid_data("Not an SCFS chunk stream"));
+    }
+
+    let len = read_frame_len(&mut input)?;
+    let mut config = Vec::new();
+    (&mut input).take(len).read_to_end(&mut config)?;
+    let config: Config = serde_json::from_slice(&config)
+        .map_err(|_| invalid_data("SCFS chunk stream contains invalid config"))?;
+    if !config.is_format_supported() {
+        return Err(invalid_data(
+            "SCFS chunk stream has unsupported format version",
+        ));
+    }
+
+    loop {
+        let len = read_frame_len(&mut input)?;
+        if len == 0 {
+            break;
+        }
+        if len > config.blocksize {
+            return Err(invalid_data("SCFS chunk stream contains oversized chunk"));
+        }
+
+        let copied = io::copy(&mut (&mut input).take(len), &mut out)?;
+        if copied != len {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_stream_round_trip() -> Result<(), std::io::Error> {
+        let dir = tempdir()?;
+
+        for data in [(0..100).collect::<Vec<u8>>(), (0..98).collect(), Vec::new()] {
+            let file = dir.path().join("file");
+            fs::write(&file, &data)?;
+
+            let config = Config::default().blocksize(7).unwrap();
+            let config_len = serde_json::to_vec(&config).unwrap().len();
+
+            let mut stream = Vec::new();
+            split_to_writer(&file, config, &mut stream)?;
+
+            let chunks = data.len().div_ceil(7);
+            assert_eq!(
+                stream.len(),
+                4 + 8 + config_len + chunks * 8 + data.len() + 8
+            );
+
+            let mut restored = Vec::new();
+            cat_from_reader(stream.as_slice(), &mut restored)?;
+            assert_eq!(restored, data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_invalid_input() -> Result<(), std::io::Error> {
+        let dir = tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, (0..100).collect::<Vec<u8>>())?;
+
+        let mut stream = Vec::new();
+        split_to_writer(&file, Config::default().blocksize(7).unwrap(), &mut stream)?;
+
+        let mut wrong_magic = stream.clone();
+        wrong_magic[0] = b'X';
+        let result = cat_from_reader(wrong_magic.as_slice(), io::sink());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let truncated = &stream[..stream.len() - 12];
+        let result = cat_from_reader(truncated, io::sink());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        Ok(())
+    }
+}
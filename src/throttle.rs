@@ -0,0 +1,73 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Caps the throughput of all reads of a file system with a token bucket. The bucket holds the
+/// bytes of up to one second, reads beyond that are delayed until the bucket has been refilled.
+pub(crate) struct ReadLimiter {
+    bytes_per_sec: Option<u64>,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    // Negative once reads have been admitted in advance, which the following reads wait for
+    tokens: f64,
+
+    refilled: Instant,
+}
+
+impl ReadLimiter {
+    /// Creates a new limiter. A limit of `None` or zero lets all reads pass immediately.
+    pub(crate) fn new(bytes_per_sec: Option<u64>) -> Self {
+        let bytes_per_sec = bytes_per_sec.filter(|bytes_per_sec| *bytes_per_sec > 0);
+        ReadLimiter {
+            bytes_per_sec,
+            state: Mutex::new(BucketState {
+                tokens: bytes_per_sec.unwrap_or(0) as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes the given number of bytes from the bucket and blocks the calling thread until they
+    /// are covered by the limit.
+    pub(crate) fn throttle(&self, bytes: u64) {
+        if let Some(wait) = self.reserve(bytes) {
+            thread::sleep(wait);
+        }
+    }
+
+    // Returns how long the caller has to wait before the reserved bytes may be served.
+    fn reserve(&self, bytes: u64) -> Option<Duration> {
+        let rate = self.bytes_per_sec? as f64;
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(state.refilled).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refill).min(rate) - bytes as f64;
+        state.refilled = now;
+
+        (state.tokens < 0.0).then(|| Duration::from_secs_f64(-state.tokens / rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_limiter() {
+        assert_eq!(ReadLimiter::new(None).reserve(u64::MAX), None);
+        assert_eq!(ReadLimiter::new(Some(0)).reserve(u64::MAX), None);
+
+        // The first second worth of bytes passes, everything beyond has to wait for the refill
+        let limiter = ReadLimiter::new(Some(1000));
+        assert_eq!(limiter.reserve(600), None);
+        let wait = limiter.reserve(900).unwrap();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+
+        // Later reads also wait for the ones admitted before them
+        let wait = limiter.reserve(100).unwrap();
+        assert!(wait > Duration::from_millis(500) && wait <= Duration::from_millis(600));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::{error, warn};
+
+use crate::{Config, BLOCKSIZE_FILE_NAME};
+
+/// Periodically compares chunks read through a SplitFS mountpoint with the corresponding bytes
+/// read directly from the mirror.
+pub(crate) struct SelfVerifier {
+    mirror: PathBuf,
+    mountpoint: PathBuf,
+    config: Config,
+    files: Vec<(PathBuf, u64)>,
+}
+
+impl SelfVerifier {
+    /// Creates a verifier sampling the given files, which are presented as chunks, see
+    /// SplitFS::chunked_files. They are relative to `mirror`, which is the mounted subdirectory
+    /// if only a subdirectory of the mirror is mounted.
+    pub(crate) fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        mirror: P,
+        mountpoint: Q,
+        config: Config,
+        files: Vec<(PathBuf, u64)>,
+    ) -> Self {
+        SelfVerifier {
+            mirror: mirror.as_ref().to_path_buf(),
+            mountpoint: mountpoint.as_ref().to_path_buf(),
+            config,
+            files,
+        }
+    }
+
+    // The directory presenting the chunks of a file carries the chunked suffix, if any.
+    fn chunk_dir(&self, file: &Path) -> PathBuf {
+        let mut dir = self.mountpoint.join(file).into_os_string();
+        if let Some(suffix) = &self.config.chunked_suffix {
+            dir.push(suffix);
+        }
+        PathBuf::from(dir)
+    }
+
+    // Files from the blocksize manifest announce their own blocksize in the mountpoint.
+    fn blocksize(&self, file: &Path) -> u64 {
+        fs::read_to_string(self.chunk_dir(file).join(BLOCKSIZE_FILE_NAME))
+            .ok()
+            .and_then(|blocksize| blocksize.trim().parse().ok())
+            .unwrap_or(self.config.blocksize)
+    }
+
+    /// Verifies a single chunk, returning `Ok(false)` if the two reads differ.
+    pub(crate) fn verify_chunk(&self, file: &Path, part: u64) -> std::io::Result<bool> {
+        let config = self.config.with_blocksize(Some(self.blocksize(file)));
+        let size = fs::metadata(self.mirror.join(file))?.len();
+        let chunk = self
+            .chunk_dir(file)
+            .join(config.chunk_file_name(part, size));
+        let through_mount = fs::read(chunk)?;
+
+        let (start, end) = config.chunk_span(part, u64::MAX);
+        let mut direct = Vec::new();
+        let mut source = File::open(self.mirror.join(file))?;
+        source.seek(SeekFrom::Start(start))?;
+        source.take(end - start).read_to_end(&mut direct)?;
+
+        Ok(through_mount == direct)
+    }
+
+    /// Verifies a randomly chosen chunk of a randomly chosen file. Returns `None` if the mirror
+    /// does not contain any regular files.
+    pub(crate) fn verify_random_chunk(&self) -> Option<(PathBuf, u64, std::io::Result<bool>)> {
+        if self.files.is_empty() {
+            return None;
+        }
+
+        let (file, size) = &self.files[(random_u64() % self.files.len() as u64) as usize];
+        let parts = 1.max(f64::ceil(*size as f64 / self.blocksize(file) as f64) as u64);
+        let part = random_u64() % parts;
+
+        Some((file.clone(), part, self.verify_chunk(file, part)))
+    }
+
+    pub(crate) fn spawn(self, interval: Duration) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            match self.verify_random_chunk() {
+                Some((file, part, Ok(false))) => {
+                    error!("Self-verify: mismatch in part {} of {:?}", part, file)
+                }
+                Some((file, part, Err(e))) => {
+                    warn!(
+                        "Self-verify: could not check part {} of {:?}: {}",
+                        part, file, e
+                    )
+                }
+                _ => {}
+            }
+        })
+    }
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::shared::tests::create_files_and_symlinks;
+    use crate::{mount, Config, Settings, SplitFS};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_chunks() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![
+            ("a".to_string(), (0..100).collect::<Vec<u8>>()),
+            ("b/c".to_string(), (0..10).collect::<Vec<u8>>()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default().blocksize(7).unwrap();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.clone(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let chunked_files = fs.chunked_files();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        let verifier = SelfVerifier::new(
+            mirror.path(),
+            mountpoint.path(),
+            config,
+            chunked_files.clone(),
+        );
+
+        for part in 0..15 {
+            assert!(verifier.verify_chunk(Path::new("a"), part)?);
+        }
+        assert!(verifier.verify_chunk(Path::new("b/c"), 1)?);
+
+        for _ in 0..10 {
+            let (_, _, result) = verifier.verify_random_chunk().unwrap();
+            assert!(result?);
+        }
+
+        // A verifier with the wrong idea about the blocksize must detect the mismatch
+        let verifier = SelfVerifier::new(
+            mirror.path(),
+            mountpoint.path(),
+            Config::default().blocksize(5).unwrap(),
+            chunked_files,
+        );
+        assert!(!verifier.verify_chunk(Path::new("a"), 1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_empty_mirror() {
+        let mirror = tempdir().unwrap();
+        let verifier =
+            SelfVerifier::new(mirror.path(), mirror.path(), Config::default(), Vec::new());
+        assert!(verifier.verify_random_chunk().is_none());
+    }
+
+    #[test]
+    fn test_verify_subdir() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![
+            ("outside".to_string(), (0..20).collect::<Vec<u8>>()),
+            ("backup/file".to_string(), (0..30).collect::<Vec<u8>>()),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default().blocksize(7).unwrap();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.clone(),
+            Settings::default().subdir(Some(PathBuf::from("backup"))),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let chunked_files = fs.chunked_files();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // The files are sampled like they are presented, relative to the subdirectory
+        assert_eq!(chunked_files, vec![(PathBuf::from("file"), 30)]);
+        let verifier = SelfVerifier::new(
+            mirror.path().join("backup"),
+            mountpoint.path(),
+            config,
+            chunked_files,
+        );
+        for _ in 0..10 {
+            let (_, _, result) = verifier.verify_random_chunk().unwrap();
+            assert!(result?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_chunked_suffix() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        let files = vec![("b/c".to_string(), (0..30).collect::<Vec<u8>>())];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default()
+            .blocksize(7)
+            .unwrap()
+            .chunked_suffix(Some(".scfs".to_string()));
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.clone(),
+            Settings::default(),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let chunked_files = fs.chunked_files();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        // Only the directory of the file is marked, not its parents
+        assert!(mountpoint.path().join("b/c.scfs").is_dir());
+        let verifier = SelfVerifier::new(mirror.path(), mountpoint.path(), config, chunked_files);
+        for part in 0..5 {
+            assert!(verifier.verify_chunk(Path::new("b/c"), part)?);
+        }
+        for _ in 0..10 {
+            let (_, _, result) = verifier.verify_random_chunk().unwrap();
+            assert!(result?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_only_chunked_files() -> Result<(), std::io::Error> {
+        let mirror = tempdir()?;
+        let mountpoint = tempdir()?;
+
+        // Packed and excluded files have no chunks on the mount
+        let files = vec![
+            ("chunked".to_string(), (0..20).collect::<Vec<u8>>()),
+            ("packed".to_string(), vec![1; 3]),
+            ("excluded.tmp".to_string(), vec![2; 20]),
+        ];
+        create_files_and_symlinks(mirror.path(), &files, &Vec::new())?;
+
+        let config = Config::default().blocksize(7).unwrap();
+        let fs = SplitFS::new(
+            mirror.path().as_os_str(),
+            config.clone(),
+            Settings::default()
+                .pack_small(Some(4))
+                .exclude(vec![String::from("*.tmp")]),
+            Box::new(|| ()),
+        )
+        .unwrap();
+        let chunked_files = fs.chunked_files();
+        let _session = mount(fs, &mountpoint, Vec::new())?;
+
+        assert_eq!(chunked_files, vec![(PathBuf::from("chunked"), 20)]);
+        let verifier = SelfVerifier::new(mirror.path(), mountpoint.path(), config, chunked_files);
+        for _ in 0..10 {
+            let (file, _, result) = verifier.verify_random_chunk().unwrap();
+            assert_eq!(file, Path::new("chunked"));
+            assert!(result?);
+        }
+
+        Ok(())
+    }
+}
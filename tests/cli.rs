@@ -1,8 +1,12 @@
+use std::fs;
 use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use assert_cmd::Command;
 use lazy_static::lazy_static;
 use predicates::prelude::*;
+use tempfile::tempdir;
 
 lazy_static! {
     static ref SCFS_PATH: PathBuf = assert_cmd::cargo::cargo_bin("scfs");
@@ -71,3 +75,615 @@ fn correct_version() {
         .success()
         .stdout(format!("catfs {}\n", version));
 }
+
+#[test]
+fn dry_run_prints_chunks() {
+    let mirror = tempdir().unwrap();
+    fs::write(mirror.path().join("a"), (0..10).collect::<Vec<u8>>()).unwrap();
+    fs::create_dir(mirror.path().join("b")).unwrap();
+    fs::write(mirror.path().join("b/c"), b"").unwrap();
+
+    let output = Command::new(&*SCFS_PATH)
+        .args(["split", "--dry-run", "--blocksize", "3"])
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines.contains(&"a\t10\t4\tscfs.0000000003\t9\t10"));
+    assert!(lines.contains(&"b/c\t0\t1\tscfs.0000000000\t0\t0"));
+}
+
+#[test]
+fn mount_failure_is_reported() {
+    let mirror = tempdir().unwrap();
+    let other = tempdir().unwrap();
+
+    // A regular file cannot serve as mountpoint
+    let mountpoint = other.path().join("file");
+    fs::write(&mountpoint, b"").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg(mirror.path())
+        .arg(&mountpoint)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "Error: failed to mount at {}: ",
+            mountpoint.display()
+        )))
+        .stderr(predicate::str::contains("panicked").not());
+}
+
+#[test]
+fn invalid_mirror_is_reported() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Error: failed to index mirror: SCFS config file not found",
+        ))
+        .stderr(predicate::str::contains("panicked").not());
+
+    fs::write(mirror.path().join(".scfs_config"), "{").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("contains invalid JSON"))
+        .stderr(predicate::str::contains("panicked").not());
+}
+
+#[test]
+fn idle_timeout_unmounts() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+
+    fs::write(mirror.path().join("file"), b"content").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg("--idle-timeout")
+        .arg("1")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_dir(mountpoint.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn wait_ready_mounts_before_returning() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+
+    fs::write(mirror.path().join("file"), b"content").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg("--daemon")
+        .arg("--wait-ready")
+        .arg("--idle-timeout")
+        .arg("2")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .success();
+
+    // Without any sleep, the mountpoint must already serve the chunks
+    assert_eq!(
+        fs::read(mountpoint.path().join("file/scfs.0000000000")).unwrap(),
+        b"content"
+    );
+
+    // The idle timeout unmounts the daemon again. Listing the mountpoint would count as access,
+    // so the mount table is checked instead.
+    let mountpoint_str = mountpoint.path().to_str().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while fs::read_to_string("/proc/mounts")
+        .unwrap()
+        .lines()
+        .any(|line| line.split(' ').nth(1) == Some(mountpoint_str))
+    {
+        assert!(Instant::now() < deadline, "daemon did not unmount");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn daemon_writes_pid_file() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+    let files = tempdir().unwrap();
+    let pid_file = files.path().join("splitfs.pid");
+    let log_file = files.path().join("splitfs.log");
+
+    fs::write(mirror.path().join("file"), b"content").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg("--daemon")
+        .arg("--wait-ready")
+        .arg("--idle-timeout")
+        .arg("2")
+        .arg("--pid-file")
+        .arg(&pid_file)
+        .arg("--log-file")
+        .arg(&log_file)
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .success();
+
+    let pid = fs::read_to_string(&pid_file).unwrap();
+    let pid = pid.trim().parse::<u32>().unwrap();
+    assert!(PathBuf::from(format!("/proc/{}", pid)).exists());
+    assert!(log_file.exists());
+
+    // Once the idle timeout has unmounted the daemon, it removes its PID file
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while pid_file.exists() {
+        assert!(
+            Instant::now() < deadline,
+            "daemon did not remove its PID file"
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn fsname_and_subtype_are_listed() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+
+    fs::write(mirror.path().join("file"), b"content").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg("--daemon")
+        .arg("--wait-ready")
+        .arg("--idle-timeout")
+        .arg("2")
+        .arg("--fsname")
+        .arg("scfs-test-mirror")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .success();
+
+    // The fields after the separator are the file system type and the mount source. Only mounts
+    // through fusermount record the subtype in the type, root mounts the file system directly.
+    let mountpoint_str = mountpoint.path().to_str().unwrap();
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap();
+    let line = mountinfo
+        .lines()
+        .find(|line| line.split(' ').nth(4) == Some(mountpoint_str))
+        .expect("mountpoint is not listed");
+    let (_, fields) = line.split_once(" - ").unwrap();
+    let mut fields = fields.split(' ');
+    assert!(matches!(fields.next(), Some("fuse.splitfs" | "fuse")));
+    assert_eq!(fields.next(), Some("scfs-test-mirror"));
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while fs::read_to_string("/proc/mounts")
+        .unwrap()
+        .lines()
+        .any(|line| line.split(' ').nth(1) == Some(mountpoint_str))
+    {
+        assert!(Instant::now() < deadline, "daemon did not unmount");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn dump_prints_database() {
+    let mirror = tempdir().unwrap();
+
+    fs::write(mirror.path().join("file"), b"0123456789").unwrap();
+
+    let output = Command::new(&*SCFS_PATH)
+        .arg("dump")
+        .arg("--blocksize")
+        .arg("4")
+        .arg(mirror.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let rows = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(rows[0]["ino"], 1);
+    assert_eq!(rows[0]["parent_ino"], 0);
+
+    let file = rows.iter().find(|row| row["file_name"] == "file").unwrap();
+    assert_eq!(file["vdir"], true);
+
+    let chunks = rows
+        .iter()
+        .filter(|row| row["parent_ino"] == file["ino"])
+        .map(|row| {
+            (
+                row["file_name"].as_str().unwrap(),
+                row["part"].as_u64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        chunks,
+        vec![
+            ("scfs.0000000000", 1),
+            ("scfs.0000000001", 2),
+            ("scfs.0000000002", 3)
+        ]
+    );
+}
+
+#[test]
+fn plan_prints_chunk_counts() {
+    let mirror = tempdir().unwrap();
+
+    fs::create_dir_all(mirror.path().join("b/d")).unwrap();
+    fs::write(mirror.path().join("a"), vec![0; 10]).unwrap();
+    fs::write(mirror.path().join("b/c"), vec![0; 4]).unwrap();
+    fs::write(mirror.path().join("b/d/e"), vec![0; 17]).unwrap();
+    fs::write(mirror.path().join("empty"), b"").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("plan")
+        .arg("--blocksize=4")
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .stdout(
+            "Files: 4\n\
+             Chunks: 10\n\
+             Most chunks: 5 (b/d/e)\n\
+             Chunks per file: min 1, median 3, max 5\n",
+        );
+}
+
+#[test]
+fn doctor_round_trips_file() {
+    Command::new(&*SCFS_PATH)
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("FUSE device: ok\n"))
+        .stdout(predicate::str::contains("Round trip: ok\n"))
+        .stdout(predicate::str::ends_with("PASS\n"));
+}
+
+#[test]
+fn manifest_covers_every_chunk_once() {
+    let mirror = tempdir().unwrap();
+
+    let sizes = [("a", 25), ("dir/b", 10), ("dir/c", 3), ("empty", 0)];
+    fs::create_dir(mirror.path().join("dir")).unwrap();
+    for (file, size) in sizes {
+        fs::write(mirror.path().join(file), vec![1; size]).unwrap();
+    }
+
+    let manifest = || {
+        let output = Command::new(&*SCFS_PATH)
+            .arg("manifest")
+            .arg("--mode=split")
+            .arg("--blocksize")
+            .arg("10")
+            .arg(mirror.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let output = manifest();
+    assert_eq!(output, manifest());
+
+    let entries = output
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 3 + 1 + 1 + 1);
+
+    // The chunks of each file follow each other without gaps and end with the file
+    for (file, size) in sizes {
+        let chunks = entries
+            .iter()
+            .filter(|entry| entry["file"] == file)
+            .collect::<Vec<_>>();
+        let mut end = 0;
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk["index"], index as u64);
+            assert_eq!(chunk["offset"], end);
+            assert_eq!(
+                chunk["path"],
+                format!("{}/scfs.{:010}", file, index).as_str()
+            );
+            end += chunk["length"].as_u64().unwrap();
+        }
+        assert_eq!(end, size as u64, "{}", file);
+    }
+}
+
+#[test]
+fn check_reports_inconsistent_mirror() {
+    let mirror = tempdir().unwrap();
+
+    fs::write(mirror.path().join(".scfs_config"), r#"{"blocksize":4}"#).unwrap();
+    fs::create_dir(mirror.path().join("file")).unwrap();
+    fs::write(mirror.path().join("file/scfs.0000000000"), b"1234").unwrap();
+    fs::write(mirror.path().join("file/scfs.0000000001"), b"12").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("check")
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .stdout("PASS .scfs_config\nPASS file\n");
+
+    fs::remove_file(mirror.path().join("file/scfs.0000000000")).unwrap();
+
+    Command::new(&*CATFS_PATH)
+        .arg("--check")
+        .arg(mirror.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "FAIL file: expected part 0000000000",
+        ))
+        .stderr(predicate::str::contains("is inconsistent"));
+}
+
+#[test]
+fn cat_to_stdout_writes_file() {
+    let mirror = tempdir().unwrap();
+
+    fs::write(mirror.path().join(".scfs_config"), r#"{"blocksize":4}"#).unwrap();
+    fs::create_dir_all(mirror.path().join("dir/file")).unwrap();
+    fs::write(mirror.path().join("dir/file/scfs.0000000000"), b"1234").unwrap();
+    fs::write(mirror.path().join("dir/file/scfs.0000000001"), b"56").unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat-to-stdout")
+        .arg(mirror.path())
+        .arg("dir/file")
+        .assert()
+        .success()
+        .stdout("123456");
+
+    fs::remove_file(mirror.path().join("dir/file/scfs.0000000000")).unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat-to-stdout")
+        .arg(mirror.path())
+        .arg("dir/file")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("misses its chunk scfs.0000000000"));
+}
+
+#[test]
+fn split_to_stdout_rejects_chunk_layout_options() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("file");
+    fs::write(&file, b"123456").unwrap();
+
+    // The stream only follows the blocksize, so options changing the chunks are rejected
+    for option in [["--parts", "4"], ["--overlap", "2"]] {
+        Command::new(&*SCFS_PATH)
+            .args(["split", "--to-stdout"])
+            .arg(&file)
+            .args(option)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn split_to_dir_writes_chunks() {
+    let mirror = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+
+    let data = (0..=255).collect::<Vec<u8>>();
+    fs::create_dir(mirror.path().join("dir")).unwrap();
+    fs::write(mirror.path().join("dir/file"), &data).unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split-to-dir")
+        .arg("--blocksize=100")
+        .arg(mirror.path())
+        .arg(out_dir.path())
+        .assert()
+        .success();
+
+    assert!(out_dir.path().join(".scfs_config").is_file());
+    assert_eq!(
+        fs::read_dir(out_dir.path().join("dir/file"))
+            .unwrap()
+            .count(),
+        3
+    );
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat-to-stdout")
+        .arg(out_dir.path())
+        .arg("dir/file")
+        .assert()
+        .success()
+        .stdout(data);
+
+    Command::new(&*SCFS_PATH)
+        .arg("split-to-dir")
+        .arg(mirror.path())
+        .arg(out_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+}
+
+#[test]
+fn migrate_rewrites_config_in_current_format() {
+    let mirror = tempdir().unwrap();
+    let config = mirror.path().join(".scfs_config");
+
+    fs::write(&config, r#"{"blocksize":1024}"#).unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("migrate")
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("format_version: unset -> "));
+
+    let expected =
+        serde_json::to_string(&scfs::Config::default().blocksize(1024).unwrap()).unwrap();
+    assert_eq!(fs::read_to_string(&config).unwrap(), expected);
+    assert_eq!(fs::read_dir(mirror.path()).unwrap().count(), 1);
+
+    Command::new(&*SCFS_PATH)
+        .arg("migrate")
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .stdout(".scfs_config is up to date\n");
+
+    fs::write(&config, r#"{"blocksize":1024,"format_version":999}"#).unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("migrate")
+        .arg(mirror.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be migrated"));
+    assert!(fs::read_to_string(&config).unwrap().contains("999"));
+}
+
+#[test]
+fn blocksize_defaults_to_environment() {
+    let mirror = tempdir().unwrap();
+
+    fs::write(mirror.path().join("file"), b"content").unwrap();
+
+    // The variable only provides the default, an explicit blocksize still wins
+    for (args, blocksize) in [(vec![], 1024), (vec!["--blocksize", "2K"], 2048)] {
+        let mountpoint = tempdir().unwrap();
+
+        Command::new(&*SCFS_PATH)
+            .arg("split")
+            .env("SCFS_BLOCKSIZE", "1K")
+            .args(args)
+            .arg("--daemon")
+            .arg("--wait-ready")
+            .arg("--idle-timeout")
+            .arg("2")
+            .arg(mirror.path())
+            .arg(mountpoint.path())
+            .timeout(Duration::from_secs(30))
+            .assert()
+            .success();
+
+        let config = fs::read_to_string(mountpoint.path().join(".scfs_config")).unwrap();
+        let config = serde_json::from_str::<serde_json::Value>(&config).unwrap();
+        assert_eq!(config["blocksize"], blocksize);
+
+        let mountpoint_str = mountpoint.path().to_str().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while fs::read_to_string("/proc/mounts")
+            .unwrap()
+            .lines()
+            .any(|line| line.split(' ').nth(1) == Some(mountpoint_str))
+        {
+            assert!(Instant::now() < deadline, "daemon did not unmount");
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+#[test]
+fn parts_override_blocksize_from_environment() {
+    let mirror = tempdir().unwrap();
+    fs::write(mirror.path().join("a"), (0..10).collect::<Vec<u8>>()).unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .args(["split", "--dry-run", "--parts", "2"])
+        .env("SCFS_BLOCKSIZE", "3")
+        .arg(mirror.path())
+        .assert()
+        .success()
+        .stdout("a\t10\t2\tscfs.0000000000\t0\t5\na\t10\t2\tscfs.0000000001\t5\t10\n");
+
+    Command::new(&*SCFS_PATH)
+        .args(["split", "--dry-run", "--parts", "2", "--blocksize", "3"])
+        .arg(mirror.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Command::new(&*SPLITFS_PATH)
+        .args(["--dry-run", "--parts", "2", "--blocksize", "3"])
+        .arg(mirror.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn contradicting_mount_options_are_rejected() {
+    let mirror = tempdir().unwrap();
+    let mountpoint = tempdir().unwrap();
+
+    Command::new(&*SCFS_PATH)
+        .arg("split")
+        .arg("-o")
+        .arg("rw")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Error: Option rw contradicts the read-only mount",
+        ))
+        .stderr(predicate::str::contains("panicked").not());
+
+    Command::new(&*SCFS_PATH)
+        .arg("cat")
+        .arg(mirror.path())
+        .arg(mountpoint.path())
+        .arg("--")
+        .arg("exec,noexec")
+        .timeout(Duration::from_secs(30))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Error: Options exec and noexec contradict each other",
+        ));
+}